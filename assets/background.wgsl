@@ -0,0 +1,126 @@
+// 背景：在不透明物体之后绘制一个位于远平面的全屏三角形，
+// 深度测试会剔除被物体挡住的像素，每个剩下的像素按视线方向着色
+struct BackgroundUniform {
+    inv_view_proj: mat4x4<f32>,
+    // xyz 为指向太阳的单位向量，w 为太阳的强度
+    sun: vec4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> background: BackgroundUniform;
+@group(0) @binding(1)
+var t_skybox: texture_cube<f32>;
+@group(0) @binding(2)
+var s_skybox: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    let ndc = uv * 2.0 - 1.0;
+    var out: VertexOutput;
+    // z = w 使深度恰好为 1，只有没有被物体覆盖的像素能通过 LessEqual 测试
+    out.clip_position = vec4<f32>(ndc, 1.0, 1.0);
+    out.ndc = ndc;
+    return out;
+}
+
+// 视线上的两个点相减得到方向，与投影矩阵的深度范围约定无关
+fn view_direction(ndc: vec2<f32>) -> vec3<f32> {
+    let near = background.inv_view_proj * vec4<f32>(ndc, 0.0, 1.0);
+    let far = background.inv_view_proj * vec4<f32>(ndc, 0.5, 1.0);
+    return normalize(far.xyz / far.w - near.xyz / near.w);
+}
+
+@fragment
+fn fs_skybox(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSampleLevel(t_skybox, s_skybox, view_direction(in.ndc), 0.0).rgb;
+    return vec4<f32>(color, 1.0);
+}
+
+// 程序化天空：沿视线对大气做单次散射积分 (Rayleigh + Mie)
+const PI: f32 = 3.14159265359;
+const PLANET_RADIUS: f32 = 6371e3;
+const ATMOSPHERE_RADIUS: f32 = 6471e3;
+// 观察者位于地面以上 1km
+const ORIGIN: vec3<f32> = vec3<f32>(0.0, 6372e3, 0.0);
+const RAYLEIGH_COEFFICIENT: vec3<f32> = vec3<f32>(5.5e-6, 13.0e-6, 22.4e-6);
+const MIE_COEFFICIENT: f32 = 21e-6;
+const RAYLEIGH_SCALE_HEIGHT: f32 = 8e3;
+const MIE_SCALE_HEIGHT: f32 = 1.2e3;
+// Mie 散射的各向异性，越接近 1 太阳周围的光晕越集中
+const MIE_G: f32 = 0.758;
+const PRIMARY_STEPS: i32 = 16;
+const SECONDARY_STEPS: i32 = 8;
+
+// 射线与以原点为球心的球求交，返回进入与离开的距离，没有交点时 x > y
+fn ray_sphere(origin: vec3<f32>, dir: vec3<f32>, radius: f32) -> vec2<f32> {
+    let b = 2.0 * dot(dir, origin);
+    let c = dot(origin, origin) - radius * radius;
+    let d = b * b - 4.0 * c;
+    if d < 0.0 {
+        return vec2<f32>(1e5, -1e5);
+    }
+    return vec2<f32>(-b - sqrt(d), -b + sqrt(d)) / 2.0;
+}
+
+fn atmosphere(dir: vec3<f32>, sun_dir: vec3<f32>, sun_intensity: f32) -> vec3<f32> {
+    var range = ray_sphere(ORIGIN, dir, ATMOSPHERE_RADIUS);
+    if range.x > range.y {
+        return vec3<f32>(0.0);
+    }
+    // 视线打到地面时只积分到地面为止
+    let ground = ray_sphere(ORIGIN, dir, PLANET_RADIUS);
+    if ground.x < ground.y && ground.x > 0.0 {
+        range.y = min(range.y, ground.x);
+    }
+    let step = (range.y - max(range.x, 0.0)) / f32(PRIMARY_STEPS);
+
+    var total_rayleigh = vec3<f32>(0.0);
+    var total_mie = vec3<f32>(0.0);
+    var depth_rayleigh = 0.0;
+    var depth_mie = 0.0;
+    for (var i = 0; i < PRIMARY_STEPS; i += 1) {
+        let position = ORIGIN + dir * (max(range.x, 0.0) + (f32(i) + 0.5) * step);
+        let height = length(position) - PLANET_RADIUS;
+        let optical_rayleigh = exp(-height / RAYLEIGH_SCALE_HEIGHT) * step;
+        let optical_mie = exp(-height / MIE_SCALE_HEIGHT) * step;
+        depth_rayleigh += optical_rayleigh;
+        depth_mie += optical_mie;
+
+        // 从采样点到太阳方向的光学深度
+        let sun_step = ray_sphere(position, sun_dir, ATMOSPHERE_RADIUS).y / f32(SECONDARY_STEPS);
+        var sun_rayleigh = 0.0;
+        var sun_mie = 0.0;
+        for (var j = 0; j < SECONDARY_STEPS; j += 1) {
+            let sun_position = position + sun_dir * ((f32(j) + 0.5) * sun_step);
+            let sun_height = length(sun_position) - PLANET_RADIUS;
+            sun_rayleigh += exp(-sun_height / RAYLEIGH_SCALE_HEIGHT) * sun_step;
+            sun_mie += exp(-sun_height / MIE_SCALE_HEIGHT) * sun_step;
+        }
+
+        let attenuation = exp(-(MIE_COEFFICIENT * (depth_mie + sun_mie)
+            + RAYLEIGH_COEFFICIENT * (depth_rayleigh + sun_rayleigh)));
+        total_rayleigh += optical_rayleigh * attenuation;
+        total_mie += optical_mie * attenuation;
+    }
+
+    let mu = dot(dir, sun_dir);
+    let phase_rayleigh = 3.0 / (16.0 * PI) * (1.0 + mu * mu);
+    let g2 = MIE_G * MIE_G;
+    let phase_mie = 3.0 / (8.0 * PI) * ((1.0 - g2) * (mu * mu + 1.0))
+        / (pow(1.0 + g2 - 2.0 * mu * MIE_G, 1.5) * (2.0 + g2));
+    return sun_intensity * (phase_rayleigh * RAYLEIGH_COEFFICIENT * total_rayleigh
+        + phase_mie * MIE_COEFFICIENT * total_mie);
+}
+
+@fragment
+fn fs_sky(in: VertexOutput) -> @location(0) vec4<f32> {
+    let dir = view_direction(in.ndc);
+    let color = atmosphere(dir, normalize(background.sun.xyz), background.sun.w);
+    // 输出线性的辐射度，由色调映射压缩到显示范围
+    return vec4<f32>(color, 1.0);
+}
@@ -0,0 +1,103 @@
+// 泛光：阈值筛选后逐级降采样，再逐级升采样叠加，最后与场景合成
+// 降采样使用 Jimenez 在 "Next Generation Post Processing in Call of Duty: Advanced Warfare"
+// 中的 13 次采样滤波，升采样使用 3x3 的帐篷滤波
+struct Bloom {
+    // 亮度超过 threshold 的部分参与泛光，knee 为阈值附近平滑过渡的宽度
+    threshold: f32,
+    knee: f32,
+    intensity: f32,
+    // 升采样滤波的半径，以源纹理的纹素为单位
+    radius: f32,
+};
+
+@group(0) @binding(2)
+var<uniform> params: Bloom;
+@group(0) @binding(3)
+var t_bloom: texture_2d<f32>;
+
+const LUMA: vec3<f32> = vec3<f32>(0.2126, 0.7152, 0.0722);
+
+fn karis_weight(color: vec3<f32>) -> f32 {
+    return 1.0 / (1.0 + dot(color, LUMA));
+}
+
+// 二次曲线的软阈值，按最亮的通道缩放整个颜色以保持色相
+fn apply_threshold(color: vec3<f32>) -> vec3<f32> {
+    let brightness = max(color.r, max(color.g, color.b));
+    var soft = clamp(brightness - params.threshold + params.knee, 0.0, 2.0 * params.knee);
+    soft = soft * soft / (4.0 * params.knee + 1e-4);
+    let contribution = max(soft, brightness - params.threshold) / max(brightness, 1e-4);
+    return color * contribution;
+}
+
+fn tap(uv: vec2<f32>, offset: vec2<f32>) -> vec3<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_input));
+    return textureSampleLevel(t_input, s_input, uv + offset * texel, 0.0).rgb;
+}
+
+// 以 13 次采样组成五个互相重叠的 2x2 方框，karis 为 true 时按亮度的倒数加权平均，
+// 避免个别极亮的像素在降采样后闪烁
+fn downsample13(uv: vec2<f32>, karis: bool) -> vec3<f32> {
+    let a = tap(uv, vec2<f32>(-2.0, -2.0));
+    let b = tap(uv, vec2<f32>(0.0, -2.0));
+    let c = tap(uv, vec2<f32>(2.0, -2.0));
+    let d = tap(uv, vec2<f32>(-2.0, 0.0));
+    let e = tap(uv, vec2<f32>(0.0, 0.0));
+    let f = tap(uv, vec2<f32>(2.0, 0.0));
+    let g = tap(uv, vec2<f32>(-2.0, 2.0));
+    let h = tap(uv, vec2<f32>(0.0, 2.0));
+    let i = tap(uv, vec2<f32>(2.0, 2.0));
+    let j = tap(uv, vec2<f32>(-1.0, -1.0));
+    let k = tap(uv, vec2<f32>(1.0, -1.0));
+    let l = tap(uv, vec2<f32>(-1.0, 1.0));
+    let m = tap(uv, vec2<f32>(1.0, 1.0));
+
+    var boxes = array<vec3<f32>, 5>(
+        (j + k + l + m) * 0.25,
+        (a + b + d + e) * 0.25,
+        (b + c + e + f) * 0.25,
+        (d + e + g + h) * 0.25,
+        (e + f + h + i) * 0.25,
+    );
+    var weights = array<f32, 5>(0.5, 0.125, 0.125, 0.125, 0.125);
+    var sum = vec3<f32>(0.0);
+    var total = 0.0;
+    for (var n = 0; n < 5; n++) {
+        var weight = weights[n];
+        if karis {
+            weight *= karis_weight(boxes[n]);
+        }
+        sum += boxes[n] * weight;
+        total += weight;
+    }
+    return sum / total;
+}
+
+@fragment
+fn fs_prefilter(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(apply_threshold(downsample13(in.uv, true)), 1.0);
+}
+
+@fragment
+fn fs_downsample(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(downsample13(in.uv, false), 1.0);
+}
+
+// 结果以加法混合叠加到上一级上
+@fragment
+fn fs_upsample(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let r = params.radius;
+    var color = tap(in.uv, vec2<f32>(0.0, 0.0)) * 4.0;
+    color += (tap(in.uv, vec2<f32>(-r, 0.0)) + tap(in.uv, vec2<f32>(r, 0.0))
+        + tap(in.uv, vec2<f32>(0.0, -r)) + tap(in.uv, vec2<f32>(0.0, r))) * 2.0;
+    color += tap(in.uv, vec2<f32>(-r, -r)) + tap(in.uv, vec2<f32>(r, -r))
+        + tap(in.uv, vec2<f32>(-r, r)) + tap(in.uv, vec2<f32>(r, r));
+    return vec4<f32>(color / 16.0, 1.0);
+}
+
+@fragment
+fn fs_composite(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let scene = textureLoad(t_input, vec2<i32>(in.clip_position.xy), 0);
+    let bloom = textureSampleLevel(t_bloom, s_input, in.uv, 0.0).rgb;
+    return vec4<f32>(scene.rgb + bloom * params.intensity, scene.a);
+}
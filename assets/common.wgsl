@@ -0,0 +1,327 @@
+// 主渲染管线中各着色模型共用的部分：绑定、顶点着色器、阴影与光源衰减
+// PipelineCache 把特性常量、本文件与具体着色模型的源码依次拼接
+
+struct CameraUniform {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+};
+// kind: 0 平行光, 1 点光源, 2 聚光灯
+struct Light {
+    position: vec3<f32>,
+    kind: u32,
+    direction: vec3<f32>,
+    inner_cos: f32,
+    color: vec3<f32>,
+    outer_cos: f32,
+    attenuation: vec3<f32>,
+    intensity: f32,
+    // 在阴影贴图数组中的层，-1 表示不投射阴影
+    shadow_index: i32,
+};
+struct Lights {
+    ambient: vec3<f32>,
+    count: u32,
+    lights: array<Light>,
+};
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>
+};
+
+@group(1) @binding(0)
+var<uniform> camera: CameraUniform;
+@group(2) @binding(0)
+var<storage, read> lights: Lights;
+
+struct ShadowUniform {
+    light_view_proj: array<mat4x4<f32>, 8>,
+    // 每个级联的远端距离
+    cascade_splits: vec4<f32>,
+    // 与 vec4(position, 1.0) 点乘得到片元到摄像机的深度
+    view_depth: vec4<f32>,
+    depth_bias: f32,
+    slope_bias: f32,
+    texel_size: f32,
+    pcf_radius: u32,
+    debug_cascades: u32,
+    point_far: f32,
+    point_texel_size: f32,
+};
+@group(3) @binding(0)
+var t_shadow: texture_depth_2d_array;
+@group(3) @binding(1)
+var s_shadow: sampler_comparison;
+@group(3) @binding(2)
+var<uniform> shadow: ShadowUniform;
+@group(3) @binding(3)
+var t_point_shadow: texture_depth_cube_array;
+
+// 基于图像的光照，由 ibl.rs 预计算
+struct EnvironmentUniform {
+    intensity: f32,
+    // 预过滤贴图最后一级 mip，对应粗糙度 1
+    max_lod: f32,
+    // 为 0 时没有加载环境贴图
+    enabled: u32,
+};
+@group(4) @binding(0)
+var<uniform> environment: EnvironmentUniform;
+@group(4) @binding(1)
+var t_irradiance: texture_cube<f32>;
+@group(4) @binding(2)
+var t_prefiltered: texture_cube<f32>;
+@group(4) @binding(3)
+var t_brdf_lut: texture_2d<f32>;
+@group(4) @binding(4)
+var s_environment: sampler;
+
+// 屏幕空间环境光遮蔽，由 ssao.rs 在主 pass 之前计算，关闭时为白色
+@group(5) @binding(0)
+var t_ssao: texture_2d<f32>;
+
+struct VertexOutput {
+    // 深度预渲染与主 pass 使用同一个 vs_main，invariant 保证两者算出的深度完全相同，
+    // 主 pass 才能直接沿用预渲染的深度
+    @builtin(position) @invariant clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) world_position: vec3<f32>,
+    @location(2) world_normal: vec3<f32>,
+    @location(3) world_tangent: vec3<f32>,
+    @location(4) world_bitangent: vec3<f32>,
+};
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) tangent: vec3<f32>,
+    @location(4) bitangent: vec3<f32>,
+};
+@vertex
+fn vs_main(
+    @builtin(vertex_index) in_vertex_index: u32,
+    model: VertexInput,
+    instance: InstanceInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    // 实例只有旋转与平移，没有非均匀缩放，可以直接用模型矩阵的 3x3 部分变换法线
+    let normal_matrix = mat3x3<f32>(
+        instance.model_matrix_0.xyz,
+        instance.model_matrix_1.xyz,
+        instance.model_matrix_2.xyz,
+    );
+
+    let world_position = model_matrix * vec4<f32>(model.position, 1.0);
+    out.clip_position = camera.view_proj * world_position;
+    out.tex_coords = model.tex_coords;
+    out.world_position = world_position.xyz;
+    out.world_normal = normal_matrix * model.normal;
+    out.world_tangent = normal_matrix * model.tangent;
+    out.world_bitangent = normal_matrix * model.bitangent;
+    return out;
+}
+
+struct MaterialUniform {
+    base_color: vec4<f32>,
+    specular: vec3<f32>,
+    shininess: f32,
+    emissive: vec3<f32>,
+    // 仅在 ALPHA_MASK 变体中使用
+    alpha_cutoff: f32,
+    metallic: f32,
+    roughness: f32,
+    occlusion_strength: f32,
+};
+@group(0) @binding(0)
+var<uniform> material: MaterialUniform;
+@group(0) @binding(1)
+var t_diffuse: texture_2d<f32>;
+@group(0) @binding(2)
+var s_diffuse: sampler;
+@group(0) @binding(3)
+var t_normal: texture_2d<f32>;
+@group(0) @binding(4)
+var s_normal: sampler;
+// G 通道为粗糙度，B 通道为金属度
+@group(0) @binding(5)
+var t_metallic_roughness: texture_2d<f32>;
+@group(0) @binding(6)
+var s_metallic_roughness: sampler;
+@group(0) @binding(7)
+var t_occlusion: texture_2d<f32>;
+@group(0) @binding(8)
+var s_occlusion: sampler;
+@group(0) @binding(9)
+var t_emissive: texture_2d<f32>;
+@group(0) @binding(10)
+var s_emissive: sampler;
+
+// 返回 0 (完全处于阴影中) 到 1 (完全被照亮) 之间的值
+fn shadow_factor(index: i32, position: vec3<f32>, normal: vec3<f32>, light_dir: vec3<f32>) -> f32 {
+    let clip = shadow.light_view_proj[index] * vec4<f32>(position, 1.0);
+    if clip.w <= 0.0 {
+        return 1.0;
+    }
+    let ndc = clip.xyz / clip.w;
+    // 裁剪空间的 y 轴朝上，而纹理坐标的 y 轴朝下
+    let uv = ndc.xy * vec2<f32>(0.5, -0.5) + 0.5;
+    if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+
+    // 表面越倾斜于光线，需要的偏移越大
+    let bias = max(shadow.slope_bias * (1.0 - dot(normal, light_dir)), shadow.depth_bias);
+    let depth = ndc.z - bias;
+    let radius = i32(shadow.pcf_radius);
+    var lit = 0.0;
+    for (var y = -radius; y <= radius; y += 1) {
+        for (var x = -radius; x <= radius; x += 1) {
+            let offset = vec2<f32>(f32(x), f32(y)) * shadow.texel_size;
+            lit += textureSampleCompareLevel(t_shadow, s_shadow, uv + offset, index, depth);
+        }
+    }
+    let samples = f32((2 * radius + 1) * (2 * radius + 1));
+    return lit / samples;
+}
+
+// 点光源的立方体阴影，贴图中保存的是到光源的距离除以 point_far
+fn point_shadow_factor(index: i32, position: vec3<f32>, light_position: vec3<f32>, normal: vec3<f32>, light_dir: vec3<f32>) -> f32 {
+    let to_fragment = position - light_position;
+    let distance = length(to_fragment);
+    if distance >= shadow.point_far {
+        return 1.0;
+    }
+    let dir = to_fragment / distance;
+    let bias = max(shadow.slope_bias * (1.0 - dot(normal, light_dir)), shadow.depth_bias);
+    let depth = distance / shadow.point_far - bias;
+
+    // 在垂直于采样方向的平面内做 PCF
+    var up = vec3<f32>(0.0, 1.0, 0.0);
+    if abs(dir.y) > 0.99 {
+        up = vec3<f32>(1.0, 0.0, 0.0);
+    }
+    let tangent = normalize(cross(up, dir));
+    let bitangent = cross(dir, tangent);
+    // 立方体一个面的宽度在方向空间中为 2
+    let step = 2.0 * shadow.point_texel_size;
+    let radius = i32(shadow.pcf_radius);
+    var lit = 0.0;
+    for (var y = -radius; y <= radius; y += 1) {
+        for (var x = -radius; x <= radius; x += 1) {
+            let offset = (f32(x) * tangent + f32(y) * bitangent) * step;
+            lit += textureSampleCompareLevel(t_point_shadow, s_shadow, dir + offset, index, depth);
+        }
+    }
+    let samples = f32((2 * radius + 1) * (2 * radius + 1));
+    return lit / samples;
+}
+
+// 片元所处的级联，超出最后一个级联时返回 4
+fn cascade_index(position: vec3<f32>) -> u32 {
+    let depth = dot(shadow.view_depth, vec4<f32>(position, 1.0));
+    var cascade = 0u;
+    for (var i = 0u; i < 4u; i += 1u) {
+        if depth > shadow.cascade_splits[i] {
+            cascade = i + 1u;
+        }
+    }
+    return cascade;
+}
+
+// 光源照到片元上的方向与辐射度，已经计入距离衰减、聚光灯锥角与阴影
+struct Incident {
+    direction: vec3<f32>,
+    radiance: vec3<f32>,
+};
+fn incident(light: Light, position: vec3<f32>, normal: vec3<f32>) -> Incident {
+    var light_dir: vec3<f32>;
+    var attenuation = 1.0;
+    if light.kind == 0u {
+        light_dir = normalize(-light.direction);
+    } else {
+        let to_light = light.position - position;
+        let distance = length(to_light);
+        light_dir = to_light / distance;
+        attenuation = 1.0 / (light.attenuation.x
+            + light.attenuation.y * distance
+            + light.attenuation.z * distance * distance);
+        if light.kind == 2u {
+            // 在内外锥角之间平滑衰减
+            let theta = dot(-light_dir, normalize(light.direction));
+            attenuation *= smoothstep(light.outer_cos, light.inner_cos, theta);
+        }
+    }
+
+    if light.shadow_index >= 0 {
+        if light.kind == 0u {
+            // 平行光按片元的深度选择级联，超出级联范围的部分不投射阴影
+            let cascade = cascade_index(position);
+            if cascade < 4u {
+                let index = light.shadow_index + i32(cascade);
+                attenuation *= shadow_factor(index, position, normal, light_dir);
+            }
+        } else if light.kind == 1u {
+            attenuation *= point_shadow_factor(light.shadow_index, position, light.position, normal, light_dir);
+        } else {
+            attenuation *= shadow_factor(light.shadow_index, position, normal, light_dir);
+        }
+    }
+
+    return Incident(light_dir, light.color * light.intensity * attenuation);
+}
+
+// 用 TBN 矩阵把法线贴图中的切线空间法线变换到世界空间
+fn surface_normal(in: VertexOutput) -> vec3<f32> {
+    let object_normal = textureSample(t_normal, s_normal, in.tex_coords);
+    let tbn = mat3x3<f32>(
+        normalize(in.world_tangent),
+        normalize(in.world_bitangent),
+        normalize(in.world_normal),
+    );
+    return normalize(tbn * (object_normal.xyz * 2.0 - 1.0));
+}
+
+// 乘上材质颜色后的反照率，ALPHA_MASK 变体中丢弃低于 cutoff 的片元
+fn surface_albedo(in: VertexOutput) -> vec4<f32> {
+    let albedo = textureSample(t_diffuse, s_diffuse, in.tex_coords) * material.base_color;
+    // ALPHA_MASK 由 PipelineCache 按材质特性加在源码之前
+    if ALPHA_MASK && albedo.a < material.alpha_cutoff {
+        discard;
+    }
+    return albedo;
+}
+
+// 深度预渲染中 alpha 测试的材质使用的片元着色器，只负责丢弃片元
+@fragment
+fn fs_alpha_mask(in: VertexOutput) {
+    _ = surface_albedo(in);
+}
+
+// 片元处的屏幕空间环境光遮蔽，1 表示没有遮蔽
+fn screen_space_occlusion(clip_position: vec4<f32>) -> f32 {
+    let size = vec2<i32>(textureDimensions(t_ssao));
+    let pixel = min(vec2<i32>(clip_position.xy), size - 1);
+    return textureLoad(t_ssao, pixel, 0).r;
+}
+
+// 开启级联调试时按级联给画面染色
+fn debug_cascade_tint(color: vec3<f32>, position: vec3<f32>) -> vec3<f32> {
+    let cascade = cascade_index(position);
+    if shadow.debug_cascades == 0u || cascade >= 4u {
+        return color;
+    }
+    var tints = array<vec3<f32>, 4>(
+        vec3<f32>(1.0, 0.2, 0.2),
+        vec3<f32>(0.2, 1.0, 0.2),
+        vec3<f32>(0.2, 0.4, 1.0),
+        vec3<f32>(1.0, 1.0, 0.2),
+    );
+    return mix(color, tints[cascade], 0.3);
+}
@@ -0,0 +1,5 @@
+// 原样复制输入，输出格式的转换由渲染目标完成
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    return textureLoad(t_input, vec2<i32>(in.clip_position.xy), 0);
+}
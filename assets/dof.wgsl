@@ -0,0 +1,57 @@
+// 景深：按弥散圆半径在黄金角螺旋上采样圆盘
+struct Dof {
+    inv_view_proj: mat4x4<f32>,
+    focus_distance: f32,
+    aperture: f32,
+    max_radius: f32,
+    sample_count: u32,
+};
+
+@group(0) @binding(2)
+var<uniform> dof: Dof;
+@group(0) @binding(3)
+var t_depth: texture_2d<f32>;
+
+const GOLDEN_ANGLE: f32 = 2.39996323;
+
+// 像素到摄像机所在平面的距离，超出屏幕的坐标夹到边缘
+fn view_depth(pixel: vec2<i32>) -> f32 {
+    let size = vec2<i32>(textureDimensions(t_depth));
+    let p = clamp(pixel, vec2<i32>(0), size - 1);
+    let uv = (vec2<f32>(p) + 0.5) / vec2<f32>(size);
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, textureLoad(t_depth, p, 0).r, 1.0);
+    // 逆变换得到的 w 是裁剪空间 w 的倒数，透视投影中裁剪空间的 w 即为视图空间的深度
+    return abs(1.0 / (dof.inv_view_proj * ndc).w);
+}
+
+// 弥散圆半径 (像素)
+fn circle_of_confusion(depth: f32) -> f32 {
+    return min(dof.aperture * abs(depth - dof.focus_distance) / depth, 1.0) * dof.max_radius;
+}
+
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(textureDimensions(t_input));
+    let center_depth = view_depth(vec2<i32>(in.clip_position.xy));
+    let center_coc = circle_of_confusion(center_depth);
+    var color = textureSampleLevel(t_input, s_input, in.uv, 0.0).rgb;
+    var total = 1.0;
+    for (var i = 0u; i < dof.sample_count; i += 1u) {
+        // 半径按面积均匀分布
+        let radius = dof.max_radius * sqrt((f32(i) + 0.5) / f32(dof.sample_count));
+        let angle = f32(i) * GOLDEN_ANGLE;
+        let position = in.clip_position.xy + vec2<f32>(cos(angle), sin(angle)) * radius;
+        let depth = view_depth(vec2<i32>(floor(position)));
+        var coc = circle_of_confusion(depth);
+        // 焦内的前景不被其后的模糊背景覆盖
+        if depth > center_depth {
+            coc = min(coc, center_coc * 2.0);
+        }
+        // 样本的弥散圆覆盖到当前像素时才计入，否则以当前的平均值代替
+        let weight = smoothstep(radius - 0.5, radius + 0.5, coc);
+        let tap = textureSampleLevel(t_input, s_input, position / size, 0.0).rgb;
+        color += mix(color / total, tap, weight);
+        total += 1.0;
+    }
+    return vec4<f32>(color / total, 1.0);
+}
@@ -0,0 +1,97 @@
+// 自动曝光：统计 HDR 画面的对数亮度直方图，再求出人眼适应后的平均亮度
+struct ToneMapping {
+    curve: u32,
+    auto_exposure: u32,
+    exposure: f32,
+    delta_time: f32,
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    // 求平均时忽略最暗与最亮的像素，只统计这两个比例之间的部分
+    low_percentile: f32,
+    high_percentile: f32,
+    // 适应速度 (1/秒)，分别用于画面变亮与变暗时
+    speed_brighten: f32,
+    speed_darken: f32,
+};
+
+struct Exposure {
+    log_luminance: f32,
+    // 为 0 时直接采用本帧的测光结果，不做渐变
+    initialized: u32,
+};
+
+@group(0) @binding(0)
+var t_hdr: texture_2d<f32>;
+@group(0) @binding(1)
+var<uniform> settings: ToneMapping;
+@group(0) @binding(2)
+var<storage, read_write> histogram: array<atomic<u32>, 256>;
+@group(0) @binding(3)
+var<storage, read_write> exposure: Exposure;
+
+const BINS: u32 = 256u;
+const LUMA: vec3<f32> = vec3<f32>(0.2126, 0.7152, 0.0722);
+
+var<workgroup> local_bins: array<atomic<u32>, 256>;
+var<workgroup> counts: array<u32, 256>;
+
+// 每个工作组先在共享内存中统计 16x16 的像素，再合并到全局直方图
+@compute @workgroup_size(16, 16)
+fn build_histogram(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) index: u32,
+) {
+    atomicStore(&local_bins[index], 0u);
+    workgroupBarrier();
+    if all(id.xy < textureDimensions(t_hdr)) {
+        let color = textureLoad(t_hdr, vec2<i32>(id.xy), 0).rgb;
+        let log_luminance = log2(max(dot(color, LUMA), 1e-6));
+        let t = (log_luminance - settings.min_log_luminance) / settings.log_luminance_range;
+        let bin = min(u32(saturate(t) * f32(BINS)), BINS - 1u);
+        atomicAdd(&local_bins[bin], 1u);
+    }
+    workgroupBarrier();
+    atomicAdd(&histogram[index], atomicLoad(&local_bins[index]));
+}
+
+// 单个工作组：读出并清空直方图，由第一个线程求平均亮度并向其渐变
+@compute @workgroup_size(256)
+fn average(@builtin(local_invocation_index) index: u32) {
+    counts[index] = atomicLoad(&histogram[index]);
+    atomicStore(&histogram[index], 0u);
+    workgroupBarrier();
+    if index != 0u {
+        return;
+    }
+
+    var total = 0u;
+    for (var i = 0u; i < BINS; i++) {
+        total += counts[i];
+    }
+    let low = f32(total) * settings.low_percentile;
+    let high = f32(total) * settings.high_percentile;
+    var seen = 0.0;
+    var sum = 0.0;
+    var weight = 0.0;
+    for (var i = 0u; i < BINS; i++) {
+        let count = f32(counts[i]);
+        // 这个 bin 中落在 [low, high] 范围内的像素数
+        let inside = max(min(seen + count, high) - max(seen, low), 0.0);
+        seen += count;
+        let t = (f32(i) + 0.5) / f32(BINS);
+        sum += inside * (settings.min_log_luminance + t * settings.log_luminance_range);
+        weight += inside;
+    }
+    var measured = settings.min_log_luminance;
+    if weight > 0.0 {
+        measured = sum / weight;
+    }
+
+    if exposure.initialized == 0u {
+        exposure.log_luminance = measured;
+        exposure.initialized = 1u;
+        return;
+    }
+    let speed = select(settings.speed_darken, settings.speed_brighten, measured > exposure.log_luminance);
+    exposure.log_luminance += (measured - exposure.log_luminance) * (1.0 - exp(-settings.delta_time * speed));
+}
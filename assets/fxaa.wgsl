@@ -0,0 +1,44 @@
+// FXAA：用四角的亮度估计边缘方向，沿该方向采样两次或四次后取在局部亮度范围内的结果
+const SPAN_MAX: f32 = 8.0;
+const REDUCE_MUL: f32 = 0.125;
+const REDUCE_MIN: f32 = 0.0078125;
+
+// 输入是线性颜色，开方近似到感知亮度，使暗部的边缘也能被检测到
+fn luma(color: vec3<f32>) -> f32 {
+    return sqrt(dot(saturate(color), vec3<f32>(0.299, 0.587, 0.114)));
+}
+
+fn tap(uv: vec2<f32>) -> vec3<f32> {
+    return textureSampleLevel(t_input, s_input, uv, 0.0).rgb;
+}
+
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(t_input));
+    let center = textureSampleLevel(t_input, s_input, in.uv, 0.0);
+    let luma_nw = luma(tap(in.uv + vec2<f32>(-1.0, -1.0) * texel));
+    let luma_ne = luma(tap(in.uv + vec2<f32>(1.0, -1.0) * texel));
+    let luma_sw = luma(tap(in.uv + vec2<f32>(-1.0, 1.0) * texel));
+    let luma_se = luma(tap(in.uv + vec2<f32>(1.0, 1.0) * texel));
+    let luma_m = luma(center.rgb);
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    // 梯度的法线方向即为边缘的走向
+    var dir = vec2<f32>(
+        (luma_sw + luma_se) - (luma_nw + luma_ne),
+        (luma_nw + luma_sw) - (luma_ne + luma_se),
+    );
+    let reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+    let scale = 1.0 / (min(abs(dir.x), abs(dir.y)) + reduce);
+    dir = clamp(dir * scale, vec2<f32>(-SPAN_MAX), vec2<f32>(SPAN_MAX)) * texel;
+
+    let near = 0.5 * (tap(in.uv + dir * (1.0 / 3.0 - 0.5)) + tap(in.uv + dir * (2.0 / 3.0 - 0.5)));
+    let far = near * 0.5 + 0.25 * (tap(in.uv - dir * 0.5) + tap(in.uv + dir * 0.5));
+    // 远处的样本越过了另一条边缘时只使用近处的样本
+    let luma_far = luma(far);
+    if luma_far < luma_min || luma_far > luma_max {
+        return vec4<f32>(near, center.a);
+    }
+    return vec4<f32>(far, center.a);
+}
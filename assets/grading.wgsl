@@ -0,0 +1,75 @@
+// 调色：白平衡、饱和度、lift/gamma/gain 与 3D LUT
+struct ColorGrading {
+    lift: vec4<f32>,
+    gamma: vec4<f32>,
+    gain: vec4<f32>,
+    balance: vec4<f32>,
+    domain_min: vec4<f32>,
+    domain_max: vec4<f32>,
+    saturation: f32,
+    lut_contribution: f32,
+};
+
+@group(0) @binding(2)
+var<uniform> grading: ColorGrading;
+// Rgba32Float 不能过滤，用 textureLoad 手动插值
+@group(0) @binding(3)
+var t_lut: texture_3d<f32>;
+
+// 线性 Rec.709 与 LMS 之间的转换，按列给出
+const LINEAR_TO_LMS = mat3x3<f32>(
+    vec3<f32>(3.90405e-1, 7.08416e-2, 2.31082e-2),
+    vec3<f32>(5.49941e-1, 9.63172e-1, 1.28021e-1),
+    vec3<f32>(8.92632e-3, 1.35775e-3, 9.36245e-1),
+);
+const LMS_TO_LINEAR = mat3x3<f32>(
+    vec3<f32>(2.85847e+0, -2.10182e-1, -4.18120e-2),
+    vec3<f32>(-1.62879e+0, 1.15820e+0, -1.18169e-1),
+    vec3<f32>(-2.48910e-2, 3.24281e-4, 1.06867e+0),
+);
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3<f32>(0.0031308));
+}
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
+
+fn lut_texel(p: vec3<i32>) -> vec3<f32> {
+    return textureLoad(t_lut, p, 0).rgb;
+}
+
+// 三线性插值
+fn apply_lut(color: vec3<f32>) -> vec3<f32> {
+    let size = i32(textureDimensions(t_lut).x);
+    let range = grading.domain_max.xyz - grading.domain_min.xyz;
+    let coord = saturate((color - grading.domain_min.xyz) / range) * f32(size - 1);
+    let base = floor(coord);
+    let f = coord - base;
+    let lo = vec3<i32>(base);
+    let hi = min(lo + 1, vec3<i32>(size - 1));
+    let c00 = mix(lut_texel(lo), lut_texel(vec3<i32>(hi.x, lo.y, lo.z)), f.x);
+    let c10 = mix(lut_texel(vec3<i32>(lo.x, hi.y, lo.z)), lut_texel(vec3<i32>(hi.x, hi.y, lo.z)), f.x);
+    let c01 = mix(lut_texel(vec3<i32>(lo.x, lo.y, hi.z)), lut_texel(vec3<i32>(hi.x, lo.y, hi.z)), f.x);
+    let c11 = mix(lut_texel(vec3<i32>(lo.x, hi.y, hi.z)), lut_texel(hi), f.x);
+    return mix(mix(c00, c10, f.y), mix(c01, c11, f.y), f.z);
+}
+
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let source = textureSampleLevel(t_input, s_input, in.uv, 0.0);
+    var color = LMS_TO_LINEAR * (grading.balance.xyz * (LINEAR_TO_LMS * source.rgb));
+    let luma = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+    color = max(mix(vec3<f32>(luma), color, grading.saturation), vec3<f32>(0.0));
+
+    var encoded = linear_to_srgb(saturate(color));
+    encoded = grading.gain.rgb * (encoded + grading.lift.rgb * (1.0 - encoded));
+    encoded = pow(saturate(encoded), 1.0 / grading.gamma.rgb);
+    encoded = mix(encoded, apply_lut(encoded), grading.lut_contribution);
+    return vec4<f32>(srgb_to_linear(saturate(encoded)), source.a);
+}
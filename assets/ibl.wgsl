@@ -0,0 +1,229 @@
+// 基于图像的光照 (IBL) 的预计算
+// 每个入口点使用不同的绑定，管线布局由 wgpu 根据入口点实际用到的绑定自动生成
+
+const PI: f32 = 3.14159265359;
+
+// 等距柱状投影 (equirectangular) 的 HDR 图像，Rgba32Float 不可过滤，手动做双线性插值
+@group(0) @binding(0)
+var t_equirect: texture_2d<f32>;
+// 写入立方体贴图某一级 mip 的六个面
+@group(0) @binding(1)
+var dst_cube: texture_storage_2d_array<rgba16float, write>;
+@group(0) @binding(3)
+var t_environment: texture_cube<f32>;
+@group(0) @binding(4)
+var s_environment: sampler;
+
+struct PrefilterParams {
+    roughness: f32,
+    // 环境立方体贴图第 0 级的边长
+    source_size: f32,
+    // 环境立方体贴图的 mip 数量
+    source_mips: f32,
+    sample_count: u32,
+};
+@group(0) @binding(5)
+var<uniform> prefilter_params: PrefilterParams;
+@group(0) @binding(6)
+var dst_lut: texture_storage_2d<rgba16float, write>;
+
+// 立方体贴图面上的纹素对应的方向，面的顺序为 +X -X +Y -Y +Z -Z
+fn cube_direction(face: u32, texel: vec2<u32>, size: u32) -> vec3<f32> {
+    return face_direction(face, vec2<f32>(texel) + 0.5, size);
+}
+
+// 面上以纹素为单位的位置 pos 对应的方向
+fn face_direction(face: u32, pos: vec2<f32>, size: u32) -> vec3<f32> {
+    let uv = pos / f32(size) * 2.0 - 1.0;
+    var dir: vec3<f32>;
+    switch face {
+        case 0u: { dir = vec3<f32>(1.0, -uv.y, -uv.x); }
+        case 1u: { dir = vec3<f32>(-1.0, -uv.y, uv.x); }
+        case 2u: { dir = vec3<f32>(uv.x, 1.0, uv.y); }
+        case 3u: { dir = vec3<f32>(uv.x, -1.0, -uv.y); }
+        case 4u: { dir = vec3<f32>(uv.x, -uv.y, 1.0); }
+        default: { dir = vec3<f32>(-uv.x, -uv.y, -1.0); }
+    }
+    return normalize(dir);
+}
+
+fn load_equirect(texel: vec2<i32>, size: vec2<i32>) -> vec3<f32> {
+    // 水平方向首尾相接，竖直方向截断
+    let x = (texel.x % size.x + size.x) % size.x;
+    let y = clamp(texel.y, 0, size.y - 1);
+    return textureLoad(t_equirect, vec2<i32>(x, y), 0).rgb;
+}
+
+fn sample_equirect(dir: vec3<f32>, src_size: vec2<i32>) -> vec3<f32> {
+    let uv = vec2<f32>(atan2(dir.z, dir.x) / (2.0 * PI) + 0.5, acos(clamp(dir.y, -1.0, 1.0)) / PI);
+    let pos = uv * vec2<f32>(src_size) - 0.5;
+    let base = vec2<i32>(floor(pos));
+    let t = fract(pos);
+    let top = mix(load_equirect(base, src_size), load_equirect(base + vec2<i32>(1, 0), src_size), t.x);
+    let bottom = mix(
+        load_equirect(base + vec2<i32>(0, 1), src_size),
+        load_equirect(base + vec2<i32>(1, 1), src_size),
+        t.x,
+    );
+    return mix(top, bottom, t.y);
+}
+
+// 每级 mip 都直接从等距柱状投影生成：低级 mip 的一个纹素覆盖更多源像素，按覆盖范围超采样。
+// GL 后端把纹理作为采样视图绑定时会修改整张纹理的 base/max level，
+// 若一边采样上一级一边写入下一级，写入会落在有效范围之外而被丢弃
+@compute @workgroup_size(8, 8, 1)
+fn equirect_to_cube(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(dst_cube);
+    if id.x >= size.x || id.y >= size.y {
+        return;
+    }
+    let src_size = vec2<i32>(textureDimensions(t_equirect));
+    // 立方体一个面大约对应源图宽度的 1/4
+    let n = clamp(u32(src_size.x) / (4u * size.x), 1u, 16u);
+    var sum = vec3<f32>(0.0);
+    for (var j = 0u; j < n; j++) {
+        for (var i = 0u; i < n; i++) {
+            let offset = (vec2<f32>(f32(i), f32(j)) + 0.5) / f32(n);
+            sum += sample_equirect(face_direction(id.z, vec2<f32>(id.xy) + offset, size.x), src_size);
+        }
+    }
+    textureStore(dst_cube, id.xy, id.z, vec4<f32>(sum / f32(n * n), 1.0));
+}
+
+// 以 normal 为轴的切线空间
+fn tangent_frame(normal: vec3<f32>) -> mat3x3<f32> {
+    var up = vec3<f32>(0.0, 1.0, 0.0);
+    if abs(normal.y) > 0.999 {
+        up = vec3<f32>(0.0, 0.0, 1.0);
+    }
+    let tangent = normalize(cross(up, normal));
+    let bitangent = cross(normal, tangent);
+    return mat3x3<f32>(tangent, bitangent, normal);
+}
+
+// 漫反射辐照度：对半球内的入射光按 cos 加权积分
+@compute @workgroup_size(8, 8, 1)
+fn irradiance(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(dst_cube);
+    if id.x >= size.x || id.y >= size.y {
+        return;
+    }
+    let normal = cube_direction(id.z, id.xy, size.x);
+    let frame = tangent_frame(normal);
+    // 从与输出分辨率相近的 mip 采样，避免遗漏高频细节带来的噪点
+    let lod = max(log2(f32(textureDimensions(t_environment).x) / f32(size.x)), 0.0);
+
+    let step = 0.05;
+    var sum = vec3<f32>(0.0);
+    var count = 0.0;
+    for (var phi = 0.0; phi < 2.0 * PI; phi += step) {
+        for (var theta = 0.0; theta < 0.5 * PI; theta += step) {
+            let local = vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            let color = textureSampleLevel(t_environment, s_environment, frame * local, lod).rgb;
+            sum += color * cos(theta) * sin(theta);
+            count += 1.0;
+        }
+    }
+    textureStore(dst_cube, id.xy, id.z, vec4<f32>(PI * sum / count, 1.0));
+}
+
+// Hammersley 低差异序列
+fn hammersley(i: u32, count: u32) -> vec2<f32> {
+    return vec2<f32>(f32(i) / f32(count), f32(reverseBits(i)) * 2.3283064365386963e-10);
+}
+
+// 按 GGX 分布对半程向量做重要性采样，返回切线空间中的方向
+fn importance_sample_ggx(xi: vec2<f32>, roughness: f32) -> vec3<f32> {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+    let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+    return vec3<f32>(sin_theta * cos(phi), sin_theta * sin(phi), cos_theta);
+}
+
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+// 镜面反射的预过滤：假设视线方向等于法线方向，按粗糙度对环境做 GGX 卷积
+@compute @workgroup_size(8, 8, 1)
+fn prefilter(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(dst_cube);
+    if id.x >= size.x || id.y >= size.y {
+        return;
+    }
+    let normal = cube_direction(id.z, id.xy, size.x);
+    let frame = tangent_frame(normal);
+    let roughness = prefilter_params.roughness;
+    let count = prefilter_params.sample_count;
+    // 每个纹素在源立方体贴图上所占的立体角
+    let texel_solid_angle = 4.0 * PI / (6.0 * prefilter_params.source_size * prefilter_params.source_size);
+
+    var sum = vec3<f32>(0.0);
+    var weight = 0.0;
+    for (var i = 0u; i < count; i += 1u) {
+        let h = frame * importance_sample_ggx(hammersley(i, count), roughness);
+        let l = normalize(2.0 * dot(normal, h) * h - normal);
+        let n_dot_l = dot(normal, l);
+        if n_dot_l > 0.0 {
+            // 按样本的概率密度选择 mip，概率越低的样本覆盖的立体角越大
+            let n_dot_h = max(dot(normal, h), 0.0);
+            let pdf = distribution_ggx(n_dot_h, roughness) * 0.25 + 0.0001;
+            let sample_solid_angle = 1.0 / (f32(count) * pdf + 0.0001);
+            var lod = 0.0;
+            if roughness > 0.0 {
+                lod = clamp(
+                    0.5 * log2(sample_solid_angle / texel_solid_angle),
+                    0.0,
+                    prefilter_params.source_mips - 1.0,
+                );
+            }
+            sum += textureSampleLevel(t_environment, s_environment, l, lod).rgb * n_dot_l;
+            weight += n_dot_l;
+        }
+    }
+    textureStore(dst_cube, id.xy, id.z, vec4<f32>(sum / max(weight, 0.0001), 1.0));
+}
+
+// 环境光照的 Schlick-GGX，k = a / 2
+fn geometry_smith_ibl(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let k = roughness * roughness / 2.0;
+    let g_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    let g_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    return g_v * g_l;
+}
+
+// split-sum 近似中与环境无关的部分：x 方向为 n·v，y 方向为粗糙度，
+// 结果的 r / g 分别是 F0 的缩放与偏移
+@compute @workgroup_size(8, 8, 1)
+fn brdf_lut(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(dst_lut);
+    if id.x >= size.x || id.y >= size.y {
+        return;
+    }
+    let n_dot_v = (f32(id.x) + 0.5) / f32(size.x);
+    let roughness = (f32(id.y) + 0.5) / f32(size.y);
+    let v = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+
+    let count = 512u;
+    var scale = 0.0;
+    var bias = 0.0;
+    for (var i = 0u; i < count; i += 1u) {
+        let h = importance_sample_ggx(hammersley(i, count), roughness);
+        let l = normalize(2.0 * dot(v, h) * h - v);
+        let n_dot_l = max(l.z, 0.0);
+        if n_dot_l > 0.0 {
+            let n_dot_h = max(h.z, 0.0);
+            let v_dot_h = max(dot(v, h), 0.0);
+            let g = geometry_smith_ibl(n_dot_v, n_dot_l, roughness);
+            let g_vis = g * v_dot_h / (n_dot_h * n_dot_v);
+            let fc = pow(1.0 - v_dot_h, 5.0);
+            scale += (1.0 - fc) * g_vis;
+            bias += fc * g_vis;
+        }
+    }
+    textureStore(dst_lut, id.xy, vec4<f32>(scale / f32(count), bias / f32(count), 0.0, 1.0));
+}
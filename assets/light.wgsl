@@ -0,0 +1,57 @@
+// 以光源的颜色在每个光源的位置绘制一个缩小的模型，用于观察光源的位置
+// 每个实例对应一个光源，平行光没有位置，不绘制
+struct CameraUniform {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+};
+struct Light {
+    position: vec3<f32>,
+    kind: u32,
+    direction: vec3<f32>,
+    inner_cos: f32,
+    color: vec3<f32>,
+    outer_cos: f32,
+    attenuation: vec3<f32>,
+    intensity: f32,
+    // 在阴影贴图数组中的层，-1 表示不投射阴影
+    shadow_index: i32,
+};
+struct Lights {
+    ambient: vec3<f32>,
+    count: u32,
+    lights: array<Light>,
+};
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+@group(1) @binding(0)
+var<storage, read> lights: Lights;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+};
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+};
+
+@vertex
+fn vs_main(model: VertexInput, @builtin(instance_index) index: u32) -> VertexOutput {
+    let light = lights.lights[index];
+    let scale = 0.25;
+    var out: VertexOutput;
+    if light.kind == 0u {
+        // 退化为一个点，三角形会被光栅化阶段丢弃
+        out.clip_position = vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    } else {
+        out.clip_position = camera.view_proj * vec4<f32>(model.position * scale + light.position, 1.0);
+    }
+    // 输出 HDR 颜色，强度较高的光源在开启泛光时会产生光晕
+    out.color = light.color * light.intensity;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(in.color, 1.0);
+}
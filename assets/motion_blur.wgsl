@@ -0,0 +1,46 @@
+// 运动模糊：由深度与两帧的视图投影矩阵求出速度，沿速度方向取平均
+struct MotionBlur {
+    inv_view_proj: mat4x4<f32>,
+    prev_view_proj: mat4x4<f32>,
+    shutter: f32,
+    max_length: f32,
+    sample_count: u32,
+};
+
+@group(0) @binding(2)
+var<uniform> blur: MotionBlur;
+@group(0) @binding(3)
+var t_depth: texture_2d<f32>;
+
+// 本帧像素在屏幕上的速度 (uv/帧)
+fn velocity(pixel: vec2<i32>, uv: vec2<f32>) -> vec2<f32> {
+    let depth = textureLoad(t_depth, pixel, 0).r;
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+    let world = blur.inv_view_proj * ndc;
+    let prev_clip = blur.prev_view_proj * vec4<f32>(world.xyz / world.w, 1.0);
+    if prev_clip.w <= 0.0 {
+        // 上一帧位于摄像机之后
+        return vec2<f32>(0.0);
+    }
+    let prev_uv = prev_clip.xy / prev_clip.w * vec2<f32>(0.5, -0.5) + 0.5;
+    return uv - prev_uv;
+}
+
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    var motion = velocity(vec2<i32>(in.clip_position.xy), in.uv) * blur.shutter;
+    // 最大长度按对角线计算，换算到 uv 时两个方向分别除以宽高
+    let size = vec2<f32>(textureDimensions(t_input));
+    let pixels = length(motion * size);
+    let max_pixels = blur.max_length * length(size);
+    if pixels > max_pixels {
+        motion *= max_pixels / pixels;
+    }
+    var color = vec3<f32>(0.0);
+    for (var i = 0u; i < blur.sample_count; i += 1u) {
+        // 以本帧的位置为中心，覆盖快门开启期间的轨迹
+        let t = (f32(i) + 0.5) / f32(blur.sample_count) - 0.5;
+        color += textureSampleLevel(t_input, s_input, in.uv + motion * t, 0.0).rgb;
+    }
+    return vec4<f32>(color / f32(blur.sample_count), 1.0);
+}
@@ -0,0 +1,114 @@
+// 金属度-粗糙度工作流的 PBR 着色，源码拼接在 common.wgsl 之后
+
+const PI: f32 = 3.14159265359;
+
+// GGX / Trowbridge-Reitz 法线分布
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * d * d);
+}
+
+// Smith 遮蔽函数，使用直接光照的 Schlick-GGX 近似
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let r = roughness + 1.0;
+    let k = r * r / 8.0;
+    let g_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    let g_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    return g_v * g_l;
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: vec3<f32>) -> vec3<f32> {
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// 环境光没有单一的半程向量，用粗糙度限制掠射角处的菲涅尔增强
+fn fresnel_schlick_roughness(cos_theta: f32, f0: vec3<f32>, roughness: f32) -> vec3<f32> {
+    return f0 + (max(vec3<f32>(1.0 - roughness), f0) - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+// 环境光照：有环境贴图时使用 split-sum 近似的 IBL，否则退回到均匀的环境光
+fn ambient(
+    normal: vec3<f32>,
+    view_dir: vec3<f32>,
+    albedo: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+) -> vec3<f32> {
+    if environment.enabled == 0u {
+        return lights.ambient * albedo;
+    }
+    let n_dot_v = max(dot(normal, view_dir), 1e-4);
+    let f0 = mix(vec3<f32>(0.04), albedo, metallic);
+    let f = fresnel_schlick_roughness(n_dot_v, f0, roughness);
+    let k_d = (1.0 - f) * (1.0 - metallic);
+
+    let irradiance = textureSampleLevel(t_irradiance, s_environment, normal, 0.0).rgb;
+    let reflected = reflect(-view_dir, normal);
+    let prefiltered = textureSampleLevel(
+        t_prefiltered,
+        s_environment,
+        reflected,
+        roughness * environment.max_lod,
+    ).rgb;
+    let brdf = textureSampleLevel(t_brdf_lut, s_environment, vec2<f32>(n_dot_v, roughness), 0.0).rg;
+    let specular = prefiltered * (f0 * brdf.x + brdf.y);
+    return (k_d * irradiance * albedo + specular) * environment.intensity;
+}
+
+// 单个光源的 Cook-Torrance BRDF
+fn shade(
+    light: Light,
+    position: vec3<f32>,
+    normal: vec3<f32>,
+    view_dir: vec3<f32>,
+    albedo: vec3<f32>,
+    metallic: f32,
+    roughness: f32,
+) -> vec3<f32> {
+    let incident = incident(light, position, normal);
+    let half_dir = normalize(view_dir + incident.direction);
+    let n_dot_l = max(dot(normal, incident.direction), 0.0);
+    let n_dot_v = max(dot(normal, view_dir), 1e-4);
+    let n_dot_h = max(dot(normal, half_dir), 0.0);
+
+    // 非金属的基础反射率取 0.04，金属使用反照率
+    let f0 = mix(vec3<f32>(0.04), albedo, metallic);
+    let f = fresnel_schlick(max(dot(half_dir, view_dir), 0.0), f0);
+    let d = distribution_ggx(n_dot_h, roughness);
+    let g = geometry_smith(n_dot_v, n_dot_l, roughness);
+    let specular = d * g * f / (4.0 * n_dot_v * max(n_dot_l, 1e-4));
+
+    // 被镜面反射的能量不再参与漫反射，金属没有漫反射
+    let k_d = (1.0 - f) * (1.0 - metallic);
+    return (k_d * albedo / PI + specular) * incident.radiance * n_dot_l;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let albedo = surface_albedo(in);
+    let normal = surface_normal(in);
+    let view_dir = normalize(camera.view_pos.xyz - in.world_position);
+
+    let metallic_roughness = textureSample(t_metallic_roughness, s_metallic_roughness, in.tex_coords);
+    let metallic = clamp(metallic_roughness.b * material.metallic, 0.0, 1.0);
+    // 粗糙度过低时高光会变成无法采样到的一个点
+    let roughness = clamp(metallic_roughness.g * material.roughness, 0.045, 1.0);
+    let occlusion = mix(
+        1.0,
+        textureSample(t_occlusion, s_occlusion, in.tex_coords).r,
+        material.occlusion_strength,
+    ) * screen_space_occlusion(in.clip_position);
+    let emissive = textureSample(t_emissive, s_emissive, in.tex_coords).xyz * material.emissive;
+
+    var color = vec3<f32>(0.0);
+    for (var i = 0u; i < lights.count; i += 1u) {
+        let light = lights.lights[i];
+        color += shade(light, in.world_position, normal, view_dir, albedo.xyz, metallic, roughness);
+    }
+    color += ambient(normal, view_dir, albedo.xyz, metallic, roughness) * occlusion + emissive;
+    color = debug_cascade_tint(color, in.world_position);
+
+    return vec4<f32>(color, albedo.a);
+}
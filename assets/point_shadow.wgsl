@@ -0,0 +1,40 @@
+// 点光源阴影 pass：深度中写入片元到光源的线性距离，而不是透视投影后的深度
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>
+};
+
+struct PointFace {
+    view_proj: mat4x4<f32>,
+    light_position: vec3<f32>,
+    far: f32,
+};
+@group(0) @binding(0)
+var<uniform> face: PointFace;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+};
+
+@vertex
+fn vs_main(@location(0) position: vec3<f32>, instance: InstanceInput) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    let world_position = model_matrix * vec4<f32>(position, 1.0);
+    var out: VertexOutput;
+    out.clip_position = face.view_proj * world_position;
+    out.world_position = world_position.xyz;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @builtin(frag_depth) f32 {
+    return length(in.world_position - face.light_position) / face.far;
+}
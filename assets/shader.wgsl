@@ -1,48 +1,29 @@
-struct CameraUniform {
-    view_proj: mat4x4<f32>,
-};
-struct InstanceInput {
-    @location(5) model_matrix_0: vec4<f32>,
-    @location(6) model_matrix_1: vec4<f32>,
-    @location(7) model_matrix_2: vec4<f32>,
-    @location(8) model_matrix_3: vec4<f32>
-};
+// 兼容用的 Blinn-Phong 着色，源码拼接在 common.wgsl 之后
 
-@group(1) @binding(0)
-var<uniform> camera: CameraUniform;
-
-struct VertexOutput {
-    @builtin(position) clip_position: vec4<f32>,
-    @location(0) tex_coords: vec2<f32>
-};
-struct VertexInput {
-    @location(0) position: vec3<f32>,
-    @location(1) tex_coords: vec2<f32>
-};
-@vertex
-fn vs_main(
-    @builtin(vertex_index) in_vertex_index: u32,
-    model: VertexInput,
-    instance: InstanceInput,
-) -> VertexOutput {
-    var out: VertexOutput;
-    let model_matrix = mat4x4<f32>(
-        instance.model_matrix_0,
-        instance.model_matrix_1,
-        instance.model_matrix_2,
-        instance.model_matrix_3,
-    );
-    out.clip_position = camera.view_proj * model_matrix * vec4<f32>(model.position,1.0);
-    out.tex_coords = model.tex_coords;
-    return out;
+// 单个光源的 Blinn-Phong 漫反射与高光
+fn shade(light: Light, position: vec3<f32>, normal: vec3<f32>, view_dir: vec3<f32>) -> vec3<f32> {
+    let incident = incident(light, position, normal);
+    let half_dir = normalize(view_dir + incident.direction);
+    let diffuse_strength = max(dot(normal, incident.direction), 0.0);
+    let specular_strength = pow(max(dot(normal, half_dir), 0.0), material.shininess);
+    let specular = specular_strength * material.specular;
+    return incident.radiance * (diffuse_strength + specular);
 }
 
-@group(0) @binding(0)
-var t_diffuse: texture_2d<f32>;
-@group(0) @binding(1)
-var s_diffuse: sampler;
-
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse,s_diffuse,in.tex_coords);
-}
\ No newline at end of file
+    let albedo = surface_albedo(in);
+    let normal = surface_normal(in);
+    let view_dir = normalize(camera.view_pos.xyz - in.world_position);
+
+    var color = lights.ambient * screen_space_occlusion(in.clip_position);
+    for (var i = 0u; i < lights.count; i += 1u) {
+        color += shade(lights.lights[i], in.world_position, normal, view_dir);
+    }
+
+    let emissive = textureSample(t_emissive, s_emissive, in.tex_coords).xyz * material.emissive;
+    color = color * albedo.xyz + emissive;
+    color = debug_cascade_tint(color, in.world_position);
+
+    return vec4<f32>(color, albedo.a);
+}
@@ -0,0 +1,21 @@
+// 阴影 pass：只把实例变换到光源的裁剪空间，深度由光栅化写入
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>
+};
+
+@group(0) @binding(0)
+var<uniform> light_view_proj: mat4x4<f32>;
+
+@vertex
+fn vs_main(@location(0) position: vec3<f32>, instance: InstanceInput) -> @builtin(position) vec4<f32> {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    return light_view_proj * model_matrix * vec4<f32>(position, 1.0);
+}
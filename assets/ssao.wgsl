@@ -0,0 +1,121 @@
+// 屏幕空间环境光遮蔽：遮蔽 pass 与分离的双边模糊，深度来自主 pass 之前的深度预渲染
+struct Ssao {
+    proj: mat4x4<f32>,
+    inv_proj: mat4x4<f32>,
+    kernel: array<vec4<f32>, 64>,
+    radius: f32,
+    bias: f32,
+    strength: f32,
+    sample_count: u32,
+};
+
+@group(0) @binding(0)
+var<uniform> ssao: Ssao;
+// 以非过滤的浮点纹理绑定深度，GL 后端不支持对 texture_depth_2d 使用 textureLoad
+@group(0) @binding(1)
+var t_depth: texture_2d<f32>;
+// 遮蔽 pass 中为噪声纹理，模糊 pass 中为上一步的遮蔽结果
+@group(0) @binding(2)
+var t_input: texture_2d<f32>;
+
+// 覆盖整个屏幕的三角形
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// 超出屏幕的坐标夹到边缘
+fn load_depth(pixel: vec2<i32>) -> f32 {
+    let size = vec2<i32>(textureDimensions(t_depth));
+    return textureLoad(t_depth, clamp(pixel, vec2<i32>(0), size - 1), 0).r;
+}
+
+// 由像素中心与深度重建视图空间的位置
+fn view_position(pixel: vec2<i32>) -> vec3<f32> {
+    let size = vec2<f32>(textureDimensions(t_depth));
+    let uv = (vec2<f32>(pixel) + 0.5) / size;
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, load_depth(pixel), 1.0);
+    let position = ssao.inv_proj * ndc;
+    return position.xyz / position.w;
+}
+
+// 用相邻像素的位置差重建法线，每个方向选深度更接近的一侧，避免在物体边缘处跨越前后景
+fn view_normal(pixel: vec2<i32>, center: vec3<f32>) -> vec3<f32> {
+    let left = center - view_position(pixel - vec2<i32>(1, 0));
+    let right = view_position(pixel + vec2<i32>(1, 0)) - center;
+    let up = center - view_position(pixel - vec2<i32>(0, 1));
+    let down = view_position(pixel + vec2<i32>(0, 1)) - center;
+    let dx = select(right, left, abs(left.z) < abs(right.z));
+    let dy = select(down, up, abs(up.z) < abs(down.z));
+    let normal = normalize(cross(dx, dy));
+    // 摄像机位于原点，法线总是朝向摄像机
+    return select(normal, -normal, dot(normal, center) > 0.0);
+}
+
+@fragment
+fn fs_occlusion(@builtin(position) frag_coord: vec4<f32>) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(frag_coord.xy);
+    if load_depth(pixel) >= 1.0 {
+        // 没有几何体的背景
+        return vec4<f32>(1.0);
+    }
+    let position = view_position(pixel);
+    let normal = view_normal(pixel, position);
+
+    // 用平铺的噪声旋转切线，Gram-Schmidt 正交化得到 TBN
+    let noise_size = vec2<i32>(textureDimensions(t_input));
+    let noise = textureLoad(t_input, pixel % noise_size, 0).xyz * 2.0 - 1.0;
+    let tangent = normalize(noise - normal * dot(noise, normal));
+    let tbn = mat3x3<f32>(tangent, cross(normal, tangent), normal);
+
+    let size = vec2<f32>(textureDimensions(t_depth));
+    let bias = ssao.bias * abs(position.z);
+    var occlusion = 0.0;
+    for (var i = 0u; i < ssao.sample_count; i += 1u) {
+        let probe = position + tbn * ssao.kernel[i].xyz * ssao.radius;
+        let clip = ssao.proj * vec4<f32>(probe, 1.0);
+        let uv = clip.xy / clip.w * vec2<f32>(0.5, -0.5) + 0.5;
+        let scene = view_position(vec2<i32>(uv * size));
+        // 比较到摄像机所在平面的距离。样本被取整到像素中心，倾斜的表面上
+        // 这带来的深度误差随距离增大，偏移也随之增大
+        let occluded = select(0.0, 1.0, abs(scene.z) <= abs(probe.z) - bias);
+        // 远超出半径的前景不应遮蔽背景
+        let range = smoothstep(0.0, 1.0, ssao.radius / abs(position.z - scene.z));
+        occlusion += occluded * range;
+    }
+    let visibility = 1.0 - occlusion / f32(ssao.sample_count);
+    return vec4<f32>(pow(visibility, ssao.strength));
+}
+
+const BLUR_RADIUS: i32 = 4;
+// 深度差相对于中心深度的比例越大，权重下降得越快
+const BLUR_SHARPNESS: f32 = 40.0;
+
+// 沿 direction 的一维高斯模糊，权重按与中心的深度差衰减，保留物体边缘
+fn blur(pixel: vec2<i32>, direction: vec2<i32>) -> vec4<f32> {
+    let center = view_position(pixel).z;
+    var sum = 0.0;
+    var weight = 0.0;
+    for (var i = -BLUR_RADIUS; i <= BLUR_RADIUS; i += 1) {
+        let offset = pixel + direction * i;
+        let size = vec2<i32>(textureDimensions(t_input));
+        let value = textureLoad(t_input, clamp(offset, vec2<i32>(0), size - 1), 0).r;
+        let difference = abs(view_position(offset).z - center) / max(abs(center), 1e-4);
+        let gaussian = exp(-f32(i * i) / f32(BLUR_RADIUS * BLUR_RADIUS));
+        let w = gaussian * exp(-difference * BLUR_SHARPNESS);
+        sum += value * w;
+        weight += w;
+    }
+    return vec4<f32>(sum / weight);
+}
+
+@fragment
+fn fs_blur_x(@builtin(position) frag_coord: vec4<f32>) -> @location(0) vec4<f32> {
+    return blur(vec2<i32>(frag_coord.xy), vec2<i32>(1, 0));
+}
+
+@fragment
+fn fs_blur_y(@builtin(position) frag_coord: vec4<f32>) -> @location(0) vec4<f32> {
+    return blur(vec2<i32>(frag_coord.xy), vec2<i32>(0, 1));
+}
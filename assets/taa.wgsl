@@ -0,0 +1,64 @@
+// TAA：由深度重建世界坐标，投影到上一帧求出历史的位置，夹到邻域范围内后与当前帧混合
+struct Taa {
+    inv_view_proj: mat4x4<f32>,
+    prev_view_proj: mat4x4<f32>,
+    blend: f32,
+    reset: u32,
+};
+
+@group(0) @binding(2)
+var<uniform> taa: Taa;
+// 以非过滤的浮点纹理绑定深度，GL 后端不支持对 texture_depth_2d 使用 textureLoad
+@group(0) @binding(3)
+var t_depth: texture_2d<f32>;
+@group(0) @binding(4)
+var t_history: texture_2d<f32>;
+
+struct TaaOutput {
+    @location(0) color: vec4<f32>,
+    @location(1) history: vec4<f32>,
+};
+
+fn load_color(pixel: vec2<i32>) -> vec3<f32> {
+    let size = vec2<i32>(textureDimensions(t_input));
+    return textureLoad(t_input, clamp(pixel, vec2<i32>(0), size - 1), 0).rgb;
+}
+
+@fragment
+fn fs_main(in: FullscreenOutput) -> TaaOutput {
+    let pixel = vec2<i32>(in.clip_position.xy);
+    let current = load_color(pixel);
+    var out: TaaOutput;
+    out.color = vec4<f32>(current, 1.0);
+    out.history = out.color;
+    if taa.reset != 0u {
+        return out;
+    }
+
+    // 运动向量：本帧像素在上一帧屏幕上的位置
+    let depth = textureLoad(t_depth, pixel, 0).r;
+    let ndc = vec4<f32>(in.uv.x * 2.0 - 1.0, 1.0 - in.uv.y * 2.0, depth, 1.0);
+    let world = taa.inv_view_proj * ndc;
+    let prev_clip = taa.prev_view_proj * vec4<f32>(world.xyz / world.w, 1.0);
+    let prev_uv = prev_clip.xy / prev_clip.w * vec2<f32>(0.5, -0.5) + 0.5;
+    if any(prev_uv < vec2<f32>(0.0)) || any(prev_uv > vec2<f32>(1.0)) {
+        // 上一帧不可见，没有历史可用
+        return out;
+    }
+
+    // 历史夹到当前帧 3x3 邻域的范围内，排除被遮挡或已变化的内容
+    var low = current;
+    var high = current;
+    for (var y = -1; y <= 1; y += 1) {
+        for (var x = -1; x <= 1; x += 1) {
+            let neighbor = load_color(pixel + vec2<i32>(x, y));
+            low = min(low, neighbor);
+            high = max(high, neighbor);
+        }
+    }
+    let history = textureSampleLevel(t_history, s_input, prev_uv, 0.0).rgb;
+    let result = mix(clamp(history, low, high), current, taa.blend);
+    out.color = vec4<f32>(result, 1.0);
+    out.history = out.color;
+    return out;
+}
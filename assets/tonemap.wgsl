@@ -0,0 +1,123 @@
+// 色调映射：把 HDR 场景按曝光缩放后映射到 [0, 1]，输出到 surface
+struct ToneMapping {
+    curve: u32,
+    auto_exposure: u32,
+    // 曝光补偿 (EV)
+    exposure: f32,
+    delta_time: f32,
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    low_percentile: f32,
+    high_percentile: f32,
+    speed_brighten: f32,
+    speed_darken: f32,
+};
+
+struct Exposure {
+    // 适应后的场景平均亮度 (log2)
+    log_luminance: f32,
+    initialized: u32,
+};
+
+@group(0) @binding(0)
+var t_hdr: texture_2d<f32>;
+@group(0) @binding(1)
+var<uniform> settings: ToneMapping;
+@group(0) @binding(2)
+var<storage, read> exposure: Exposure;
+
+const CURVE_REINHARD: u32 = 1u;
+const CURVE_ACES: u32 = 2u;
+const CURVE_AGX: u32 = 3u;
+
+// 自动曝光把场景的平均亮度映射到中灰
+const MIDDLE_GREY: f32 = 0.18;
+const LUMA: vec3<f32> = vec3<f32>(0.2126, 0.7152, 0.0722);
+
+// 基于亮度的 Reinhard，按同一比例压缩三个通道以保持色相
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    return color / (1.0 + dot(color, LUMA));
+}
+
+// Stephen Hill 对 ACES RRT + ODT 的拟合，矩阵按列给出
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let input = mat3x3<f32>(
+        vec3<f32>(0.59719, 0.07600, 0.02840),
+        vec3<f32>(0.35458, 0.90834, 0.13383),
+        vec3<f32>(0.04823, 0.01566, 0.83777),
+    );
+    let output = mat3x3<f32>(
+        vec3<f32>(1.60475, -0.10208, -0.00327),
+        vec3<f32>(-0.53108, 1.10813, -0.07276),
+        vec3<f32>(-0.07367, -0.00605, 1.07602),
+    );
+    let v = input * color;
+    let a = v * (v + 0.0245786) - 0.000090537;
+    let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+    return output * (a / b);
+}
+
+// AgX 的默认对比度曲线，对 log2 编码后的值做六次多项式近似
+fn agx_contrast(x: vec3<f32>) -> vec3<f32> {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    return 15.5 * x4 * x2
+        - 40.14 * x4 * x
+        + 31.96 * x4
+        - 6.868 * x2 * x
+        + 0.4298 * x2
+        + 0.1191 * x
+        - 0.00232;
+}
+
+fn agx(color: vec3<f32>) -> vec3<f32> {
+    let inset = mat3x3<f32>(
+        vec3<f32>(0.842479062253094, 0.0423282422610123, 0.0423756549057051),
+        vec3<f32>(0.0784335999999992, 0.878468636469772, 0.0784336),
+        vec3<f32>(0.0792237451477643, 0.0791661274605434, 0.879142973793104),
+    );
+    let outset = mat3x3<f32>(
+        vec3<f32>(1.19687900512017, -0.0528968517574562, -0.0529716355144438),
+        vec3<f32>(-0.0980208811401368, 1.15190312990417, -0.0980434501171241),
+        vec3<f32>(-0.0990297440797205, -0.0989611768448433, 1.15107367264116),
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+    var v = inset * color;
+    v = clamp(log2(max(v, vec3<f32>(1e-10))), vec3<f32>(min_ev), vec3<f32>(max_ev));
+    v = agx_contrast((v - min_ev) / (max_ev - min_ev));
+    // 曲线的输出已经带有 2.2 的伽马，还原为线性值交给 sRGB 格式的输出纹理编码
+    return pow(max(outset * v, vec3<f32>(0.0)), vec3<f32>(2.2));
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let hdr = textureLoad(t_hdr, vec2<i32>(position.xy), 0).rgb;
+    var scale = exp2(settings.exposure);
+    if settings.auto_exposure != 0u {
+        scale *= MIDDLE_GREY / exp2(exposure.log_luminance);
+    }
+    let color = hdr * scale;
+    var mapped: vec3<f32>;
+    switch settings.curve {
+        case CURVE_REINHARD: {
+            mapped = reinhard(color);
+        }
+        case CURVE_ACES: {
+            mapped = aces(color);
+        }
+        case CURVE_AGX: {
+            mapped = agx(color);
+        }
+        default: {
+            mapped = color;
+        }
+    }
+    return vec4<f32>(saturate(mapped), 1.0);
+}
@@ -0,0 +1,18 @@
+// 暗角：从 radius 开始向画面四角逐渐变暗
+struct Vignette {
+    intensity: f32,
+    radius: f32,
+    softness: f32,
+};
+
+@group(0) @binding(2)
+var<uniform> params: Vignette;
+
+@fragment
+fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+    let color = textureLoad(t_input, vec2<i32>(in.clip_position.xy), 0);
+    // 中心为 0，四边中点为 1
+    let d = length((in.uv - 0.5) * 2.0);
+    let falloff = smoothstep(params.radius, params.radius + params.softness, d);
+    return vec4<f32>(color.rgb * (1.0 - params.intensity * falloff), color.a);
+}
@@ -0,0 +1,299 @@
+//! 场景的背景
+//!
+//! 纯色背景直接作为清屏颜色；天空盒与程序化天空则在不透明物体之后绘制
+//! 一个位于远平面的全屏三角形，借助深度测试只为没有被物体覆盖的像素着色。
+
+use na::{Matrix4, Vector3, Vector4};
+
+use crate::{exts::state::DeviceTrait, geom::camera::Camera, texture};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SkySettings {
+  /// 指向太阳的方向
+  pub sun_direction: Vector3<f32>,
+  pub sun_intensity: f32,
+}
+impl Default for SkySettings {
+  fn default() -> Self {
+    Self {
+      sun_direction: Vector3::new(0.3, 0.25, -0.5).normalize(),
+      sun_intensity: 22.0,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackgroundMode {
+  /// 纯色
+  Color(Vector3<f64>),
+  /// 立方体贴图天空盒，来自六张图片或 IBL 的环境贴图，没有加载时为黑色
+  Skybox,
+  /// 由太阳方向驱动的大气散射天空
+  Sky(SkySettings),
+}
+impl Default for BackgroundMode {
+  fn default() -> Self {
+    BackgroundMode::Color(Vector3::new(0.1, 0.7, 0.2))
+  }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct BackgroundUniform {
+  // 把屏幕上的点变换回世界空间，得到每个像素的视线方向
+  inv_view_proj: Matrix4<f32>,
+  // xyz 指向太阳，w 为太阳强度
+  sun: Vector4<f32>,
+}
+
+pub struct Background {
+  mode: BackgroundMode,
+  layout: wgpu::BindGroupLayout,
+  uniform: BackgroundUniform,
+  buffer: wgpu::Buffer,
+  sampler: wgpu::Sampler,
+  bind_group: wgpu::BindGroup,
+  has_skybox: bool,
+  shader: wgpu::ShaderModule,
+  pipeline_layout: wgpu::PipelineLayout,
+  color_format: wgpu::TextureFormat,
+  skybox_pipeline: wgpu::RenderPipeline,
+  sky_pipeline: wgpu::RenderPipeline,
+}
+
+impl Background {
+  pub fn new<T: DeviceTrait>(device: &T, color_format: wgpu::TextureFormat) -> Self {
+    let layout = device.create_bind_group_layout(
+      "background_bind_group_layout",
+      &[
+        wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 1,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::Cube,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+          },
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 2,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+          count: None,
+        },
+      ],
+    );
+    let uniform = BackgroundUniform {
+      inv_view_proj: Matrix4::identity(),
+      sun: Vector4::zeros(),
+    };
+    let buffer = device.create_buffer_init(
+      "Background Buffer",
+      bytemuck::cast_slice(&[uniform]),
+      wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    );
+    let sampler = device
+      .get_device()
+      .create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("skybox_sampler"),
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+      });
+    // 没有天空盒时绑定一个 1x1 的占位立方体贴图
+    let placeholder = device
+      .get_device()
+      .create_texture(&wgpu::TextureDescriptor {
+        label: Some("skybox_placeholder"),
+        size: wgpu::Extent3d {
+          width: 1,
+          height: 1,
+          depth_or_array_layers: 6,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+      });
+    let placeholder = placeholder.create_view(&wgpu::TextureViewDescriptor {
+      dimension: Some(wgpu::TextureViewDimension::Cube),
+      ..Default::default()
+    });
+    let bind_group = Self::create_bind_group(device, &layout, &buffer, &placeholder, &sampler);
+
+    let shader = device.create_shader_module(wgpu::include_wgsl!("../assets/background.wgsl"));
+    let pipeline_layout =
+      device.create_pipeline_layout("Background Pipeline Layout", &[&layout], &[]);
+    let [skybox_pipeline, sky_pipeline] =
+      Self::create_pipelines(device, &shader, &pipeline_layout, color_format, 1);
+
+    Self {
+      mode: BackgroundMode::default(),
+      layout,
+      uniform,
+      buffer,
+      sampler,
+      bind_group,
+      has_skybox: false,
+      shader,
+      pipeline_layout,
+      color_format,
+      skybox_pipeline,
+      sky_pipeline,
+    }
+  }
+
+  /// 依次为天空盒与程序化天空的管线
+  fn create_pipelines<T: DeviceTrait>(
+    device: &T,
+    shader: &wgpu::ShaderModule,
+    layout: &wgpu::PipelineLayout,
+    color_format: wgpu::TextureFormat,
+    sample_count: u32,
+  ) -> [wgpu::RenderPipeline; 2] {
+    let pipeline = |label, entry_point| {
+      device.create_render_pipeline(
+        label,
+        Some(layout),
+        wgpu::VertexState {
+          module: shader,
+          entry_point: "vs_main",
+          buffers: &[],
+        },
+        wgpu::PrimitiveState::default(),
+        // 背景位于远平面，只在深度仍为清屏值的像素上绘制，并且不写入深度
+        Some(wgpu::DepthStencilState {
+          format: texture::Texture::DEPTH_FORMAT,
+          depth_write_enabled: false,
+          depth_compare: wgpu::CompareFunction::LessEqual,
+          stencil: wgpu::StencilState::default(),
+          bias: wgpu::DepthBiasState::default(),
+        }),
+        wgpu::MultisampleState {
+          count: sample_count,
+          ..Default::default()
+        },
+        wgpu::FragmentState {
+          module: shader,
+          entry_point,
+          targets: &[Some(wgpu::ColorTargetState {
+            format: color_format,
+            blend: Some(wgpu::BlendState::REPLACE),
+            write_mask: wgpu::ColorWrites::ALL,
+          })],
+        },
+        None,
+      )
+    };
+    [
+      pipeline("Skybox Pipeline", "fs_skybox"),
+      pipeline("Sky Pipeline", "fs_sky"),
+    ]
+  }
+
+  /// 多重采样数改变时重建管线
+  pub fn set_sample_count<T: DeviceTrait>(&mut self, device: &T, sample_count: u32) {
+    [self.skybox_pipeline, self.sky_pipeline] = Self::create_pipelines(
+      device,
+      &self.shader,
+      &self.pipeline_layout,
+      self.color_format,
+      sample_count,
+    );
+  }
+
+  fn create_bind_group<T: DeviceTrait>(
+    device: &T,
+    layout: &wgpu::BindGroupLayout,
+    buffer: &wgpu::Buffer,
+    skybox: &wgpu::TextureView,
+    sampler: &wgpu::Sampler,
+  ) -> wgpu::BindGroup {
+    device.create_bind_group(
+      "background_bind_group",
+      layout,
+      &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: wgpu::BindingResource::TextureView(skybox),
+        },
+        wgpu::BindGroupEntry {
+          binding: 2,
+          resource: wgpu::BindingResource::Sampler(sampler),
+        },
+      ],
+    )
+  }
+
+  pub fn mode(&self) -> &BackgroundMode {
+    &self.mode
+  }
+
+  pub fn set_mode(&mut self, mode: BackgroundMode) {
+    self.mode = mode;
+  }
+
+  /// 设置天空盒使用的立方体贴图，view 的维度必须是 Cube
+  pub fn set_skybox<T: DeviceTrait>(&mut self, device: &T, view: &wgpu::TextureView) {
+    self.bind_group =
+      Self::create_bind_group(device, &self.layout, &self.buffer, view, &self.sampler);
+    self.has_skybox = true;
+  }
+
+  /// render pass 的清屏颜色，非纯色模式下会被背景覆盖
+  pub fn clear_color(&self) -> wgpu::Color {
+    match self.mode {
+      BackgroundMode::Color(color) => wgpu::Color {
+        r: color.x,
+        g: color.y,
+        b: color.z,
+        a: 1.0,
+      },
+      _ => wgpu::Color::BLACK,
+    }
+  }
+
+  pub fn update(&mut self, queue: &wgpu::Queue, camera: &Camera, aspect: f32) {
+    self.uniform.inv_view_proj = camera
+      .get_vp_mat(aspect)
+      .try_inverse()
+      .unwrap_or_else(Matrix4::identity);
+    if let BackgroundMode::Sky(sky) = self.mode {
+      self.uniform.sun = sky.sun_direction.normalize().push(sky.sun_intensity);
+    }
+    queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+  }
+
+  /// 在不透明物体之后、半透明物体之前调用
+  pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+    let pipeline = match self.mode {
+      BackgroundMode::Color(_) => return,
+      BackgroundMode::Skybox if !self.has_skybox => return,
+      BackgroundMode::Skybox => &self.skybox_pipeline,
+      BackgroundMode::Sky(_) => &self.sky_pipeline,
+    };
+    render_pass.set_pipeline(pipeline);
+    render_pass.set_bind_group(0, &self.bind_group, &[]);
+    render_pass.draw(0..3, 0..1);
+  }
+}
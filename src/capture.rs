@@ -0,0 +1,236 @@
+//! 截图与帧序列录制
+//!
+//! State 在每次 render 之后检查 [`Capture::wants_frame`]，若需要则把当前帧读回 CPU
+//! 并交给 [`Capture::submit`]。PNG 的编码与写盘在一个后台线程中进行；
+//! 等待写盘的帧数有上限，写盘跟不上时 submit 会阻塞，而不是无限地堆积帧。
+
+use std::{
+  path::{Path, PathBuf},
+  sync::mpsc::{self, SyncSender},
+  thread::JoinHandle,
+};
+
+use color_eyre::eyre::Result;
+use image::RgbaImage;
+use tracing::{error, info};
+
+use crate::time;
+
+/// 最多有多少帧在等待写盘
+const QUEUED_FRAMES: usize = 4;
+
+struct Recording {
+  dir: PathBuf,
+  next_frame: u32,
+}
+
+/// 一帧画面与要写入的所有路径
+struct Job {
+  frame: RgbaImage,
+  paths: Vec<PathBuf>,
+}
+
+struct Writer {
+  sender: SyncSender<Job>,
+  thread: JoinHandle<()>,
+}
+
+impl Writer {
+  fn spawn() -> Self {
+    let (sender, receiver) = mpsc::sync_channel::<Job>(QUEUED_FRAMES);
+    let thread = std::thread::spawn(move || {
+      for job in receiver {
+        for path in job.paths {
+          save(&job.frame, &path);
+        }
+      }
+    });
+    Self { sender, thread }
+  }
+}
+
+#[derive(Default)]
+pub struct Capture {
+  screenshots: Vec<PathBuf>,
+  recording: Option<Recording>,
+  writer: Option<Writer>,
+}
+
+impl Capture {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// 在下一次 render 之后将画面保存到 path
+  pub fn request_screenshot(&mut self, path: impl Into<PathBuf>) {
+    self.screenshots.push(path.into());
+  }
+
+  /// 开始录制：之后的每一帧都会写成 dir 下编号的 PNG，
+  /// 并且 [`time::get_delta`] 固定为 1 / fps，使录制结果与实际帧率无关
+  pub fn start_recording(&mut self, dir: impl Into<PathBuf>, fps: f32) -> Result<()> {
+    let dir = dir.into();
+    std::fs::create_dir_all(&dir)?;
+    info!("recording frames to {} at {fps} fps", dir.display());
+    time::set_fixed_delta(Some(1.0 / fps));
+    self.recording = Some(Recording { dir, next_frame: 0 });
+    Ok(())
+  }
+
+  /// 停止录制并等待所有帧写盘完成，返回录制的帧数
+  pub fn stop_recording(&mut self) -> Option<u32> {
+    let recording = self.recording.take()?;
+    time::set_fixed_delta(None);
+    self.flush();
+    info!(
+      "recorded {} frames to {}",
+      recording.next_frame,
+      recording.dir.display()
+    );
+    Some(recording.next_frame)
+  }
+
+  pub fn is_recording(&self) -> bool {
+    self.recording.is_some()
+  }
+
+  pub fn wants_frame(&self) -> bool {
+    !self.screenshots.is_empty() || self.recording.is_some()
+  }
+
+  /// 把帧交给写盘线程，排队的帧已满时阻塞直到写盘线程跟上
+  pub fn submit(&mut self, frame: RgbaImage) {
+    let mut paths = std::mem::take(&mut self.screenshots);
+    if let Some(recording) = &mut self.recording {
+      paths.push(
+        recording
+          .dir
+          .join(format!("frame-{:05}.png", recording.next_frame)),
+      );
+      recording.next_frame += 1;
+    }
+    if paths.is_empty() {
+      return;
+    }
+    let writer = self.writer.get_or_insert_with(Writer::spawn);
+    if writer.sender.send(Job { frame, paths }).is_err() {
+      error!("capture writer thread has exited, dropping frame");
+    }
+  }
+
+  /// 等待所有尚未完成的写盘任务
+  pub fn flush(&mut self) {
+    if let Some(writer) = self.writer.take() {
+      drop(writer.sender);
+      let _ = writer.thread.join();
+    }
+  }
+}
+
+impl Drop for Capture {
+  fn drop(&mut self) {
+    self.stop_recording();
+    self.flush();
+  }
+}
+
+fn save(frame: &RgbaImage, path: &Path) {
+  if let Some(parent) = path.parent() {
+    let _ = std::fs::create_dir_all(parent);
+  }
+  match frame.save(path) {
+    Ok(_) => info!("saved frame to {}", path.display()),
+    Err(e) => error!("failed to save frame to {}: {e}", path.display()),
+  }
+}
+
+fn timestamp() -> String {
+  chrono::Local::now().format("%Y%m%d-%H%M%S").to_string()
+}
+
+pub fn default_screenshot_path() -> PathBuf {
+  Path::new("screenshots").join(format!("screenshot-{}.png", timestamp()))
+}
+
+pub fn default_recording_dir() -> PathBuf {
+  Path::new("recordings").join(timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{golden, state::State};
+
+  #[test]
+  fn writes_every_submitted_frame() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+      .join("target")
+      .join("capture-queue-test");
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut capture = Capture::new();
+    // 远多于排队上限的帧，submit 在写盘跟不上时阻塞而不是丢帧
+    let count = QUEUED_FRAMES * 4;
+    for i in 0..count {
+      capture.request_screenshot(dir.join(format!("{i}.png")));
+      capture.submit(RgbaImage::new(8, 8));
+    }
+    capture.flush();
+    for i in 0..count {
+      assert!(
+        dir.join(format!("{i}.png")).exists(),
+        "{i}.png was not written"
+      );
+    }
+  }
+
+  #[tokio::test]
+  async fn records_numbered_frames() -> Result<()> {
+    if golden::skip_rendering_tests() {
+      return Ok(());
+    }
+    let mut state = State::new_headless(32, 32).await?;
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+      .join("target")
+      .join("capture-test");
+    let _ = std::fs::remove_dir_all(&dir);
+
+    state.capture_mut().start_recording(&dir, 30.0)?;
+    state.capture_mut().request_screenshot(dir.join("shot.png"));
+    for _ in 0..3 {
+      state.update();
+      state.render()?;
+    }
+    assert_eq!(state.capture_mut().stop_recording(), Some(3));
+
+    for name in ["frame-00000.png", "frame-00002.png", "shot.png"] {
+      assert!(dir.join(name).exists(), "{name} was not written");
+    }
+    assert!(!dir.join("frame-00003.png").exists());
+    Ok(())
+  }
+
+  #[tokio::test]
+  async fn captures_surfaces_that_cannot_be_read_back() -> Result<()> {
+    if golden::skip_rendering_tests() {
+      return Ok(());
+    }
+    // 16 位浮点的输出不能直接读回，这一帧经过 8 位的中转纹理
+    let mut state =
+      State::new_headless_with_format(32, 32, wgpu::TextureFormat::Rgba16Float).await?;
+    assert!(state.read_frame().is_err());
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+      .join("target")
+      .join("capture-hdr-test");
+    let _ = std::fs::remove_dir_all(&dir);
+    state.capture_mut().request_screenshot(dir.join("shot.png"));
+    state.update();
+    state.render()?;
+    state.capture_mut().flush();
+
+    let shot = image::open(dir.join("shot.png"))?.to_rgba8();
+    assert_eq!(shot.dimensions(), (32, 32));
+    // 画出了场景，而不只是清屏的颜色
+    assert!(shot.pixels().any(|p| p != shot.get_pixel(0, 0)));
+    Ok(())
+  }
+}
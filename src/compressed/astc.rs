@@ -0,0 +1,706 @@
+//! ASTC 二维 LDR 块的 CPU 解码
+//!
+//! 按 Khronos 数据格式规范实现。HDR 端点模式与出错的块按规范解码为品红色
+
+const ERROR_COLOR: [u8; 4] = [255, 0, 255, 255];
+
+/// 颜色端点与权重可用的量化级数，都是 2^n、3 * 2^n 或 5 * 2^n
+const COLOR_LEVELS: [u32; 21] = [
+  2, 3, 4, 5, 6, 8, 10, 12, 16, 20, 24, 32, 40, 48, 64, 80, 96, 128, 160, 192, 256,
+];
+const WEIGHT_LEVELS: [u32; 12] = [2, 3, 4, 5, 6, 8, 10, 12, 16, 20, 24, 32];
+
+/// 从块中的任意位置读取不超过 32 位
+fn bits(block: u128, start: u32, count: u32) -> u32 {
+  if count == 0 {
+    0
+  } else {
+    (block >> start) as u32 & (u32::MAX >> (32 - count))
+  }
+}
+
+/// 顺序读取整数序列编码 (ISE) 的数据，超出长度的位按 0 处理
+struct Reader {
+  bits: u128,
+  pos: u32,
+}
+impl Reader {
+  fn new(block: u128, start: u32, len: u32) -> Self {
+    let data = block >> start;
+    let mask = if len >= 128 {
+      u128::MAX
+    } else {
+      (1 << len) - 1
+    };
+    Self {
+      bits: data & mask,
+      pos: 0,
+    }
+  }
+
+  fn read(&mut self, count: u32) -> u32 {
+    let value = if self.pos >= 128 {
+      0
+    } else {
+      bits(self.bits, self.pos, count.min(128 - self.pos))
+    };
+    self.pos += count;
+    value
+  }
+}
+
+/// 量化级数对应的编码方式：(3 表示 trit，5 表示 quint，1 表示纯二进制, 低位的位数)
+fn encoding(levels: u32) -> (u32, u32) {
+  let (kind, base) = if levels.is_multiple_of(3) {
+    (3, levels / 3)
+  } else if levels.is_multiple_of(5) {
+    (5, levels / 5)
+  } else {
+    (1, levels)
+  };
+  (kind, base.trailing_zeros())
+}
+
+fn ise_bit_count(count: u32, levels: u32) -> u32 {
+  match encoding(levels) {
+    (3, bits) => count * bits + (8 * count).div_ceil(5),
+    (5, bits) => count * bits + (7 * count).div_ceil(3),
+    (_, bits) => count * bits,
+  }
+}
+
+/// 8 位打包的 5 个 trit
+fn decode_trits(t: u32) -> [u32; 5] {
+  let bit = |value: u32, n: u32| (value >> n) & 1;
+  let (c, t4, t3);
+  if (t >> 2) & 7 == 7 {
+    c = (((t >> 5) & 7) << 2) | (t & 3);
+    t4 = 2;
+    t3 = 2;
+  } else {
+    c = t & 0x1F;
+    if (t >> 5) & 3 == 3 {
+      t4 = 2;
+      t3 = bit(t, 7);
+    } else {
+      t4 = bit(t, 7);
+      t3 = (t >> 5) & 3;
+    }
+  }
+  let (t2, t1, t0);
+  if c & 3 == 3 {
+    t2 = 2;
+    t1 = bit(c, 4);
+    t0 = (bit(c, 3) << 1) | (bit(c, 2) & !bit(c, 3) & 1);
+  } else if (c >> 2) & 3 == 3 {
+    t2 = 2;
+    t1 = 2;
+    t0 = c & 3;
+  } else {
+    t2 = bit(c, 4);
+    t1 = (c >> 2) & 3;
+    t0 = (bit(c, 1) << 1) | (bit(c, 0) & !bit(c, 1) & 1);
+  }
+  [t0, t1, t2, t3, t4]
+}
+
+/// 7 位打包的 3 个 quint
+fn decode_quints(q: u32) -> [u32; 3] {
+  let bit = |value: u32, n: u32| (value >> n) & 1;
+  if (q >> 1) & 3 == 3 && (q >> 5) & 3 == 0 {
+    let q0 = bit(q, 0);
+    let q2 = (q0 << 2) | ((bit(q, 4) & !q0 & 1) << 1) | (bit(q, 3) & !q0 & 1);
+    return [4, 4, q2];
+  }
+  let (c, q2) = if (q >> 1) & 3 == 3 {
+    let c = (((q >> 3) & 3) << 3) | ((!(q >> 5) & 3) << 1) | (q & 1);
+    (c, 4)
+  } else {
+    (q & 0x1F, (q >> 5) & 3)
+  };
+  if c & 7 == 5 {
+    [(c >> 3) & 3, 4, q2]
+  } else {
+    [c & 7, (c >> 3) & 3, q2]
+  }
+}
+
+fn decode_ise(reader: &mut Reader, levels: u32, count: usize) -> Vec<u32> {
+  let (kind, bits) = encoding(levels);
+  let mut values = Vec::with_capacity(count + 4);
+  while values.len() < count {
+    match kind {
+      3 => {
+        let mut m = [0; 5];
+        m[0] = reader.read(bits);
+        let mut t = reader.read(2);
+        m[1] = reader.read(bits);
+        t |= reader.read(2) << 2;
+        m[2] = reader.read(bits);
+        t |= reader.read(1) << 4;
+        m[3] = reader.read(bits);
+        t |= reader.read(2) << 5;
+        m[4] = reader.read(bits);
+        t |= reader.read(1) << 7;
+        for (trit, m) in decode_trits(t).into_iter().zip(m) {
+          values.push((trit << bits) | m);
+        }
+      }
+      5 => {
+        let mut m = [0; 3];
+        m[0] = reader.read(bits);
+        let mut q = reader.read(3);
+        m[1] = reader.read(bits);
+        q |= reader.read(2) << 3;
+        m[2] = reader.read(bits);
+        q |= reader.read(2) << 5;
+        for (quint, m) in decode_quints(q).into_iter().zip(m) {
+          values.push((quint << bits) | m);
+        }
+      }
+      _ => values.push(reader.read(bits)),
+    }
+  }
+  values.truncate(count);
+  values
+}
+
+/// 把 from 位的值重复填满 to 位
+fn replicate(value: u32, from: u32, to: u32) -> u32 {
+  let mut result = 0;
+  let mut shift = to as i32 - from as i32;
+  while shift > -(from as i32) {
+    result |= if shift >= 0 {
+      value << shift
+    } else {
+      value >> -shift
+    };
+    shift -= from as i32;
+  }
+  result & ((1 << to) - 1)
+}
+
+/// 颜色端点反量化到 0..=255
+fn unquantize_color(value: u32, levels: u32) -> u32 {
+  let (kind, bits) = encoding(levels);
+  if kind == 1 {
+    return replicate(value, bits, 8);
+  }
+  let d = value >> bits;
+  let m = value & ((1 << bits) - 1);
+  let a = if m & 1 == 1 { 0x1FF } else { 0 };
+  let bit = |n: u32| (m >> n) & 1;
+  let (b, c) = (bit(1), bit(2));
+  let (d_, e, f) = (bit(3), bit(4), bit(5));
+  let (pattern, scale) = match (kind, bits) {
+    (3, 1) => (0, 204),
+    (3, 2) => ((b << 8) | (b << 4) | (b << 2) | (b << 1), 93),
+    (3, 3) => ((c << 8) | (b << 7) | (c << 3) | (b << 2) | (c << 1) | b, 44),
+    (3, 4) => (
+      (d_ << 8) | (c << 7) | (b << 6) | (d_ << 2) | (c << 1) | b,
+      22,
+    ),
+    (3, 5) => (
+      (e << 8) | (d_ << 7) | (c << 6) | (b << 5) | (e << 1) | d_,
+      11,
+    ),
+    (3, _) => ((f << 8) | (e << 7) | (d_ << 6) | (c << 5) | (b << 4) | f, 5),
+    (_, 1) => (0, 113),
+    (_, 2) => ((b << 8) | (b << 3) | (b << 2), 54),
+    (_, 3) => ((c << 8) | (b << 7) | (c << 2) | (b << 1) | c, 26),
+    (_, 4) => ((d_ << 8) | (c << 7) | (b << 6) | (d_ << 1) | c, 13),
+    (_, _) => ((e << 8) | (d_ << 7) | (c << 6) | (b << 5) | e, 6),
+  };
+  let t = (d * scale + pattern) ^ a;
+  (a & 0x80) | (t >> 2)
+}
+
+/// 权重反量化到 0..=64
+fn unquantize_weight(value: u32, levels: u32) -> u32 {
+  let (kind, bits) = encoding(levels);
+  let weight = if kind == 1 {
+    replicate(value, bits, 6)
+  } else if bits == 0 {
+    return value * 64 / (levels - 1);
+  } else {
+    let d = value >> bits;
+    let m = value & ((1 << bits) - 1);
+    let a = if m & 1 == 1 { 0x7F } else { 0 };
+    let (b, c) = ((m >> 1) & 1, (m >> 2) & 1);
+    let (pattern, scale) = match (kind, bits) {
+      (3, 1) => (0, 50),
+      (3, 2) => ((b << 6) | (b << 2) | b, 23),
+      (3, _) => ((c << 6) | (b << 5) | (c << 1) | b, 11),
+      (_, 1) => (0, 28),
+      (_, _) => ((b << 6) | (b << 1), 13),
+    };
+    let t = (d * scale + pattern) ^ a;
+    (a & 0x20) | (t >> 2)
+  };
+  if weight > 32 { weight + 1 } else { weight }
+}
+
+struct BlockMode {
+  grid_width: usize,
+  grid_height: usize,
+  weight_levels: u32,
+  dual_plane: bool,
+}
+
+fn decode_block_mode(mode: u32) -> Option<BlockMode> {
+  let bit = |n: u32| (mode >> n) & 1;
+  let mut quant = bit(4);
+  let mut high_precision = bit(9);
+  let mut dual_plane = bit(10);
+  let a = (mode >> 5) & 3;
+  let (width, height);
+  if mode & 3 != 0 {
+    quant |= (mode & 3) << 1;
+    let b = (mode >> 7) & 3;
+    (width, height) = match (mode >> 2) & 3 {
+      0 => (b + 4, a + 2),
+      1 => (b + 8, a + 2),
+      2 => (a + 2, b + 8),
+      _ if bit(8) == 1 => ((b & 1) + 2, a + 2),
+      _ => (a + 2, (b & 1) + 6),
+    };
+  } else {
+    quant |= ((mode >> 2) & 3) << 1;
+    if (mode >> 2) & 3 == 0 {
+      return None;
+    }
+    let b = (mode >> 9) & 3;
+    (width, height) = match (mode >> 7) & 3 {
+      0 => (12, a + 2),
+      1 => (a + 2, 12),
+      2 => {
+        high_precision = 0;
+        dual_plane = 0;
+        (a + 6, b + 6)
+      }
+      _ => match a {
+        0 => (6, 10),
+        1 => (10, 6),
+        _ => return None,
+      },
+    };
+  }
+  let weight_levels = WEIGHT_LEVELS[(quant - 2 + 6 * high_precision) as usize];
+  let weight_count = width * height * (dual_plane + 1);
+  let weight_bits = ise_bit_count(weight_count, weight_levels);
+  if weight_count > 64 || !(24..=96).contains(&weight_bits) {
+    return None;
+  }
+  Some(BlockMode {
+    grid_width: width as usize,
+    grid_height: height as usize,
+    weight_levels,
+    dual_plane: dual_plane == 1,
+  })
+}
+
+/// 把有符号的偏移量从 a 的高位转移到 b，返回 (偏移量, 基准值)
+fn bit_transfer_signed(a: i32, b: i32) -> (i32, i32) {
+  let b = (b >> 1) | (a & 0x80);
+  let mut a = (a >> 1) & 0x3F;
+  if a & 0x20 != 0 {
+    a -= 0x40;
+  }
+  (a, b)
+}
+
+fn blue_contract(r: i32, g: i32, b: i32, a: i32) -> [i32; 4] {
+  [(r + b) >> 1, (g + b) >> 1, b, a]
+}
+
+/// 按颜色端点模式 (CEM) 解出两个 RGBA 端点，HDR 模式返回 None
+fn decode_endpoints(cem: u32, v: &[u32]) -> Option<[[u8; 4]; 2]> {
+  let v: Vec<i32> = v.iter().map(|&v| v as i32).collect();
+  let (e0, e1) = match cem {
+    0 => ([v[0], v[0], v[0], 255], [v[1], v[1], v[1], 255]),
+    1 => {
+      let l0 = (v[0] >> 2) | (v[1] & 0xC0);
+      let l1 = (l0 + (v[1] & 0x3F)).min(255);
+      ([l0, l0, l0, 255], [l1, l1, l1, 255])
+    }
+    4 => ([v[0], v[0], v[0], v[2]], [v[1], v[1], v[1], v[3]]),
+    5 => {
+      let (offset, base) = bit_transfer_signed(v[1], v[0]);
+      let (alpha_offset, alpha) = bit_transfer_signed(v[3], v[2]);
+      let l1 = base + offset;
+      (
+        [base, base, base, alpha],
+        [l1, l1, l1, alpha + alpha_offset],
+      )
+    }
+    6 => (
+      [
+        (v[0] * v[3]) >> 8,
+        (v[1] * v[3]) >> 8,
+        (v[2] * v[3]) >> 8,
+        255,
+      ],
+      [v[0], v[1], v[2], 255],
+    ),
+    8 | 12 => {
+      let (a0, a1) = if cem == 12 { (v[6], v[7]) } else { (255, 255) };
+      if v[1] + v[3] + v[5] >= v[0] + v[2] + v[4] {
+        ([v[0], v[2], v[4], a0], [v[1], v[3], v[5], a1])
+      } else {
+        (
+          blue_contract(v[1], v[3], v[5], a1),
+          blue_contract(v[0], v[2], v[4], a0),
+        )
+      }
+    }
+    9 | 13 => {
+      let (r_offset, r) = bit_transfer_signed(v[1], v[0]);
+      let (g_offset, g) = bit_transfer_signed(v[3], v[2]);
+      let (b_offset, b) = bit_transfer_signed(v[5], v[4]);
+      let (a_offset, a) = if cem == 13 {
+        bit_transfer_signed(v[7], v[6])
+      } else {
+        (0, 255)
+      };
+      if r_offset + g_offset + b_offset >= 0 {
+        (
+          [r, g, b, a],
+          [r + r_offset, g + g_offset, b + b_offset, a + a_offset],
+        )
+      } else {
+        (
+          blue_contract(r + r_offset, g + g_offset, b + b_offset, a + a_offset),
+          blue_contract(r, g, b, a),
+        )
+      }
+    }
+    10 => (
+      [
+        (v[0] * v[3]) >> 8,
+        (v[1] * v[3]) >> 8,
+        (v[2] * v[3]) >> 8,
+        v[4],
+      ],
+      [v[0], v[1], v[2], v[5]],
+    ),
+    _ => return None,
+  };
+  let clamp = |e: [i32; 4]| e.map(|c| c.clamp(0, 255) as u8);
+  Some([clamp(e0), clamp(e1)])
+}
+
+fn hash52(mut p: u32) -> u32 {
+  p ^= p >> 15;
+  p = p.wrapping_sub(p << 17);
+  p = p.wrapping_add(p << 7);
+  p = p.wrapping_add(p << 4);
+  p ^= p >> 5;
+  p = p.wrapping_add(p << 16);
+  p ^= p >> 7;
+  p ^= p >> 3;
+  p ^= p << 6;
+  p ^= p >> 17;
+  p
+}
+
+/// 由分区种子与纹素坐标计算所属的分区
+fn select_partition(seed: u32, x: u32, y: u32, partitions: u32, small_block: bool) -> usize {
+  let (x, y) = if small_block {
+    (x << 1, y << 1)
+  } else {
+    (x, y)
+  };
+  let seed = seed + (partitions - 1) * 1024;
+  let rnum = hash52(seed);
+  let mut seeds = [
+    rnum,
+    rnum >> 4,
+    rnum >> 8,
+    rnum >> 12,
+    rnum >> 16,
+    rnum >> 20,
+    rnum >> 24,
+    rnum >> 28,
+    rnum >> 18,
+    rnum >> 22,
+    rnum >> 26,
+    rnum.rotate_left(2),
+  ]
+  .map(|s| (s & 0xF) * (s & 0xF));
+  let (sh1, sh2) = if seed & 1 == 1 {
+    (
+      if seed & 2 != 0 { 4 } else { 5 },
+      if partitions == 3 { 6 } else { 5 },
+    )
+  } else {
+    (
+      if partitions == 3 { 6 } else { 5 },
+      if seed & 2 != 0 { 4 } else { 5 },
+    )
+  };
+  let sh3 = if seed & 0x10 != 0 { sh1 } else { sh2 };
+  for (i, s) in seeds.iter_mut().enumerate() {
+    *s >>= match i {
+      0..=7 if i % 2 == 0 => sh1,
+      0..=7 => sh2,
+      _ => sh3,
+    };
+  }
+  // z 坐标恒为 0，seeds[8..12] 只在三维块中用到
+  let a = (seeds[0] * x + seeds[1] * y + (rnum >> 14)) & 0x3F;
+  let b = (seeds[2] * x + seeds[3] * y + (rnum >> 10)) & 0x3F;
+  let c = if partitions >= 3 {
+    (seeds[4] * x + seeds[5] * y + (rnum >> 6)) & 0x3F
+  } else {
+    0
+  };
+  let d = if partitions >= 4 {
+    (seeds[6] * x + seeds[7] * y + (rnum >> 2)) & 0x3F
+  } else {
+    0
+  };
+  if a >= b && a >= c && a >= d {
+    0
+  } else if b >= c && b >= d {
+    1
+  } else if c >= d {
+    2
+  } else {
+    3
+  }
+}
+
+/// 把权重网格双线性插值到块内的纹素
+fn infill_weight(
+  weights: &[u32],
+  mode: &BlockMode,
+  plane: usize,
+  block: (usize, usize),
+  texel: (usize, usize),
+) -> u32 {
+  let planes = if mode.dual_plane { 2 } else { 1 };
+  let ds = (1024 + block.0 / 2) / (block.0 - 1);
+  let dt = (1024 + block.1 / 2) / (block.1 - 1);
+  let gs = (ds * texel.0 * (mode.grid_width - 1) + 32) >> 6;
+  let gt = (dt * texel.1 * (mode.grid_height - 1) + 32) >> 6;
+  let (js, fs) = (gs >> 4, (gs & 0xF) as u32);
+  let (jt, ft) = (gt >> 4, (gt & 0xF) as u32);
+  let v0 = js + jt * mode.grid_width;
+  let w11 = (fs * ft + 8) >> 4;
+  let w10 = ft - w11;
+  let w01 = fs - w11;
+  let w00 = 16 - fs - ft + w11;
+  let weight = |i: usize| weights.get(i * planes + plane).copied().unwrap_or(0);
+  (weight(v0) * w00
+    + weight(v0 + 1) * w01
+    + weight(v0 + mode.grid_width) * w10
+    + weight(v0 + mode.grid_width + 1) * w11
+    + 8)
+    >> 4
+}
+
+/// 解码一个 block_width x block_height 的块，out 按行优先排列
+pub fn decode_astc(
+  block: &[u8],
+  block_width: usize,
+  block_height: usize,
+  srgb: bool,
+  out: &mut [[u8; 4]],
+) {
+  if decode_block(block, block_width, block_height, srgb, out).is_none() {
+    out.fill(ERROR_COLOR);
+  }
+}
+
+fn decode_block(
+  block: &[u8],
+  block_width: usize,
+  block_height: usize,
+  srgb: bool,
+  out: &mut [[u8; 4]],
+) -> Option<()> {
+  let data = u128::from_le_bytes(block.try_into().ok()?);
+  let mode = bits(data, 0, 11);
+  // 单色块，LDR 时颜色为四个 16 位的 unorm
+  if mode & 0x1FF == 0x1FC {
+    if mode & 0x200 != 0 {
+      return None;
+    }
+    let color = [64, 80, 96, 112].map(|start| (bits(data, start, 16) >> 8) as u8);
+    out.fill(color);
+    return Some(());
+  }
+
+  let mode = decode_block_mode(mode)?;
+  if mode.grid_width > block_width || mode.grid_height > block_height {
+    return None;
+  }
+  let partitions = bits(data, 11, 2) + 1;
+  if mode.dual_plane && partitions == 4 {
+    return None;
+  }
+  let planes = if mode.dual_plane { 2 } else { 1 };
+  let weight_count = mode.grid_width * mode.grid_height * planes;
+  let weight_bits = ise_bit_count(weight_count as u32, mode.weight_levels);
+
+  // 颜色端点模式：多个分区时，除了固定位置的 6 位，剩余的位放在权重数据下方
+  let mut below_weights = 128 - weight_bits;
+  let mut cems = [0; 4];
+  let color_start = if partitions == 1 {
+    cems[0] = bits(data, 13, 4);
+    17
+  } else {
+    let mut encoded = bits(data, 23, 6);
+    if encoded & 3 == 0 {
+      cems = [encoded >> 2; 4];
+    } else {
+      let extra = 3 * partitions - 4;
+      below_weights -= extra;
+      encoded |= bits(data, below_weights, extra) << 6;
+      let base = (encoded & 3) - 1;
+      for (i, cem) in cems.iter_mut().take(partitions as usize).enumerate() {
+        let class = base + ((encoded >> (2 + i)) & 1);
+        let mode = (encoded >> (2 + partitions as usize + 2 * i)) & 3;
+        *cem = (class << 2) | mode;
+      }
+    }
+    29
+  };
+  let plane2_component = if mode.dual_plane {
+    below_weights -= 2;
+    Some(bits(data, below_weights, 2) as usize)
+  } else {
+    None
+  };
+
+  let color_count: u32 = cems
+    .iter()
+    .take(partitions as usize)
+    .map(|cem| ((cem >> 2) + 1) * 2)
+    .sum();
+  if color_count > 18 || below_weights < color_start {
+    return None;
+  }
+  // 颜色端点使用剩余空间能容纳的最高精度
+  let available = below_weights - color_start;
+  let color_levels = *COLOR_LEVELS
+    .iter()
+    .rev()
+    .find(|&&levels| ise_bit_count(color_count, levels) <= available)?;
+  if color_levels < 6 {
+    return None;
+  }
+  let mut reader = Reader::new(data, color_start, ise_bit_count(color_count, color_levels));
+  let colors: Vec<u32> = decode_ise(&mut reader, color_levels, color_count as usize)
+    .into_iter()
+    .map(|value| unquantize_color(value, color_levels))
+    .collect();
+  let mut endpoints = [[[0; 4]; 2]; 4];
+  let mut offset = 0;
+  for (cem, endpoints) in cems
+    .iter()
+    .zip(endpoints.iter_mut())
+    .take(partitions as usize)
+  {
+    let count = (((cem >> 2) + 1) * 2) as usize;
+    *endpoints = decode_endpoints(*cem, &colors[offset..offset + count])?;
+    offset += count;
+  }
+
+  // 权重从块的最高位开始反向存储
+  let mut reader = Reader::new(data.reverse_bits(), 0, weight_bits);
+  let weights: Vec<u32> = decode_ise(&mut reader, mode.weight_levels, weight_count)
+    .into_iter()
+    .map(|value| unquantize_weight(value, mode.weight_levels))
+    .collect();
+
+  let seed = bits(data, 13, 10);
+  let small_block = block_width * block_height < 31;
+  for y in 0..block_height {
+    for x in 0..block_width {
+      let partition = if partitions > 1 {
+        select_partition(seed, x as u32, y as u32, partitions, small_block)
+      } else {
+        0
+      };
+      let block = (block_width, block_height);
+      let plane_weights = [
+        infill_weight(&weights, &mode, 0, block, (x, y)),
+        if mode.dual_plane {
+          infill_weight(&weights, &mode, 1, block, (x, y))
+        } else {
+          0
+        },
+      ];
+      let [e0, e1] = endpoints[partition];
+      let texel = &mut out[y * block_width + x];
+      for channel in 0..4 {
+        let weight = plane_weights[(plane2_component == Some(channel)) as usize];
+        // 先扩展到 16 位再插值，sRGB 的颜色通道低 8 位填 0x80
+        let expand = |e: u8| {
+          if srgb && channel < 3 {
+            ((e as u32) << 8) | 0x80
+          } else {
+            e as u32 * 257
+          }
+        };
+        let value = (expand(e0[channel]) * (64 - weight) + expand(e1[channel]) * weight + 32) >> 6;
+        texel[channel] = (value >> 8) as u8;
+      }
+    }
+  }
+  Some(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::collections::HashSet;
+
+  #[test]
+  fn trits_and_quints_cover_all_combinations() {
+    let trits: HashSet<_> = (0..256).map(decode_trits).collect();
+    assert_eq!(trits.len(), 243);
+    assert!(trits.iter().flatten().all(|&t| t < 3));
+    let quints: HashSet<_> = (0..128).map(decode_quints).collect();
+    assert_eq!(quints.len(), 125);
+    assert!(quints.iter().flatten().all(|&q| q < 5));
+  }
+
+  #[test]
+  fn unquantized_ranges_span_endpoints() {
+    for levels in COLOR_LEVELS.into_iter().filter(|&levels| levels >= 6) {
+      let values: HashSet<_> = (0..levels).map(|v| unquantize_color(v, levels)).collect();
+      assert_eq!(values.len(), levels as usize, "{levels} color levels");
+      assert!(values.contains(&0) && values.contains(&255));
+    }
+    for levels in WEIGHT_LEVELS {
+      let values: HashSet<_> = (0..levels).map(|v| unquantize_weight(v, levels)).collect();
+      assert_eq!(values.len(), levels as usize, "{levels} weight levels");
+      assert!(values.contains(&0) && values.contains(&64));
+    }
+  }
+
+  #[test]
+  fn void_extent_block() {
+    let mut block = [0u8; 16];
+    block[0] = 0xFC;
+    block[1] = 0x01;
+    // 其余的坐标位全为 1 表示没有范围
+    block[1] |= 0xFC;
+    block[2..8].fill(0xFF);
+    for (i, value) in [0x1234u16, 0x8000, 0xFFFF, 0x0000].iter().enumerate() {
+      block[8 + i * 2..10 + i * 2].copy_from_slice(&value.to_le_bytes());
+    }
+    let mut out = [[0; 4]; 36];
+    decode_astc(&block, 6, 6, false, &mut out);
+    assert!(out.iter().all(|&texel| texel == [0x12, 0x80, 0xFF, 0x00]));
+  }
+
+  #[test]
+  fn reserved_block_mode_is_an_error() {
+    let mut out = [[0; 4]; 16];
+    decode_astc(&[0; 16], 4, 4, false, &mut out);
+    assert!(out.iter().all(|&texel| texel == ERROR_COLOR));
+  }
+}
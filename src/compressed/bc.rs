@@ -0,0 +1,1129 @@
+//! BC1 - BC7 的 CPU 解码，每次解码一个 4x4 的块，像素按行优先排列
+
+/// 从块的最低位开始按位读取
+pub(super) struct BitReader {
+  bits: u128,
+  pos: u32,
+}
+impl BitReader {
+  pub fn new(block: &[u8]) -> Self {
+    let mut bytes = [0; 16];
+    bytes[..block.len()].copy_from_slice(block);
+    Self {
+      bits: u128::from_le_bytes(bytes),
+      pos: 0,
+    }
+  }
+
+  pub fn read(&mut self, count: u32) -> u32 {
+    if count == 0 {
+      return 0;
+    }
+    let value = (self.bits >> self.pos) as u32 & (u32::MAX >> (32 - count));
+    self.pos += count;
+    value
+  }
+
+  /// 按相反的顺序读取，先读到的位是结果的最高位
+  fn read_reversed(&mut self, count: u32) -> u32 {
+    (0..count).fold(0, |value, _| (value << 1) | self.read(1))
+  }
+}
+
+fn rgb565(color: u16) -> [u8; 3] {
+  let r = (color >> 11) as u8 & 0x1F;
+  let g = (color >> 5) as u8 & 0x3F;
+  let b = color as u8 & 0x1F;
+  [
+    (r << 3) | (r >> 2),
+    (g << 2) | (g >> 4),
+    (b << 3) | (b >> 2),
+  ]
+}
+
+/// BC1 的颜色部分。BC2 / BC3 中的颜色块总是使用四色模式
+fn decode_color(block: &[u8], four_color: bool, out: &mut [[u8; 4]; 16]) {
+  let c0 = u16::from_le_bytes([block[0], block[1]]);
+  let c1 = u16::from_le_bytes([block[2], block[3]]);
+  let (e0, e1) = (rgb565(c0), rgb565(c1));
+  let mut palette = [[0; 4]; 4];
+  palette[0] = [e0[0], e0[1], e0[2], 255];
+  palette[1] = [e1[0], e1[1], e1[2], 255];
+  for c in 0..3 {
+    let (a, b) = (e0[c] as u32, e1[c] as u32);
+    if four_color || c0 > c1 {
+      palette[2][c] = ((2 * a + b) / 3) as u8;
+      palette[3][c] = ((a + 2 * b) / 3) as u8;
+    } else {
+      palette[2][c] = ((a + b) / 2) as u8;
+    }
+  }
+  palette[2][3] = 255;
+  // 三色模式下第四种颜色是透明的黑色
+  palette[3][3] = if four_color || c0 > c1 { 255 } else { 0 };
+
+  let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+  for (i, pixel) in out.iter_mut().enumerate() {
+    *pixel = palette[(indices >> (2 * i)) as usize & 3];
+  }
+}
+
+/// BC4 的单通道块，也用于 BC3 的透明度与 BC5 的两个通道
+fn decode_channel(block: &[u8], signed: bool) -> [u8; 16] {
+  // 有符号时按 -127..=127 插值，结果映射回字节的补码表示
+  let value = |byte: u8| {
+    if signed {
+      (byte as i8).max(-127) as i32
+    } else {
+      byte as i32
+    }
+  };
+  let (a0, a1) = (value(block[0]), value(block[1]));
+  let (min, max) = if signed { (-127, 127) } else { (0, 255) };
+  let mut palette = [a0, a1, 0, 0, 0, 0, 0, 0];
+  if a0 > a1 {
+    for (k, entry) in palette.iter_mut().enumerate().skip(2) {
+      *entry = ((8 - k as i32) * a0 + (k as i32 - 1) * a1) / 7;
+    }
+  } else {
+    for (k, entry) in palette.iter_mut().enumerate().take(6).skip(2) {
+      *entry = ((6 - k as i32) * a0 + (k as i32 - 1) * a1) / 5;
+    }
+    palette[6] = min;
+    palette[7] = max;
+  }
+  let mut indices = [0; 8];
+  indices[..6].copy_from_slice(&block[2..8]);
+  let indices = u64::from_le_bytes(indices);
+  let mut out = [0; 16];
+  for (i, texel) in out.iter_mut().enumerate() {
+    *texel = palette[(indices >> (3 * i)) as usize & 7] as u8;
+  }
+  out
+}
+
+pub fn decode_bc1(block: &[u8], out: &mut [[u8; 4]; 16]) {
+  decode_color(block, false, out);
+}
+
+pub fn decode_bc2(block: &[u8], out: &mut [[u8; 4]; 16]) {
+  decode_color(&block[8..], true, out);
+  let alpha = u64::from_le_bytes(block[..8].try_into().unwrap());
+  for (i, pixel) in out.iter_mut().enumerate() {
+    pixel[3] = (alpha >> (4 * i)) as u8 & 0xF;
+    pixel[3] |= pixel[3] << 4;
+  }
+}
+
+pub fn decode_bc3(block: &[u8], out: &mut [[u8; 4]; 16]) {
+  decode_color(&block[8..], true, out);
+  for (pixel, alpha) in out.iter_mut().zip(decode_channel(&block[..8], false)) {
+    pixel[3] = alpha;
+  }
+}
+
+/// 结果放在 R 通道，有符号时为补码表示的 snorm
+pub fn decode_bc4(block: &[u8], signed: bool, out: &mut [[u8; 4]; 16]) {
+  let one = if signed { 127 } else { 255 };
+  for (pixel, r) in out.iter_mut().zip(decode_channel(block, signed)) {
+    *pixel = [r, 0, 0, one];
+  }
+}
+
+pub fn decode_bc5(block: &[u8], signed: bool, out: &mut [[u8; 4]; 16]) {
+  let one = if signed { 127 } else { 255 };
+  let red = decode_channel(&block[..8], signed);
+  let green = decode_channel(&block[8..], signed);
+  for (i, pixel) in out.iter_mut().enumerate() {
+    *pixel = [red[i], green[i], 0, one];
+  }
+}
+
+const WEIGHTS2: [u32; 4] = [0, 21, 43, 64];
+const WEIGHTS3: [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const WEIGHTS4: [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+fn weights(index_bits: u32) -> &'static [u32] {
+  match index_bits {
+    2 => &WEIGHTS2,
+    3 => &WEIGHTS3,
+    _ => &WEIGHTS4,
+  }
+}
+
+/// 两个子集的分区，第 i 位为第 i 个像素所属的子集
+const PARTITIONS2: [u16; 64] = [
+  0xCCCC, 0x8888, 0xEEEE, 0xECC8, 0xC880, 0xFEEC, 0xFEC8, 0xEC80, 0xC800, 0xFFEC, 0xFE80, 0xE800,
+  0xFFE8, 0xFF00, 0xFFF0, 0xF000, 0xF710, 0x008E, 0x7100, 0x08CE, 0x008C, 0x7310, 0x3100, 0x8CCE,
+  0x088C, 0x3110, 0x6666, 0x366C, 0x17E8, 0x0FF0, 0x718E, 0x399C, 0xAAAA, 0xF0F0, 0x5A5A, 0x33CC,
+  0x3C3C, 0x55AA, 0x9696, 0xA55A, 0x73CE, 0x13C8, 0x324C, 0x3BDC, 0x6996, 0xC33C, 0x9966, 0x0660,
+  0x0272, 0x04E4, 0x4E40, 0x2720, 0xC936, 0x936C, 0x39C6, 0x639C, 0x9336, 0x9CC6, 0x817E, 0xE718,
+  0xCCF0, 0x0FCC, 0x7744, 0xEE22,
+];
+
+/// 三个子集的分区，依次为每个像素所属的子集
+const PARTITIONS3: [[u8; 16]; 64] = [
+  [0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 1, 2, 2, 2, 2],
+  [0, 0, 0, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 2, 1],
+  [0, 0, 0, 0, 2, 0, 0, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+  [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 1, 0, 1, 1, 1],
+  [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2],
+  [0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 2, 2],
+  [0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1],
+  [0, 0, 1, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+  [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2],
+  [0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2],
+  [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+  [0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2],
+  [0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2],
+  [0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2],
+  [0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2, 1, 2, 2, 2],
+  [0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0, 2, 2, 2, 0],
+  [0, 0, 0, 1, 0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2],
+  [0, 1, 1, 1, 0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0],
+  [0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2],
+  [0, 0, 2, 2, 0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1],
+  [0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2, 0, 2, 2, 2],
+  [0, 0, 0, 1, 0, 0, 0, 1, 2, 2, 2, 1, 2, 2, 2, 1],
+  [0, 0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2],
+  [0, 0, 0, 0, 1, 1, 0, 0, 2, 2, 1, 0, 2, 2, 1, 0],
+  [0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1, 0, 0, 0, 0],
+  [0, 0, 1, 2, 0, 0, 1, 2, 1, 1, 2, 2, 2, 2, 2, 2],
+  [0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1, 0, 1, 1, 0],
+  [0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1],
+  [0, 0, 2, 2, 1, 1, 0, 2, 1, 1, 0, 2, 0, 0, 2, 2],
+  [0, 1, 1, 0, 0, 1, 1, 0, 2, 0, 0, 2, 2, 2, 2, 2],
+  [0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1],
+  [0, 0, 0, 0, 2, 0, 0, 0, 2, 2, 1, 1, 2, 2, 2, 1],
+  [0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 2, 2, 2],
+  [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 2, 0, 0, 1, 1],
+  [0, 0, 1, 1, 0, 0, 1, 2, 0, 0, 2, 2, 0, 2, 2, 2],
+  [0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0],
+  [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0],
+  [0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0],
+  [0, 1, 2, 0, 2, 0, 1, 2, 1, 2, 0, 1, 0, 1, 2, 0],
+  [0, 0, 1, 1, 2, 2, 0, 0, 1, 1, 2, 2, 0, 0, 1, 1],
+  [0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0, 1, 1],
+  [0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+  [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1],
+  [0, 0, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2, 1, 1, 2, 2],
+  [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 1, 1],
+  [0, 2, 2, 0, 1, 2, 2, 1, 0, 2, 2, 0, 1, 2, 2, 1],
+  [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 0, 1, 0, 1],
+  [0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1],
+  [0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2],
+  [0, 2, 2, 2, 0, 1, 1, 1, 0, 2, 2, 2, 0, 1, 1, 1],
+  [0, 0, 0, 2, 1, 1, 1, 2, 0, 0, 0, 2, 1, 1, 1, 2],
+  [0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2],
+  [0, 2, 2, 2, 0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2],
+  [0, 0, 0, 2, 1, 1, 1, 2, 1, 1, 1, 2, 0, 0, 0, 2],
+  [0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2],
+  [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2],
+  [0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2, 2, 2, 2, 2],
+  [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2],
+  [0, 0, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2],
+  [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2],
+  [0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1],
+  [0, 2, 2, 2, 1, 2, 2, 2, 0, 2, 2, 2, 1, 2, 2, 2],
+  [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2],
+  [0, 1, 1, 1, 2, 0, 1, 1, 2, 2, 0, 1, 2, 2, 2, 0],
+];
+
+/// 两个子集时第二个子集的锚点像素
+const ANCHORS2: [u8; 64] = [
+  15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 2, 8, 2, 2, 8, 8, 15, 2, 8,
+  2, 2, 8, 8, 2, 2, 15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6, 6, 2, 6, 8, 15, 15, 2, 2,
+  15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+/// 三个子集时第二、第三个子集的锚点像素
+const ANCHORS3: [[u8; 2]; 64] = [
+  [3, 15],
+  [3, 8],
+  [15, 8],
+  [15, 3],
+  [8, 15],
+  [3, 15],
+  [15, 3],
+  [15, 8],
+  [8, 15],
+  [8, 15],
+  [6, 15],
+  [6, 15],
+  [6, 15],
+  [5, 15],
+  [3, 15],
+  [3, 8],
+  [3, 15],
+  [3, 8],
+  [8, 15],
+  [15, 3],
+  [3, 15],
+  [3, 8],
+  [6, 15],
+  [10, 8],
+  [5, 3],
+  [8, 15],
+  [8, 6],
+  [6, 10],
+  [8, 15],
+  [5, 15],
+  [15, 10],
+  [15, 8],
+  [8, 15],
+  [15, 3],
+  [3, 15],
+  [5, 10],
+  [6, 10],
+  [10, 8],
+  [8, 9],
+  [15, 10],
+  [15, 6],
+  [3, 15],
+  [15, 8],
+  [5, 15],
+  [15, 3],
+  [15, 6],
+  [15, 6],
+  [15, 8],
+  [3, 15],
+  [15, 3],
+  [5, 15],
+  [5, 15],
+  [5, 15],
+  [8, 15],
+  [5, 15],
+  [10, 15],
+  [5, 15],
+  [10, 15],
+  [8, 15],
+  [13, 15],
+  [15, 3],
+  [12, 15],
+  [3, 15],
+  [3, 8],
+];
+
+fn subset(subsets: u32, partition: usize, pixel: usize) -> usize {
+  match subsets {
+    2 => (PARTITIONS2[partition] >> pixel) as usize & 1,
+    3 => PARTITIONS3[partition][pixel] as usize,
+    _ => 0,
+  }
+}
+
+fn is_anchor(subsets: u32, partition: usize, pixel: usize) -> bool {
+  pixel == 0
+    || match subsets {
+      2 => ANCHORS2[partition] as usize == pixel,
+      3 => ANCHORS3[partition].contains(&(pixel as u8)),
+      _ => false,
+    }
+}
+
+struct Bc7Mode {
+  subsets: u32,
+  partition_bits: u32,
+  rotation_bits: u32,
+  index_selection_bits: u32,
+  color_bits: u32,
+  alpha_bits: u32,
+  /// 每个端点独立的 p 位
+  endpoint_pbits: bool,
+  /// 每个子集共享的 p 位
+  shared_pbits: bool,
+  index_bits: u32,
+  secondary_index_bits: u32,
+}
+
+const BC7_MODES: [Bc7Mode; 8] = {
+  #[allow(clippy::too_many_arguments)]
+  const fn mode(
+    subsets: u32,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    endpoint_pbits: bool,
+    shared_pbits: bool,
+    index_bits: u32,
+    secondary_index_bits: u32,
+  ) -> Bc7Mode {
+    Bc7Mode {
+      subsets,
+      partition_bits,
+      rotation_bits,
+      index_selection_bits,
+      color_bits,
+      alpha_bits,
+      endpoint_pbits,
+      shared_pbits,
+      index_bits,
+      secondary_index_bits,
+    }
+  }
+  [
+    mode(3, 4, 0, 0, 4, 0, true, false, 3, 0),
+    mode(2, 6, 0, 0, 6, 0, false, true, 3, 0),
+    mode(3, 6, 0, 0, 5, 0, false, false, 2, 0),
+    mode(2, 6, 0, 0, 7, 0, true, false, 2, 0),
+    mode(1, 0, 2, 1, 5, 6, false, false, 2, 3),
+    mode(1, 0, 2, 0, 7, 8, false, false, 2, 2),
+    mode(1, 0, 0, 0, 7, 7, true, false, 4, 0),
+    mode(2, 6, 0, 0, 5, 5, true, false, 2, 0),
+  ]
+};
+
+/// 把 bits 位的值扩展到 8 位
+fn expand(value: u32, bits: u32) -> u32 {
+  let value = value << (8 - bits);
+  value | (value >> bits)
+}
+
+pub fn decode_bc7(block: &[u8], out: &mut [[u8; 4]; 16]) {
+  let mut bits = BitReader::new(block);
+  let Some(mode_index) = (0..8).find(|_| bits.read(1) == 1) else {
+    // 保留的模式解码为透明的黑色
+    *out = [[0; 4]; 16];
+    return;
+  };
+  let mode = &BC7_MODES[mode_index];
+  let partition = bits.read(mode.partition_bits) as usize;
+  let rotation = bits.read(mode.rotation_bits);
+  let index_selection = bits.read(mode.index_selection_bits);
+
+  // 端点按通道存储：所有端点的 R，然后是 G、B、A
+  let endpoint_count = mode.subsets as usize * 2;
+  let mut endpoints = [[0u32; 4]; 6];
+  for channel in 0..4 {
+    let channel_bits = if channel < 3 {
+      mode.color_bits
+    } else {
+      mode.alpha_bits
+    };
+    for endpoint in endpoints.iter_mut().take(endpoint_count) {
+      endpoint[channel] = bits.read(channel_bits);
+    }
+  }
+  let mut pbits = [0; 6];
+  if mode.endpoint_pbits {
+    for pbit in pbits.iter_mut().take(endpoint_count) {
+      *pbit = bits.read(1);
+    }
+  } else if mode.shared_pbits {
+    for subset in 0..mode.subsets as usize {
+      let pbit = bits.read(1);
+      pbits[subset * 2] = pbit;
+      pbits[subset * 2 + 1] = pbit;
+    }
+  }
+  let has_pbits = mode.endpoint_pbits || mode.shared_pbits;
+  for (endpoint, pbit) in endpoints.iter_mut().zip(pbits).take(endpoint_count) {
+    for (channel, value) in endpoint.iter_mut().enumerate() {
+      let channel_bits = if channel < 3 {
+        mode.color_bits
+      } else {
+        mode.alpha_bits
+      };
+      *value = if channel_bits == 0 {
+        255
+      } else if has_pbits {
+        expand((*value << 1) | pbit, channel_bits + 1)
+      } else {
+        expand(*value, channel_bits)
+      };
+    }
+  }
+
+  // 锚点像素的索引省略了最高位
+  let mut indices = [0; 16];
+  for (pixel, index) in indices.iter_mut().enumerate() {
+    let anchor = is_anchor(mode.subsets, partition, pixel);
+    *index = bits.read(mode.index_bits - anchor as u32);
+  }
+  let mut secondary = [0; 16];
+  if mode.secondary_index_bits > 0 {
+    for (pixel, index) in secondary.iter_mut().enumerate() {
+      *index = bits.read(mode.secondary_index_bits - (pixel == 0) as u32);
+    }
+  }
+
+  for (pixel, out) in out.iter_mut().enumerate() {
+    let subset = subset(mode.subsets, partition, pixel);
+    let (e0, e1) = (endpoints[subset * 2], endpoints[subset * 2 + 1]);
+    let (color_weight, alpha_weight) = if mode.secondary_index_bits == 0 {
+      let weight = weights(mode.index_bits)[indices[pixel] as usize];
+      (weight, weight)
+    } else {
+      let primary = weights(mode.index_bits)[indices[pixel] as usize];
+      let secondary = weights(mode.secondary_index_bits)[secondary[pixel] as usize];
+      if index_selection == 0 {
+        (primary, secondary)
+      } else {
+        (secondary, primary)
+      }
+    };
+    for channel in 0..4 {
+      let weight = if channel < 3 {
+        color_weight
+      } else {
+        alpha_weight
+      };
+      out[channel] = (((64 - weight) * e0[channel] + weight * e1[channel] + 32) >> 6) as u8;
+    }
+    match rotation {
+      1 => out.swap(0, 3),
+      2 => out.swap(1, 3),
+      3 => out.swap(2, 3),
+      _ => {}
+    }
+  }
+}
+
+/// BC6H 各模式的端点位数、三个通道差值的位数，以及是否以差值存储
+struct Bc6hMode {
+  endpoint_bits: u32,
+  delta_bits: [u32; 3],
+  transformed: bool,
+}
+
+fn sign_extend(value: i32, bits: u32) -> i32 {
+  let shift = 32 - bits;
+  (value << shift) >> shift
+}
+
+fn unquantize_bc6h(value: i32, bits: u32, signed: bool) -> i32 {
+  if !signed {
+    if bits >= 15 || value == 0 {
+      value
+    } else if value == (1 << bits) - 1 {
+      0xFFFF
+    } else {
+      ((value << 16) + 0x8000) >> bits
+    }
+  } else if bits >= 16 {
+    value
+  } else {
+    let magnitude = value.abs();
+    let unquantized = if magnitude == 0 {
+      0
+    } else if magnitude >= (1 << (bits - 1)) - 1 {
+      0x7FFF
+    } else {
+      ((magnitude << 15) + 0x4000) >> (bits - 1)
+    };
+    if value < 0 { -unquantized } else { unquantized }
+  }
+}
+
+/// 把插值结果缩放为半精度浮点数的位
+fn finish_unquantize_bc6h(value: i32, signed: bool) -> u16 {
+  if !signed {
+    ((value * 31) >> 6) as u16
+  } else if value < 0 {
+    0x8000 | (((-value) * 31) >> 5) as u16
+  } else {
+    ((value * 31) >> 5) as u16
+  }
+}
+
+/// 解码为 RGBA 半精度浮点数，透明度固定为 1
+pub fn decode_bc6h(block: &[u8], signed: bool, out: &mut [[u16; 4]; 16]) {
+  let mut bits = BitReader::new(block);
+  // 端点 [w, x, y, z]：w / x 是第一个子集，y / z 是第二个子集
+  let mut r = [0i32; 4];
+  let mut g = [0i32; 4];
+  let mut b = [0i32; 4];
+  let mut mode_bits = bits.read(2);
+  if mode_bits > 1 {
+    mode_bits |= bits.read(3) << 2;
+  }
+
+  // 按 BC6H 规范中各模式的位布局依次读取，字段交错排列
+  macro_rules! read {
+    ($target:expr, $count:expr) => {
+      $target |= bits.read($count) as i32
+    };
+    ($target:expr, $count:expr, $shift:expr) => {
+      $target |= (bits.read($count) as i32) << $shift
+    };
+  }
+  let mode = match mode_bits {
+    0b00 => {
+      read!(g[2], 1, 4);
+      read!(b[2], 1, 4);
+      read!(b[3], 1, 4);
+      read!(r[0], 10);
+      read!(g[0], 10);
+      read!(b[0], 10);
+      read!(r[1], 5);
+      read!(g[3], 1, 4);
+      read!(g[2], 4);
+      read!(g[1], 5);
+      read!(b[3], 1);
+      read!(g[3], 4);
+      read!(b[1], 5);
+      read!(b[3], 1, 1);
+      read!(b[2], 4);
+      read!(r[2], 5);
+      read!(b[3], 1, 2);
+      read!(r[3], 5);
+      read!(b[3], 1, 3);
+      Bc6hMode {
+        endpoint_bits: 10,
+        delta_bits: [5, 5, 5],
+        transformed: true,
+      }
+    }
+    0b01 => {
+      read!(g[2], 1, 5);
+      read!(g[3], 1, 4);
+      read!(g[3], 1, 5);
+      read!(r[0], 7);
+      read!(b[3], 1);
+      read!(b[3], 1, 1);
+      read!(b[2], 1, 4);
+      read!(g[0], 7);
+      read!(b[2], 1, 5);
+      read!(b[3], 1, 2);
+      read!(g[2], 1, 4);
+      read!(b[0], 7);
+      read!(b[3], 1, 3);
+      read!(b[3], 1, 5);
+      read!(b[3], 1, 4);
+      read!(r[1], 6);
+      read!(g[2], 4);
+      read!(g[1], 6);
+      read!(g[3], 4);
+      read!(b[1], 6);
+      read!(b[2], 4);
+      read!(r[2], 6);
+      read!(r[3], 6);
+      Bc6hMode {
+        endpoint_bits: 7,
+        delta_bits: [6, 6, 6],
+        transformed: true,
+      }
+    }
+    0b00010 => {
+      read!(r[0], 10);
+      read!(g[0], 10);
+      read!(b[0], 10);
+      read!(r[1], 5);
+      read!(r[0], 1, 10);
+      read!(g[2], 4);
+      read!(g[1], 4);
+      read!(g[0], 1, 10);
+      read!(b[3], 1);
+      read!(g[3], 4);
+      read!(b[1], 4);
+      read!(b[0], 1, 10);
+      read!(b[3], 1, 1);
+      read!(b[2], 4);
+      read!(r[2], 5);
+      read!(b[3], 1, 2);
+      read!(r[3], 5);
+      read!(b[3], 1, 3);
+      Bc6hMode {
+        endpoint_bits: 11,
+        delta_bits: [5, 4, 4],
+        transformed: true,
+      }
+    }
+    0b00110 => {
+      read!(r[0], 10);
+      read!(g[0], 10);
+      read!(b[0], 10);
+      read!(r[1], 4);
+      read!(r[0], 1, 10);
+      read!(g[3], 1, 4);
+      read!(g[2], 4);
+      read!(g[1], 5);
+      read!(g[0], 1, 10);
+      read!(g[3], 4);
+      read!(b[1], 4);
+      read!(b[0], 1, 10);
+      read!(b[3], 1, 1);
+      read!(b[2], 4);
+      read!(r[2], 4);
+      read!(b[3], 1);
+      read!(b[3], 1, 2);
+      read!(r[3], 4);
+      read!(g[2], 1, 4);
+      read!(b[3], 1, 3);
+      Bc6hMode {
+        endpoint_bits: 11,
+        delta_bits: [4, 5, 4],
+        transformed: true,
+      }
+    }
+    0b01010 => {
+      read!(r[0], 10);
+      read!(g[0], 10);
+      read!(b[0], 10);
+      read!(r[1], 4);
+      read!(r[0], 1, 10);
+      read!(b[2], 1, 4);
+      read!(g[2], 4);
+      read!(g[1], 4);
+      read!(g[0], 1, 10);
+      read!(b[3], 1);
+      read!(g[3], 4);
+      read!(b[1], 5);
+      read!(b[0], 1, 10);
+      read!(b[2], 4);
+      read!(r[2], 4);
+      read!(b[3], 1, 1);
+      read!(b[3], 1, 2);
+      read!(r[3], 4);
+      read!(b[3], 1, 4);
+      read!(b[3], 1, 3);
+      Bc6hMode {
+        endpoint_bits: 11,
+        delta_bits: [4, 4, 5],
+        transformed: true,
+      }
+    }
+    0b01110 => {
+      read!(r[0], 9);
+      read!(b[2], 1, 4);
+      read!(g[0], 9);
+      read!(g[2], 1, 4);
+      read!(b[0], 9);
+      read!(b[3], 1, 4);
+      read!(r[1], 5);
+      read!(g[3], 1, 4);
+      read!(g[2], 4);
+      read!(g[1], 5);
+      read!(b[3], 1);
+      read!(g[3], 4);
+      read!(b[1], 5);
+      read!(b[3], 1, 1);
+      read!(b[2], 4);
+      read!(r[2], 5);
+      read!(b[3], 1, 2);
+      read!(r[3], 5);
+      read!(b[3], 1, 3);
+      Bc6hMode {
+        endpoint_bits: 9,
+        delta_bits: [5, 5, 5],
+        transformed: true,
+      }
+    }
+    0b10010 => {
+      read!(r[0], 8);
+      read!(g[3], 1, 4);
+      read!(b[2], 1, 4);
+      read!(g[0], 8);
+      read!(b[3], 1, 2);
+      read!(g[2], 1, 4);
+      read!(b[0], 8);
+      read!(b[3], 1, 3);
+      read!(b[3], 1, 4);
+      read!(r[1], 6);
+      read!(g[2], 4);
+      read!(g[1], 5);
+      read!(b[3], 1);
+      read!(g[3], 4);
+      read!(b[1], 5);
+      read!(b[3], 1, 1);
+      read!(b[2], 4);
+      read!(r[2], 6);
+      read!(r[3], 6);
+      Bc6hMode {
+        endpoint_bits: 8,
+        delta_bits: [6, 5, 5],
+        transformed: true,
+      }
+    }
+    0b10110 => {
+      read!(r[0], 8);
+      read!(b[3], 1);
+      read!(b[2], 1, 4);
+      read!(g[0], 8);
+      read!(g[2], 1, 5);
+      read!(g[2], 1, 4);
+      read!(b[0], 8);
+      read!(g[3], 1, 5);
+      read!(b[3], 1, 4);
+      read!(r[1], 5);
+      read!(g[3], 1, 4);
+      read!(g[2], 4);
+      read!(g[1], 6);
+      read!(g[3], 4);
+      read!(b[1], 5);
+      read!(b[3], 1, 1);
+      read!(b[2], 4);
+      read!(r[2], 5);
+      read!(b[3], 1, 2);
+      read!(r[3], 5);
+      read!(b[3], 1, 3);
+      Bc6hMode {
+        endpoint_bits: 8,
+        delta_bits: [5, 6, 5],
+        transformed: true,
+      }
+    }
+    0b11010 => {
+      read!(r[0], 8);
+      read!(b[3], 1, 1);
+      read!(b[2], 1, 4);
+      read!(g[0], 8);
+      read!(b[2], 1, 5);
+      read!(g[2], 1, 4);
+      read!(b[0], 8);
+      read!(b[3], 1, 5);
+      read!(b[3], 1, 4);
+      read!(r[1], 5);
+      read!(g[3], 1, 4);
+      read!(g[2], 4);
+      read!(g[1], 5);
+      read!(b[3], 1);
+      read!(g[3], 4);
+      read!(b[1], 6);
+      read!(b[2], 4);
+      read!(r[2], 5);
+      read!(b[3], 1, 2);
+      read!(r[3], 5);
+      read!(b[3], 1, 3);
+      Bc6hMode {
+        endpoint_bits: 8,
+        delta_bits: [5, 5, 6],
+        transformed: true,
+      }
+    }
+    0b11110 => {
+      read!(r[0], 6);
+      read!(g[3], 1, 4);
+      read!(b[3], 1);
+      read!(b[3], 1, 1);
+      read!(b[2], 1, 4);
+      read!(g[0], 6);
+      read!(g[2], 1, 5);
+      read!(b[2], 1, 5);
+      read!(b[3], 1, 2);
+      read!(g[2], 1, 4);
+      read!(b[0], 6);
+      read!(g[3], 1, 5);
+      read!(b[3], 1, 3);
+      read!(b[3], 1, 5);
+      read!(b[3], 1, 4);
+      read!(r[1], 6);
+      read!(g[2], 4);
+      read!(g[1], 6);
+      read!(g[3], 4);
+      read!(b[1], 6);
+      read!(b[2], 4);
+      read!(r[2], 6);
+      read!(r[3], 6);
+      Bc6hMode {
+        endpoint_bits: 6,
+        delta_bits: [6, 6, 6],
+        transformed: false,
+      }
+    }
+    0b00011 => {
+      read!(r[0], 10);
+      read!(g[0], 10);
+      read!(b[0], 10);
+      read!(r[1], 10);
+      read!(g[1], 10);
+      read!(b[1], 10);
+      Bc6hMode {
+        endpoint_bits: 10,
+        delta_bits: [10, 10, 10],
+        transformed: false,
+      }
+    }
+    0b00111 => {
+      read!(r[0], 10);
+      read!(g[0], 10);
+      read!(b[0], 10);
+      read!(r[1], 9);
+      read!(r[0], 1, 10);
+      read!(g[1], 9);
+      read!(g[0], 1, 10);
+      read!(b[1], 9);
+      read!(b[0], 1, 10);
+      Bc6hMode {
+        endpoint_bits: 11,
+        delta_bits: [9, 9, 9],
+        transformed: true,
+      }
+    }
+    // 高位以相反的顺序存储
+    0b01011 => {
+      read!(r[0], 10);
+      read!(g[0], 10);
+      read!(b[0], 10);
+      read!(r[1], 8);
+      r[0] |= (bits.read_reversed(2) as i32) << 10;
+      read!(g[1], 8);
+      g[0] |= (bits.read_reversed(2) as i32) << 10;
+      read!(b[1], 8);
+      b[0] |= (bits.read_reversed(2) as i32) << 10;
+      Bc6hMode {
+        endpoint_bits: 12,
+        delta_bits: [8, 8, 8],
+        transformed: true,
+      }
+    }
+    0b01111 => {
+      read!(r[0], 10);
+      read!(g[0], 10);
+      read!(b[0], 10);
+      read!(r[1], 4);
+      r[0] |= (bits.read_reversed(6) as i32) << 10;
+      read!(g[1], 4);
+      g[0] |= (bits.read_reversed(6) as i32) << 10;
+      read!(b[1], 4);
+      b[0] |= (bits.read_reversed(6) as i32) << 10;
+      Bc6hMode {
+        endpoint_bits: 16,
+        delta_bits: [4, 4, 4],
+        transformed: true,
+      }
+    }
+    _ => {
+      // 保留的模式解码为黑色
+      *out = [[0, 0, 0, 0x3C00]; 16];
+      return;
+    }
+  };
+
+  let two_subsets = mode_bits < 0b00011 || mode_bits & 0b11 == 0b10;
+  let (partition, index_bits, endpoint_count) = if two_subsets {
+    (bits.read(5) as usize, 3, 4)
+  } else {
+    (0, 4, 2)
+  };
+
+  // 端点先做符号扩展，以差值存储的端点再加上基准端点
+  for (channel, endpoints) in [&mut r, &mut g, &mut b].into_iter().enumerate() {
+    let endpoint_bits = mode.endpoint_bits;
+    let delta_bits = mode.delta_bits[channel];
+    if signed {
+      endpoints[0] = sign_extend(endpoints[0], endpoint_bits);
+    }
+    for i in 1..endpoint_count {
+      if signed || mode.transformed {
+        endpoints[i] = sign_extend(endpoints[i], delta_bits);
+      }
+      if mode.transformed {
+        endpoints[i] = (endpoints[i] + endpoints[0]) & ((1 << endpoint_bits) - 1);
+        if signed {
+          endpoints[i] = sign_extend(endpoints[i], endpoint_bits);
+        }
+      }
+    }
+    for endpoint in endpoints.iter_mut().take(endpoint_count) {
+      *endpoint = unquantize_bc6h(*endpoint, endpoint_bits, signed);
+    }
+  }
+
+  let weights = weights(index_bits);
+  for (pixel, out) in out.iter_mut().enumerate() {
+    let subset = if two_subsets {
+      subset(2, partition, pixel)
+    } else {
+      0
+    };
+    let anchor = is_anchor(if two_subsets { 2 } else { 1 }, partition, pixel);
+    let weight = weights[bits.read(index_bits - anchor as u32) as usize] as i32;
+    let (e0, e1) = (subset * 2, subset * 2 + 1);
+    let interpolate = |endpoints: &[i32; 4]| {
+      let value = ((64 - weight) * endpoints[e0] + weight * endpoints[e1] + 32) >> 6;
+      finish_unquantize_bc6h(value, signed)
+    };
+    *out = [interpolate(&r), interpolate(&g), interpolate(&b), 0x3C00];
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// 第 i 个像素的 3 位索引为 i % 8
+  const CHANNEL_INDICES: [u8; 6] = [0x88, 0xC6, 0xFA, 0x88, 0xC6, 0xFA];
+
+  fn channel_block(a0: u8, a1: u8) -> [u8; 8] {
+    let mut block = [a0, a1, 0, 0, 0, 0, 0, 0];
+    block[2..].copy_from_slice(&CHANNEL_INDICES);
+    block
+  }
+
+  /// 按从低到高的顺序把 (值, 位数) 写入 128 位的块
+  fn pack(fields: &[(u32, u32)]) -> [u8; 16] {
+    let mut bits = 0u128;
+    let mut pos = 0;
+    for &(value, count) in fields {
+      bits |= ((value & (u32::MAX >> (32 - count))) as u128) << pos;
+      pos += count;
+    }
+    assert!(pos <= 128);
+    bits.to_le_bytes()
+  }
+
+  #[test]
+  fn bc1_four_and_three_color_modes() {
+    let mut out = [[0; 4]; 16];
+    // 红色与蓝色端点，每行的索引依次为 0 1 2 3
+    decode_bc1(&[0x00, 0xF8, 0x1F, 0x00, 0xE4, 0xE4, 0xE4, 0xE4], &mut out);
+    let row = [
+      [255, 0, 0, 255],
+      [0, 0, 255, 255],
+      [170, 0, 85, 255],
+      [85, 0, 170, 255],
+    ];
+    assert_eq!(out.to_vec(), row.repeat(4));
+
+    // c0 <= c1 时为三色模式，第四种颜色是透明的黑色
+    decode_bc1(&[0x1F, 0x00, 0x00, 0xF8, 0xE4, 0xE4, 0xE4, 0xE4], &mut out);
+    let row = [
+      [0, 0, 255, 255],
+      [255, 0, 0, 255],
+      [127, 0, 127, 255],
+      [0, 0, 0, 0],
+    ];
+    assert_eq!(out.to_vec(), row.repeat(4));
+  }
+
+  #[test]
+  fn bc2_explicit_alpha() {
+    let mut out = [[0; 4]; 16];
+    // 第 i 个像素的透明度为 i / 15。颜色块 c0 < c1，但 BC2 总是四色模式
+    let block = [
+      0x10, 0x32, 0x54, 0x76, 0x98, 0xBA, 0xDC, 0xFE, 0x00, 0x00, 0x10, 0x84, 0xFF, 0xFF, 0xFF,
+      0xFF,
+    ];
+    decode_bc2(&block, &mut out);
+    for (i, pixel) in out.iter().enumerate() {
+      assert_eq!(*pixel, [88, 86, 88, i as u8 * 17]);
+    }
+  }
+
+  #[test]
+  fn bc3_interpolated_alpha() {
+    let mut out = [[0; 4]; 16];
+    let mut block = [0; 16];
+    block[..8].copy_from_slice(&channel_block(252, 7));
+    block[8..12].copy_from_slice(&[0xFF, 0xFF, 0x00, 0x00]);
+    decode_bc3(&block, &mut out);
+    let alpha = [252, 7, 217, 182, 147, 112, 77, 42];
+    for (i, pixel) in out.iter().enumerate() {
+      assert_eq!(*pixel, [255, 255, 255, alpha[i % 8]]);
+    }
+  }
+
+  #[test]
+  fn bc4_unorm_and_snorm() {
+    let mut out = [[0; 4]; 16];
+    // a0 <= a1 时插值 4 个值，另外两个为最小值与最大值
+    decode_bc4(&channel_block(0, 255), false, &mut out);
+    let red = [0, 255, 51, 102, 153, 204, 0, 255];
+    for (i, pixel) in out.iter().enumerate() {
+      assert_eq!(*pixel, [red[i % 8], 0, 0, 255]);
+    }
+
+    // -128 按 -127 处理，结果为补码表示
+    decode_bc4(&channel_block(0x80, 123), true, &mut out);
+    let red = [-127i8, 123, -77, -27, 23, 73, -127, 127];
+    for (i, pixel) in out.iter().enumerate() {
+      assert_eq!(*pixel, [red[i % 8] as u8, 0, 0, 127]);
+    }
+  }
+
+  #[test]
+  fn bc5_unorm_and_snorm() {
+    let mut out = [[0; 4]; 16];
+    let mut block = [0; 16];
+    block[..8].copy_from_slice(&channel_block(0, 255));
+    block[8..].copy_from_slice(&channel_block(252, 7));
+    decode_bc5(&block, false, &mut out);
+    let red = [0, 255, 51, 102, 153, 204, 0, 255];
+    let green = [252, 7, 217, 182, 147, 112, 77, 42];
+    for (i, pixel) in out.iter().enumerate() {
+      assert_eq!(*pixel, [red[i % 8], green[i % 8], 0, 255]);
+    }
+
+    block[..8].copy_from_slice(&channel_block(0x80, 123));
+    block[8..].copy_from_slice(&channel_block(126, -126i8 as u8));
+    decode_bc5(&block, true, &mut out);
+    let red = [-127i8, 123, -77, -27, 23, 73, -127, 127];
+    let green = [126i8, -126, 90, 54, 18, -18, -54, -90];
+    for (i, pixel) in out.iter().enumerate() {
+      assert_eq!(*pixel, [red[i % 8] as u8, green[i % 8] as u8, 0, 127]);
+    }
+  }
+
+  /// 模式 11：单个子集，10 位端点。第 i 个像素的索引为 i
+  fn bc6h_mode11_block(e0: u32, e1: u32) -> [u8; 16] {
+    let mut fields = vec![(0b11, 2), (0b000, 3)];
+    fields.extend([(e0, 10); 3]);
+    fields.extend([(e1, 10); 3]);
+    // 第一个像素是锚点，索引少一位
+    fields.push((0, 3));
+    fields.extend((1..16).map(|i| (i, 4)));
+    pack(&fields)
+  }
+
+  #[test]
+  fn bc6h_unsigned() {
+    let mut out = [[0; 4]; 16];
+    decode_bc6h(&bc6h_mode11_block(0, 1023), false, &mut out);
+    let expected = [
+      0x0000, 0x07C0, 0x1170, 0x1930, 0x20F0, 0x28B0, 0x3260, 0x3A20, 0x41DF, 0x499F, 0x534F,
+      0x5B0F, 0x62CF, 0x6A8F, 0x743F, 0x7BFF,
+    ];
+    for (pixel, value) in out.iter().zip(expected) {
+      assert_eq!(*pixel, [value, value, value, 0x3C00]);
+    }
+
+    // 模式 1：两个子集，端点以相对于第一个端点的 5 位差值存储。
+    // 分区 0 中右边两列属于第二个子集，索引都为 0，取各子集的第一个端点
+    let block = pack(&[
+      (0b00, 2),
+      (1, 1), // g2 的第 4 位
+      (1, 1), // b2 的第 4 位
+      (0, 1), // b3 的第 4 位
+      (100, 10),
+      (100, 10),
+      (100, 10),
+      (5, 5),  // r1
+      (0, 1),  // g3 的第 4 位
+      (12, 4), // g2 的低 4 位
+      (5, 5),  // g1
+      (0, 1),  // b3 的第 0 位
+      (0, 4),  // g3 的低 4 位
+      (5, 5),  // b1
+      (0, 1),  // b3 的第 1 位
+      (12, 4), // b2 的低 4 位
+      (28, 5), // r2，即 -4
+      (0, 1),  // b3 的第 2 位
+      (0, 5),  // r3
+      (0, 1),  // b3 的第 3 位
+      (0, 5),  // 分区
+    ]);
+    decode_bc6h(&block, false, &mut out);
+    for (i, pixel) in out.iter().enumerate() {
+      // 100 与 96 反量化后的半精度浮点数
+      let value = if i % 4 < 2 { 0x0C2B } else { 0x0BAF };
+      assert_eq!(*pixel, [value, value, value, 0x3C00], "pixel {i}");
+    }
+  }
+
+  #[test]
+  fn bc6h_signed() {
+    let mut out = [[0; 4]; 16];
+    // 10 位的 -512 与 511 分别反量化为最小值与最大值
+    decode_bc6h(&bc6h_mode11_block(0x200, 0x1FF), true, &mut out);
+    let expected = [
+      0xFBFF, 0xEC7F, 0xD91F, 0xC99F, 0xBA20, 0xAAA0, 0x9740, 0x87C0, 0x07C0, 0x1740, 0x2AA0,
+      0x3A20, 0x499F, 0x591F, 0x6C7F, 0x7BFF,
+    ];
+    for (pixel, value) in out.iter().zip(expected) {
+      assert_eq!(*pixel, [value, value, value, 0x3C00]);
+    }
+  }
+}
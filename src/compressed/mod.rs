@@ -0,0 +1,389 @@
+//! GPU 压缩纹理格式
+//!
+//! 解析 KTX2 与 DDS 容器中的 BC1-7 与 ASTC 块数据。设备支持对应的压缩格式时
+//! 直接上传，否则在 CPU 上解码为未压缩的 RGBA 数据。
+
+mod astc;
+mod bc;
+
+use std::io::Read;
+
+use color_eyre::eyre::{bail, eyre, Result};
+use wgpu::{AstcBlock, AstcChannel, TextureFormat};
+
+const KTX2_MAGIC: [u8; 12] = [
+  0xAB, b'K', b'T', b'X', b' ', b'2', b'0', 0xBB, b'\r', b'\n', 0x1A, b'\n',
+];
+const DDS_MAGIC: [u8; 4] = *b"DDS ";
+
+/// 一张二维纹理及其 mip 链
+#[derive(Debug, Clone)]
+pub struct TextureData {
+  pub format: TextureFormat,
+  pub width: u32,
+  pub height: u32,
+  /// 从第 0 级开始的每级数据，压缩格式按块行优先排列
+  pub levels: Vec<Vec<u8>>,
+}
+
+/// 根据文件头判断是否为 KTX2 或 DDS 容器
+pub fn is_container(data: &[u8]) -> bool {
+  data.starts_with(&KTX2_MAGIC) || data.starts_with(&DDS_MAGIC)
+}
+
+impl TextureData {
+  /// 解析 KTX2 或 DDS 容器
+  pub fn parse(data: &[u8]) -> Result<Self> {
+    if data.starts_with(&KTX2_MAGIC) {
+      Self::from_ktx2(data)
+    } else if data.starts_with(&DDS_MAGIC) {
+      Self::from_dds(data)
+    } else {
+      bail!("not a KTX2 or DDS file")
+    }
+  }
+
+  /// 只支持单层、单面的二维纹理，超压缩方式只支持 Zstandard
+  pub fn from_ktx2(data: &[u8]) -> Result<Self> {
+    let reader = ktx2::Reader::new(data).map_err(|e| eyre!("invalid KTX2 file: {e:?}"))?;
+    let header = reader.header();
+    if header.pixel_depth > 1 || header.layer_count > 1 || header.face_count != 1 {
+      bail!("only single 2D KTX2 textures are supported");
+    }
+    let vk_format = header
+      .format
+      .ok_or_else(|| eyre!("KTX2 files without a vkFormat (Basis Universal) are not supported"))?;
+    let format =
+      ktx2_format(vk_format).ok_or_else(|| eyre!("unsupported KTX2 format {vk_format:?}"))?;
+    let levels = reader
+      .levels()
+      .map(|level| match header.supercompression_scheme {
+        None => Ok(level.to_vec()),
+        Some(ktx2::SupercompressionScheme::Zstandard) => {
+          let mut decoded = Vec::new();
+          ruzstd::StreamingDecoder::new(level)
+            .map_err(|e| eyre!("invalid Zstandard data: {e}"))?
+            .read_to_end(&mut decoded)?;
+          Ok(decoded)
+        }
+        Some(scheme) => bail!("unsupported KTX2 supercompression {scheme:?}"),
+      })
+      .collect::<Result<_>>()?;
+    Self::new(format, header.pixel_width, header.pixel_height, levels)
+  }
+
+  /// 支持 DX10 头中的 BC1-7，以及旧式 DXT1/3/5 与 ATI1/ATI2
+  pub fn from_dds(data: &[u8]) -> Result<Self> {
+    let dds = ddsfile::Dds::read(data)?;
+    if dds.get_depth() > 1 || dds.get_num_array_layers() > 1 {
+      bail!("only single 2D DDS textures are supported");
+    }
+    let format = dds
+      .get_dxgi_format()
+      .and_then(dxgi_format)
+      .or_else(|| dds.get_d3d_format().and_then(d3d_format))
+      .ok_or_else(|| eyre!("unsupported DDS format"))?;
+    let (width, height) = (dds.get_width(), dds.get_height());
+    let mut data = dds.get_data(0)?;
+    let mut levels = Vec::new();
+    for level in 0..dds.get_num_mipmap_levels().max(1) {
+      let size = level_size(format, width, height, level);
+      if data.len() < size {
+        break;
+      }
+      let (level, rest) = data.split_at(size);
+      levels.push(level.to_vec());
+      data = rest;
+    }
+    Self::new(format, width, height, levels)
+  }
+
+  fn new(format: TextureFormat, width: u32, height: u32, levels: Vec<Vec<u8>>) -> Result<Self> {
+    if width == 0 || height == 0 || levels.is_empty() {
+      bail!("empty texture");
+    }
+    for (i, level) in levels.iter().enumerate() {
+      if level.len() < level_size(format, width, height, i as u32) {
+        bail!("mip level {i} is truncated");
+      }
+    }
+    Ok(Self {
+      format,
+      width,
+      height,
+      levels,
+    })
+  }
+
+  /// 第 level 级 mip 的尺寸
+  pub fn level_extent(&self, level: u32) -> (u32, u32) {
+    ((self.width >> level).max(1), (self.height >> level).max(1))
+  }
+
+  /// 在 CPU 上解码为未压缩的格式：sRGB 与 unorm 解码为 RGBA8，
+  /// 有符号的 BC4/BC5 解码为 Rgba8Snorm，BC6H 解码为 Rgba16Float
+  pub fn decompress(&self) -> TextureData {
+    let format = decompressed_format(self.format);
+    let levels = self
+      .levels
+      .iter()
+      .enumerate()
+      .map(|(i, data)| {
+        let (width, height) = self.level_extent(i as u32);
+        decode_level(self.format, data, width, height)
+      })
+      .collect();
+    TextureData {
+      format,
+      width: self.width,
+      height: self.height,
+      levels,
+    }
+  }
+}
+
+fn level_size(format: TextureFormat, width: u32, height: u32, level: u32) -> usize {
+  let (block_width, block_height) = format.block_dimensions();
+  let width = (width >> level).max(1);
+  let height = (height >> level).max(1);
+  let block_size = format.block_copy_size(None).unwrap_or(4);
+  (width.div_ceil(block_width) * height.div_ceil(block_height) * block_size) as usize
+}
+
+fn ktx2_format(format: ktx2::Format) -> Option<TextureFormat> {
+  use ktx2::Format as F;
+  Some(match format {
+    // BC1 不带 alpha 的变体与带 alpha 的块布局相同
+    F::BC1_RGB_UNORM_BLOCK | F::BC1_RGBA_UNORM_BLOCK => TextureFormat::Bc1RgbaUnorm,
+    F::BC1_RGB_SRGB_BLOCK | F::BC1_RGBA_SRGB_BLOCK => TextureFormat::Bc1RgbaUnormSrgb,
+    F::BC2_UNORM_BLOCK => TextureFormat::Bc2RgbaUnorm,
+    F::BC2_SRGB_BLOCK => TextureFormat::Bc2RgbaUnormSrgb,
+    F::BC3_UNORM_BLOCK => TextureFormat::Bc3RgbaUnorm,
+    F::BC3_SRGB_BLOCK => TextureFormat::Bc3RgbaUnormSrgb,
+    F::BC4_UNORM_BLOCK => TextureFormat::Bc4RUnorm,
+    F::BC4_SNORM_BLOCK => TextureFormat::Bc4RSnorm,
+    F::BC5_UNORM_BLOCK => TextureFormat::Bc5RgUnorm,
+    F::BC5_SNORM_BLOCK => TextureFormat::Bc5RgSnorm,
+    F::BC6H_UFLOAT_BLOCK => TextureFormat::Bc6hRgbUfloat,
+    F::BC6H_SFLOAT_BLOCK => TextureFormat::Bc6hRgbFloat,
+    F::BC7_UNORM_BLOCK => TextureFormat::Bc7RgbaUnorm,
+    F::BC7_SRGB_BLOCK => TextureFormat::Bc7RgbaUnormSrgb,
+    // ASTC LDR 格式按块尺寸依次排列，每种尺寸先 unorm 后 sRGB
+    _ => {
+      let index = format
+        .0
+        .get()
+        .checked_sub(F::ASTC_4x4_UNORM_BLOCK.0.get())?;
+      let block = [
+        AstcBlock::B4x4,
+        AstcBlock::B5x4,
+        AstcBlock::B5x5,
+        AstcBlock::B6x5,
+        AstcBlock::B6x6,
+        AstcBlock::B8x5,
+        AstcBlock::B8x6,
+        AstcBlock::B8x8,
+        AstcBlock::B10x5,
+        AstcBlock::B10x6,
+        AstcBlock::B10x8,
+        AstcBlock::B10x10,
+        AstcBlock::B12x10,
+        AstcBlock::B12x12,
+      ]
+      .get(index as usize / 2)?;
+      let channel = if index % 2 == 0 {
+        AstcChannel::Unorm
+      } else {
+        AstcChannel::UnormSrgb
+      };
+      TextureFormat::Astc {
+        block: *block,
+        channel,
+      }
+    }
+  })
+}
+
+fn dxgi_format(format: ddsfile::DxgiFormat) -> Option<TextureFormat> {
+  use ddsfile::DxgiFormat as F;
+  Some(match format {
+    F::BC1_Typeless | F::BC1_UNorm => TextureFormat::Bc1RgbaUnorm,
+    F::BC1_UNorm_sRGB => TextureFormat::Bc1RgbaUnormSrgb,
+    F::BC2_Typeless | F::BC2_UNorm => TextureFormat::Bc2RgbaUnorm,
+    F::BC2_UNorm_sRGB => TextureFormat::Bc2RgbaUnormSrgb,
+    F::BC3_Typeless | F::BC3_UNorm => TextureFormat::Bc3RgbaUnorm,
+    F::BC3_UNorm_sRGB => TextureFormat::Bc3RgbaUnormSrgb,
+    F::BC4_Typeless | F::BC4_UNorm => TextureFormat::Bc4RUnorm,
+    F::BC4_SNorm => TextureFormat::Bc4RSnorm,
+    F::BC5_Typeless | F::BC5_UNorm => TextureFormat::Bc5RgUnorm,
+    F::BC5_SNorm => TextureFormat::Bc5RgSnorm,
+    F::BC6H_Typeless | F::BC6H_UF16 => TextureFormat::Bc6hRgbUfloat,
+    F::BC6H_SF16 => TextureFormat::Bc6hRgbFloat,
+    F::BC7_Typeless | F::BC7_UNorm => TextureFormat::Bc7RgbaUnorm,
+    F::BC7_UNorm_sRGB => TextureFormat::Bc7RgbaUnormSrgb,
+    _ => return None,
+  })
+}
+
+fn d3d_format(format: ddsfile::D3DFormat) -> Option<TextureFormat> {
+  use ddsfile::D3DFormat as F;
+  Some(match format {
+    F::DXT1 => TextureFormat::Bc1RgbaUnorm,
+    F::DXT2 | F::DXT3 => TextureFormat::Bc2RgbaUnorm,
+    F::DXT4 | F::DXT5 => TextureFormat::Bc3RgbaUnorm,
+    _ => return None,
+  })
+}
+
+fn decompressed_format(format: TextureFormat) -> TextureFormat {
+  match format {
+    TextureFormat::Bc4RSnorm | TextureFormat::Bc5RgSnorm => TextureFormat::Rgba8Snorm,
+    TextureFormat::Bc6hRgbUfloat | TextureFormat::Bc6hRgbFloat => TextureFormat::Rgba16Float,
+    format if format.is_srgb() => TextureFormat::Rgba8UnormSrgb,
+    _ => TextureFormat::Rgba8Unorm,
+  }
+}
+
+/// 把一个块解码为按行优先排列的纹素
+fn decode_block(format: TextureFormat, block: &[u8], out: &mut [u8]) {
+  let mut texels = [[0u8; 4]; 16];
+  match format {
+    TextureFormat::Bc1RgbaUnorm | TextureFormat::Bc1RgbaUnormSrgb => {
+      bc::decode_bc1(block, &mut texels)
+    }
+    TextureFormat::Bc2RgbaUnorm | TextureFormat::Bc2RgbaUnormSrgb => {
+      bc::decode_bc2(block, &mut texels)
+    }
+    TextureFormat::Bc3RgbaUnorm | TextureFormat::Bc3RgbaUnormSrgb => {
+      bc::decode_bc3(block, &mut texels)
+    }
+    TextureFormat::Bc4RUnorm => bc::decode_bc4(block, false, &mut texels),
+    TextureFormat::Bc4RSnorm => bc::decode_bc4(block, true, &mut texels),
+    TextureFormat::Bc5RgUnorm => bc::decode_bc5(block, false, &mut texels),
+    TextureFormat::Bc5RgSnorm => bc::decode_bc5(block, true, &mut texels),
+    TextureFormat::Bc6hRgbUfloat | TextureFormat::Bc6hRgbFloat => {
+      let mut texels = [[0u16; 4]; 16];
+      let signed = format == TextureFormat::Bc6hRgbFloat;
+      bc::decode_bc6h(block, signed, &mut texels);
+      out.copy_from_slice(bytemuck::cast_slice(&texels));
+      return;
+    }
+    TextureFormat::Bc7RgbaUnorm | TextureFormat::Bc7RgbaUnormSrgb => {
+      bc::decode_bc7(block, &mut texels)
+    }
+    TextureFormat::Astc { .. } => {
+      let (width, height) = format.block_dimensions();
+      let mut texels = vec![[0u8; 4]; (width * height) as usize];
+      let srgb = format.is_srgb();
+      astc::decode_astc(block, width as usize, height as usize, srgb, &mut texels);
+      out.copy_from_slice(bytemuck::cast_slice(&texels));
+      return;
+    }
+    _ => unreachable!("{format:?} is not a supported compressed format"),
+  }
+  out.copy_from_slice(bytemuck::cast_slice(&texels));
+}
+
+fn decode_level(format: TextureFormat, data: &[u8], width: u32, height: u32) -> Vec<u8> {
+  let (block_width, block_height) = format.block_dimensions();
+  let block_size = format.block_copy_size(None).unwrap_or(16) as usize;
+  let texel_size = decompressed_format(format)
+    .block_copy_size(None)
+    .unwrap_or(4) as usize;
+  let blocks_x = width.div_ceil(block_width) as usize;
+  let blocks_y = height.div_ceil(block_height) as usize;
+  let (block_width, block_height) = (block_width as usize, block_height as usize);
+  let (width, height) = (width as usize, height as usize);
+
+  let mut out = vec![0; width * height * texel_size];
+  let mut texels = vec![0; block_width * block_height * texel_size];
+  for by in 0..blocks_y {
+    for bx in 0..blocks_x {
+      let offset = (by * blocks_x + bx) * block_size;
+      decode_block(format, &data[offset..offset + block_size], &mut texels);
+      // 裁掉超出纹理边界的部分
+      let x = bx * block_width;
+      let columns = block_width.min(width - x);
+      for row in 0..block_height.min(height - by * block_height) {
+        let y = by * block_height + row;
+        let src = row * block_width * texel_size;
+        let dst = (y * width + x) * texel_size;
+        out[dst..dst + columns * texel_size]
+          .copy_from_slice(&texels[src..src + columns * texel_size]);
+      }
+    }
+  }
+  out
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn load(name: &str) -> TextureData {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+      .join("assets")
+      .join("compressed")
+      .join(name);
+    TextureData::parse(&std::fs::read(path).unwrap()).unwrap()
+  }
+
+  /// 解码结果与原图的平均误差应在压缩误差以内
+  fn assert_close_to_reference(texture: &TextureData, max_mean_error: f64) {
+    let reference = image::open(
+      std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("assets")
+        .join("compressed")
+        .join("reference.png"),
+    )
+    .unwrap()
+    .to_rgba8();
+    let decoded = texture.decompress();
+    assert_eq!((decoded.width, decoded.height), reference.dimensions());
+    let total: u64 = decoded.levels[0]
+      .iter()
+      .zip(reference.as_raw())
+      .map(|(a, b)| a.abs_diff(*b) as u64)
+      .sum();
+    let mean = total as f64 / reference.as_raw().len() as f64;
+    assert!(mean < max_mean_error, "mean error {mean}");
+  }
+
+  #[test]
+  fn decodes_bc7_dds() {
+    let texture = load("checker_bc7.dds");
+    assert_eq!(texture.format, TextureFormat::Bc7RgbaUnormSrgb);
+    assert_eq!(texture.levels.len(), 7);
+    assert_close_to_reference(&texture, 4.0);
+    let decoded = texture.decompress();
+    assert_eq!(decoded.format, TextureFormat::Rgba8UnormSrgb);
+    assert_eq!(decoded.levels[6].len(), 4);
+  }
+
+  #[test]
+  fn decodes_astc_ktx2() {
+    let texture = load("checker_astc.ktx2");
+    assert_eq!(
+      texture.format,
+      TextureFormat::Astc {
+        block: AstcBlock::B4x4,
+        channel: AstcChannel::UnormSrgb,
+      }
+    );
+    assert_close_to_reference(&texture, 6.0);
+  }
+
+  #[test]
+  fn crops_partial_blocks() {
+    // 一个全白的 BC1 块，只取左上角 3x2 个纹素
+    let block = [0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0];
+    let level = decode_level(TextureFormat::Bc1RgbaUnorm, &block, 3, 2);
+    assert_eq!(level, vec![255; 3 * 2 * 4]);
+  }
+
+  #[test]
+  fn rejects_truncated_levels() {
+    let result = TextureData::new(TextureFormat::Bc7RgbaUnorm, 8, 8, vec![vec![0; 32]]);
+    assert!(result.is_err());
+  }
+}
@@ -5,12 +5,25 @@ use wgpu::{
   *,
 };
 
+use crate::texture::MipmapGenerator;
+
 pub struct DeviceWarp<'a> {
   pub inner: &'a wgpu::Device,
+  pub mipmaps: Option<&'a MipmapGenerator>,
 }
 impl DeviceWarp<'_> {
   pub fn wrap<'a>(inner: &'a wgpu::Device) -> DeviceWarp<'a> {
-    DeviceWarp { inner }
+    DeviceWarp {
+      inner,
+      mipmaps: None,
+    }
+  }
+  /// 加载的纹理共享 mipmaps 生成 mip
+  pub fn with_mipmaps<'a>(inner: &'a wgpu::Device, mipmaps: &'a MipmapGenerator) -> DeviceWarp<'a> {
+    DeviceWarp {
+      inner,
+      mipmaps: Some(mipmaps),
+    }
   }
 }
 impl DeviceTrait for DeviceWarp<'_> {
@@ -18,10 +31,19 @@ impl DeviceTrait for DeviceWarp<'_> {
   fn get_device(&self) -> &wgpu::Device {
     &self.inner
   }
+  #[inline(always)]
+  fn mipmap_generator(&self) -> Option<&MipmapGenerator> {
+    self.mipmaps
+  }
 }
 
 pub trait DeviceTrait {
   fn get_device(&self) -> &wgpu::Device;
+  /// 在该设备上生成 mip 时复用的生成器，为 None 时每张纹理临时创建
+  #[inline(always)]
+  fn mipmap_generator(&self) -> Option<&MipmapGenerator> {
+    None
+  }
   #[inline(always)]
   fn create_bind_group_layout<'a>(
     &self,
@@ -61,6 +83,15 @@ pub trait DeviceTrait {
     })
   }
   #[inline(always)]
+  fn create_buffer(&self, label: &str, size: BufferAddress, usage: BufferUsages) -> Buffer {
+    self.get_device().create_buffer(&BufferDescriptor {
+      label: Some(label),
+      size,
+      usage,
+      mapped_at_creation: false,
+    })
+  }
+  #[inline(always)]
   fn create_pipeline_layout<'a>(
     &self,
     label: &str,
@@ -100,4 +131,27 @@ pub trait DeviceTrait {
         multiview,
       })
   }
+  /// 只写入深度、没有片元着色器的管线，用于阴影等深度 pass
+  #[inline(always)]
+  fn create_depth_only_pipeline<'a>(
+    &self,
+    label: &str,
+    layout: Option<&'a PipelineLayout>,
+    vertex: VertexState<'a>,
+    primitive: PrimitiveState,
+    depth_stencil: DepthStencilState,
+  ) -> RenderPipeline {
+    self
+      .get_device()
+      .create_render_pipeline(&RenderPipelineDescriptor {
+        label: Some(label),
+        layout,
+        vertex,
+        primitive,
+        depth_stencil: Some(depth_stencil),
+        multisample: MultisampleState::default(),
+        fragment: None,
+        multiview: None,
+      })
+  }
 }
@@ -1,4 +1,4 @@
-use na::{Matrix4, Point3, Vector3};
+use na::{Matrix4, Point3, Vector2, Vector3, Vector4};
 use winit::keyboard::KeyCode;
 
 use crate::{input, time};
@@ -19,6 +19,8 @@ pub struct Camera {
   zfar: f32,
   // 视域(角度)
   fov: f32,
+  // 投影后在 NDC 中的亚像素偏移，用于 TAA
+  jitter: Vector2<f32>,
 }
 impl Camera {
   pub fn new(eye: Point3<f32>) -> Self {
@@ -35,6 +37,7 @@ impl Camera {
       znear: 0.1,
       zfar: 100.0,
       fov: 45.0,
+      jitter: Vector2::zeros(),
     }
   }
 
@@ -74,6 +77,26 @@ impl Camera {
     self.gs_process()
   }
 
+  // 直接设置摄像机的位置与朝向(角度)，用于脚本化的镜头
+  pub fn set_pose(&mut self, eye: Point3<f32>, yaw: f32, pitch: f32) {
+    self.eye = eye;
+    self.yaw = yaw;
+    self.pitch = pitch.clamp(-89.0, 89.0);
+    self.toward.x = self.pitch.to_radians().cos() * self.yaw.to_radians().cos();
+    self.toward.y = self.pitch.to_radians().sin();
+    self.toward.z = self.pitch.to_radians().cos() * self.yaw.to_radians().sin();
+    self.gs_process()
+  }
+
+  // 将摄像机放在 eye 处并看向 target
+  // 由于 look_at_lh 与右手系的透视投影组合使用，画面实际朝向 -toward
+  pub fn look_at(&mut self, eye: Point3<f32>, target: Point3<f32>) {
+    let dir = (eye - target).normalize();
+    let yaw = dir.z.atan2(dir.x).to_degrees();
+    let pitch = dir.y.asin().to_degrees();
+    self.set_pose(eye, yaw, pitch)
+  }
+
   // Gram-Schmidt Process, 正交化
   fn gs_process(&mut self) {
     let right = Vector3::y_axis().cross(&self.toward);
@@ -85,10 +108,39 @@ impl Camera {
     Matrix4::look_at_lh(&self.eye, &(&self.eye + &self.toward), &self.up)
   }
 
-  // 获得透视投影矩阵
+  // 获得透视投影矩阵，已经加上了 jitter
   // aspect: 宽高比
   pub fn get_proj_mat(&self, aspect: f32) -> Matrix4<f32> {
-    Matrix4::new_perspective(aspect, self.fov, self.znear, self.zfar)
+    // 在裁剪空间中平移 jitter * w，透视除法后整个画面在 NDC 中偏移 jitter
+    Matrix4::new_translation(&self.jitter.push(0.0))
+      * self.get_proj_mat_with_range(aspect, self.znear, self.zfar)
+  }
+
+  // 不带 jitter 的视图投影矩阵，用于在帧之间重投影
+  pub fn get_unjittered_vp_mat(&self, aspect: f32) -> Matrix4<f32> {
+    self.get_proj_mat_with_range(aspect, self.znear, self.zfar) * self.get_view_mat()
+  }
+
+  pub fn jitter(&self) -> Vector2<f32> {
+    self.jitter
+  }
+
+  // 设置 NDC 中的投影偏移，一个像素的宽度为 2 / width
+  pub fn set_jitter(&mut self, jitter: Vector2<f32>) {
+    self.jitter = jitter;
+  }
+
+  // 以指定的近平面与远平面获得透视投影矩阵，用于把视锥切分为多段
+  pub fn get_proj_mat_with_range(&self, aspect: f32, znear: f32, zfar: f32) -> Matrix4<f32> {
+    Matrix4::new_perspective(aspect, self.fov, znear, zfar)
+  }
+
+  pub fn znear(&self) -> f32 {
+    self.znear
+  }
+
+  pub fn zfar(&self) -> f32 {
+    self.zfar
   }
 
   pub fn get_vp_mat(&self, aspect: f32) -> Matrix4<f32> {
@@ -129,16 +181,20 @@ impl Camera {
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraUniform {
+  // 摄像机的位置，用于计算高光。使用 Vector4 以满足 uniform 的 16 字节对齐
+  view_position: Vector4<f32>,
   view_proj: Matrix4<f32>,
 }
 impl CameraUniform {
   pub fn new() -> Self {
     Self {
+      view_position: Vector4::zeros(),
       view_proj: Matrix4::identity(),
     }
   }
 
   pub fn update_view_proj(&mut self, camera: &Camera, aspect: f32) {
+    self.view_position = camera.eye.to_homogeneous();
     self.view_proj = camera.get_vp_mat(aspect)
   }
 }
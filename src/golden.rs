@@ -0,0 +1,1061 @@
+//! 基于参考图像的渲染回归测试
+//!
+//! 以无窗口模式驱动 State，按脚本设置场景与摄像机，将渲染结果与
+//! `tests/golden/` 下的参考 PNG 逐像素比较。不匹配时会在
+//! `target/golden/` 下写出实际结果与差异图。
+//!
+//! 设置环境变量 `GOLDEN_BLESS=1` 可以用当前渲染结果覆盖参考图像。
+//!
+//! 没有可用的适配器时测试失败，而不是在什么都没渲染的情况下通过。确实无法渲染的环境
+//! 可以设置 `GOLDEN_SKIP=1` 显式跳过这些测试。
+
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{bail, Result};
+use image::{Rgba, RgbaImage};
+use na::{Point3, UnitQuaternion, Vector2, Vector3};
+
+use crate::{
+  background::{BackgroundMode, SkySettings},
+  ibl,
+  instance::{self, Instance},
+  postprocess::{
+    bloom::{Bloom, BloomSettings},
+    dof::{DepthOfField, DofSettings},
+    grading::ColorGradingSettings,
+    motion_blur::{MotionBlur, MotionBlurSettings},
+    tonemap::{AutoExposure, ToneMapping, ToneMappingSettings},
+    Effect, EffectContext, FullscreenPass, Stage,
+  },
+  ssao::SsaoSettings,
+  state::{AntiAliasing, State},
+};
+
+const WIDTH: u32 = 128;
+const HEIGHT: u32 = 128;
+
+/// 允许的误差：单个通道的最大差值，以及超出该差值的像素所占的最大比例
+#[derive(Debug, Clone, Copy)]
+pub struct Tolerance {
+  pub channel: u8,
+  pub max_mismatched: f32,
+}
+impl Default for Tolerance {
+  fn default() -> Self {
+    Self {
+      channel: 3,
+      max_mismatched: 0.001,
+    }
+  }
+}
+
+/// 一个摄像机镜头：从 eye 看向 target
+pub struct Shot {
+  pub name: &'static str,
+  pub eye: Point3<f32>,
+  pub target: Point3<f32>,
+}
+
+pub struct Comparison {
+  pub mismatched: usize,
+  pub max_delta: u8,
+  pub diff: RgbaImage,
+}
+
+/// 逐像素比较两幅图像，差异图中超出容差的像素标为红色，其余为变暗的参考图
+pub fn compare(expected: &RgbaImage, actual: &RgbaImage, tolerance: Tolerance) -> Comparison {
+  let mut diff = RgbaImage::new(expected.width(), expected.height());
+  let mut mismatched = 0;
+  let mut max_delta = 0;
+  for (x, y, e) in expected.enumerate_pixels() {
+    let a = actual.get_pixel(x, y);
+    let delta = e
+      .0
+      .iter()
+      .zip(a.0.iter())
+      .map(|(e, a)| e.abs_diff(*a))
+      .max()
+      .unwrap_or(0);
+    max_delta = max_delta.max(delta);
+    let pixel = if delta > tolerance.channel {
+      mismatched += 1;
+      Rgba([255, 0, 0, 255])
+    } else {
+      let luma = (e[0] as u32 + e[1] as u32 + e[2] as u32) / 3 / 4;
+      Rgba([luma as u8, luma as u8, luma as u8, 255])
+    };
+    diff.put_pixel(x, y, pixel);
+  }
+  Comparison {
+    mismatched,
+    max_delta,
+    diff,
+  }
+}
+
+fn reference_dir() -> PathBuf {
+  Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden")
+}
+
+fn output_dir() -> PathBuf {
+  Path::new(env!("CARGO_MANIFEST_DIR"))
+    .join("target")
+    .join("golden")
+}
+
+/// 将渲染结果与名为 name 的参考图像比较
+pub fn check(name: &str, actual: &RgbaImage, tolerance: Tolerance) -> Result<()> {
+  let reference = reference_dir().join(format!("{name}.png"));
+  if std::env::var_os("GOLDEN_BLESS").is_some() {
+    std::fs::create_dir_all(reference_dir())?;
+    actual.save(&reference)?;
+    return Ok(());
+  }
+  if !reference.exists() {
+    bail!(
+      "missing reference image {}, rerun with GOLDEN_BLESS=1 to create it",
+      reference.display()
+    );
+  }
+  let expected = image::open(&reference)?.to_rgba8();
+  if expected.dimensions() != actual.dimensions() {
+    bail!(
+      "{name}: expected {:?}, rendered {:?}",
+      expected.dimensions(),
+      actual.dimensions()
+    );
+  }
+
+  let comparison = compare(&expected, actual, tolerance);
+  let total = (actual.width() * actual.height()) as f32;
+  if comparison.mismatched as f32 / total > tolerance.max_mismatched {
+    let out = output_dir();
+    std::fs::create_dir_all(&out)?;
+    actual.save(out.join(format!("{name}.actual.png")))?;
+    comparison.diff.save(out.join(format!("{name}.diff.png")))?;
+    bail!(
+      "{name}: {} pixels differ (max channel delta {}), see {}",
+      comparison.mismatched,
+      comparison.max_delta,
+      out.display()
+    );
+  }
+  Ok(())
+}
+
+/// 是否显式跳过需要适配器的测试
+pub(crate) fn skip_rendering_tests() -> bool {
+  std::env::var_os("GOLDEN_SKIP").is_some()
+}
+
+/// 创建无窗口的 State。没有可用的适配器时返回错误，设置了 `GOLDEN_SKIP` 时返回 None，
+/// 测试随之跳过
+///
+/// 光源保持静止并绘制出来，使结果与帧间隔无关
+async fn headless_state() -> Result<Option<State>> {
+  if skip_rendering_tests() {
+    eprintln!("skipping golden test, GOLDEN_SKIP is set");
+    return Ok(None);
+  }
+  let mut state = State::new_headless(WIDTH, HEIGHT)
+    .await
+    .map_err(|e| e.wrap_err("no headless adapter, set GOLDEN_SKIP=1 to skip golden tests"))?;
+  for (_, light) in state.lights_mut().iter_mut() {
+    light.orbit_speed = 0.0;
+  }
+  state.show_light = true;
+  Ok(Some(state))
+}
+
+/// 场景的内容，未指定的部分使用 State 的默认值
+#[derive(Default)]
+struct Scene<'a> {
+  /// 为 None 时使用默认的立方体模型
+  model: Option<&'a Path>,
+  /// 等距柱状投影的 HDR 环境贴图
+  environment: Option<&'a Path>,
+  /// 六张图片组成的天空盒，顺序为 +X -X +Y -Y +Z -Z
+  skybox: Option<[&'a Path; 6]>,
+  background: Option<BackgroundMode>,
+  /// MSAA 采样数
+  sample_count: Option<u32>,
+  tone_mapping: Option<ToneMappingSettings>,
+  /// 调色用的 LUT，需要同时在 effects 中启用 color_grading
+  lut: Option<&'a Path>,
+  color_grading: Option<ColorGradingSettings>,
+  /// 额外启用的后处理效果的名字
+  effects: &'a [&'a str],
+  ssao: Option<SsaoSettings>,
+  instances: Option<Vec<Instance>>,
+}
+
+/// 依次渲染每个镜头，并与 `{name}_{shot}.png` 比较，收集所有失败后再统一报告
+async fn run_scene(name: &str, scene: Scene<'_>, shots: &[Shot]) -> Result<()> {
+  let Some(mut state) = headless_state().await? else {
+    return Ok(());
+  };
+  if let Some(model) = scene.model {
+    state.load_model(model).await?;
+  }
+  if let Some(environment) = scene.environment {
+    state.load_environment(environment).await?;
+  }
+  if let Some(skybox) = scene.skybox {
+    state.load_skybox(skybox).await?;
+  }
+  if let Some(background) = scene.background {
+    state.set_background_mode(background);
+  }
+  if let Some(sample_count) = scene.sample_count {
+    state.set_sample_count(sample_count)?;
+  }
+  if let Some(tone_mapping) = scene.tone_mapping {
+    state.set_tone_mapping(tone_mapping);
+  }
+  if let Some(lut) = scene.lut {
+    state.load_lut(lut).await?;
+  }
+  if let Some(color_grading) = scene.color_grading {
+    state.set_color_grading(color_grading);
+  }
+  if let Some(ssao) = scene.ssao {
+    state.set_ssao_settings(ssao);
+  }
+  for effect in scene.effects {
+    if !state.post_process_mut().set_enabled(effect, true) {
+      bail!("unknown post-process effect {effect}");
+    }
+  }
+  if let Some(instances) = scene.instances {
+    state.set_instances(instances);
+  }
+  let mut failures = Vec::new();
+  for shot in shots {
+    state.camera_mut().look_at(shot.eye, shot.target);
+    state.update();
+    let frame = state.render_to_image()?;
+    if let Err(e) = check(
+      &format!("{name}_{}", shot.name),
+      &frame,
+      Tolerance::default(),
+    ) {
+      failures.push(e.to_string());
+    }
+  }
+  if !failures.is_empty() {
+    bail!("{}", failures.join("\n"));
+  }
+  Ok(())
+}
+
+#[tokio::test]
+async fn cube_grid() -> Result<()> {
+  run_scene(
+    "cube_grid",
+    Scene::default(),
+    &[
+      Shot {
+        name: "front",
+        eye: Point3::new(0.0, 2.0, -20.0),
+        target: Point3::origin(),
+      },
+      Shot {
+        name: "above",
+        eye: Point3::new(0.0, 30.0, -1.0),
+        target: Point3::origin(),
+      },
+      Shot {
+        name: "corner",
+        eye: Point3::new(-22.0, 10.0, -22.0),
+        target: Point3::origin(),
+      },
+    ],
+  )
+  .await
+}
+
+#[tokio::test]
+async fn single_cube() -> Result<()> {
+  run_scene(
+    "single_cube",
+    Scene {
+      instances: Some(instance::grid(1, 0.0)),
+      ..Default::default()
+    },
+    &[
+      Shot {
+        name: "front",
+        eye: Point3::new(0.0, 0.0, -5.0),
+        target: Point3::origin(),
+      },
+      Shot {
+        name: "side",
+        eye: Point3::new(5.0, 3.0, 1.0),
+        target: Point3::origin(),
+      },
+    ],
+  )
+  .await
+}
+
+#[tokio::test]
+async fn gltf_cubes() -> Result<()> {
+  run_scene(
+    "gltf_cubes",
+    Scene {
+      model: Some(Path::new("gltf/cubes.gltf")),
+      instances: Some(instance::grid(1, 0.0)),
+      ..Default::default()
+    },
+    &[
+      Shot {
+        name: "front",
+        eye: Point3::new(0.0, 1.0, -5.0),
+        target: Point3::origin(),
+      },
+      Shot {
+        name: "below",
+        eye: Point3::new(2.0, -4.0, -3.0),
+        target: Point3::origin(),
+      },
+    ],
+  )
+  .await
+}
+
+#[tokio::test]
+async fn ibl_cubes() -> Result<()> {
+  run_scene(
+    "ibl_cubes",
+    Scene {
+      model: Some(Path::new("gltf/cubes.gltf")),
+      environment: Some(Path::new("env/sky.hdr")),
+      instances: Some(instance::grid(1, 0.0)),
+      ..Default::default()
+    },
+    &[
+      Shot {
+        name: "front",
+        eye: Point3::new(0.0, 1.0, -5.0),
+        target: Point3::origin(),
+      },
+      Shot {
+        name: "below",
+        eye: Point3::new(2.0, -4.0, -3.0),
+        target: Point3::origin(),
+      },
+    ],
+  )
+  .await
+}
+
+#[tokio::test]
+async fn environment_intensity_applies_immediately() -> Result<()> {
+  let Some(mut state) = headless_state().await? else {
+    return Ok(());
+  };
+  state.load_model(Path::new("gltf/cubes.gltf")).await?;
+  state.load_environment(Path::new("env/sky.hdr")).await?;
+  state.set_instances(instance::grid(1, 0.0));
+  state
+    .camera_mut()
+    .look_at(Point3::new(0.0, 1.0, -5.0), Point3::origin());
+  state.update();
+  let lit = state.render_to_image()?;
+
+  // 不重新加载环境贴图，新的亮度也要在下一帧生效
+  let settings = ibl::EnvironmentSettings {
+    intensity: 4.0,
+    ..*state.environment_settings()
+  };
+  state.set_environment_settings(settings);
+  state.update();
+  let brighter = state.render_to_image()?;
+  assert!(compare(&lit, &brighter, Tolerance::default()).mismatched > 0);
+  Ok(())
+}
+
+#[tokio::test]
+async fn skybox_faces() -> Result<()> {
+  run_scene(
+    "skybox_faces",
+    Scene {
+      skybox: Some([
+        Path::new("skybox/px.png"),
+        Path::new("skybox/nx.png"),
+        Path::new("skybox/py.png"),
+        Path::new("skybox/ny.png"),
+        Path::new("skybox/pz.png"),
+        Path::new("skybox/nz.png"),
+      ]),
+      instances: Some(instance::grid(1, 0.0)),
+      ..Default::default()
+    },
+    &[
+      Shot {
+        name: "front",
+        eye: Point3::new(0.0, 1.0, -5.0),
+        target: Point3::origin(),
+      },
+      Shot {
+        name: "corner",
+        eye: Point3::new(3.0, -3.0, 3.0),
+        target: Point3::origin(),
+      },
+    ],
+  )
+  .await
+}
+
+#[tokio::test]
+async fn procedural_sky() -> Result<()> {
+  run_scene(
+    "procedural_sky",
+    Scene {
+      background: Some(BackgroundMode::Sky(SkySettings::default())),
+      instances: Some(instance::grid(1, 0.0)),
+      ..Default::default()
+    },
+    &[
+      Shot {
+        name: "horizon",
+        eye: Point3::new(0.0, 1.0, 5.0),
+        target: Point3::new(0.0, 1.5, -5.0),
+      },
+      Shot {
+        name: "sun",
+        eye: Point3::new(0.0, 0.0, 0.0),
+        target: Point3::new(0.3, 0.25, -0.5),
+      },
+    ],
+  )
+  .await
+}
+
+#[tokio::test]
+async fn compressed_textures() -> Result<()> {
+  run_scene(
+    "compressed_textures",
+    Scene {
+      model: Some(Path::new("compressed/cubes.obj")),
+      instances: Some(instance::grid(1, 0.0)),
+      ..Default::default()
+    },
+    &[
+      Shot {
+        name: "front",
+        eye: Point3::new(0.0, 1.5, -6.0),
+        target: Point3::origin(),
+      },
+      Shot {
+        name: "far",
+        eye: Point3::new(-6.0, 10.0, -30.0),
+        target: Point3::origin(),
+      },
+    ],
+  )
+  .await
+}
+
+#[tokio::test]
+async fn msaa_cube_grid() -> Result<()> {
+  run_scene(
+    "msaa_cube_grid",
+    Scene {
+      // WebGPU 保证所有适配器都支持 4x
+      sample_count: Some(4),
+      background: Some(BackgroundMode::Sky(SkySettings::default())),
+      ..Default::default()
+    },
+    &[Shot {
+      name: "corner",
+      eye: Point3::new(-22.0, 10.0, -22.0),
+      target: Point3::origin(),
+    }],
+  )
+  .await
+}
+
+#[tokio::test]
+async fn unsupported_sample_count_is_refused() -> Result<()> {
+  let Some(mut state) = headless_state().await? else {
+    return Ok(());
+  };
+  assert!(state.supported_sample_counts().contains(&4));
+  assert!(state.set_sample_count(3).is_err());
+  assert_eq!(state.sample_count(), 1);
+  state.set_sample_count(4)?;
+  state.set_sample_count(1)?;
+  state.render_to_image()?;
+  Ok(())
+}
+
+#[tokio::test]
+async fn fxaa_cube_grid() -> Result<()> {
+  run_scene(
+    "fxaa_cube_grid",
+    Scene {
+      effects: &["fxaa"],
+      ..Default::default()
+    },
+    &[Shot {
+      name: "corner",
+      eye: Point3::new(-22.0, 10.0, -22.0),
+      target: Point3::origin(),
+    }],
+  )
+  .await
+}
+
+#[tokio::test]
+async fn taa_converges_on_static_scene() -> Result<()> {
+  let Some(mut state) = headless_state().await? else {
+    return Ok(());
+  };
+  state.set_anti_aliasing(AntiAliasing::Taa)?;
+  state
+    .camera_mut()
+    .look_at(Point3::new(-22.0, 10.0, -22.0), Point3::origin());
+  // 两轮 jitter 序列之后历史已经收敛
+  let mut frame = None;
+  for _ in 0..16 {
+    state.update();
+    frame = Some(state.render_to_image()?);
+  }
+  check(
+    "taa_cube_grid_corner",
+    &frame.unwrap(),
+    Tolerance::default(),
+  )?;
+  // 关闭 TAA 后投影不再偏移
+  state.set_anti_aliasing(AntiAliasing::None)?;
+  state.update();
+  assert_eq!(state.camera_mut().jitter(), Vector2::zeros());
+  Ok(())
+}
+
+#[tokio::test]
+async fn anti_aliasing_modes_are_exclusive() -> Result<()> {
+  let Some(mut state) = headless_state().await? else {
+    return Ok(());
+  };
+  assert_eq!(state.anti_aliasing(), AntiAliasing::None);
+  let enabled = |state: &State| {
+    state
+      .post_process()
+      .effects()
+      .filter(|effect| matches!(effect.name(), "fxaa" | "taa") && effect.enabled())
+      .map(|effect| effect.name().to_string())
+      .collect::<Vec<_>>()
+  };
+  for mode in [
+    AntiAliasing::Fxaa,
+    AntiAliasing::Taa,
+    AntiAliasing::Msaa(4),
+    AntiAliasing::None,
+  ] {
+    state.set_anti_aliasing(mode)?;
+    assert_eq!(state.anti_aliasing(), mode);
+    assert!(enabled(&state).len() <= 1);
+    state.update();
+    state.render_to_image()?;
+  }
+  // 不支持的采样数不改变当前的方式
+  state.set_anti_aliasing(AntiAliasing::Taa)?;
+  assert!(state.set_anti_aliasing(AntiAliasing::Msaa(3)).is_err());
+  assert_eq!(state.anti_aliasing(), AntiAliasing::Taa);
+  Ok(())
+}
+
+#[tokio::test]
+async fn tone_mapping_curves() -> Result<()> {
+  // 提高一档曝光，让高光超出 [0, 1] 以区分各条曲线
+  for (name, curve) in [
+    ("none", ToneMapping::None),
+    ("reinhard", ToneMapping::Reinhard),
+    ("aces", ToneMapping::Aces),
+    ("agx", ToneMapping::AgX),
+  ] {
+    run_scene(
+      &format!("tone_mapping_{name}"),
+      Scene {
+        background: Some(BackgroundMode::Sky(SkySettings::default())),
+        tone_mapping: Some(ToneMappingSettings {
+          curve,
+          exposure: 1.0,
+          auto_exposure: None,
+        }),
+        instances: Some(instance::grid(1, 0.0)),
+        ..Default::default()
+      },
+      &[Shot {
+        name: "horizon",
+        eye: Point3::new(2.0, 1.0, 4.0),
+        target: Point3::new(0.0, 0.5, 0.0),
+      }],
+    )
+    .await?;
+  }
+  Ok(())
+}
+
+#[tokio::test]
+async fn auto_exposure_adapts_to_scene_brightness() -> Result<()> {
+  let Some(mut state) = headless_state().await? else {
+    return Ok(());
+  };
+  state.set_instances(instance::grid(1, 0.0));
+  // 背向立方体，画面中只有纯色背景
+  state
+    .camera_mut()
+    .look_at(Point3::new(0.0, 0.0, 5.0), Point3::new(0.0, 0.0, 20.0));
+  let render = |state: &mut State, brightness: f64, auto_exposure| -> Result<u8> {
+    state.set_background_mode(BackgroundMode::Color(Vector3::repeat(brightness)));
+    state.set_tone_mapping(ToneMappingSettings {
+      auto_exposure,
+      ..Default::default()
+    });
+    state.update();
+    let frame = state.render_to_image()?;
+    Ok(frame.get_pixel(WIDTH / 2, HEIGHT / 2)[1])
+  };
+
+  let dim = render(&mut state, 0.02, None)?;
+  let bright = render(&mut state, 2.0, None)?;
+  assert!(
+    bright > dim + 100,
+    "manual exposure: dim {dim}, bright {bright}"
+  );
+
+  // 切换场景时重新开启自动曝光，跳过渐变，两者都应被拉到中灰附近
+  let dim = render(&mut state, 0.02, Some(AutoExposure::default()))?;
+  state.set_tone_mapping(Default::default());
+  let bright = render(&mut state, 2.0, Some(AutoExposure::default()))?;
+  assert!(
+    dim.abs_diff(bright) <= 8,
+    "auto exposure: dim {dim}, bright {bright}"
+  );
+  Ok(())
+}
+
+#[tokio::test]
+async fn post_process_vignette() -> Result<()> {
+  run_scene(
+    "post_process_vignette",
+    Scene {
+      effects: &["vignette"],
+      ..Default::default()
+    },
+    &[Shot {
+      name: "front",
+      eye: Point3::new(0.0, 2.0, -20.0),
+      target: Point3::origin(),
+    }],
+  )
+  .await
+}
+
+#[tokio::test]
+async fn color_grading_lut() -> Result<()> {
+  run_scene(
+    "color_grading_lut",
+    Scene {
+      lut: Some(Path::new("lut/teal_orange.cube")),
+      color_grading: Some(ColorGradingSettings {
+        saturation: 1.2,
+        temperature: 0.2,
+        lift: Vector3::new(0.0, 0.0, 0.02),
+        ..Default::default()
+      }),
+      effects: &["color_grading"],
+      ..Default::default()
+    },
+    &[Shot {
+      name: "corner",
+      eye: Point3::new(-22.0, 10.0, -22.0),
+      target: Point3::origin(),
+    }],
+  )
+  .await
+}
+
+#[tokio::test]
+async fn neutral_color_grading_keeps_colors() -> Result<()> {
+  let Some(mut state) = headless_state().await? else {
+    return Ok(());
+  };
+  state
+    .camera_mut()
+    .look_at(Point3::new(-22.0, 10.0, -22.0), Point3::origin());
+  state.update();
+  let plain = state.render_to_image()?;
+  // 默认的参数与 LUT 不改变颜色，只有编码转换带来的舍入误差
+  state.post_process_mut().set_enabled("color_grading", true);
+  state.update();
+  let graded = state.render_to_image()?;
+  assert_eq!(compare(&plain, &graded, Tolerance::default()).mismatched, 0);
+
+  state.set_color_grading(ColorGradingSettings {
+    saturation: 0.0,
+    ..Default::default()
+  });
+  state.update();
+  let grey = state.render_to_image()?;
+  assert!(
+    grey
+      .pixels()
+      .all(|p| p[0].abs_diff(p[1]) <= 1 && p[1].abs_diff(p[2]) <= 1)
+  );
+  Ok(())
+}
+
+#[tokio::test]
+async fn post_process_bloom() -> Result<()> {
+  run_scene(
+    "post_process_bloom",
+    Scene {
+      effects: &["bloom"],
+      ..Default::default()
+    },
+    &[Shot {
+      name: "front",
+      eye: Point3::new(0.0, 2.0, -20.0),
+      target: Point3::origin(),
+    }],
+  )
+  .await
+}
+
+#[tokio::test]
+async fn bloom_threshold_excludes_dim_pixels() -> Result<()> {
+  let Some(mut state) = headless_state().await? else {
+    return Ok(());
+  };
+  state
+    .camera_mut()
+    .look_at(Point3::new(0.0, 2.0, -20.0), Point3::origin());
+  state.update();
+  let plain = state.render_to_image()?;
+  // 阈值高于画面中所有亮度时，泛光不应改变画面
+  let bloom = state.post_process_mut().get_mut::<Bloom>().unwrap();
+  bloom.set_settings(BloomSettings {
+    threshold: 1000.0,
+    ..*bloom.settings()
+  });
+  assert!(state.post_process_mut().set_enabled("bloom", true));
+  state.update();
+  let thresholded = state.render_to_image()?;
+  let comparison = compare(&plain, &thresholded, Tolerance::default());
+  assert_eq!(comparison.mismatched, 0);
+  // 降低阈值后，明亮的光源周围出现光晕
+  let bloom = state.post_process_mut().get_mut::<Bloom>().unwrap();
+  bloom.set_settings(BloomSettings {
+    threshold: 0.5,
+    intensity: 1.0,
+    ..*bloom.settings()
+  });
+  state.update();
+  let bloomed = state.render_to_image()?;
+  assert!(compare(&plain, &bloomed, Tolerance::default()).mismatched > 0);
+  Ok(())
+}
+
+#[tokio::test]
+async fn depth_of_field_blurs_out_of_focus() -> Result<()> {
+  let Some(mut state) = headless_state().await? else {
+    return Ok(());
+  };
+  state
+    .camera_mut()
+    .look_at(Point3::new(-22.0, 10.0, -22.0), Point3::origin());
+  state.update();
+  let sharp = state.render_to_image()?;
+
+  // 对焦在网格中心，近处与远处的立方体被模糊
+  let settings = DofSettings {
+    focus_distance: 32.0,
+    aperture: 1.0,
+    max_radius: 0.05,
+    ..Default::default()
+  };
+  state.post_process_mut().set_enabled("depth_of_field", true);
+  let dof = state.post_process_mut().get_mut::<DepthOfField>().unwrap();
+  dof.set_settings(DofSettings {
+    aperture: 0.0,
+    ..settings
+  });
+  // 光圈为 0 时画面不变
+  state.update();
+  let closed = state.render_to_image()?;
+  assert_eq!(compare(&sharp, &closed, Tolerance::default()).mismatched, 0);
+
+  let dof = state.post_process_mut().get_mut::<DepthOfField>().unwrap();
+  dof.set_settings(settings);
+  state.update();
+  let blurred = state.render_to_image()?;
+  check("depth_of_field_corner", &blurred, Tolerance::default())
+}
+
+#[tokio::test]
+async fn depth_of_field_runs_with_msaa() -> Result<()> {
+  let Some(mut state) = headless_state().await? else {
+    return Ok(());
+  };
+  state.set_sample_count(4)?;
+  state
+    .camera_mut()
+    .look_at(Point3::new(-22.0, 10.0, -22.0), Point3::origin());
+  state.update();
+  let sharp = state.render_to_image()?;
+
+  // 多重采样的深度不能被采样，景深读取预渲染的单采样深度
+  state.post_process_mut().set_enabled("depth_of_field", true);
+  let dof = state.post_process_mut().get_mut::<DepthOfField>().unwrap();
+  dof.set_settings(DofSettings {
+    focus_distance: 32.0,
+    aperture: 1.0,
+    max_radius: 0.05,
+    ..Default::default()
+  });
+  state.update();
+  let blurred = state.render_to_image()?;
+  assert!(compare(&sharp, &blurred, Tolerance::default()).mismatched > 0);
+  Ok(())
+}
+
+#[tokio::test]
+async fn motion_blur_follows_camera_motion() -> Result<()> {
+  let Some(mut state) = headless_state().await? else {
+    return Ok(());
+  };
+  state.post_process_mut().set_enabled("motion_blur", true);
+  let motion_blur = state.post_process_mut().get_mut::<MotionBlur>().unwrap();
+  motion_blur.set_settings(MotionBlurSettings {
+    shutter: 1.0,
+    ..Default::default()
+  });
+  state
+    .camera_mut()
+    .look_at(Point3::new(0.0, 2.0, -20.0), Point3::origin());
+  state.update();
+  let still = state.render_to_image()?;
+  // 摄像机静止时速度为 0，与关闭运动模糊的结果相同
+  state.update();
+  assert_eq!(
+    compare(&still, &state.render_to_image()?, Tolerance::default()).mismatched,
+    0
+  );
+  state.post_process_mut().set_enabled("motion_blur", false);
+  state.update();
+  let plain = state.render_to_image()?;
+  assert_eq!(compare(&still, &plain, Tolerance::default()).mismatched, 0);
+
+  // 水平平移摄像机
+  state.post_process_mut().set_enabled("motion_blur", true);
+  state
+    .camera_mut()
+    .look_at(Point3::new(1.0, 2.0, -20.0), Point3::new(1.0, 0.0, 0.0));
+  state.update();
+  let blurred = state.render_to_image()?;
+  check("motion_blur_pan", &blurred, Tolerance::default())
+}
+
+/// 紧挨着的立方体铺成的地面，中间叠放两个立方体，形成可被遮蔽的内角
+fn stacked_cubes() -> Vec<Instance> {
+  let mut instances = (-2..=2)
+    .flat_map(|z| (-2..=2).map(move |x| Point3::new(x as f32 * 2.0, -2.0, z as f32 * 2.0)))
+    .collect::<Vec<_>>();
+  instances.extend([Point3::origin(), Point3::new(2.0, 0.0, 0.0)]);
+  instances
+    .into_iter()
+    .map(|position| Instance {
+      position,
+      rotation: UnitQuaternion::identity(),
+    })
+    .collect()
+}
+
+#[tokio::test]
+async fn ssao_darkens_corners() -> Result<()> {
+  run_scene(
+    "ssao",
+    Scene {
+      ssao: Some(SsaoSettings {
+        enabled: true,
+        ..Default::default()
+      }),
+      instances: Some(stacked_cubes()),
+      ..Default::default()
+    },
+    &[Shot {
+      name: "corner",
+      eye: Point3::new(-5.0, 4.0, -6.0),
+      target: Point3::origin(),
+    }],
+  )
+  .await
+}
+
+#[tokio::test]
+async fn ssao_strength_scales_occlusion() -> Result<()> {
+  let Some(mut state) = headless_state().await? else {
+    return Ok(());
+  };
+  state.set_instances(stacked_cubes());
+  state
+    .camera_mut()
+    .look_at(Point3::new(-5.0, 4.0, -6.0), Point3::origin());
+  let mut render = |settings: SsaoSettings| {
+    state.set_ssao_settings(settings);
+    state.update();
+    state.render_to_image()
+  };
+  let disabled = render(SsaoSettings::default())?;
+  let enabled = SsaoSettings {
+    enabled: true,
+    ..Default::default()
+  };
+  // 强度为 0 时不产生遮蔽，与关闭时相同
+  let zero = render(SsaoSettings {
+    strength: 0.0,
+    ..enabled
+  })?;
+  let comparison = compare(&disabled, &zero, Tolerance::default());
+  assert_eq!(comparison.mismatched, 0);
+  // 遮蔽只会让画面变暗
+  let occluded = render(enabled)?;
+  let luminance = |image: &RgbaImage| {
+    image
+      .pixels()
+      .map(|p| p.0[..3].iter().map(|&c| c as u64).sum::<u64>())
+      .sum::<u64>()
+  };
+  assert!(luminance(&occluded) < luminance(&disabled));
+  Ok(())
+}
+
+/// 反相颜色，用于验证自定义效果
+struct Invert {
+  pass: FullscreenPass,
+}
+impl Effect for Invert {
+  fn name(&self) -> &str {
+    "invert"
+  }
+  fn stage(&self) -> Stage {
+    Stage::Hdr
+  }
+  fn enabled(&self) -> bool {
+    true
+  }
+  fn set_enabled(&mut self, _enabled: bool) {}
+  fn render(
+    &mut self,
+    ctx: &mut EffectContext,
+    input: &wgpu::TextureView,
+    output: &wgpu::TextureView,
+    format: wgpu::TextureFormat,
+  ) {
+    self.pass.draw(ctx, input, output, format);
+  }
+}
+
+#[tokio::test]
+async fn custom_effects_run_in_stage_order() -> Result<()> {
+  let Some(mut state) = headless_state().await? else {
+    return Ok(());
+  };
+  state.set_instances(instance::grid(1, 0.0));
+  state
+    .camera_mut()
+    .look_at(Point3::new(0.0, 0.0, -5.0), Point3::origin());
+  state.update();
+  let before = state.render_to_image()?;
+
+  let invert = Invert {
+    pass: FullscreenPass::new(
+      &state,
+      "invert",
+      "@fragment
+      fn fs_main(in: FullscreenOutput) -> @location(0) vec4<f32> {
+          let color = textureSample(t_input, s_input, in.uv);
+          return vec4<f32>(1.0 - saturate(color.rgb), color.a);
+      }",
+      None,
+      false,
+    ),
+  };
+  state.post_process_mut().add(invert);
+  let names = state
+    .post_process()
+    .effects()
+    .map(|effect| effect.name().to_string())
+    .collect::<Vec<_>>();
+  assert_eq!(
+    names,
+    [
+      "taa",
+      "depth_of_field",
+      "motion_blur",
+      "bloom",
+      "invert",
+      "tone_mapping",
+      "color_grading",
+      "fxaa",
+      "vignette"
+    ]
+  );
+  assert!(state.post_process().get::<Invert>().is_some());
+
+  // 反相发生在色调映射之前，画面的明暗关系随之反转
+  let after = state.render_to_image()?;
+  let luma = |image: &RgbaImage, x, y| image.get_pixel(x, y)[1];
+  let (center, corner) = ((WIDTH / 2, HEIGHT / 2), (2, 2));
+  assert_eq!(
+    luma(&before, center.0, center.1) < luma(&before, corner.0, corner.1),
+    luma(&after, center.0, center.1) > luma(&after, corner.0, corner.1),
+  );
+  Ok(())
+}
+
+#[tokio::test]
+async fn disabled_chain_copies_scene() -> Result<()> {
+  let Some(mut state) = headless_state().await? else {
+    return Ok(());
+  };
+  state
+    .camera_mut()
+    .look_at(Point3::new(-22.0, 10.0, -22.0), Point3::origin());
+  // 不做映射的色调映射与直接复制 HDR 纹理应当得到相同的结果
+  state.set_tone_mapping(ToneMappingSettings {
+    curve: ToneMapping::None,
+    ..Default::default()
+  });
+  state.update();
+  let mapped = state.render_to_image()?;
+  assert!(state.post_process_mut().toggle("tone_mapping"));
+  assert_eq!(
+    state.post_process_mut().is_enabled("tone_mapping"),
+    Some(false)
+  );
+  assert!(!state.post_process_mut().toggle("no_such_effect"));
+  state.update();
+  let copied = state.render_to_image()?;
+  let comparison = compare(&mapped, &copied, Tolerance::default());
+  assert_eq!(comparison.mismatched, 0);
+  Ok(())
+}
+
+#[test]
+fn compare_reports_mismatches() {
+  let expected = RgbaImage::from_pixel(4, 4, Rgba([100, 100, 100, 255]));
+  let mut actual = expected.clone();
+  actual.put_pixel(1, 2, Rgba([110, 100, 100, 255]));
+  actual.put_pixel(3, 3, Rgba([102, 100, 100, 255]));
+
+  let comparison = compare(&expected, &actual, Tolerance::default());
+  assert_eq!(comparison.mismatched, 1);
+  assert_eq!(comparison.max_delta, 10);
+  assert_eq!(*comparison.diff.get_pixel(1, 2), Rgba([255, 0, 0, 255]));
+  assert_ne!(*comparison.diff.get_pixel(3, 3), Rgba([255, 0, 0, 255]));
+}
@@ -0,0 +1,604 @@
+//! 基于图像的光照 (IBL)
+//!
+//! 从等距柱状投影的 HDR 图像出发，在 GPU 上依次生成：
+//! 环境立方体贴图 (带 mip)、漫反射辐照度贴图、按粗糙度预过滤的镜面反射贴图，
+//! 以及与环境无关的 BRDF 查找表 (split-sum 近似)。
+//! 主 pass 的 PBR 着色通过 [`Environment::bind_group`] 读取它们，
+//! 环境立方体贴图本身可以交给 [`crate::background`] 作为天空盒绘制。
+
+use color_eyre::eyre::{bail, Result};
+
+use crate::exts::state::DeviceTrait;
+
+/// 预计算使用的纹理格式，可以写入 storage texture 且可以过滤
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+const WORKGROUP_SIZE: u32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EnvironmentSettings {
+  /// 环境立方体贴图每个面的边长(像素)
+  pub resolution: u32,
+  /// 漫反射辐照度贴图每个面的边长，辐照度变化平缓，很小的尺寸就足够
+  pub irradiance_resolution: u32,
+  /// 预过滤贴图第 0 级的边长，每级 mip 对应一个粗糙度
+  pub prefiltered_resolution: u32,
+  pub prefiltered_mips: u32,
+  /// 预过滤时每个纹素的采样数
+  pub prefilter_samples: u32,
+  /// BRDF 查找表的边长
+  pub brdf_lut_resolution: u32,
+  /// 环境光照与天空盒的亮度倍率
+  pub intensity: f32,
+}
+impl EnvironmentSettings {
+  /// 检查贴图尺寸与 mip 级数能否用 width x height 的图像在 limits 下生成
+  fn validate(&self, width: u32, height: u32, limits: &wgpu::Limits) -> Result<()> {
+    let max = limits.max_texture_dimension_2d;
+    if width == 0 || height == 0 || width > max || height > max {
+      bail!("{width}x{height} environment image exceeds the {max} pixel texture limit");
+    }
+    for (name, size) in [
+      ("resolution", self.resolution),
+      ("irradiance_resolution", self.irradiance_resolution),
+      ("prefiltered_resolution", self.prefiltered_resolution),
+      ("brdf_lut_resolution", self.brdf_lut_resolution),
+    ] {
+      if size == 0 || size > max {
+        bail!("{name} {size} must be between 1 and {max}");
+      }
+    }
+    let max_mips = self.prefiltered_resolution.ilog2() + 1;
+    if self.prefiltered_mips == 0 || self.prefiltered_mips > max_mips {
+      bail!(
+        "prefiltered_mips {} must be between 1 and {max_mips} for a resolution of {}",
+        self.prefiltered_mips,
+        self.prefiltered_resolution
+      );
+    }
+    Ok(())
+  }
+}
+impl Default for EnvironmentSettings {
+  fn default() -> Self {
+    Self {
+      resolution: 512,
+      irradiance_resolution: 32,
+      prefiltered_resolution: 128,
+      prefiltered_mips: 5,
+      prefilter_samples: 256,
+      brdf_lut_resolution: 128,
+      intensity: 1.0,
+    }
+  }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct EnvironmentUniform {
+  intensity: f32,
+  max_lod: f32,
+  // 没有加载环境时为 0，PBR 着色退回到 Lights 的环境光
+  enabled: u32,
+  _padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PrefilterParams {
+  roughness: f32,
+  source_size: f32,
+  source_mips: f32,
+  sample_count: u32,
+}
+
+/// 引用全部环境贴图的 bind group，它会保持贴图存活
+struct EnvironmentMaps {
+  // 用作天空盒的环境立方体贴图
+  environment: wgpu::TextureView,
+  bind_group: wgpu::BindGroup,
+}
+
+pub struct Environment {
+  settings: EnvironmentSettings,
+  layout: wgpu::BindGroupLayout,
+  uniform: EnvironmentUniform,
+  buffer: wgpu::Buffer,
+  sampler: wgpu::Sampler,
+  maps: EnvironmentMaps,
+}
+
+impl Environment {
+  /// 创建一个空的环境：贴图都是 1x1 的占位
+  pub fn new<T: DeviceTrait>(device: &T, settings: EnvironmentSettings) -> Self {
+    let cube_entry = |binding| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::FRAGMENT,
+      ty: wgpu::BindingType::Texture {
+        multisampled: false,
+        view_dimension: wgpu::TextureViewDimension::Cube,
+        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+      },
+      count: None,
+    };
+    let layout = device.create_bind_group_layout(
+      "environment_bind_group_layout",
+      &[
+        wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+          count: None,
+        },
+        // 辐照度与预过滤立方体贴图
+        cube_entry(1),
+        cube_entry(2),
+        // BRDF 查找表
+        wgpu::BindGroupLayoutEntry {
+          binding: 3,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+          },
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 4,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+          count: None,
+        },
+      ],
+    );
+    let uniform = EnvironmentUniform {
+      intensity: settings.intensity,
+      max_lod: 0.0,
+      enabled: 0,
+      _padding: 0,
+    };
+    let buffer = device.create_buffer_init(
+      "Environment Buffer",
+      bytemuck::cast_slice(&[uniform]),
+      wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    );
+    let sampler = device
+      .get_device()
+      .create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("environment_sampler"),
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+      });
+
+    let placeholder = create_cube(device, 1, 1, "environment_placeholder");
+    let lut = create_texture(device, 1, "brdf_lut_placeholder");
+    let maps = EnvironmentMaps::new(
+      device,
+      &layout,
+      &buffer,
+      &sampler,
+      &placeholder,
+      [&placeholder, &placeholder],
+      &lut,
+    );
+
+    Self {
+      settings,
+      layout,
+      uniform,
+      buffer,
+      sampler,
+      maps,
+    }
+  }
+
+  pub fn layout(&self) -> &wgpu::BindGroupLayout {
+    &self.layout
+  }
+
+  pub fn bind_group(&self) -> &wgpu::BindGroup {
+    &self.maps.bind_group
+  }
+
+  pub fn settings(&self) -> &EnvironmentSettings {
+    &self.settings
+  }
+
+  /// 亮度倍率立即生效，贴图的尺寸在下一次 [`Environment::load_equirect`] 时生效
+  pub fn set_settings(&mut self, queue: &wgpu::Queue, settings: EnvironmentSettings) {
+    self.settings = settings;
+    self.uniform.intensity = settings.intensity;
+    queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+  }
+
+  /// 是否已加载环境贴图
+  pub fn is_loaded(&self) -> bool {
+    self.uniform.enabled != 0
+  }
+
+  /// 已加载时返回环境立方体贴图 (Rgba16Float，带 mip) 的视图
+  pub fn environment_view(&self) -> Option<&wgpu::TextureView> {
+    self.is_loaded().then_some(&self.maps.environment)
+  }
+
+  /// 从等距柱状投影的 HDR 图像生成全部环境贴图。
+  /// 图像或设置超出设备限制时返回错误，已加载的环境保持不变
+  pub fn load_equirect<T: DeviceTrait>(
+    &mut self,
+    device: &T,
+    queue: &wgpu::Queue,
+    image: &image::Rgba32FImage,
+  ) -> Result<()> {
+    let settings = self.settings;
+    settings.validate(image.width(), image.height(), &device.get_device().limits())?;
+    let environment_mips = settings.resolution.ilog2() + 1;
+    let environment = create_cube(device, settings.resolution, environment_mips, "environment");
+    let irradiance = create_cube(device, settings.irradiance_resolution, 1, "irradiance");
+    let prefiltered = create_cube(
+      device,
+      settings.prefiltered_resolution,
+      settings.prefiltered_mips,
+      "prefiltered",
+    );
+    let lut = create_texture(device, settings.brdf_lut_resolution, "brdf_lut");
+
+    let equirect = device
+      .get_device()
+      .create_texture(&wgpu::TextureDescriptor {
+        label: Some("equirect"),
+        size: wgpu::Extent3d {
+          width: image.width(),
+          height: image.height(),
+          depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba32Float,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+      });
+    queue.write_texture(
+      equirect.as_image_copy(),
+      bytemuck::cast_slice(image.as_raw()),
+      wgpu::ImageDataLayout {
+        offset: 0,
+        bytes_per_row: Some(16 * image.width()),
+        rows_per_image: Some(image.height()),
+      },
+      equirect.size(),
+    );
+
+    let shader = device.create_shader_module(wgpu::include_wgsl!("../assets/ibl.wgsl"));
+    let pipeline = |entry_point, layout| {
+      device
+        .get_device()
+        .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+          label: Some(entry_point),
+          layout,
+          module: &shader,
+          entry_point,
+        })
+    };
+    let bind_group = |pipeline: &wgpu::ComputePipeline, entries: &[wgpu::BindGroupEntry]| {
+      device.create_bind_group("ibl", &pipeline.get_bind_group_layout(0), entries)
+    };
+    let equirect_view = equirect.create_view(&Default::default());
+    let environment_view = cube_view(&environment, 0, environment_mips);
+
+    let mut encoder = device
+      .get_device()
+      .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("IBL Encoder"),
+      });
+
+    // 1. 等距柱状投影 -> 立方体贴图，每级 mip 都直接从源图生成
+    // 自动生成的布局会把纹理当作可过滤的，而 Rgba32Float 不能过滤，需要手动指定
+    let to_cube_layout = device.create_bind_group_layout(
+      "equirect_to_cube_layout",
+      &[
+        wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+          },
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 1,
+          visibility: wgpu::ShaderStages::COMPUTE,
+          ty: wgpu::BindingType::StorageTexture {
+            access: wgpu::StorageTextureAccess::WriteOnly,
+            format: FORMAT,
+            view_dimension: wgpu::TextureViewDimension::D2Array,
+          },
+          count: None,
+        },
+      ],
+    );
+    let to_cube_pipeline_layout =
+      device.create_pipeline_layout("equirect_to_cube", &[&to_cube_layout], &[]);
+    let to_cube = pipeline("equirect_to_cube", Some(&to_cube_pipeline_layout));
+    for mip in 0..environment_mips {
+      let group = bind_group(
+        &to_cube,
+        &[
+          wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(&equirect_view),
+          },
+          wgpu::BindGroupEntry {
+            binding: 1,
+            resource: wgpu::BindingResource::TextureView(&layers_view(&environment, mip)),
+          },
+        ],
+      );
+      dispatch(
+        &mut encoder,
+        &to_cube,
+        &group,
+        (settings.resolution >> mip).max(1),
+        6,
+      );
+    }
+
+    // 2. 漫反射辐照度
+    let irradiance_pipeline = pipeline("irradiance", None);
+    let group = bind_group(
+      &irradiance_pipeline,
+      &[
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: wgpu::BindingResource::TextureView(&layers_view(&irradiance, 0)),
+        },
+        wgpu::BindGroupEntry {
+          binding: 3,
+          resource: wgpu::BindingResource::TextureView(&environment_view),
+        },
+        wgpu::BindGroupEntry {
+          binding: 4,
+          resource: wgpu::BindingResource::Sampler(&self.sampler),
+        },
+      ],
+    );
+    dispatch(
+      &mut encoder,
+      &irradiance_pipeline,
+      &group,
+      settings.irradiance_resolution,
+      6,
+    );
+
+    // 3. 每级 mip 对应一个粗糙度的镜面反射预过滤
+    let prefilter = pipeline("prefilter", None);
+    let mut params_buffers = Vec::new();
+    for mip in 0..settings.prefiltered_mips {
+      let params = PrefilterParams {
+        roughness: mip as f32 / (settings.prefiltered_mips - 1).max(1) as f32,
+        source_size: settings.resolution as f32,
+        source_mips: environment_mips as f32,
+        sample_count: settings.prefilter_samples,
+      };
+      params_buffers.push(device.create_buffer_init(
+        "Prefilter Params",
+        bytemuck::cast_slice(&[params]),
+        wgpu::BufferUsages::UNIFORM,
+      ));
+    }
+    for (mip, params) in (0..).zip(&params_buffers) {
+      let group = bind_group(
+        &prefilter,
+        &[
+          wgpu::BindGroupEntry {
+            binding: 1,
+            resource: wgpu::BindingResource::TextureView(&layers_view(&prefiltered, mip)),
+          },
+          wgpu::BindGroupEntry {
+            binding: 3,
+            resource: wgpu::BindingResource::TextureView(&environment_view),
+          },
+          wgpu::BindGroupEntry {
+            binding: 4,
+            resource: wgpu::BindingResource::Sampler(&self.sampler),
+          },
+          wgpu::BindGroupEntry {
+            binding: 5,
+            resource: params.as_entire_binding(),
+          },
+        ],
+      );
+      dispatch(
+        &mut encoder,
+        &prefilter,
+        &group,
+        (settings.prefiltered_resolution >> mip).max(1),
+        6,
+      );
+    }
+
+    // 4. BRDF 查找表
+    let lut_pipeline = pipeline("brdf_lut", None);
+    let lut_view = lut.create_view(&Default::default());
+    let group = bind_group(
+      &lut_pipeline,
+      &[wgpu::BindGroupEntry {
+        binding: 6,
+        resource: wgpu::BindingResource::TextureView(&lut_view),
+      }],
+    );
+    dispatch(
+      &mut encoder,
+      &lut_pipeline,
+      &group,
+      settings.brdf_lut_resolution,
+      1,
+    );
+
+    queue.submit(std::iter::once(encoder.finish()));
+
+    self.maps = EnvironmentMaps::new(
+      device,
+      &self.layout,
+      &self.buffer,
+      &self.sampler,
+      &environment,
+      [&irradiance, &prefiltered],
+      &lut,
+    );
+    self.uniform.enabled = 1;
+    self.uniform.max_lod = (settings.prefiltered_mips - 1) as f32;
+    queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+    Ok(())
+  }
+}
+
+impl EnvironmentMaps {
+  fn new<T: DeviceTrait>(
+    device: &T,
+    layout: &wgpu::BindGroupLayout,
+    buffer: &wgpu::Buffer,
+    sampler: &wgpu::Sampler,
+    environment: &wgpu::Texture,
+    cubes: [&wgpu::Texture; 2],
+    lut: &wgpu::Texture,
+  ) -> Self {
+    let views = cubes.map(|cube| cube_view(cube, 0, cube.mip_level_count()));
+    let lut_view = lut.create_view(&Default::default());
+    let mut entries = vec![wgpu::BindGroupEntry {
+      binding: 0,
+      resource: buffer.as_entire_binding(),
+    }];
+    for (binding, view) in (1..).zip(&views) {
+      entries.push(wgpu::BindGroupEntry {
+        binding,
+        resource: wgpu::BindingResource::TextureView(view),
+      });
+    }
+    entries.push(wgpu::BindGroupEntry {
+      binding: 3,
+      resource: wgpu::BindingResource::TextureView(&lut_view),
+    });
+    entries.push(wgpu::BindGroupEntry {
+      binding: 4,
+      resource: wgpu::BindingResource::Sampler(sampler),
+    });
+    Self {
+      environment: cube_view(environment, 0, environment.mip_level_count()),
+      bind_group: device.create_bind_group("environment_bind_group", layout, &entries),
+    }
+  }
+}
+
+fn create_cube<T: DeviceTrait>(device: &T, size: u32, mips: u32, label: &str) -> wgpu::Texture {
+  device
+    .get_device()
+    .create_texture(&wgpu::TextureDescriptor {
+      label: Some(label),
+      size: wgpu::Extent3d {
+        width: size,
+        height: size,
+        depth_or_array_layers: 6,
+      },
+      mip_level_count: mips,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format: FORMAT,
+      usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+      view_formats: &[],
+    })
+}
+
+fn create_texture<T: DeviceTrait>(device: &T, size: u32, label: &str) -> wgpu::Texture {
+  device
+    .get_device()
+    .create_texture(&wgpu::TextureDescriptor {
+      label: Some(label),
+      size: wgpu::Extent3d {
+        width: size,
+        height: size,
+        depth_or_array_layers: 1,
+      },
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format: FORMAT,
+      usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+      view_formats: &[],
+    })
+}
+
+/// 用于采样的立方体视图
+fn cube_view(texture: &wgpu::Texture, base_mip: u32, mips: u32) -> wgpu::TextureView {
+  texture.create_view(&wgpu::TextureViewDescriptor {
+    dimension: Some(wgpu::TextureViewDimension::Cube),
+    base_mip_level: base_mip,
+    mip_level_count: Some(mips - base_mip),
+    ..Default::default()
+  })
+}
+
+/// 单级 mip 的六个面，作为 storage texture 写入
+fn layers_view(texture: &wgpu::Texture, mip: u32) -> wgpu::TextureView {
+  texture.create_view(&wgpu::TextureViewDescriptor {
+    dimension: Some(wgpu::TextureViewDimension::D2Array),
+    base_mip_level: mip,
+    mip_level_count: Some(1),
+    ..Default::default()
+  })
+}
+
+fn dispatch(
+  encoder: &mut wgpu::CommandEncoder,
+  pipeline: &wgpu::ComputePipeline,
+  bind_group: &wgpu::BindGroup,
+  size: u32,
+  layers: u32,
+) {
+  let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+    label: Some("IBL Pass"),
+    timestamp_writes: None,
+  });
+  pass.set_pipeline(pipeline);
+  pass.set_bind_group(0, bind_group, &[]);
+  let groups = size.div_ceil(WORKGROUP_SIZE);
+  pass.dispatch_workgroups(groups, groups, layers);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn settings_are_checked_against_limits() {
+    let limits = wgpu::Limits::downlevel_defaults();
+    let settings = EnvironmentSettings::default();
+    assert!(settings.validate(1024, 512, &limits).is_ok());
+    assert!(settings.validate(4096, 2048, &limits).is_err());
+    assert!(settings.validate(0, 512, &limits).is_err());
+    // 128 的预过滤贴图最多 8 级 mip
+    let mips = |prefiltered_mips| EnvironmentSettings {
+      prefiltered_mips,
+      ..settings
+    };
+    assert!(mips(8).validate(1024, 512, &limits).is_ok());
+    assert!(mips(9).validate(1024, 512, &limits).is_err());
+    assert!(mips(0).validate(1024, 512, &limits).is_err());
+    let empty = EnvironmentSettings {
+      irradiance_resolution: 0,
+      ..settings
+    };
+    assert!(empty.validate(1024, 512, &limits).is_err());
+  }
+}
@@ -18,6 +18,30 @@ impl Instance {
   }
 }
 
+/// 在 xz 平面上生成 per_row * per_row 个以原点为中心的实例
+pub fn grid(per_row: u32, space_between: f32) -> Vec<Instance> {
+  (0..per_row)
+    .flat_map(|z| {
+      (0..per_row).map(move |x| {
+        let x = space_between * (x as f32 - per_row as f32 / 2.0);
+        let z = space_between * (z as f32 - per_row as f32 / 2.0);
+        let position = Point3::new(x, 0.0, z);
+        let rotation = if position == Point3::origin() {
+          // 需要这行特殊处理，这样在 (0, 0, 0) 的物体不会被缩放到 0
+          // 因为错误的四元数会影响到缩放
+          UnitQuaternion::from_axis_angle(&na::Vector3::z_axis(), 0.0_f32.to_radians())
+        } else {
+          UnitQuaternion::from_axis_angle(
+            &na::Unit::new_unchecked(position.coords.normalize()),
+            45.0_f32.to_radians(),
+          )
+        };
+        Instance { position, rotation }
+      })
+    })
+    .collect()
+}
+
 impl InstanceRaw {
   const ATTRIBS: [wgpu::VertexAttribute; 4] =
     wgpu::vertex_attr_array![5 => Float32x4, 6 => Float32x4,7 => Float32x4,8 => Float32x4];
@@ -1,10 +1,409 @@
+use na::{Point3, UnitQuaternion, Vector3};
+
+use crate::{exts::state::DeviceTrait, shadow};
+
+// 与 shader 中 Light.kind 的取值一一对应
+const KIND_DIRECTIONAL: u32 = 0;
+const KIND_POINT: u32 = 1;
+const KIND_SPOT: u32 = 2;
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct LightUniform {
+pub struct LightRaw {
   position: [f32; 3],
-  // Due to uniforms requiring 16 byte (4 float) spacing, we need to use a padding field here
-  _padding: u32,
+  kind: u32,
+  direction: [f32; 3],
+  // 聚光灯内外锥角的余弦
+  inner_cos: f32,
   color: [f32; 3],
-  // Due to uniforms requiring 16 byte (4 float) spacing, we need to use a padding field here
-  _padding2: u32,
+  outer_cos: f32,
+  // 常数项、一次项、二次项衰减系数
+  attenuation: [f32; 3],
+  intensity: f32,
+  // 在阴影贴图数组中的起始层，-1 表示不投射阴影。平行光占用连续的 CASCADE_COUNT 层，
+  // 点光源则是立方体阴影贴图数组中的下标
+  shadow_index: i32,
+  _padding: [u32; 3],
+}
+
+// storage buffer 的头部，紧随其后的是 LightRaw 数组
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightsHeader {
+  ambient: [f32; 3],
+  count: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightKind {
+  /// 平行光，direction 为光线照射的方向
+  Directional { direction: Vector3<f32> },
+  /// 点光源，按 1 / (c + l * d + q * d^2) 衰减
+  Point {
+    position: Point3<f32>,
+    attenuation: Vector3<f32>,
+  },
+  /// 聚光灯，在内外锥角(角度)之间平滑过渡
+  Spot {
+    position: Point3<f32>,
+    direction: Vector3<f32>,
+    attenuation: Vector3<f32>,
+    inner_angle: f32,
+    outer_angle: f32,
+  },
+}
+
+#[derive(Debug, Clone)]
+pub struct Light {
+  pub kind: LightKind,
+  pub color: Vector3<f32>,
+  pub intensity: f32,
+  // 绕 y 轴公转的速度(角度/秒)，为 0 时光源静止
+  pub orbit_speed: f32,
+  pub cast_shadows: bool,
+}
+impl Light {
+  // 大约 100 个单位的有效照射距离
+  pub const DEFAULT_ATTENUATION: Vector3<f32> = Vector3::new(1.0, 0.045, 0.0075);
+
+  pub fn directional(direction: Vector3<f32>, color: Vector3<f32>) -> Self {
+    Self::new(LightKind::Directional {
+      direction: direction.normalize(),
+    })
+    .with_color(color)
+  }
+
+  pub fn point(position: Point3<f32>, color: Vector3<f32>) -> Self {
+    Self::new(LightKind::Point {
+      position,
+      attenuation: Self::DEFAULT_ATTENUATION,
+    })
+    .with_color(color)
+  }
+
+  pub fn spot(
+    position: Point3<f32>,
+    direction: Vector3<f32>,
+    inner_angle: f32,
+    outer_angle: f32,
+    color: Vector3<f32>,
+  ) -> Self {
+    Self::new(LightKind::Spot {
+      position,
+      direction: direction.normalize(),
+      attenuation: Self::DEFAULT_ATTENUATION,
+      inner_angle,
+      outer_angle,
+    })
+    .with_color(color)
+  }
+
+  fn new(kind: LightKind) -> Self {
+    Self {
+      kind,
+      color: Vector3::new(1.0, 1.0, 1.0),
+      intensity: 1.0,
+      orbit_speed: 0.0,
+      cast_shadows: true,
+    }
+  }
+
+  fn with_color(mut self, color: Vector3<f32>) -> Self {
+    self.color = color;
+    self
+  }
+
+  pub fn position(&self) -> Option<Point3<f32>> {
+    match self.kind {
+      LightKind::Directional { .. } => None,
+      LightKind::Point { position, .. } | LightKind::Spot { position, .. } => Some(position),
+    }
+  }
+
+  pub fn direction(&self) -> Option<Vector3<f32>> {
+    match self.kind {
+      LightKind::Point { .. } => None,
+      LightKind::Directional { direction } | LightKind::Spot { direction, .. } => Some(direction),
+    }
+  }
+
+  /// 移动光源，对平行光无效
+  pub fn set_position(&mut self, new_position: Point3<f32>) {
+    match &mut self.kind {
+      LightKind::Directional { .. } => {}
+      LightKind::Point { position, .. } | LightKind::Spot { position, .. } => {
+        *position = new_position
+      }
+    }
+  }
+
+  /// 改变光源朝向，对点光源无效
+  pub fn set_direction(&mut self, new_direction: Vector3<f32>) {
+    match &mut self.kind {
+      LightKind::Point { .. } => {}
+      LightKind::Directional { direction } | LightKind::Spot { direction, .. } => {
+        *direction = new_direction.normalize()
+      }
+    }
+  }
+
+  pub fn update(&mut self, delta: f32) {
+    if self.orbit_speed == 0.0 {
+      return;
+    }
+    let rotation =
+      UnitQuaternion::from_axis_angle(&Vector3::y_axis(), (self.orbit_speed * delta).to_radians());
+    if let Some(position) = self.position() {
+      self.set_position(rotation * position);
+    }
+    if let Some(direction) = self.direction() {
+      self.set_direction(rotation * direction);
+    }
+  }
+
+  pub fn casts_shadows(&self) -> bool {
+    self.cast_shadows
+  }
+
+  /// 在阴影贴图数组中占用的层数，点光源使用单独的立方体贴图，不占用该数组
+  pub fn shadow_layers(&self) -> usize {
+    match self.kind {
+      _ if !self.casts_shadows() => 0,
+      LightKind::Directional { .. } => shadow::CASCADE_COUNT,
+      LightKind::Spot { .. } => 1,
+      LightKind::Point { .. } => 0,
+    }
+  }
+
+  pub fn to_raw(&self, shadow_index: Option<usize>) -> LightRaw {
+    let mut raw = LightRaw {
+      position: [0.0; 3],
+      kind: KIND_DIRECTIONAL,
+      direction: [0.0, -1.0, 0.0],
+      inner_cos: 1.0,
+      color: self.color.into(),
+      outer_cos: 1.0,
+      attenuation: [1.0, 0.0, 0.0],
+      intensity: self.intensity,
+      shadow_index: shadow_index.map_or(-1, |i| i as i32),
+      _padding: [0; 3],
+    };
+    match self.kind {
+      LightKind::Directional { direction } => {
+        raw.direction = direction.into();
+      }
+      LightKind::Point {
+        position,
+        attenuation,
+      } => {
+        raw.kind = KIND_POINT;
+        raw.position = position.into();
+        raw.attenuation = attenuation.into();
+      }
+      LightKind::Spot {
+        position,
+        direction,
+        attenuation,
+        inner_angle,
+        outer_angle,
+      } => {
+        raw.kind = KIND_SPOT;
+        raw.position = position.into();
+        raw.direction = direction.into();
+        raw.attenuation = attenuation.into();
+        raw.inner_cos = inner_angle.to_radians().cos();
+        raw.outer_cos = outer_angle.to_radians().cos();
+      }
+    }
+    raw
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LightId(u32);
+
+/// 场景中的所有光源，保存在一个 storage buffer 中，容量不足时自动扩容
+pub struct Lights {
+  lights: Vec<(LightId, Light)>,
+  next_id: u32,
+  pub ambient: Vector3<f32>,
+
+  capacity: usize,
+  buffer: wgpu::Buffer,
+  layout: wgpu::BindGroupLayout,
+  bind_group: wgpu::BindGroup,
+}
+impl Lights {
+  const INITIAL_CAPACITY: usize = 16;
+
+  pub fn new<T: DeviceTrait>(device: &T) -> Self {
+    let layout = device.create_bind_group_layout(
+      "light_bind_group_layout",
+      &[wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+          ty: wgpu::BufferBindingType::Storage { read_only: true },
+          has_dynamic_offset: false,
+          min_binding_size: None,
+        },
+        count: None,
+      }],
+    );
+    let (buffer, bind_group) = Self::create_buffer(device, &layout, Self::INITIAL_CAPACITY);
+    Self {
+      lights: Vec::new(),
+      next_id: 0,
+      ambient: Vector3::new(0.1, 0.1, 0.1),
+      capacity: Self::INITIAL_CAPACITY,
+      buffer,
+      layout,
+      bind_group,
+    }
+  }
+
+  fn create_buffer<T: DeviceTrait>(
+    device: &T,
+    layout: &wgpu::BindGroupLayout,
+    capacity: usize,
+  ) -> (wgpu::Buffer, wgpu::BindGroup) {
+    let size = std::mem::size_of::<LightsHeader>() + capacity * std::mem::size_of::<LightRaw>();
+    let buffer = device.create_buffer(
+      "Light Buffer",
+      size as wgpu::BufferAddress,
+      wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+    );
+    let bind_group = device.create_bind_group(
+      "light_bind_group",
+      layout,
+      &[wgpu::BindGroupEntry {
+        binding: 0,
+        resource: buffer.as_entire_binding(),
+      }],
+    );
+    (buffer, bind_group)
+  }
+
+  pub fn add(&mut self, light: Light) -> LightId {
+    let id = LightId(self.next_id);
+    self.next_id += 1;
+    self.lights.push((id, light));
+    id
+  }
+
+  pub fn remove(&mut self, id: LightId) -> Option<Light> {
+    let index = self.lights.iter().position(|(i, _)| *i == id)?;
+    Some(self.lights.remove(index).1)
+  }
+
+  pub fn get(&self, id: LightId) -> Option<&Light> {
+    self.lights.iter().find(|(i, _)| *i == id).map(|(_, l)| l)
+  }
+
+  pub fn get_mut(&mut self, id: LightId) -> Option<&mut Light> {
+    self
+      .lights
+      .iter_mut()
+      .find(|(i, _)| *i == id)
+      .map(|(_, l)| l)
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (LightId, &Light)> {
+    self.lights.iter().map(|(i, l)| (*i, l))
+  }
+
+  pub fn iter_mut(&mut self) -> impl Iterator<Item = (LightId, &mut Light)> {
+    self.lights.iter_mut().map(|(i, l)| (*i, l))
+  }
+
+  pub fn len(&self) -> usize {
+    self.lights.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.lights.is_empty()
+  }
+
+  pub fn layout(&self) -> &wgpu::BindGroupLayout {
+    &self.layout
+  }
+
+  pub fn bind_group(&self) -> &wgpu::BindGroup {
+    &self.bind_group
+  }
+
+  /// 每个光源在阴影贴图数组中的起始层(点光源为立方体贴图的下标)，
+  /// 层数不够或点光源超过 max_point_shadows 个时，后面的光源不投射阴影
+  fn shadow_bases(&self, max_point_shadows: usize) -> Vec<Option<usize>> {
+    let mut next = 0;
+    let mut next_cube = 0;
+    self
+      .lights
+      .iter()
+      .map(|(_, l)| {
+        if !l.casts_shadows() {
+          return None;
+        }
+        if let LightKind::Point { .. } = l.kind {
+          if next_cube >= max_point_shadows {
+            return None;
+          }
+          next_cube += 1;
+          return Some(next_cube - 1);
+        }
+        let layers = l.shadow_layers();
+        if next + layers > shadow::MAX_SHADOW_LAYERS {
+          return None;
+        }
+        next += layers;
+        Some(next - layers)
+      })
+      .collect()
+  }
+
+  /// 投射阴影的光源及其起始层(点光源为立方体贴图的下标)
+  pub fn shadow_casters(&self, max_point_shadows: usize) -> impl Iterator<Item = (usize, &Light)> {
+    self
+      .shadow_bases(max_point_shadows)
+      .into_iter()
+      .zip(&self.lights)
+      .filter_map(|(base, (_, l))| Some((base?, l)))
+  }
+
+  pub fn update(&mut self, delta: f32) {
+    for (_, light) in &mut self.lights {
+      light.update(delta);
+    }
+  }
+
+  /// 将所有光源写入 storage buffer，max_point_shadows 应与 [`shadow::ShadowSettings`] 一致
+  pub fn upload<T: DeviceTrait>(
+    &mut self,
+    device: &T,
+    queue: &wgpu::Queue,
+    max_point_shadows: usize,
+  ) {
+    if self.lights.len() > self.capacity {
+      self.capacity = self.lights.len().next_power_of_two();
+      (self.buffer, self.bind_group) = Self::create_buffer(device, &self.layout, self.capacity);
+    }
+    let header = LightsHeader {
+      ambient: self.ambient.into(),
+      count: self.lights.len() as u32,
+    };
+    queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&header));
+    if !self.lights.is_empty() {
+      let raw = self
+        .shadow_bases(max_point_shadows)
+        .into_iter()
+        .zip(&self.lights)
+        .map(|(base, (_, l))| l.to_raw(base))
+        .collect::<Vec<_>>();
+      queue.write_buffer(
+        &self.buffer,
+        std::mem::size_of::<LightsHeader>() as wgpu::BufferAddress,
+        bytemuck::cast_slice(&raw),
+      );
+    }
+  }
 }
@@ -1,21 +1,34 @@
+pub mod background;
+pub mod capture;
+pub mod compressed;
 pub mod ext;
 pub mod exts;
 pub mod geom;
+pub mod ibl;
+#[cfg(test)]
+mod golden;
 pub mod input;
 pub mod instance;
-mod light;
+pub mod light;
+pub mod material;
 mod log;
 pub mod model;
+pub mod postprocess;
 pub mod res;
+pub mod shadow;
+pub mod ssao;
 pub mod state;
 pub mod texture;
 pub mod time;
 mod world;
 
-use std::sync::Arc;
+use std::{path::Path, sync::Arc};
 
+use background::BackgroundMode;
 use color_eyre::eyre::Result;
-use state::State;
+use ext::ResultExt;
+use postprocess::tonemap::ToneMapping;
+use state::{AntiAliasing, State};
 use winit::{
   event::*,
   event_loop::EventLoop,
@@ -23,6 +36,17 @@ use winit::{
   window::{CursorGrabMode, WindowBuilder},
 };
 
+// 录制模式下模拟的帧率
+const RECORD_FPS: f32 = 30.0;
+// 切换各个后处理效果的按键
+const EFFECT_KEYS: [(KeyCode, &str); 5] = [
+  (KeyCode::KeyV, "vignette"),
+  (KeyCode::KeyG, "bloom"),
+  (KeyCode::KeyF, "depth_of_field"),
+  (KeyCode::KeyN, "motion_blur"),
+  (KeyCode::KeyK, "color_grading"),
+];
+
 #[tokio::main]
 async fn main() -> Result<()> {
   #[cfg(debug_assertions)]
@@ -38,6 +62,16 @@ async fn main() -> Result<()> {
   let window = WindowBuilder::new().build(&event_loop)?;
   let window = Arc::new(window);
   let mut state = State::new(window.clone()).await?;
+  // 天空盒与基于图像的光照，加载失败时保持纯色背景
+  state
+    .load_environment(Path::new("env/sky.hdr"))
+    .await
+    .log();
+  // 调色默认关闭，按 K 切换
+  state
+    .load_lut(Path::new("lut/teal_orange.cube"))
+    .await
+    .log();
 
   let mut focus = false;
   let mut cursor_visible = true;
@@ -86,6 +120,94 @@ async fn main() -> Result<()> {
         }
         _ => {}
       }
+      if input::get_key_with_cooldown(KeyCode::F12, 0.3) {
+        state
+          .capture_mut()
+          .request_screenshot(capture::default_screenshot_path());
+      }
+      if input::get_key_with_cooldown(KeyCode::F9, 0.3) {
+        let capture = state.capture_mut();
+        if capture.is_recording() {
+          capture.stop_recording();
+        } else {
+          capture
+            .start_recording(capture::default_recording_dir(), RECORD_FPS)
+            .log();
+        }
+      }
+      if input::get_key_with_cooldown(KeyCode::KeyL, 0.3) {
+        state.show_light = !state.show_light;
+      }
+      if input::get_key_with_cooldown(KeyCode::KeyP, 0.3) {
+        state.pbr_enabled = !state.pbr_enabled;
+      }
+      if input::get_key_with_cooldown(KeyCode::KeyB, 0.3) {
+        // 纯色 -> 天空盒 -> 程序化天空 -> 纯色
+        let mode = match state.background_mode() {
+          BackgroundMode::Color(_) => BackgroundMode::Skybox,
+          BackgroundMode::Skybox => BackgroundMode::Sky(Default::default()),
+          BackgroundMode::Sky(_) => BackgroundMode::default(),
+        };
+        state.set_background_mode(mode);
+      }
+      if input::get_key_with_cooldown(KeyCode::KeyM, 0.3) {
+        // 无 -> FXAA -> TAA -> 适配器支持的各个 MSAA 采样数 -> 无
+        let mut modes = vec![AntiAliasing::None, AntiAliasing::Fxaa, AntiAliasing::Taa];
+        modes.extend(
+          state
+            .supported_sample_counts()
+            .iter()
+            .filter(|&&count| count > 1)
+            .map(|&count| AntiAliasing::Msaa(count)),
+        );
+        let next = modes
+          .iter()
+          .position(|&mode| mode == state.anti_aliasing())
+          .map_or(0, |i| (i + 1) % modes.len());
+        state.set_anti_aliasing(modes[next]).log();
+      }
+      if input::get_key_with_cooldown(KeyCode::KeyT, 0.3) {
+        // 无 -> Reinhard -> ACES -> AgX -> 无
+        let mut settings = *state.tone_mapping();
+        settings.curve = match settings.curve {
+          ToneMapping::None => ToneMapping::Reinhard,
+          ToneMapping::Reinhard => ToneMapping::Aces,
+          ToneMapping::Aces => ToneMapping::AgX,
+          ToneMapping::AgX => ToneMapping::None,
+        };
+        state.set_tone_mapping(settings);
+      }
+      for (key, effect) in EFFECT_KEYS {
+        if input::get_key_with_cooldown(key, 0.3) {
+          state.post_process_mut().toggle(effect);
+        }
+      }
+      if input::get_key_with_cooldown(KeyCode::KeyX, 0.3) {
+        let mut settings = *state.tone_mapping();
+        settings.auto_exposure = match settings.auto_exposure {
+          Some(_) => None,
+          None => Some(Default::default()),
+        };
+        state.set_tone_mapping(settings);
+      }
+      // 曝光补偿，每次半档
+      for (key, step) in [(KeyCode::Minus, -0.5), (KeyCode::Equal, 0.5)] {
+        if input::get_key_with_cooldown(key, 0.15) {
+          let mut settings = *state.tone_mapping();
+          settings.exposure += step;
+          state.set_tone_mapping(settings);
+        }
+      }
+      if input::get_key_with_cooldown(KeyCode::KeyC, 0.3) {
+        let mut settings = *state.shadow_settings();
+        settings.debug_cascades = !settings.debug_cascades;
+        state.set_shadow_settings(settings);
+      }
+      if input::get_key_with_cooldown(KeyCode::KeyO, 0.3) {
+        let mut settings = *state.ssao_settings();
+        settings.enabled = !settings.enabled;
+        state.set_ssao_settings(settings);
+      }
       if input::get_key_with_cooldown(KeyCode::ControlLeft, 0.3) {
         cursor_visible = !cursor_visible;
         window.set_cursor_visible(cursor_visible);
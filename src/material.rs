@@ -0,0 +1,425 @@
+//! 材质参数与按材质特性缓存的渲染管线
+//!
+//! 每个 [`crate::model::Material`] 携带一个 uniform 块 ([`MaterialParams`])，
+//! 并根据着色模型、透明模式、是否双面等特性选择一个 [`PipelineKey`]。
+//! [`PipelineCache`] 按 key 懒创建管线，相同 key 的材质共用同一条管线。
+
+use std::collections::HashMap;
+
+use na::{Vector3, Vector4};
+
+use crate::{
+  exts::state::DeviceTrait,
+  instance::InstanceRaw,
+  model::{self, VertexTrait},
+  texture,
+};
+
+/// 材质的透明模式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlphaMode {
+  /// 忽略 alpha，完全不透明
+  Opaque,
+  /// alpha 低于 cutoff 的片元被丢弃，其余不透明
+  Mask { cutoff: f32 },
+  /// 与背景按 alpha 混合，不写入深度，在不透明物体之后绘制
+  Blend,
+}
+
+/// 材质使用的光照模型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ShadingModel {
+  /// Blinn-Phong，使用 specular 与 shininess (shader.wgsl)
+  Phong,
+  /// 金属度-粗糙度工作流的 Cook-Torrance BRDF (pbr.wgsl)
+  Pbr,
+}
+impl ShadingModel {
+  fn source(&self) -> &'static str {
+    match self {
+      ShadingModel::Phong => include_str!("../assets/shader.wgsl"),
+      ShadingModel::Pbr => include_str!("../assets/pbr.wgsl"),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MaterialParams {
+  pub shading: ShadingModel,
+  /// 与漫反射贴图相乘的颜色(线性空间)，alpha 同时参与透明
+  pub base_color: Vector4<f32>,
+  /// 高光颜色
+  pub specular: Vector3<f32>,
+  /// Blinn-Phong 的高光指数
+  pub shininess: f32,
+  /// 与金属度-粗糙度贴图相乘，仅 PBR 使用
+  pub metallic: f32,
+  pub roughness: f32,
+  /// 环境光遮蔽贴图的强度，0 表示不使用，仅 PBR 使用
+  pub occlusion_strength: f32,
+  /// 自发光颜色，与自发光贴图相乘，不受光照影响
+  pub emissive: Vector3<f32>,
+  pub alpha_mode: AlphaMode,
+  /// 双面材质不剔除背面
+  pub double_sided: bool,
+}
+impl Default for MaterialParams {
+  fn default() -> Self {
+    Self {
+      shading: ShadingModel::Phong,
+      base_color: Vector4::new(1.0, 1.0, 1.0, 1.0),
+      specular: Vector3::new(1.0, 1.0, 1.0),
+      shininess: 32.0,
+      metallic: 1.0,
+      roughness: 1.0,
+      occlusion_strength: 1.0,
+      emissive: Vector3::zeros(),
+      alpha_mode: AlphaMode::Opaque,
+      double_sided: false,
+    }
+  }
+}
+impl MaterialParams {
+  pub fn to_uniform(&self) -> MaterialUniform {
+    MaterialUniform {
+      base_color: self.base_color.into(),
+      specular: self.specular.into(),
+      shininess: self.shininess,
+      emissive: self.emissive.into(),
+      alpha_cutoff: match self.alpha_mode {
+        AlphaMode::Mask { cutoff } => cutoff,
+        _ => 0.0,
+      },
+      metallic: self.metallic,
+      roughness: self.roughness,
+      occlusion_strength: self.occlusion_strength,
+      _padding: 0.0,
+    }
+  }
+
+  pub fn pipeline_key(&self) -> PipelineKey {
+    PipelineKey {
+      vertex_layout: VertexLayout::Model,
+      shading: self.shading,
+      blend: match self.alpha_mode {
+        AlphaMode::Blend => BlendMode::Alpha,
+        _ => BlendMode::Opaque,
+      },
+      cull_mode: if self.double_sided {
+        None
+      } else {
+        Some(wgpu::Face::Back)
+      },
+      features: ShaderFeatures {
+        alpha_mask: matches!(self.alpha_mode, AlphaMode::Mask { .. }),
+      },
+    }
+  }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialUniform {
+  base_color: [f32; 4],
+  specular: [f32; 3],
+  shininess: f32,
+  emissive: [f32; 3],
+  alpha_cutoff: f32,
+  metallic: f32,
+  roughness: f32,
+  occlusion_strength: f32,
+  _padding: f32,
+}
+
+/// 管线使用的顶点缓冲布局，第二个缓冲总是实例数据
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VertexLayout {
+  Model,
+}
+impl VertexLayout {
+  fn buffers(&self) -> [wgpu::VertexBufferLayout<'static>; 2] {
+    match self {
+      VertexLayout::Model => [model::ModelVertex::desc(), InstanceRaw::desc()],
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+  Opaque,
+  Alpha,
+}
+
+/// 在编译期决定的 shader 特性，每种组合对应一个 shader 变体
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ShaderFeatures {
+  pub alpha_mask: bool,
+}
+impl ShaderFeatures {
+  // 以常量的形式加在 shader 源码之前
+  fn defines(&self) -> String {
+    format!("const ALPHA_MASK: bool = {};\n", self.alpha_mask)
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+  pub vertex_layout: VertexLayout,
+  pub shading: ShadingModel,
+  pub blend: BlendMode,
+  pub cull_mode: Option<wgpu::Face>,
+  pub features: ShaderFeatures,
+}
+
+/// 按 [`PipelineKey`] 缓存的主渲染管线与深度预渲染管线
+///
+/// 渲染时 render pass 只能借用管线，所以需要先调用 [`PipelineCache::prepare`]
+/// 创建本帧用到的所有管线，再通过 [`PipelineCache::get`] 取用
+pub struct PipelineCache {
+  layout: wgpu::PipelineLayout,
+  // 深度预渲染只用到材质与摄像机，即主管线的 group 0 与 1
+  depth_layout: wgpu::PipelineLayout,
+  color_format: wgpu::TextureFormat,
+  sample_count: u32,
+  shaders: HashMap<(ShadingModel, ShaderFeatures), wgpu::ShaderModule>,
+  pipelines: HashMap<PipelineKey, wgpu::RenderPipeline>,
+  depth_pipelines: HashMap<PipelineKey, wgpu::RenderPipeline>,
+}
+
+impl PipelineCache {
+  /// 各着色模型共用的绑定、顶点着色器与阴影
+  const COMMON: &'static str = include_str!("../assets/common.wgsl");
+
+  pub fn new(
+    layout: wgpu::PipelineLayout,
+    depth_layout: wgpu::PipelineLayout,
+    color_format: wgpu::TextureFormat,
+  ) -> Self {
+    Self {
+      layout,
+      depth_layout,
+      color_format,
+      sample_count: 1,
+      shaders: HashMap::new(),
+      pipelines: HashMap::new(),
+      depth_pipelines: HashMap::new(),
+    }
+  }
+
+  /// 修改多重采样数，已创建的主管线会被丢弃，着色器模块保留。深度预渲染总是单采样的
+  pub fn set_sample_count(&mut self, sample_count: u32) {
+    if sample_count != self.sample_count {
+      self.sample_count = sample_count;
+      self.pipelines.clear();
+    }
+  }
+
+  /// 确保 keys 中的每条管线都已创建
+  pub fn prepare<T: DeviceTrait>(
+    &mut self,
+    device: &T,
+    keys: impl IntoIterator<Item = PipelineKey>,
+  ) {
+    for key in keys {
+      if !self.pipelines.contains_key(&key) {
+        let pipeline = self.create_pipeline(device, key);
+        self.pipelines.insert(key, pipeline);
+      }
+    }
+  }
+
+  pub fn get(&self, key: &PipelineKey) -> Option<&wgpu::RenderPipeline> {
+    self.pipelines.get(key)
+  }
+
+  /// 确保 keys 中每个不透明材质的深度预渲染管线都已创建，半透明的材质不写入深度
+  pub fn prepare_depth<T: DeviceTrait>(
+    &mut self,
+    device: &T,
+    keys: impl IntoIterator<Item = PipelineKey>,
+  ) {
+    for key in keys {
+      if key.blend == BlendMode::Opaque && !self.depth_pipelines.contains_key(&key) {
+        let pipeline = self.create_depth_pipeline(device, key);
+        self.depth_pipelines.insert(key, pipeline);
+      }
+    }
+  }
+
+  pub fn get_depth(&self, key: &PipelineKey) -> Option<&wgpu::RenderPipeline> {
+    self.depth_pipelines.get(key)
+  }
+
+  fn shader<'s, T: DeviceTrait>(
+    shaders: &'s mut HashMap<(ShadingModel, ShaderFeatures), wgpu::ShaderModule>,
+    device: &T,
+    key: PipelineKey,
+  ) -> &'s wgpu::ShaderModule {
+    shaders
+      .entry((key.shading, key.features))
+      .or_insert_with(|| {
+        let source = key.features.defines() + Self::COMMON + key.shading.source();
+        device.create_shader_module(wgpu::ShaderModuleDescriptor {
+          label: Some(&format!("shader {:?} {:?}", key.shading, key.features)),
+          source: wgpu::ShaderSource::Wgsl(source.into()),
+        })
+      })
+  }
+
+  /// 与主管线使用同一个着色器模块的 vs_main 与剔除方式，alpha 测试的材质在片元着色器中丢弃片元
+  fn create_depth_pipeline<T: DeviceTrait>(
+    &mut self,
+    device: &T,
+    key: PipelineKey,
+  ) -> wgpu::RenderPipeline {
+    let shader = Self::shader(&mut self.shaders, device, key);
+    let label = format!("Depth Pipeline {key:?}");
+    let buffers = key.vertex_layout.buffers();
+    let vertex = wgpu::VertexState {
+      module: shader,
+      entry_point: "vs_main",
+      buffers: &buffers,
+    };
+    let primitive = wgpu::PrimitiveState {
+      cull_mode: key.cull_mode,
+      ..Default::default()
+    };
+    let depth_stencil = wgpu::DepthStencilState {
+      format: texture::Texture::DEPTH_FORMAT,
+      depth_write_enabled: true,
+      depth_compare: wgpu::CompareFunction::Less,
+      stencil: wgpu::StencilState::default(),
+      bias: wgpu::DepthBiasState::default(),
+    };
+    if !key.features.alpha_mask {
+      return device.create_depth_only_pipeline(
+        &label,
+        Some(&self.depth_layout),
+        vertex,
+        primitive,
+        depth_stencil,
+      );
+    }
+    device.create_render_pipeline(
+      &label,
+      Some(&self.depth_layout),
+      vertex,
+      primitive,
+      Some(depth_stencil),
+      wgpu::MultisampleState::default(),
+      wgpu::FragmentState {
+        module: shader,
+        entry_point: "fs_alpha_mask",
+        targets: &[],
+      },
+      None,
+    )
+  }
+
+  fn create_pipeline<T: DeviceTrait>(
+    &mut self,
+    device: &T,
+    key: PipelineKey,
+  ) -> wgpu::RenderPipeline {
+    let shader = Self::shader(&mut self.shaders, device, key);
+    let (blend, depth_write_enabled) = match key.blend {
+      BlendMode::Opaque => (wgpu::BlendState::REPLACE, true),
+      // 半透明物体之间不互相遮挡
+      BlendMode::Alpha => (wgpu::BlendState::ALPHA_BLENDING, false),
+    };
+    device.create_render_pipeline(
+      &format!("Render Pipeline {key:?}"),
+      Some(&self.layout),
+      wgpu::VertexState {
+        module: shader,
+        // 指定应将着色器中的哪个函数作为 entry_point
+        entry_point: "vs_main",
+        // buffers 字段用于告知 wgpu 我们要传递给顶点着色器的顶点类型
+        buffers: &key.vertex_layout.buffers(),
+      },
+      // primitive 字段描述了应如何将我们所提供的顶点数据转为三角形
+      wgpu::PrimitiveState {
+        // PrimitiveTopology::TriangleList 表示每三个顶点将对应一个三角形
+        topology: wgpu::PrimitiveTopology::TriangleList,
+        strip_index_format: None,
+        // front_face 和 cull_mode 字段告诉 wgpu 应如何确定某个三角形是否朝前
+        // FrontFace::Ccw 表示如果顶点按逆时针方向排列，则判定三角形是朝前的
+        front_face: wgpu::FrontFace::Ccw,
+        // 不满足朝前条件的三角形会被剔除（即不被渲染），双面材质为 None
+        cull_mode: key.cull_mode,
+        // 如果将该字段设置为除了 Fill 之外的任何值，都需要 Features::NON_FILL_POLYGON_MODE
+        polygon_mode: wgpu::PolygonMode::Fill,
+        // 需要 Features::DEPTH_CLIP_ENABLE
+        unclipped_depth: false,
+        // 需要 Features::CONSERVATIVE_RASTERIZATION
+        conservative: false,
+      },
+      // 深度 / 模板缓冲区
+      Some(wgpu::DepthStencilState {
+        format: texture::Texture::DEPTH_FORMAT,
+        depth_write_enabled,
+        // 用于确定何时丢弃一个新像素。深度预渲染已经写入了同样的深度，
+        // 所以使用 LESS_EQUAL 而不是 LESS
+        depth_compare: wgpu::CompareFunction::LessEqual,
+        stencil: wgpu::StencilState::default(),
+        bias: wgpu::DepthBiasState::default(),
+      }),
+      wgpu::MultisampleState {
+        // count 决定了 pipeline 将使用多少次采样
+        count: self.sample_count,
+        // mask 指定了哪些采样应被设为活跃。目前我们将使用所有的采样
+        mask: !0,
+        // 抗锯齿
+        alpha_to_coverage_enabled: false,
+      },
+      wgpu::FragmentState {
+        module: shader,
+        // 指定应将着色器中的哪个函数作为 entry_point
+        entry_point: "fs_main",
+        // targets 字段告诉 wgpu 应该设置哪些颜色输出
+        targets: &[Some(wgpu::ColorTargetState {
+          format: self.color_format,
+          blend: Some(blend),
+          // 要求 wgpu 写入所有像素通道的颜色，即红、蓝、绿和 alpha
+          write_mask: wgpu::ColorWrites::ALL,
+        })],
+      },
+      None,
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn pipeline_keys_follow_features() {
+    let opaque = MaterialParams::default();
+    let tinted = MaterialParams {
+      base_color: Vector4::new(1.0, 0.0, 0.0, 1.0),
+      shininess: 8.0,
+      ..opaque
+    };
+    // 只改变 uniform 的材质共用管线
+    assert_eq!(opaque.pipeline_key(), tinted.pipeline_key());
+
+    let blend = MaterialParams {
+      alpha_mode: AlphaMode::Blend,
+      ..opaque
+    };
+    assert_eq!(blend.pipeline_key().blend, BlendMode::Alpha);
+    assert!(!blend.pipeline_key().features.alpha_mask);
+
+    let mask = MaterialParams {
+      alpha_mode: AlphaMode::Mask { cutoff: 0.5 },
+      double_sided: true,
+      ..opaque
+    };
+    let key = mask.pipeline_key();
+    assert_eq!(key.blend, BlendMode::Opaque);
+    assert_eq!(key.cull_mode, None);
+    assert!(key.features.alpha_mask);
+    assert_eq!(mask.to_uniform().alpha_cutoff, 0.5);
+  }
+}
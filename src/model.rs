@@ -1,10 +1,15 @@
 use std::{ops::Range, path::Path};
 
+use color_eyre::eyre::Result;
 use na::{Point2, Point3, Vector3};
 use tobj::LoadOptions;
 use wgpu::{vertex_attr_array, VertexAttribute};
 
-use crate::{exts::state::DeviceTrait, texture};
+use crate::{
+  exts::state::DeviceTrait,
+  material::{MaterialParams, PipelineKey},
+  texture,
+};
 
 pub trait VertexTrait {
   fn desc<'a>() -> wgpu::VertexBufferLayout<'a>;
@@ -16,9 +21,118 @@ pub struct ModelVertex {
   pub position: Point3<f32>,
   pub tex_coords: Point2<f32>,
   pub normal: Vector3<f32>,
+  // 切线与副切线，和法线一起构成切线空间，用于法线贴图
+  pub tangent: Vector3<f32>,
+  pub bitangent: Vector3<f32>,
 }
 impl ModelVertex {
-  const ATTRI: [VertexAttribute; 3] = vertex_attr_array![0=> Float32x3,1=> Float32x2,2=> Float32x3];
+  const ATTRI: [VertexAttribute; 5] = vertex_attr_array![
+    0 => Float32x3,
+    1 => Float32x2,
+    2 => Float32x3,
+    3 => Float32x3,
+    4 => Float32x3
+  ];
+}
+
+/// 根据三角形的位置与纹理坐标计算每个顶点的切线与副切线，
+/// 共享顶点的结果取平均
+pub fn compute_tangents(vertices: &mut [ModelVertex], indices: &[u32]) {
+  let mut triangles_included = vec![0u32; vertices.len()];
+  for v in vertices.iter_mut() {
+    v.tangent = Vector3::zeros();
+    v.bitangent = Vector3::zeros();
+  }
+
+  for c in indices.chunks_exact(3) {
+    let (i0, i1, i2) = (c[0] as usize, c[1] as usize, c[2] as usize);
+    let (v0, v1, v2) = (vertices[i0], vertices[i1], vertices[i2]);
+
+    // 三角形的边与对应的 uv 差值
+    let delta_pos1 = v1.position - v0.position;
+    let delta_pos2 = v2.position - v0.position;
+    let delta_uv1 = v1.tex_coords - v0.tex_coords;
+    let delta_uv2 = v2.tex_coords - v0.tex_coords;
+
+    // delta_pos1 = delta_uv1.x * T + delta_uv1.y * B
+    // delta_pos2 = delta_uv2.x * T + delta_uv2.y * B
+    let det = delta_uv1.x * delta_uv2.y - delta_uv1.y * delta_uv2.x;
+    if det.abs() < f32::EPSILON {
+      continue;
+    }
+    let r = 1.0 / det;
+    let tangent = (delta_pos1 * delta_uv2.y - delta_pos2 * delta_uv1.y) * r;
+    // 翻转副切线，使其与 wgpu 纹理坐标的 y 轴方向一致
+    let bitangent = (delta_pos2 * delta_uv1.x - delta_pos1 * delta_uv2.x) * -r;
+
+    for i in [i0, i1, i2] {
+      vertices[i].tangent += tangent;
+      vertices[i].bitangent += bitangent;
+      triangles_included[i] += 1;
+    }
+  }
+
+  for (v, n) in vertices.iter_mut().zip(triangles_included) {
+    if n > 0 {
+      v.tangent /= n as f32;
+      v.bitangent /= n as f32;
+    }
+  }
+}
+
+/// 为没有法线的网格计算平滑法线，按三角形面积加权
+pub fn compute_normals(vertices: &mut [ModelVertex], indices: &[u32]) {
+  for v in vertices.iter_mut() {
+    v.normal = Vector3::zeros();
+  }
+  for c in indices.chunks_exact(3) {
+    let (i0, i1, i2) = (c[0] as usize, c[1] as usize, c[2] as usize);
+    // 叉积的长度是三角形面积的两倍，直接累加即为面积加权
+    let normal = (vertices[i1].position - vertices[i0].position)
+      .cross(&(vertices[i2].position - vertices[i0].position));
+    for i in [i0, i1, i2] {
+      vertices[i].normal += normal;
+    }
+  }
+  for v in vertices.iter_mut() {
+    v.normal = v.normal.try_normalize(f32::EPSILON).unwrap_or_else(Vector3::y);
+  }
+}
+
+/// 网格没有法线时的生成方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Normals {
+  /// 相邻三角形共享顶点，法线取平均，见 [`compute_normals`]
+  #[default]
+  Smooth,
+  /// 每个三角形使用自己的顶点，法线垂直于所在的面，见 [`compute_flat_normals`]
+  Flat,
+}
+
+/// 为没有法线的网格计算面法线。共享的顶点按三角形拆开，返回新的顶点与索引
+pub fn compute_flat_normals(
+  vertices: &[ModelVertex],
+  indices: &[u32],
+) -> (Vec<ModelVertex>, Vec<u32>) {
+  let mut flat = Vec::with_capacity(indices.len());
+  for c in indices.chunks_exact(3) {
+    let [v0, v1, v2] = [c[0], c[1], c[2]].map(|i| vertices[i as usize]);
+    let normal = (v1.position - v0.position)
+      .cross(&(v2.position - v0.position))
+      .try_normalize(f32::EPSILON)
+      .unwrap_or_else(Vector3::y);
+    flat.extend([v0, v1, v2].map(|v| ModelVertex { normal, ..v }));
+  }
+  let indices = (0..flat.len() as u32).collect();
+  (flat, indices)
+}
+
+/// 按 mode 为没有法线的网格生成法线，Flat 会替换顶点与索引
+pub fn generate_normals(mode: Normals, vertices: &mut Vec<ModelVertex>, indices: &mut Vec<u32>) {
+  match mode {
+    Normals::Smooth => compute_normals(vertices, indices),
+    Normals::Flat => (*vertices, *indices) = compute_flat_normals(vertices, indices),
+  }
 }
 impl VertexTrait for ModelVertex {
   fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
@@ -35,11 +149,186 @@ pub struct Model {
   pub materials: Vec<Material>,
 }
 
+/// 材质用到的全部贴图
+pub struct MaterialTextures {
+  pub diffuse: texture::Texture,
+  pub normal: texture::Texture,
+  /// G 通道为粗糙度，B 通道为金属度，与 glTF 的约定一致
+  pub metallic_roughness: texture::Texture,
+  /// R 通道为环境光遮蔽
+  pub occlusion: texture::Texture,
+  pub emissive: texture::Texture,
+}
+impl MaterialTextures {
+  /// 只有漫反射与法线贴图的材质，其余贴图为不产生影响的白色
+  pub fn new<T: DeviceTrait>(
+    device: &T,
+    queue: &wgpu::Queue,
+    diffuse: texture::Texture,
+    normal: texture::Texture,
+  ) -> Result<Self> {
+    Ok(Self {
+      diffuse,
+      normal,
+      metallic_roughness: texture::Texture::white(device, queue, true)?,
+      occlusion: texture::Texture::white(device, queue, true)?,
+      emissive: texture::Texture::white(device, queue, false)?,
+    })
+  }
+
+  fn iter(&self) -> [&texture::Texture; 5] {
+    [
+      &self.diffuse,
+      &self.normal,
+      &self.metallic_roughness,
+      &self.occlusion,
+      &self.emissive,
+    ]
+  }
+
+  fn iter_mut(&mut self) -> [&mut texture::Texture; 5] {
+    [
+      &mut self.diffuse,
+      &mut self.normal,
+      &mut self.metallic_roughness,
+      &mut self.occlusion,
+      &mut self.emissive,
+    ]
+  }
+}
+
 pub struct Material {
   pub name: String,
-  pub diffuse_texture: texture::Texture,
+  pub textures: MaterialTextures,
+  params: MaterialParams,
+  uniform_buffer: wgpu::Buffer,
   pub bind_group: wgpu::BindGroup,
 }
+impl Material {
+  /// 材质参数 uniform 的绑定位置，其余绑定依次是
+  /// [`MaterialTextures`] 中各贴图的纹理与采样器
+  const UNIFORM_BINDING: u32 = 0;
+
+  /// 材质 bind group 的布局：参数 uniform 与五张贴图
+  pub fn create_bind_group_layout<T: DeviceTrait>(device: &T) -> wgpu::BindGroupLayout {
+    let mut entries = vec![wgpu::BindGroupLayoutEntry {
+      binding: Self::UNIFORM_BINDING,
+      visibility: wgpu::ShaderStages::FRAGMENT,
+      ty: wgpu::BindingType::Buffer {
+        ty: wgpu::BufferBindingType::Uniform,
+        has_dynamic_offset: false,
+        min_binding_size: None,
+      },
+      count: None,
+    }];
+    for i in 0..5 {
+      entries.push(wgpu::BindGroupLayoutEntry {
+        binding: 1 + i * 2,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+          multisampled: false,
+          view_dimension: wgpu::TextureViewDimension::D2,
+          sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        },
+        count: None,
+      });
+      entries.push(wgpu::BindGroupLayoutEntry {
+        binding: 2 + i * 2,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        // SamplerBindingType::Comparison 仅可供 TextureSampleType::Depth 使用
+        // 如果纹理的 sample_type 是 TextureSampleType::Float { filterable: true }
+        // 那么就应当使用 SamplerBindingType::Filtering
+        // 否则会报错
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+      });
+    }
+    device.create_bind_group_layout("texture_bind_group_layout", &entries)
+  }
+
+  pub fn new<T: DeviceTrait>(
+    device: &T,
+    name: &str,
+    textures: MaterialTextures,
+    params: MaterialParams,
+    layout: &wgpu::BindGroupLayout,
+  ) -> Self {
+    let uniform_buffer = device.create_buffer_init(
+      &format!("{name} Material Buffer"),
+      bytemuck::cast_slice(&[params.to_uniform()]),
+      wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    );
+    let bind_group = Self::create_bind_group(device, name, &textures, &uniform_buffer, layout);
+    Self {
+      name: name.to_string(),
+      textures,
+      params,
+      uniform_buffer,
+      bind_group,
+    }
+  }
+
+  fn create_bind_group<T: DeviceTrait>(
+    device: &T,
+    name: &str,
+    textures: &MaterialTextures,
+    uniform_buffer: &wgpu::Buffer,
+    layout: &wgpu::BindGroupLayout,
+  ) -> wgpu::BindGroup {
+    let mut entries = vec![wgpu::BindGroupEntry {
+      binding: Self::UNIFORM_BINDING,
+      resource: uniform_buffer.as_entire_binding(),
+    }];
+    for (i, texture) in (0..).zip(textures.iter()) {
+      entries.push(wgpu::BindGroupEntry {
+        binding: 1 + i * 2,
+        resource: wgpu::BindingResource::TextureView(&texture.view),
+      });
+      entries.push(wgpu::BindGroupEntry {
+        binding: 2 + i * 2,
+        resource: wgpu::BindingResource::Sampler(&texture.sampler),
+      });
+    }
+    device.create_bind_group(name, layout, &entries)
+  }
+
+  pub fn params(&self) -> &MaterialParams {
+    &self.params
+  }
+
+  /// 修改材质参数，透明模式或双面属性变化时会在下一帧切换到对应的管线
+  pub fn set_params(&mut self, queue: &wgpu::Queue, params: MaterialParams) {
+    self.params = params;
+    queue.write_buffer(
+      &self.uniform_buffer,
+      0,
+      bytemuck::cast_slice(&[params.to_uniform()]),
+    );
+  }
+
+  pub fn pipeline_key(&self) -> PipelineKey {
+    self.params.pipeline_key()
+  }
+
+  /// 替换所有贴图的采样器并重建 bind group
+  pub fn set_sampler<T: DeviceTrait>(
+    &mut self,
+    device: &T,
+    settings: &texture::SamplerSettings,
+    layout: &wgpu::BindGroupLayout,
+  ) {
+    for texture in self.textures.iter_mut() {
+      texture.set_sampler(device, settings);
+    }
+    self.bind_group = Self::create_bind_group(
+      device,
+      &self.name,
+      &self.textures,
+      &self.uniform_buffer,
+      layout,
+    );
+  }
+}
 
 pub struct Mesh {
   pub name: String,
@@ -56,6 +345,7 @@ pub trait DrawModel<'a> {
     mesh: &'a Mesh,
     material: &'a Material,
     camera_bind_group: &'a wgpu::BindGroup,
+    light_bind_group: &'a wgpu::BindGroup,
   );
   fn draw_mesh_instanced(
     &mut self,
@@ -63,13 +353,20 @@ pub trait DrawModel<'a> {
     material: &'a Material,
     instances: Range<u32>,
     camera_bind_group: &'a wgpu::BindGroup,
+    light_bind_group: &'a wgpu::BindGroup,
+  );
+  fn draw_model(
+    &mut self,
+    model: &'a Model,
+    camera_bind_group: &'a wgpu::BindGroup,
+    light_bind_group: &'a wgpu::BindGroup,
   );
-  fn draw_model(&mut self, model: &'a Model, camera_bind_group: &'a wgpu::BindGroup);
   fn draw_model_instanced(
     &mut self,
     model: &'a Model,
     instances: Range<u32>,
     camera_bind_group: &'a wgpu::BindGroup,
+    light_bind_group: &'a wgpu::BindGroup,
   );
 }
 
@@ -82,8 +379,9 @@ where
     mesh: &'b Mesh,
     material: &'b Material,
     camera_bind_group: &'b wgpu::BindGroup,
+    light_bind_group: &'b wgpu::BindGroup,
   ) {
-    self.draw_mesh_instanced(mesh, material, 0..1, camera_bind_group);
+    self.draw_mesh_instanced(mesh, material, 0..1, camera_bind_group, light_bind_group);
   }
 
   fn draw_mesh_instanced(
@@ -92,16 +390,23 @@ where
     material: &'b Material,
     instances: Range<u32>,
     camera_bind_group: &'b wgpu::BindGroup,
+    light_bind_group: &'b wgpu::BindGroup,
   ) {
     self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
     self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
     self.set_bind_group(0, &material.bind_group, &[]);
     self.set_bind_group(1, camera_bind_group, &[]);
+    self.set_bind_group(2, light_bind_group, &[]);
     self.draw_indexed(0..mesh.num_elements, 0, instances);
   }
 
-  fn draw_model(&mut self, model: &'b Model, camera_bind_group: &'b wgpu::BindGroup) {
-    self.draw_model_instanced(model, 0..1, camera_bind_group);
+  fn draw_model(
+    &mut self,
+    model: &'b Model,
+    camera_bind_group: &'b wgpu::BindGroup,
+    light_bind_group: &'b wgpu::BindGroup,
+  ) {
+    self.draw_model_instanced(model, 0..1, camera_bind_group, light_bind_group);
   }
 
   fn draw_model_instanced(
@@ -109,10 +414,124 @@ where
     model: &'b Model,
     instances: Range<u32>,
     camera_bind_group: &'b wgpu::BindGroup,
+    light_bind_group: &'b wgpu::BindGroup,
   ) {
     for mesh in &model.meshes {
       let material = &model.materials[mesh.material];
-      self.draw_mesh_instanced(mesh, material, instances.clone(), camera_bind_group);
+      self.draw_mesh_instanced(
+        mesh,
+        material,
+        instances.clone(),
+        camera_bind_group,
+        light_bind_group,
+      );
+    }
+  }
+}
+
+// 绘制光源本身，不需要材质。每个实例对应光源数组中的一个光源
+pub trait DrawLight<'a> {
+  fn draw_light_mesh_instanced(
+    &mut self,
+    mesh: &'a Mesh,
+    instances: Range<u32>,
+    camera_bind_group: &'a wgpu::BindGroup,
+    light_bind_group: &'a wgpu::BindGroup,
+  );
+  fn draw_light_model_instanced(
+    &mut self,
+    model: &'a Model,
+    instances: Range<u32>,
+    camera_bind_group: &'a wgpu::BindGroup,
+    light_bind_group: &'a wgpu::BindGroup,
+  );
+}
+
+impl<'a, 'b> DrawLight<'b> for wgpu::RenderPass<'a>
+where
+  'b: 'a,
+{
+  fn draw_light_mesh_instanced(
+    &mut self,
+    mesh: &'b Mesh,
+    instances: Range<u32>,
+    camera_bind_group: &'b wgpu::BindGroup,
+    light_bind_group: &'b wgpu::BindGroup,
+  ) {
+    self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+    self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+    self.set_bind_group(0, camera_bind_group, &[]);
+    self.set_bind_group(1, light_bind_group, &[]);
+    self.draw_indexed(0..mesh.num_elements, 0, instances);
+  }
+
+  fn draw_light_model_instanced(
+    &mut self,
+    model: &'b Model,
+    instances: Range<u32>,
+    camera_bind_group: &'b wgpu::BindGroup,
+    light_bind_group: &'b wgpu::BindGroup,
+  ) {
+    for mesh in &model.meshes {
+      self.draw_light_mesh_instanced(
+        mesh,
+        instances.clone(),
+        camera_bind_group,
+        light_bind_group,
+      );
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn vertex(position: [f32; 3], tex_coords: [f32; 2]) -> ModelVertex {
+    ModelVertex {
+      position: position.into(),
+      tex_coords: tex_coords.into(),
+      normal: Vector3::z(),
+      tangent: Vector3::zeros(),
+      bitangent: Vector3::zeros(),
+    }
+  }
+
+  #[test]
+  fn tangents_follow_uv_axes() {
+    // xy 平面上的四边形，u 沿 +x，v 沿 -y (wgpu 的纹理坐标原点在左上角)
+    let mut vertices = vec![
+      vertex([0.0, 0.0, 0.0], [0.0, 1.0]),
+      vertex([1.0, 0.0, 0.0], [1.0, 1.0]),
+      vertex([1.0, 1.0, 0.0], [1.0, 0.0]),
+      vertex([0.0, 1.0, 0.0], [0.0, 0.0]),
+    ];
+    compute_tangents(&mut vertices, &[0, 1, 2, 0, 2, 3]);
+    for v in &vertices {
+      assert!((v.tangent - Vector3::x()).norm() < 1e-5);
+      assert!((v.bitangent - Vector3::y()).norm() < 1e-5);
+    }
+  }
+
+  #[test]
+  fn flat_normals_split_shared_vertices() {
+    // 沿 y 轴折起的两个三角形，共享一条边
+    let vertices = vec![
+      vertex([0.0, 0.0, 0.0], [0.0, 0.0]),
+      vertex([0.0, 1.0, 0.0], [0.0, 0.0]),
+      vertex([1.0, 0.0, 0.0], [0.0, 0.0]),
+      vertex([0.0, 0.0, 1.0], [0.0, 0.0]),
+    ];
+    let indices = [0, 2, 1, 0, 1, 3];
+    let (flat, flat_indices) = compute_flat_normals(&vertices, &indices);
+    assert_eq!(flat.len(), 6);
+    assert_eq!(flat_indices, [0, 1, 2, 3, 4, 5]);
+    for v in &flat[..3] {
+      assert!((v.normal - Vector3::z()).norm() < 1e-5);
+    }
+    for v in &flat[3..] {
+      assert!((v.normal - Vector3::x()).norm() < 1e-5);
     }
+    assert_eq!(flat[4].position, vertices[1].position);
   }
 }
@@ -0,0 +1,414 @@
+//! 泛光效果
+//!
+//! 先对场景做带阈值的降采样，得到半分辨率的 mip 链，逐级降采样后再从最小的一级
+//! 开始升采样并叠加到上一级，越小的 mip 贡献越宽的光晕。最后把第 0 级加到场景上。
+
+use std::collections::HashMap;
+
+use super::{Effect, EffectContext, FrameInfo, Stage, HDR_FORMAT};
+use crate::exts::state::{DeviceTrait, DeviceWarp};
+
+/// mip 链的最大级数
+const MAX_MIPS: u32 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BloomSettings {
+  /// 光晕叠加到场景上的强度
+  pub intensity: f32,
+  /// 亮度超过阈值的部分才会产生光晕，为 0 时所有像素都会轻微扩散
+  pub threshold: f32,
+  /// 阈值附近平滑过渡的宽度
+  pub knee: f32,
+  /// 升采样滤波的半径 (纹素)，越大光晕越柔和
+  pub radius: f32,
+}
+impl Default for BloomSettings {
+  fn default() -> Self {
+    Self {
+      intensity: 0.3,
+      threshold: 1.0,
+      knee: 0.5,
+      radius: 1.0,
+    }
+  }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct BloomUniform {
+  threshold: f32,
+  knee: f32,
+  intensity: f32,
+  radius: f32,
+}
+
+pub struct Bloom {
+  settings: BloomSettings,
+  enabled: bool,
+  params: wgpu::Buffer,
+  sampler: wgpu::Sampler,
+  layout: wgpu::BindGroupLayout,
+  composite_layout: wgpu::BindGroupLayout,
+  shader: wgpu::ShaderModule,
+  composite_pipeline_layout: wgpu::PipelineLayout,
+  prefilter_pipeline: wgpu::RenderPipeline,
+  downsample_pipeline: wgpu::RenderPipeline,
+  upsample_pipeline: wgpu::RenderPipeline,
+  // 按输出格式缓存
+  composite_pipelines: HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>,
+  // 每一级 mip 的 view，以及以它为输入的 bind group
+  mip_views: Vec<wgpu::TextureView>,
+  mip_bind_groups: Vec<wgpu::BindGroup>,
+}
+
+impl Bloom {
+  /// width 与 height 为场景的尺寸
+  pub fn new<T: DeviceTrait>(device: &T, width: u32, height: u32, settings: BloomSettings) -> Self {
+    let entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::FRAGMENT,
+      ty,
+      count: None,
+    };
+    let texture = wgpu::BindingType::Texture {
+      multisampled: false,
+      view_dimension: wgpu::TextureViewDimension::D2,
+      sample_type: wgpu::TextureSampleType::Float { filterable: true },
+    };
+    let entries = [
+      entry(0, texture),
+      entry(
+        1,
+        wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+      ),
+      entry(
+        2,
+        wgpu::BindingType::Buffer {
+          ty: wgpu::BufferBindingType::Uniform,
+          has_dynamic_offset: false,
+          min_binding_size: None,
+        },
+      ),
+      // 合成时读取第 0 级 mip
+      entry(3, texture),
+    ];
+    let layout = device.create_bind_group_layout("bloom_bind_group_layout", &entries[..3]);
+    let composite_layout =
+      device.create_bind_group_layout("bloom_composite_bind_group_layout", &entries);
+
+    let params = device.create_buffer_init(
+      "Bloom Buffer",
+      bytemuck::cast_slice(&[0.0f32; 4]),
+      wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    );
+    let sampler = device
+      .get_device()
+      .create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("bloom_sampler"),
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+      });
+
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+      label: Some("bloom"),
+      source: wgpu::ShaderSource::Wgsl(
+        format!(
+          "{}{}",
+          include_str!("../../assets/postprocess.wgsl"),
+          include_str!("../../assets/bloom.wgsl")
+        )
+        .into(),
+      ),
+    });
+    let pipeline_layout = device.create_pipeline_layout("Bloom Pipeline Layout", &[&layout], &[]);
+    let composite_pipeline_layout =
+      device.create_pipeline_layout("Bloom Composite Pipeline Layout", &[&composite_layout], &[]);
+    // 升采样的结果以加法混合叠加到上一级已有的降采样结果上
+    let additive = wgpu::BlendState {
+      color: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+      },
+      alpha: wgpu::BlendComponent::REPLACE,
+    };
+    let prefilter_pipeline = Self::create_pipeline(
+      device,
+      &shader,
+      &pipeline_layout,
+      "fs_prefilter",
+      HDR_FORMAT,
+      None,
+    );
+    let downsample_pipeline = Self::create_pipeline(
+      device,
+      &shader,
+      &pipeline_layout,
+      "fs_downsample",
+      HDR_FORMAT,
+      None,
+    );
+    let upsample_pipeline = Self::create_pipeline(
+      device,
+      &shader,
+      &pipeline_layout,
+      "fs_upsample",
+      HDR_FORMAT,
+      Some(additive),
+    );
+
+    let mut bloom = Self {
+      settings,
+      enabled: true,
+      params,
+      sampler,
+      layout,
+      composite_layout,
+      shader,
+      composite_pipeline_layout,
+      prefilter_pipeline,
+      downsample_pipeline,
+      upsample_pipeline,
+      composite_pipelines: HashMap::new(),
+      mip_views: Vec::new(),
+      mip_bind_groups: Vec::new(),
+    };
+    bloom.create_mips(device, width, height);
+    bloom
+  }
+
+  fn create_pipeline<T: DeviceTrait>(
+    device: &T,
+    shader: &wgpu::ShaderModule,
+    layout: &wgpu::PipelineLayout,
+    entry_point: &str,
+    format: wgpu::TextureFormat,
+    blend: Option<wgpu::BlendState>,
+  ) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(
+      entry_point,
+      Some(layout),
+      wgpu::VertexState {
+        module: shader,
+        entry_point: "vs_main",
+        buffers: &[],
+      },
+      wgpu::PrimitiveState::default(),
+      None,
+      wgpu::MultisampleState::default(),
+      wgpu::FragmentState {
+        module: shader,
+        entry_point,
+        targets: &[Some(wgpu::ColorTargetState {
+          format,
+          blend,
+          write_mask: wgpu::ColorWrites::ALL,
+        })],
+      },
+      None,
+    )
+  }
+
+  /// 第 0 级为场景的一半大小，最小的一级不小于 1 像素
+  fn create_mips<T: DeviceTrait>(&mut self, device: &T, width: u32, height: u32) {
+    let size = wgpu::Extent3d {
+      width: (width / 2).max(1),
+      height: (height / 2).max(1),
+      depth_or_array_layers: 1,
+    };
+    let mip_count = size.max_mips(wgpu::TextureDimension::D2).min(MAX_MIPS);
+    let texture = device
+      .get_device()
+      .create_texture(&wgpu::TextureDescriptor {
+        label: Some("bloom_texture"),
+        size,
+        mip_level_count: mip_count,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: HDR_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+      });
+    self.mip_views = (0..mip_count)
+      .map(|level| {
+        texture.create_view(&wgpu::TextureViewDescriptor {
+          base_mip_level: level,
+          mip_level_count: Some(1),
+          ..Default::default()
+        })
+      })
+      .collect();
+    self.mip_bind_groups = self
+      .mip_views
+      .iter()
+      .map(|view| {
+        device.create_bind_group(
+          "bloom_bind_group",
+          &self.layout,
+          &[
+            wgpu::BindGroupEntry {
+              binding: 0,
+              resource: wgpu::BindingResource::TextureView(view),
+            },
+            wgpu::BindGroupEntry {
+              binding: 1,
+              resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+            wgpu::BindGroupEntry {
+              binding: 2,
+              resource: self.params.as_entire_binding(),
+            },
+          ],
+        )
+      })
+      .collect();
+  }
+
+  pub fn settings(&self) -> &BloomSettings {
+    &self.settings
+  }
+
+  pub fn set_settings(&mut self, settings: BloomSettings) {
+    self.settings = settings;
+  }
+
+  fn fullscreen_pass(
+    encoder: &mut wgpu::CommandEncoder,
+    label: &str,
+    pipeline: &wgpu::RenderPipeline,
+    bind_group: &wgpu::BindGroup,
+    output: &wgpu::TextureView,
+    load: wgpu::LoadOp<wgpu::Color>,
+  ) {
+    let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      label: Some(label),
+      color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+        view: output,
+        resolve_target: None,
+        ops: wgpu::Operations {
+          load,
+          store: wgpu::StoreOp::Store,
+        },
+      })],
+      ..Default::default()
+    });
+    pass.set_pipeline(pipeline);
+    pass.set_bind_group(0, bind_group, &[]);
+    pass.draw(0..3, 0..1);
+  }
+}
+
+impl Effect for Bloom {
+  fn name(&self) -> &str {
+    "bloom"
+  }
+
+  fn stage(&self) -> Stage {
+    Stage::Hdr
+  }
+
+  fn enabled(&self) -> bool {
+    self.enabled
+  }
+
+  fn set_enabled(&mut self, enabled: bool) {
+    self.enabled = enabled;
+  }
+
+  fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+    self.create_mips(&DeviceWarp::wrap(device), width, height);
+  }
+
+  fn update(&mut self, queue: &wgpu::Queue, _frame: &FrameInfo) {
+    // 升采样时每一级都会叠加一次，按级数归一化，使强度与分辨率无关
+    let uniform = BloomUniform {
+      threshold: self.settings.threshold,
+      knee: self.settings.knee,
+      intensity: self.settings.intensity / self.mip_views.len() as f32,
+      radius: self.settings.radius,
+    };
+    queue.write_buffer(&self.params, 0, bytemuck::cast_slice(&[uniform]));
+  }
+
+  fn render(
+    &mut self,
+    ctx: &mut EffectContext,
+    input: &wgpu::TextureView,
+    output: &wgpu::TextureView,
+    format: wgpu::TextureFormat,
+  ) {
+    let device = DeviceWarp::wrap(ctx.device);
+    let mut entries = vec![
+      wgpu::BindGroupEntry {
+        binding: 0,
+        resource: wgpu::BindingResource::TextureView(input),
+      },
+      wgpu::BindGroupEntry {
+        binding: 1,
+        resource: wgpu::BindingResource::Sampler(&self.sampler),
+      },
+      wgpu::BindGroupEntry {
+        binding: 2,
+        resource: self.params.as_entire_binding(),
+      },
+    ];
+    let prefilter = device.create_bind_group("bloom_prefilter", &self.layout, &entries);
+    let clear = wgpu::LoadOp::Clear(wgpu::Color::BLACK);
+    Self::fullscreen_pass(
+      ctx.encoder,
+      "Bloom Prefilter",
+      &self.prefilter_pipeline,
+      &prefilter,
+      &self.mip_views[0],
+      clear,
+    );
+    for level in 1..self.mip_views.len() {
+      Self::fullscreen_pass(
+        ctx.encoder,
+        "Bloom Downsample",
+        &self.downsample_pipeline,
+        &self.mip_bind_groups[level - 1],
+        &self.mip_views[level],
+        clear,
+      );
+    }
+    for level in (1..self.mip_views.len()).rev() {
+      Self::fullscreen_pass(
+        ctx.encoder,
+        "Bloom Upsample",
+        &self.upsample_pipeline,
+        &self.mip_bind_groups[level],
+        &self.mip_views[level - 1],
+        wgpu::LoadOp::Load,
+      );
+    }
+
+    entries.push(wgpu::BindGroupEntry {
+      binding: 3,
+      resource: wgpu::BindingResource::TextureView(&self.mip_views[0]),
+    });
+    let composite = device.create_bind_group("bloom_composite", &self.composite_layout, &entries);
+    let pipeline = self.composite_pipelines.entry(format).or_insert_with(|| {
+      Self::create_pipeline(
+        &device,
+        &self.shader,
+        &self.composite_pipeline_layout,
+        "fs_composite",
+        format,
+        None,
+      )
+    });
+    Self::fullscreen_pass(
+      ctx.encoder,
+      "Bloom Composite",
+      pipeline,
+      &composite,
+      output,
+      wgpu::LoadOp::Load,
+    );
+  }
+}
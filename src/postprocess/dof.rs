@@ -0,0 +1,147 @@
+//! 景深
+//!
+//! 由场景深度求出每个像素的弥散圆 (circle of confusion) 半径，再在半径内按黄金角螺旋
+//! 采样圆盘，得到散景 (bokeh) 形状的模糊。以 gather 模拟 scatter：样本只在其自身的
+//! 弥散圆覆盖到当前像素时才被计入，焦外的前景因此会模糊到焦内的背景之上，反之则不会。
+
+use na::Matrix4;
+
+use super::{Effect, EffectContext, FrameInfo, FullscreenPass, Quality, Stage};
+use crate::exts::state::DeviceTrait;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DofSettings {
+  /// 对焦平面到摄像机的距离
+  pub focus_distance: f32,
+  /// 光圈大小，越大焦外越模糊。为 1 时无穷远处的弥散圆达到 max_radius
+  pub aperture: f32,
+  /// 弥散圆的最大半径，以画面高度的比例表示，与分辨率无关
+  pub max_radius: f32,
+  pub quality: Quality,
+}
+impl Default for DofSettings {
+  fn default() -> Self {
+    Self {
+      focus_distance: 10.0,
+      aperture: 0.5,
+      max_radius: 0.02,
+      quality: Quality::default(),
+    }
+  }
+}
+
+impl DofSettings {
+  /// 每个像素的采样数
+  pub fn sample_count(&self) -> u32 {
+    match self.quality {
+      Quality::Low => 16,
+      Quality::Medium => 32,
+      Quality::High => 64,
+    }
+  }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct DofUniform {
+  // 用于由深度还原到摄像机的距离
+  inv_view_proj: Matrix4<f32>,
+  focus_distance: f32,
+  aperture: f32,
+  // 像素
+  max_radius: f32,
+  sample_count: u32,
+}
+impl DofUniform {
+  fn new(settings: &DofSettings, height: u32, inv_view_proj: Matrix4<f32>) -> Self {
+    Self {
+      inv_view_proj,
+      focus_distance: settings.focus_distance,
+      aperture: settings.aperture,
+      max_radius: settings.max_radius * height as f32,
+      sample_count: settings.sample_count(),
+    }
+  }
+}
+
+pub struct DepthOfField {
+  settings: DofSettings,
+  enabled: bool,
+  height: u32,
+  pass: FullscreenPass,
+}
+
+impl DepthOfField {
+  /// height 为场景的高度
+  pub fn new<T: DeviceTrait>(device: &T, height: u32, settings: DofSettings) -> Self {
+    let uniform = DofUniform::new(&settings, height, Matrix4::identity());
+    let pass = FullscreenPass::new(
+      device,
+      "depth_of_field",
+      include_str!("../../assets/dof.wgsl"),
+      Some(bytemuck::cast_slice(&[uniform])),
+      true,
+    );
+    Self {
+      settings,
+      enabled: true,
+      height,
+      pass,
+    }
+  }
+
+  pub fn settings(&self) -> &DofSettings {
+    &self.settings
+  }
+
+  pub fn set_settings(&mut self, settings: DofSettings) {
+    self.settings = settings;
+  }
+}
+
+impl Effect for DepthOfField {
+  fn name(&self) -> &str {
+    "depth_of_field"
+  }
+
+  fn stage(&self) -> Stage {
+    Stage::Hdr
+  }
+
+  fn enabled(&self) -> bool {
+    self.enabled
+  }
+
+  fn set_enabled(&mut self, enabled: bool) {
+    self.enabled = enabled;
+  }
+
+  fn requires_depth(&self) -> bool {
+    true
+  }
+
+  fn resize(&mut self, _device: &wgpu::Device, _width: u32, height: u32) {
+    self.height = height;
+  }
+
+  fn update(&mut self, queue: &wgpu::Queue, frame: &FrameInfo) {
+    let inv_view_proj = frame
+      .view_proj
+      .try_inverse()
+      .unwrap_or_else(Matrix4::identity);
+    let uniform = DofUniform::new(&self.settings, self.height, inv_view_proj);
+    self
+      .pass
+      .write_params(queue, bytemuck::cast_slice(&[uniform]));
+  }
+
+  fn render(
+    &mut self,
+    ctx: &mut EffectContext,
+    input: &wgpu::TextureView,
+    output: &wgpu::TextureView,
+    format: wgpu::TextureFormat,
+  ) {
+    self.pass.draw(ctx, input, output, format);
+  }
+}
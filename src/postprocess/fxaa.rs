@@ -0,0 +1,56 @@
+//! 快速近似抗锯齿 (FXAA)
+//!
+//! 在色调映射之后按亮度检测边缘，沿边缘方向做少量采样并混合。
+//! 不需要额外的几何信息，也能处理着色器产生的锯齿，代价是画面会略微变软。
+
+use super::{Effect, EffectContext, FullscreenPass, Stage};
+use crate::exts::state::DeviceTrait;
+
+pub struct Fxaa {
+  enabled: bool,
+  pass: FullscreenPass,
+}
+
+impl Fxaa {
+  pub fn new<T: DeviceTrait>(device: &T) -> Self {
+    let pass = FullscreenPass::new(
+      device,
+      "fxaa",
+      include_str!("../../assets/fxaa.wgsl"),
+      None,
+      false,
+    );
+    Self {
+      enabled: true,
+      pass,
+    }
+  }
+}
+
+impl Effect for Fxaa {
+  fn name(&self) -> &str {
+    "fxaa"
+  }
+
+  fn stage(&self) -> Stage {
+    Stage::Ldr
+  }
+
+  fn enabled(&self) -> bool {
+    self.enabled
+  }
+
+  fn set_enabled(&mut self, enabled: bool) {
+    self.enabled = enabled;
+  }
+
+  fn render(
+    &mut self,
+    ctx: &mut EffectContext,
+    input: &wgpu::TextureView,
+    output: &wgpu::TextureView,
+    format: wgpu::TextureFormat,
+  ) {
+    self.pass.draw(ctx, input, output, format);
+  }
+}
@@ -0,0 +1,577 @@
+//! 调色
+//!
+//! 在色调映射之后调整画面的颜色：先在线性空间中做白平衡与饱和度，再转换到 sRGB 编码，
+//! 依次应用 lift/gamma/gain 与 3D 查找表 (LUT)，最后转换回线性。调色软件导出的 LUT
+//! 都以 sRGB 编码的颜色为输入与输出。
+//!
+//! LUT 可以从 `.cube` 文件 ([`Lut::parse_cube`]) 或二维的条带图片 ([`Lut::from_strip`]) 加载。
+//! 它以 Rgba32Float 的 3D 纹理上传，这种格式不能过滤，着色器中手动做三线性插值。
+
+use std::{collections::HashMap, ops::RangeInclusive};
+
+use color_eyre::eyre::{bail, eyre, Result};
+use na::{Vector3, Vector4};
+
+use super::{Effect, EffectContext, FrameInfo, Stage};
+use crate::exts::state::{DeviceTrait, DeviceWarp};
+
+/// LUT 边长的范围，与 .cube 格式的规定一致
+const LUT_SIZES: RangeInclusive<u32> = 2..=256;
+
+/// 3D 查找表，从输入颜色的 [domain_min, domain_max] 映射到输出颜色
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lut {
+  size: u32,
+  // 按 r 变化最快、b 变化最慢的顺序排列，与 .cube 文件一致
+  data: Vec<[f32; 3]>,
+  domain_min: Vector3<f32>,
+  domain_max: Vector3<f32>,
+}
+
+impl Lut {
+  /// 不改变颜色的 LUT。三线性插值对线性函数是精确的，边长为 2 就足够
+  pub fn identity(size: u32) -> Self {
+    let scale = (size - 1) as f32;
+    let data = (0..size.pow(3))
+      .map(|i| {
+        let (r, g, b) = (i % size, i / size % size, i / (size * size));
+        [r as f32 / scale, g as f32 / scale, b as f32 / scale]
+      })
+      .collect();
+    Self {
+      size,
+      data,
+      domain_min: Vector3::zeros(),
+      domain_max: Vector3::repeat(1.0),
+    }
+  }
+
+  /// 解析 Adobe/Resolve 的 .cube 文本格式，只支持 3D LUT
+  pub fn parse_cube(text: &str) -> Result<Self> {
+    let mut size = None;
+    let mut domain_min = Vector3::zeros();
+    let mut domain_max = Vector3::repeat(1.0);
+    let mut data = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+      let line = line.trim();
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+      let mut words = line.split_whitespace();
+      let keyword = words.next().unwrap_or_default();
+      let floats = |words: std::str::SplitWhitespace| -> Result<Vec<f32>> {
+        words
+          .map(|word| {
+            word
+              .parse::<f32>()
+              .map_err(|e| eyre!("line {}: invalid number {word:?}: {e}", number + 1))
+          })
+          .collect()
+      };
+      let vector = |values: Vec<f32>| -> Result<Vector3<f32>> {
+        match values[..] {
+          [r, g, b] => Ok(Vector3::new(r, g, b)),
+          _ => bail!("line {}: expected three values", number + 1),
+        }
+      };
+      match keyword {
+        "TITLE" => {}
+        "LUT_1D_SIZE" => bail!("1D LUTs are not supported"),
+        "LUT_3D_SIZE" => {
+          let value = words.next().unwrap_or_default();
+          let value = value
+            .parse::<u32>()
+            .map_err(|e| eyre!("line {}: invalid LUT size {value:?}: {e}", number + 1))?;
+          if !LUT_SIZES.contains(&value) {
+            bail!("line {}: LUT size {value} is out of range", number + 1);
+          }
+          size = Some(value);
+        }
+        "DOMAIN_MIN" => domain_min = vector(floats(words)?)?,
+        "DOMAIN_MAX" => domain_max = vector(floats(words)?)?,
+        // Resolve 的写法，三个通道使用相同的范围
+        "LUT_3D_INPUT_RANGE" => match floats(words)?[..] {
+          [min, max] => {
+            domain_min = Vector3::repeat(min);
+            domain_max = Vector3::repeat(max);
+          }
+          _ => bail!("line {}: expected two values", number + 1),
+        },
+        _ if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+          // 其余关键字不影响查找表本身
+        }
+        _ => {
+          let color = vector(floats(line.split_whitespace())?)?;
+          data.push(color.into());
+        }
+      }
+    }
+    let size = size.ok_or_else(|| eyre!("missing LUT_3D_SIZE"))?;
+    if data.len() != size.pow(3) as usize {
+      bail!(
+        "expected {} entries for a {size}x{size}x{size} LUT, found {}",
+        size.pow(3),
+        data.len()
+      );
+    }
+    if (0..3).any(|i| domain_max[i] <= domain_min[i]) {
+      bail!("DOMAIN_MAX must be greater than DOMAIN_MIN");
+    }
+    Ok(Self {
+      size,
+      data,
+      domain_min,
+      domain_max,
+    })
+  }
+
+  /// 从二维的条带图片读取 LUT。图片高为 N、宽为 N * N，横向排列 N 个切片：
+  /// 第 b 个切片中 x 对应红色、y 对应绿色，左上角为黑色，与 Unreal 的中性 LUT 图片一致。
+  /// 像素值即为 sRGB 编码的输出颜色
+  pub fn from_strip(image: &image::Rgba32FImage) -> Result<Self> {
+    let size = image.height();
+    if !LUT_SIZES.contains(&size) || image.width() != size * size {
+      bail!(
+        "a LUT strip must be N * N by N pixels with N from 2 to 256, got {}x{}",
+        image.width(),
+        image.height()
+      );
+    }
+    let data = (0..size.pow(3))
+      .map(|i| {
+        let (r, g, b) = (i % size, i / size % size, i / (size * size));
+        let pixel = image.get_pixel(b * size + r, g);
+        [pixel[0], pixel[1], pixel[2]]
+      })
+      .collect();
+    Ok(Self {
+      size,
+      data,
+      domain_min: Vector3::zeros(),
+      domain_max: Vector3::repeat(1.0),
+    })
+  }
+
+  /// 每条边上的采样数
+  pub fn size(&self) -> u32 {
+    self.size
+  }
+
+  /// 网格上 (r, g, b) 处的输出颜色
+  pub fn get(&self, r: u32, g: u32, b: u32) -> Vector3<f32> {
+    let index = (b * self.size + g) * self.size + r;
+    Vector3::from(self.data[index as usize])
+  }
+}
+
+/// 由色温与色调求出 LMS 空间中各通道的缩放，把对应的光源变换到 D65。
+/// 色温 -1 到 1 对应偏冷到偏暖，色调 -1 到 1 对应偏绿到偏品红，两者都为 0 时不做调整
+pub fn white_balance(temperature: f32, tint: f32) -> Vector3<f32> {
+  // 沿黑体轨迹附近移动 D65 的色度坐标
+  let t1 = temperature * 10.0 / 6.0;
+  let t2 = tint * 10.0 / 6.0;
+  let x = 0.31271 - t1 * if t1 < 0.0 { 0.1 } else { 0.05 };
+  let standard_illuminant_y = 2.87 * x - 3.0 * x * x - 0.27509507;
+  let y = standard_illuminant_y + t2 * 0.05;
+  // CIE xy -> XYZ (Y = 1) -> LMS (CAT02)
+  let (cx, cy, cz) = (x / y, 1.0, (1.0 - x - y) / y);
+  let lms = Vector3::new(
+    0.7328 * cx + 0.4296 * cy - 0.1624 * cz,
+    -0.7036 * cx + 1.6975 * cy + 0.0061 * cz,
+    0.0030 * cx + 0.0136 * cy + 0.9834 * cz,
+  );
+  // D65 在 LMS 空间中的坐标
+  Vector3::new(0.949237, 1.03542, 1.08728).component_div(&lms)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorGradingSettings {
+  /// 抬高暗部，0 为不变
+  pub lift: Vector3<f32>,
+  /// 中间调的幂的倒数，大于 1 时变亮，1 为不变
+  pub gamma: Vector3<f32>,
+  /// 亮部的增益，1 为不变
+  pub gain: Vector3<f32>,
+  /// 0 为灰度，1 为不变
+  pub saturation: f32,
+  /// 色温，负值偏冷，正值偏暖
+  pub temperature: f32,
+  /// 色调，负值偏绿，正值偏品红
+  pub tint: f32,
+  /// LUT 的结果与原颜色的混合比例
+  pub lut_contribution: f32,
+}
+impl Default for ColorGradingSettings {
+  fn default() -> Self {
+    Self {
+      lift: Vector3::zeros(),
+      gamma: Vector3::repeat(1.0),
+      gain: Vector3::repeat(1.0),
+      saturation: 1.0,
+      temperature: 0.0,
+      tint: 0.0,
+      lut_contribution: 1.0,
+    }
+  }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ColorGradingUniform {
+  lift: Vector4<f32>,
+  gamma: Vector4<f32>,
+  gain: Vector4<f32>,
+  // LMS 空间中的白平衡系数
+  balance: Vector4<f32>,
+  domain_min: Vector4<f32>,
+  domain_max: Vector4<f32>,
+  saturation: f32,
+  lut_contribution: f32,
+  _padding: [f32; 2],
+}
+impl ColorGradingUniform {
+  fn new(settings: &ColorGradingSettings, lut: &Lut) -> Self {
+    Self {
+      lift: settings.lift.push(0.0),
+      gamma: settings.gamma.push(1.0),
+      gain: settings.gain.push(1.0),
+      balance: white_balance(settings.temperature, settings.tint).push(1.0),
+      domain_min: lut.domain_min.push(0.0),
+      domain_max: lut.domain_max.push(1.0),
+      saturation: settings.saturation,
+      lut_contribution: settings.lut_contribution,
+      _padding: [0.0; 2],
+    }
+  }
+}
+
+pub struct ColorGrading {
+  settings: ColorGradingSettings,
+  enabled: bool,
+  // 当前的 LUT，更新参数时需要它的定义域
+  lut: Lut,
+  lut_view: wgpu::TextureView,
+  params: wgpu::Buffer,
+  sampler: wgpu::Sampler,
+  layout: wgpu::BindGroupLayout,
+  shader: wgpu::ShaderModule,
+  pipeline_layout: wgpu::PipelineLayout,
+  // 按输出格式缓存
+  pipelines: HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>,
+}
+
+impl ColorGrading {
+  pub fn new<T: DeviceTrait>(
+    device: &T,
+    queue: &wgpu::Queue,
+    settings: ColorGradingSettings,
+  ) -> Result<Self> {
+    let entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::FRAGMENT,
+      ty,
+      count: None,
+    };
+    let layout = device.create_bind_group_layout(
+      "color_grading_bind_group_layout",
+      &[
+        entry(
+          0,
+          wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+          },
+        ),
+        entry(
+          1,
+          wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        ),
+        entry(
+          2,
+          wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+        ),
+        entry(
+          3,
+          wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::D3,
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+          },
+        ),
+      ],
+    );
+    let lut = Lut::identity(2);
+    let params = device.create_buffer_init(
+      "Color Grading Buffer",
+      bytemuck::cast_slice(&[ColorGradingUniform::new(&settings, &lut)]),
+      wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    );
+    let sampler = device
+      .get_device()
+      .create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("color_grading_sampler"),
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+      });
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+      label: Some("color_grading"),
+      source: wgpu::ShaderSource::Wgsl(
+        format!(
+          "{}{}",
+          include_str!("../../assets/postprocess.wgsl"),
+          include_str!("../../assets/grading.wgsl")
+        )
+        .into(),
+      ),
+    });
+    let pipeline_layout =
+      device.create_pipeline_layout("Color Grading Pipeline Layout", &[&layout], &[]);
+    Ok(Self {
+      settings,
+      enabled: true,
+      lut_view: Self::upload_lut(device, queue, &lut)?,
+      lut,
+      params,
+      sampler,
+      layout,
+      shader,
+      pipeline_layout,
+      pipelines: HashMap::new(),
+    })
+  }
+
+  fn upload_lut<T: DeviceTrait>(
+    device: &T,
+    queue: &wgpu::Queue,
+    lut: &Lut,
+  ) -> Result<wgpu::TextureView> {
+    let max = device.get_device().limits().max_texture_dimension_3d;
+    if lut.size > max {
+      bail!(
+        "a {0}x{0}x{0} LUT exceeds the {max} texel 3D texture limit",
+        lut.size
+      );
+    }
+    let size = wgpu::Extent3d {
+      width: lut.size,
+      height: lut.size,
+      depth_or_array_layers: lut.size,
+    };
+    let texture = device
+      .get_device()
+      .create_texture(&wgpu::TextureDescriptor {
+        label: Some("color_grading_lut"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D3,
+        format: wgpu::TextureFormat::Rgba32Float,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+      });
+    let texels = lut
+      .data
+      .iter()
+      .map(|&[r, g, b]| [r, g, b, 1.0])
+      .collect::<Vec<_>>();
+    queue.write_texture(
+      texture.as_image_copy(),
+      bytemuck::cast_slice(&texels),
+      wgpu::ImageDataLayout {
+        offset: 0,
+        bytes_per_row: Some(16 * lut.size),
+        rows_per_image: Some(lut.size),
+      },
+      size,
+    );
+    Ok(texture.create_view(&Default::default()))
+  }
+
+  pub fn settings(&self) -> &ColorGradingSettings {
+    &self.settings
+  }
+
+  pub fn set_settings(&mut self, settings: ColorGradingSettings) {
+    self.settings = settings;
+  }
+
+  /// 替换 LUT，None 时恢复为不改变颜色的 LUT。超出设备限制时返回错误，保留原来的 LUT
+  pub fn set_lut(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    lut: Option<Lut>,
+  ) -> Result<()> {
+    let lut = lut.unwrap_or_else(|| Lut::identity(2));
+    self.lut_view = Self::upload_lut(&DeviceWarp::wrap(device), queue, &lut)?;
+    self.lut = lut;
+    Ok(())
+  }
+}
+
+impl Effect for ColorGrading {
+  fn name(&self) -> &str {
+    "color_grading"
+  }
+
+  fn stage(&self) -> Stage {
+    Stage::Ldr
+  }
+
+  fn enabled(&self) -> bool {
+    self.enabled
+  }
+
+  fn set_enabled(&mut self, enabled: bool) {
+    self.enabled = enabled;
+  }
+
+  fn update(&mut self, queue: &wgpu::Queue, _frame: &FrameInfo) {
+    queue.write_buffer(
+      &self.params,
+      0,
+      bytemuck::cast_slice(&[ColorGradingUniform::new(&self.settings, &self.lut)]),
+    );
+  }
+
+  fn render(
+    &mut self,
+    ctx: &mut EffectContext,
+    input: &wgpu::TextureView,
+    output: &wgpu::TextureView,
+    format: wgpu::TextureFormat,
+  ) {
+    let device = DeviceWarp::wrap(ctx.device);
+    let bind_group = device.create_bind_group(
+      "color_grading_bind_group",
+      &self.layout,
+      &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: wgpu::BindingResource::TextureView(input),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: wgpu::BindingResource::Sampler(&self.sampler),
+        },
+        wgpu::BindGroupEntry {
+          binding: 2,
+          resource: self.params.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 3,
+          resource: wgpu::BindingResource::TextureView(&self.lut_view),
+        },
+      ],
+    );
+    let pipeline = self.pipelines.entry(format).or_insert_with(|| {
+      device.create_render_pipeline(
+        "Color Grading Pipeline",
+        Some(&self.pipeline_layout),
+        wgpu::VertexState {
+          module: &self.shader,
+          entry_point: "vs_main",
+          buffers: &[],
+        },
+        wgpu::PrimitiveState::default(),
+        None,
+        wgpu::MultisampleState::default(),
+        wgpu::FragmentState {
+          module: &self.shader,
+          entry_point: "fs_main",
+          targets: &[Some(wgpu::ColorTargetState {
+            format,
+            blend: None,
+            write_mask: wgpu::ColorWrites::ALL,
+          })],
+        },
+        None,
+      )
+    });
+    let mut pass = ctx.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      label: Some("Color Grading Pass"),
+      color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+        view: output,
+        resolve_target: None,
+        ops: wgpu::Operations {
+          load: wgpu::LoadOp::Load,
+          store: wgpu::StoreOp::Store,
+        },
+      })],
+      ..Default::default()
+    });
+    pass.set_pipeline(pipeline);
+    pass.set_bind_group(0, &bind_group, &[]);
+    pass.draw(0..3, 0..1);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_cube_files() {
+    let text = "# exported by a grading tool
+TITLE \"swap\"
+LUT_3D_SIZE 2
+DOMAIN_MIN 0 0 0
+DOMAIN_MAX 1 1 2
+
+0 0 0
+0 1 0
+1 0 0
+1 1 0
+0 0 1
+0 1 1
+1 0 1
+1 1 1
+";
+    let lut = Lut::parse_cube(text).unwrap();
+    assert_eq!(lut.size(), 2);
+    assert_eq!(lut.domain_max, Vector3::new(1.0, 1.0, 2.0));
+    // 红色变化最快：(r=1, g=0, b=0) 是第二项，这个 LUT 交换了红绿两个通道
+    assert_eq!(lut.get(1, 0, 0), Vector3::new(0.0, 1.0, 0.0));
+    assert_eq!(lut.get(0, 1, 1), Vector3::new(1.0, 0.0, 1.0));
+
+    assert!(Lut::parse_cube("LUT_3D_SIZE 2\n0 0 0\n").is_err());
+    assert!(Lut::parse_cube("0 0 0\n").is_err());
+    assert!(Lut::parse_cube("LUT_1D_SIZE 2\n0 0 0\n1 1 1\n").is_err());
+    assert!(Lut::parse_cube("LUT_3D_SIZE 2\n0 0 x\n").is_err());
+  }
+
+  #[test]
+  fn strip_matches_cube_layout() {
+    let size = 4;
+    let strip = image::Rgba32FImage::from_fn(size * size, size, |x, y| {
+      let scale = (size - 1) as f32;
+      image::Rgba([
+        (x % size) as f32 / scale,
+        y as f32 / scale,
+        (x / size) as f32 / scale,
+        1.0,
+      ])
+    });
+    assert_eq!(Lut::from_strip(&strip).unwrap(), Lut::identity(size));
+    assert!(Lut::from_strip(&image::Rgba32FImage::new(8, 4)).is_err());
+    assert!(Lut::from_strip(&image::Rgba32FImage::new(1, 1)).is_err());
+  }
+
+  #[test]
+  fn neutral_white_balance_is_identity() {
+    let balance = white_balance(0.0, 0.0);
+    assert!((balance - Vector3::repeat(1.0)).amax() < 1e-2);
+    // 调暖时蓝色通道 (S) 减弱
+    let warm = white_balance(0.5, 0.0);
+    assert!(warm.z < balance.z && warm.x > warm.z);
+  }
+}
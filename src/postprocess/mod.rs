@@ -0,0 +1,455 @@
+//! 后处理链
+//!
+//! 场景渲染到 HDR 纹理后，依次经过链中启用的效果，最后一个效果直接写入输出纹理，
+//! 其余的在两张中间纹理之间交替读写。效果按 [`Stage`] 排序：HDR 效果在色调映射之前，
+//! LDR 效果在之后。添加新效果只需实现 [`Effect`] 并调用 [`PostProcess::add`]，
+//! 简单的效果可以借助 [`FullscreenPass`]，只编写片元着色器。
+
+pub mod bloom;
+pub mod dof;
+pub mod fxaa;
+pub mod grading;
+pub mod motion_blur;
+pub mod taa;
+pub mod tonemap;
+pub mod vignette;
+
+use std::{any::Any, collections::HashMap};
+
+use na::{Matrix4, Vector2};
+
+use crate::{
+  exts::state::{DeviceTrait, DeviceWarp},
+  texture,
+};
+
+/// 场景与中间纹理使用的 HDR 颜色格式
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// 效果在链中的位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+  /// 作用于线性的 HDR 颜色，如泛光、景深
+  Hdr,
+  ToneMapping,
+  /// 作用于映射到 [0, 1] 之后的颜色，如暗角、调色、FXAA
+  Ldr,
+}
+
+/// 效果的质量档位，档位越高采样越多
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Quality {
+  Low,
+  #[default]
+  Medium,
+  High,
+}
+
+/// 每帧渲染前传给效果的信息
+#[derive(Debug, Clone, Copy)]
+pub struct FrameInfo {
+  /// 距上一帧的秒数
+  pub delta_time: f32,
+  /// 本帧与上一帧不带 jitter 的视图投影矩阵，用于由深度重建运动向量
+  pub view_proj: Matrix4<f32>,
+  pub prev_view_proj: Matrix4<f32>,
+  /// 本帧投影在 NDC 中的亚像素偏移，没有开启 TAA 时为 0
+  pub jitter: Vector2<f32>,
+}
+
+/// 效果渲染时可以使用的资源
+pub struct EffectContext<'a> {
+  pub device: &'a wgpu::Device,
+  pub encoder: &'a mut wgpu::CommandEncoder,
+  /// 场景深度。开启 MSAA 时为深度预渲染得到的单采样深度，没有深度可用时为 None
+  pub depth: Option<&'a wgpu::TextureView>,
+  /// 输入与输出纹理的尺寸
+  pub width: u32,
+  pub height: u32,
+}
+
+pub trait Effect: Any {
+  fn name(&self) -> &str;
+  fn stage(&self) -> Stage;
+  fn enabled(&self) -> bool;
+  fn set_enabled(&mut self, enabled: bool);
+  /// 需要场景深度的效果在没有深度时被跳过
+  fn requires_depth(&self) -> bool {
+    false
+  }
+  /// 输出尺寸改变时调用，用于重建效果自己的纹理
+  fn resize(&mut self, _device: &wgpu::Device, _width: u32, _height: u32) {}
+  /// 每帧渲染前调用，无论效果是否启用，通常在这里上传参数
+  fn update(&mut self, _queue: &wgpu::Queue, _frame: &FrameInfo) {}
+  /// 读取 input，把结果写入格式为 format 的 output
+  fn render(
+    &mut self,
+    ctx: &mut EffectContext,
+    input: &wgpu::TextureView,
+    output: &wgpu::TextureView,
+    format: wgpu::TextureFormat,
+  );
+}
+
+/// 只由一个片元着色器构成的全屏 pass
+///
+/// 着色器源码前会加上 `postprocess.wgsl`，其中定义了全屏三角形的 `vs_main`，
+/// 以及 group 0 中的输入纹理 `t_input` (binding 0) 与线性采样器 `s_input` (binding 1)。
+/// 有参数时以 uniform 绑定在 binding 2，使用深度时以 `texture_2d<f32>` 绑定在 binding 3
+/// (GL 后端不支持对 `texture_depth_2d` 使用 textureLoad)，只能用 textureLoad 读取，
+/// 这两者由效果的着色器自行声明。管线按输出格式缓存
+pub struct FullscreenPass {
+  label: String,
+  shader: wgpu::ShaderModule,
+  layout: wgpu::BindGroupLayout,
+  pipeline_layout: wgpu::PipelineLayout,
+  pipelines: HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>,
+  sampler: wgpu::Sampler,
+  params: Option<wgpu::Buffer>,
+  uses_depth: bool,
+}
+
+impl FullscreenPass {
+  const COMMON: &'static str = include_str!("../../assets/postprocess.wgsl");
+
+  /// params 为参数 uniform 的初始内容，为 None 时没有参数
+  pub fn new<T: DeviceTrait>(
+    device: &T,
+    label: &str,
+    source: &str,
+    params: Option<&[u8]>,
+    uses_depth: bool,
+  ) -> Self {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+      label: Some(label),
+      source: wgpu::ShaderSource::Wgsl(format!("{}{source}", Self::COMMON).into()),
+    });
+    let mut entries = vec![
+      wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+          multisampled: false,
+          view_dimension: wgpu::TextureViewDimension::D2,
+          sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        },
+        count: None,
+      },
+      wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+      },
+    ];
+    if params.is_some() {
+      entries.push(wgpu::BindGroupLayoutEntry {
+        binding: 2,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+          ty: wgpu::BufferBindingType::Uniform,
+          has_dynamic_offset: false,
+          min_binding_size: None,
+        },
+        count: None,
+      });
+    }
+    if uses_depth {
+      entries.push(wgpu::BindGroupLayoutEntry {
+        binding: 3,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+          multisampled: false,
+          view_dimension: wgpu::TextureViewDimension::D2,
+          sample_type: wgpu::TextureSampleType::Float { filterable: false },
+        },
+        count: None,
+      });
+    }
+    let layout = device.create_bind_group_layout(label, &entries);
+    let pipeline_layout = device.create_pipeline_layout(label, &[&layout], &[]);
+    let sampler = device
+      .get_device()
+      .create_sampler(&wgpu::SamplerDescriptor {
+        label: Some(label),
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+      });
+    let params = params.map(|contents| {
+      device.create_buffer_init(
+        label,
+        contents,
+        wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      )
+    });
+    Self {
+      label: label.to_string(),
+      shader,
+      layout,
+      pipeline_layout,
+      pipelines: HashMap::new(),
+      sampler,
+      params,
+      uses_depth,
+    }
+  }
+
+  /// 更新参数 uniform，大小必须与创建时一致
+  pub fn write_params(&self, queue: &wgpu::Queue, contents: &[u8]) {
+    if let Some(params) = &self.params {
+      queue.write_buffer(params, 0, contents);
+    }
+  }
+
+  pub fn draw(
+    &mut self,
+    ctx: &mut EffectContext,
+    input: &wgpu::TextureView,
+    output: &wgpu::TextureView,
+    format: wgpu::TextureFormat,
+  ) {
+    let device = DeviceWarp::wrap(ctx.device);
+    let pipeline = self.pipelines.entry(format).or_insert_with(|| {
+      device.create_render_pipeline(
+        &self.label,
+        Some(&self.pipeline_layout),
+        wgpu::VertexState {
+          module: &self.shader,
+          entry_point: "vs_main",
+          buffers: &[],
+        },
+        wgpu::PrimitiveState::default(),
+        None,
+        wgpu::MultisampleState::default(),
+        wgpu::FragmentState {
+          module: &self.shader,
+          entry_point: "fs_main",
+          targets: &[Some(wgpu::ColorTargetState {
+            format,
+            blend: None,
+            write_mask: wgpu::ColorWrites::ALL,
+          })],
+        },
+        None,
+      )
+    });
+
+    let mut entries = vec![
+      wgpu::BindGroupEntry {
+        binding: 0,
+        resource: wgpu::BindingResource::TextureView(input),
+      },
+      wgpu::BindGroupEntry {
+        binding: 1,
+        resource: wgpu::BindingResource::Sampler(&self.sampler),
+      },
+    ];
+    if let Some(params) = &self.params {
+      entries.push(wgpu::BindGroupEntry {
+        binding: 2,
+        resource: params.as_entire_binding(),
+      });
+    }
+    if self.uses_depth {
+      let depth = ctx
+        .depth
+        .expect("effects that sample depth must report requires_depth");
+      entries.push(wgpu::BindGroupEntry {
+        binding: 3,
+        resource: wgpu::BindingResource::TextureView(depth),
+      });
+    }
+    let bind_group = device.create_bind_group(&self.label, &self.layout, &entries);
+
+    let mut pass = ctx.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      label: Some(&self.label),
+      color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+        view: output,
+        resolve_target: None,
+        ops: wgpu::Operations {
+          // 全屏三角形覆盖每个像素，不需要清屏
+          load: wgpu::LoadOp::Load,
+          store: wgpu::StoreOp::Store,
+        },
+      })],
+      ..Default::default()
+    });
+    pass.set_pipeline(pipeline);
+    pass.set_bind_group(0, &bind_group, &[]);
+    pass.draw(0..3, 0..1);
+  }
+}
+
+pub struct PostProcess {
+  // 按 Stage 排序，同一阶段内保持添加的顺序
+  effects: Vec<Box<dyn Effect>>,
+  // 两张交替读写的中间纹理
+  targets: [texture::Texture; 2],
+  // 没有启用任何效果时，把场景直接复制到输出
+  copy: FullscreenPass,
+}
+
+impl PostProcess {
+  pub fn new<T: DeviceTrait>(device: &T, config: &wgpu::SurfaceConfiguration) -> Self {
+    Self {
+      effects: Vec::new(),
+      targets: Self::create_targets(device, config),
+      copy: FullscreenPass::new(
+        device,
+        "copy",
+        include_str!("../../assets/copy.wgsl"),
+        None,
+        false,
+      ),
+    }
+  }
+
+  fn create_targets<T: DeviceTrait>(
+    device: &T,
+    config: &wgpu::SurfaceConfiguration,
+  ) -> [texture::Texture; 2] {
+    ["post_process_ping", "post_process_pong"]
+      .map(|label| texture::Texture::create_render_target(device, config, HDR_FORMAT, label))
+  }
+
+  /// 把效果插入到其所在阶段的末尾
+  pub fn add(&mut self, effect: impl Effect) {
+    let stage = effect.stage();
+    let index = self.effects.partition_point(|e| e.stage() <= stage);
+    self.effects.insert(index, Box::new(effect));
+  }
+
+  /// 按类型取出链中的第一个该类型的效果
+  pub fn get<E: Effect>(&self) -> Option<&E> {
+    self
+      .effects
+      .iter()
+      .find_map(|effect| (effect.as_ref() as &dyn Any).downcast_ref())
+  }
+
+  pub fn get_mut<E: Effect>(&mut self) -> Option<&mut E> {
+    self
+      .effects
+      .iter_mut()
+      .find_map(|effect| (effect.as_mut() as &mut dyn Any).downcast_mut())
+  }
+
+  /// 按执行顺序遍历所有效果
+  pub fn effects(&self) -> impl Iterator<Item = &dyn Effect> {
+    self.effects.iter().map(|effect| effect.as_ref())
+  }
+
+  /// 是否有启用的效果需要场景深度
+  pub fn needs_depth(&self) -> bool {
+    self
+      .effects
+      .iter()
+      .any(|effect| effect.enabled() && effect.requires_depth())
+  }
+
+  /// 按名字查询效果是否启用，没有该效果时返回 None
+  pub fn is_enabled(&self, name: &str) -> Option<bool> {
+    self
+      .effects
+      .iter()
+      .find(|effect| effect.name() == name)
+      .map(|effect| effect.enabled())
+  }
+
+  /// 按名字启用或禁用效果，没有该效果时返回 false
+  pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+    match self.effects.iter_mut().find(|effect| effect.name() == name) {
+      Some(effect) => {
+        effect.set_enabled(enabled);
+        true
+      }
+      None => false,
+    }
+  }
+
+  /// 按名字切换效果的启用状态，没有该效果时返回 false
+  pub fn toggle(&mut self, name: &str) -> bool {
+    match self.is_enabled(name) {
+      Some(enabled) => self.set_enabled(name, !enabled),
+      None => false,
+    }
+  }
+
+  pub fn resize<T: DeviceTrait>(&mut self, device: &T, config: &wgpu::SurfaceConfiguration) {
+    self.targets = Self::create_targets(device, config);
+    for effect in &mut self.effects {
+      effect.resize(device.get_device(), config.width, config.height);
+    }
+  }
+
+  pub fn update(&mut self, queue: &wgpu::Queue, frame: &FrameInfo) {
+    for effect in &mut self.effects {
+      effect.update(queue, frame);
+    }
+  }
+
+  /// 把 input 原样复制到 output，用于把已经处理好的画面转存到其他格式的纹理
+  pub fn blit(
+    &mut self,
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    input: &wgpu::TextureView,
+    output: &wgpu::TextureView,
+    output_format: wgpu::TextureFormat,
+  ) {
+    let size = self.targets[0].texture.size();
+    let mut ctx = EffectContext {
+      device,
+      encoder,
+      depth: None,
+      width: size.width,
+      height: size.height,
+    };
+    self.copy.draw(&mut ctx, input, output, output_format);
+  }
+
+  /// 在场景渲染完成后调用，把 scene 经过所有启用的效果写入 output
+  pub fn render(
+    &mut self,
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    scene: &wgpu::TextureView,
+    depth: Option<&wgpu::TextureView>,
+    output: &wgpu::TextureView,
+    output_format: wgpu::TextureFormat,
+  ) {
+    let size = self.targets[0].texture.size();
+    let mut ctx = EffectContext {
+      device,
+      encoder,
+      depth,
+      width: size.width,
+      height: size.height,
+    };
+    let mut active = self
+      .effects
+      .iter_mut()
+      .filter(|effect| effect.enabled() && (depth.is_some() || !effect.requires_depth()))
+      .peekable();
+    if active.peek().is_none() {
+      self.copy.draw(&mut ctx, scene, output, output_format);
+      return;
+    }
+    let mut input = scene;
+    let mut i = 0;
+    while let Some(effect) = active.next() {
+      if active.peek().is_none() {
+        effect.render(&mut ctx, input, output, output_format);
+      } else {
+        let target = &self.targets[i % 2].view;
+        effect.render(&mut ctx, input, target, HDR_FORMAT);
+        input = target;
+        i += 1;
+      }
+    }
+  }
+}
@@ -0,0 +1,140 @@
+//! 运动模糊
+//!
+//! 由深度还原每个像素的世界坐标，用上一帧摄像机的视图投影矩阵求出它在上一帧屏幕上的位置，
+//! 两者之差即为像素的速度，再沿速度方向采样并取平均。只考虑摄像机的运动，场景中的物体被视为静止的。
+
+use na::Matrix4;
+
+use super::{Effect, EffectContext, FrameInfo, FullscreenPass, Quality, Stage};
+use crate::exts::state::DeviceTrait;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MotionBlurSettings {
+  /// 快门开启的时间占一帧的比例，0.5 相当于 180° 快门
+  pub shutter: f32,
+  /// 模糊的最大长度，以画面对角线的比例表示，避免摄像机跳变时整个画面糊成一片
+  pub max_length: f32,
+  pub quality: Quality,
+}
+impl Default for MotionBlurSettings {
+  fn default() -> Self {
+    Self {
+      shutter: 0.5,
+      max_length: 0.05,
+      quality: Quality::default(),
+    }
+  }
+}
+
+impl MotionBlurSettings {
+  /// 每个像素沿速度方向的采样数
+  pub fn sample_count(&self) -> u32 {
+    match self.quality {
+      Quality::Low => 8,
+      Quality::Medium => 16,
+      Quality::High => 32,
+    }
+  }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct MotionBlurUniform {
+  inv_view_proj: Matrix4<f32>,
+  prev_view_proj: Matrix4<f32>,
+  shutter: f32,
+  max_length: f32,
+  sample_count: u32,
+  _padding: u32,
+}
+impl MotionBlurUniform {
+  fn new(
+    settings: &MotionBlurSettings,
+    inv_view_proj: Matrix4<f32>,
+    prev_view_proj: Matrix4<f32>,
+  ) -> Self {
+    Self {
+      inv_view_proj,
+      prev_view_proj,
+      shutter: settings.shutter,
+      max_length: settings.max_length,
+      sample_count: settings.sample_count(),
+      _padding: 0,
+    }
+  }
+}
+
+pub struct MotionBlur {
+  settings: MotionBlurSettings,
+  enabled: bool,
+  pass: FullscreenPass,
+}
+
+impl MotionBlur {
+  pub fn new<T: DeviceTrait>(device: &T, settings: MotionBlurSettings) -> Self {
+    let uniform = MotionBlurUniform::new(&settings, Matrix4::identity(), Matrix4::identity());
+    let pass = FullscreenPass::new(
+      device,
+      "motion_blur",
+      include_str!("../../assets/motion_blur.wgsl"),
+      Some(bytemuck::cast_slice(&[uniform])),
+      true,
+    );
+    Self {
+      settings,
+      enabled: true,
+      pass,
+    }
+  }
+
+  pub fn settings(&self) -> &MotionBlurSettings {
+    &self.settings
+  }
+
+  pub fn set_settings(&mut self, settings: MotionBlurSettings) {
+    self.settings = settings;
+  }
+}
+
+impl Effect for MotionBlur {
+  fn name(&self) -> &str {
+    "motion_blur"
+  }
+
+  fn stage(&self) -> Stage {
+    Stage::Hdr
+  }
+
+  fn enabled(&self) -> bool {
+    self.enabled
+  }
+
+  fn set_enabled(&mut self, enabled: bool) {
+    self.enabled = enabled;
+  }
+
+  fn requires_depth(&self) -> bool {
+    true
+  }
+
+  fn update(&mut self, queue: &wgpu::Queue, frame: &FrameInfo) {
+    let inv_view_proj = frame
+      .view_proj
+      .try_inverse()
+      .unwrap_or_else(Matrix4::identity);
+    let uniform = MotionBlurUniform::new(&self.settings, inv_view_proj, frame.prev_view_proj);
+    self
+      .pass
+      .write_params(queue, bytemuck::cast_slice(&[uniform]));
+  }
+
+  fn render(
+    &mut self,
+    ctx: &mut EffectContext,
+    input: &wgpu::TextureView,
+    output: &wgpu::TextureView,
+    format: wgpu::TextureFormat,
+  ) {
+    self.pass.draw(ctx, input, output, format);
+  }
+}
@@ -0,0 +1,357 @@
+//! 时间性抗锯齿 (TAA)
+//!
+//! 每帧把投影矩阵偏移一个亚像素的 jitter ([`jitter`])，使同一像素在连续的帧中覆盖不同的
+//! 采样位置。本效果由深度与上一帧的视图投影矩阵求出每个像素在上一帧中的位置，
+//! 从历史缓冲区取出累积的结果，夹到当前帧 3x3 邻域的颜色范围内以避免拖影，
+//! 再与当前帧混合并写回历史缓冲区。运动向量只来自摄像机的运动，场景中的物体被视为静止的。
+
+use std::collections::HashMap;
+
+use na::{Matrix4, Vector2};
+
+use super::{Effect, EffectContext, FrameInfo, Stage, HDR_FORMAT};
+use crate::{
+  exts::state::{DeviceTrait, DeviceWarp},
+  texture,
+};
+
+/// jitter 序列的长度
+const JITTER_PHASES: u32 = 8;
+
+/// 以 base 为底的 Halton 序列的第 index 项，在 (0, 1) 之间
+fn halton(mut index: u32, base: u32) -> f32 {
+  let mut result = 0.0;
+  let mut fraction = 1.0;
+  while index > 0 {
+    fraction /= base as f32;
+    result += fraction * (index % base) as f32;
+    index /= base;
+  }
+  result
+}
+
+/// 第 frame 帧在 NDC 中的 jitter，不超过半个像素。使用 Halton(2, 3) 序列，
+/// 每 JITTER_PHASES 帧循环一次
+pub fn jitter(frame: u32, width: u32, height: u32) -> Vector2<f32> {
+  let index = frame % JITTER_PHASES + 1;
+  let offset = Vector2::new(halton(index, 2), halton(index, 3)) - Vector2::repeat(0.5);
+  // 一个像素在 NDC 中的宽度为 2 / width
+  offset.component_mul(&Vector2::new(2.0 / width as f32, 2.0 / height as f32))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TaaSettings {
+  /// 当前帧在结果中的权重，越小越平滑，但画面变化时收敛得越慢
+  pub blend: f32,
+}
+impl Default for TaaSettings {
+  fn default() -> Self {
+    Self { blend: 0.1 }
+  }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct TaaUniform {
+  // 本帧不带 jitter 的视图投影矩阵的逆。两帧都不计入 jitter，静止的像素的运动向量恰好为 0，
+  // 历史不会因为反复的双线性采样而模糊
+  inv_view_proj: Matrix4<f32>,
+  prev_view_proj: Matrix4<f32>,
+  blend: f32,
+  // 为 1 时历史缓冲区无效，直接输出当前帧
+  reset: u32,
+  _padding: [u32; 2],
+}
+
+pub struct Taa {
+  settings: TaaSettings,
+  enabled: bool,
+  uniform: TaaUniform,
+  params: wgpu::Buffer,
+  sampler: wgpu::Sampler,
+  layout: wgpu::BindGroupLayout,
+  shader: wgpu::ShaderModule,
+  pipeline_layout: wgpu::PipelineLayout,
+  // 按输出格式缓存
+  pipelines: HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>,
+  // 交替作为上一帧的历史与本帧的结果
+  history: [texture::Texture; 2],
+  current: usize,
+  reset: bool,
+}
+
+impl Taa {
+  /// width 与 height 为场景的尺寸
+  pub fn new<T: DeviceTrait>(device: &T, width: u32, height: u32, settings: TaaSettings) -> Self {
+    let entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::FRAGMENT,
+      ty,
+      count: None,
+    };
+    let texture = |filterable| wgpu::BindingType::Texture {
+      multisampled: false,
+      view_dimension: wgpu::TextureViewDimension::D2,
+      sample_type: wgpu::TextureSampleType::Float { filterable },
+    };
+    let layout = device.create_bind_group_layout(
+      "taa_bind_group_layout",
+      &[
+        entry(0, texture(true)),
+        entry(
+          1,
+          wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        ),
+        entry(
+          2,
+          wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+        ),
+        // GL 后端不能对 texture_depth_2d 使用 textureLoad，深度以非过滤的浮点纹理绑定
+        entry(3, texture(false)),
+        entry(4, texture(true)),
+      ],
+    );
+    let uniform = TaaUniform {
+      inv_view_proj: Matrix4::identity(),
+      prev_view_proj: Matrix4::identity(),
+      blend: settings.blend,
+      reset: 1,
+      _padding: [0; 2],
+    };
+    let params = device.create_buffer_init(
+      "TAA Buffer",
+      bytemuck::cast_slice(&[uniform]),
+      wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    );
+    let sampler = device
+      .get_device()
+      .create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("taa_sampler"),
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+      });
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+      label: Some("taa"),
+      source: wgpu::ShaderSource::Wgsl(
+        format!(
+          "{}{}",
+          include_str!("../../assets/postprocess.wgsl"),
+          include_str!("../../assets/taa.wgsl")
+        )
+        .into(),
+      ),
+    });
+    let pipeline_layout = device.create_pipeline_layout("TAA Pipeline Layout", &[&layout], &[]);
+    Self {
+      settings,
+      enabled: true,
+      uniform,
+      params,
+      sampler,
+      layout,
+      shader,
+      pipeline_layout,
+      pipelines: HashMap::new(),
+      history: Self::create_history(device, width, height),
+      current: 0,
+      reset: true,
+    }
+  }
+
+  fn create_history<T: DeviceTrait>(device: &T, width: u32, height: u32) -> [texture::Texture; 2] {
+    let config = wgpu::SurfaceConfiguration {
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+      format: HDR_FORMAT,
+      width,
+      height,
+      present_mode: wgpu::PresentMode::Fifo,
+      desired_maximum_frame_latency: 2,
+      alpha_mode: wgpu::CompositeAlphaMode::Auto,
+      view_formats: vec![],
+    };
+    [0, 1].map(|i| {
+      texture::Texture::create_render_target(
+        device,
+        &config,
+        HDR_FORMAT,
+        &format!("taa_history_{i}"),
+      )
+    })
+  }
+
+  pub fn settings(&self) -> &TaaSettings {
+    &self.settings
+  }
+
+  pub fn set_settings(&mut self, settings: TaaSettings) {
+    self.settings = settings;
+  }
+
+  /// 丢弃累积的历史，例如在镜头切换时避免上一个镜头残留在画面中
+  pub fn reset_history(&mut self) {
+    self.reset = true;
+  }
+}
+
+impl Effect for Taa {
+  fn name(&self) -> &str {
+    "taa"
+  }
+
+  fn stage(&self) -> Stage {
+    Stage::Hdr
+  }
+
+  fn enabled(&self) -> bool {
+    self.enabled
+  }
+
+  fn set_enabled(&mut self, enabled: bool) {
+    // 关闭期间历史没有更新，重新开启时不能再使用
+    if enabled && !self.enabled {
+      self.reset = true;
+    }
+    self.enabled = enabled;
+  }
+
+  fn requires_depth(&self) -> bool {
+    true
+  }
+
+  fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+    self.history = Self::create_history(&DeviceWarp::wrap(device), width, height);
+    self.reset = true;
+  }
+
+  fn update(&mut self, queue: &wgpu::Queue, frame: &FrameInfo) {
+    self.uniform.inv_view_proj = frame
+      .view_proj
+      .try_inverse()
+      .unwrap_or_else(Matrix4::identity);
+    self.uniform.prev_view_proj = frame.prev_view_proj;
+    self.uniform.blend = self.settings.blend;
+    self.uniform.reset = self.reset as u32;
+    queue.write_buffer(&self.params, 0, bytemuck::cast_slice(&[self.uniform]));
+  }
+
+  fn render(
+    &mut self,
+    ctx: &mut EffectContext,
+    input: &wgpu::TextureView,
+    output: &wgpu::TextureView,
+    format: wgpu::TextureFormat,
+  ) {
+    let device = DeviceWarp::wrap(ctx.device);
+    let depth = ctx
+      .depth
+      .expect("effects that sample depth must report requires_depth");
+    let previous = &self.history[self.current];
+    let next = &self.history[1 - self.current];
+    let bind_group = device.create_bind_group(
+      "taa_bind_group",
+      &self.layout,
+      &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: wgpu::BindingResource::TextureView(input),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: wgpu::BindingResource::Sampler(&self.sampler),
+        },
+        wgpu::BindGroupEntry {
+          binding: 2,
+          resource: self.params.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 3,
+          resource: wgpu::BindingResource::TextureView(depth),
+        },
+        wgpu::BindGroupEntry {
+          binding: 4,
+          resource: wgpu::BindingResource::TextureView(&previous.view),
+        },
+      ],
+    );
+    // 结果同时写入输出与历史缓冲区
+    let pipeline = self.pipelines.entry(format).or_insert_with(|| {
+      let target = |format| {
+        Some(wgpu::ColorTargetState {
+          format,
+          blend: None,
+          write_mask: wgpu::ColorWrites::ALL,
+        })
+      };
+      device.create_render_pipeline(
+        "TAA Pipeline",
+        Some(&self.pipeline_layout),
+        wgpu::VertexState {
+          module: &self.shader,
+          entry_point: "vs_main",
+          buffers: &[],
+        },
+        wgpu::PrimitiveState::default(),
+        None,
+        wgpu::MultisampleState::default(),
+        wgpu::FragmentState {
+          module: &self.shader,
+          entry_point: "fs_main",
+          targets: &[target(format), target(HDR_FORMAT)],
+        },
+        None,
+      )
+    });
+    let attachment = |view| {
+      Some(wgpu::RenderPassColorAttachment {
+        view,
+        resolve_target: None,
+        ops: wgpu::Operations {
+          load: wgpu::LoadOp::Load,
+          store: wgpu::StoreOp::Store,
+        },
+      })
+    };
+    let mut pass = ctx.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      label: Some("TAA Pass"),
+      color_attachments: &[attachment(output), attachment(&next.view)],
+      ..Default::default()
+    });
+    pass.set_pipeline(pipeline);
+    pass.set_bind_group(0, &bind_group, &[]);
+    pass.draw(0..3, 0..1);
+    drop(pass);
+
+    self.current = 1 - self.current;
+    self.reset = false;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn jitter_stays_within_half_a_pixel() {
+    assert_eq!(halton(1, 2), 0.5);
+    assert_eq!(halton(2, 3), 2.0 / 3.0);
+    let jitters = (0..JITTER_PHASES)
+      .map(|frame| jitter(frame, 100, 50))
+      .collect::<Vec<_>>();
+    for j in &jitters {
+      assert!(j.x.abs() <= 1.0 / 100.0 && j.y.abs() <= 1.0 / 50.0);
+    }
+    // 序列中没有重复的位置，并且按周期循环
+    for (i, a) in jitters.iter().enumerate() {
+      assert!(jitters[i + 1..].iter().all(|b| a != b));
+    }
+    assert_eq!(jitter(JITTER_PHASES, 100, 50), jitters[0]);
+  }
+}
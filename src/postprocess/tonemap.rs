@@ -0,0 +1,393 @@
+//! 色调映射与曝光
+//!
+//! 场景渲染到 HDR 纹理中，光照结果可以超出 [0, 1]。这个效果按曝光缩放后用所选的曲线
+//! 映射到 [0, 1]。开启自动曝光时，两个 compute pass 先统计画面的对数亮度直方图，
+//! 再求出平均亮度并随时间向它渐变，模拟人眼的适应过程。
+
+use std::collections::HashMap;
+
+use super::{Effect, EffectContext, FrameInfo, Stage};
+use crate::exts::state::{DeviceTrait, DeviceWarp};
+
+/// 直方图 pass 的工作组边长
+const WORKGROUP_SIZE: u32 = 16;
+/// 直方图的 bin 数，与 exposure.wgsl 一致
+const HISTOGRAM_BINS: u64 = 256;
+
+/// 色调映射曲线，数值与 tonemap.wgsl 中的常量对应
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ToneMapping {
+  /// 不做映射，超出 [0, 1] 的部分直接截断
+  None = 0,
+  Reinhard = 1,
+  /// ACES 电影曲线的拟合
+  #[default]
+  Aces = 2,
+  /// 高光向白色过渡更自然，饱和颜色不易偏色
+  AgX = 3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AutoExposure {
+  /// 直方图覆盖的亮度范围 (log2)，超出的像素计入两端的 bin
+  pub min_log_luminance: f32,
+  pub max_log_luminance: f32,
+  /// 求平均时忽略最暗与最亮的像素，只统计这两个比例之间的部分
+  pub low_percentile: f32,
+  pub high_percentile: f32,
+  /// 画面变亮时的适应速度 (1/秒)
+  pub speed_brighten: f32,
+  /// 画面变暗时的适应速度 (1/秒)，人眼适应黑暗要慢得多
+  pub speed_darken: f32,
+}
+impl Default for AutoExposure {
+  fn default() -> Self {
+    Self {
+      min_log_luminance: -8.0,
+      max_log_luminance: 4.0,
+      low_percentile: 0.1,
+      high_percentile: 0.9,
+      speed_brighten: 3.0,
+      speed_darken: 1.0,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ToneMappingSettings {
+  pub curve: ToneMapping,
+  /// 曝光补偿 (EV)，每增加 1 亮度翻倍。自动曝光时叠加在测光结果上
+  pub exposure: f32,
+  /// 为 None 时只使用手动曝光
+  pub auto_exposure: Option<AutoExposure>,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ToneMappingUniform {
+  curve: u32,
+  auto_exposure: u32,
+  exposure: f32,
+  delta_time: f32,
+  min_log_luminance: f32,
+  log_luminance_range: f32,
+  low_percentile: f32,
+  high_percentile: f32,
+  speed_brighten: f32,
+  speed_darken: f32,
+  _padding: [u32; 2],
+}
+impl ToneMappingUniform {
+  fn new(settings: &ToneMappingSettings, delta_time: f32) -> Self {
+    let auto = settings.auto_exposure.unwrap_or_default();
+    Self {
+      curve: settings.curve as u32,
+      auto_exposure: settings.auto_exposure.is_some() as u32,
+      exposure: settings.exposure,
+      delta_time,
+      min_log_luminance: auto.min_log_luminance,
+      log_luminance_range: (auto.max_log_luminance - auto.min_log_luminance).max(f32::EPSILON),
+      low_percentile: auto.low_percentile,
+      high_percentile: auto.high_percentile,
+      speed_brighten: auto.speed_brighten,
+      speed_darken: auto.speed_darken,
+      _padding: [0; 2],
+    }
+  }
+}
+
+/// 与 shader 中的 Exposure 对应，initialized 为 0 时下一帧直接采用测光结果
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ExposureState {
+  log_luminance: f32,
+  initialized: u32,
+}
+
+pub struct ToneMapper {
+  settings: ToneMappingSettings,
+  enabled: bool,
+  // 下一帧开始前需要丢弃已适应的亮度
+  reset: bool,
+  buffer: wgpu::Buffer,
+  histogram: wgpu::Buffer,
+  exposure: wgpu::Buffer,
+  layout: wgpu::BindGroupLayout,
+  exposure_layout: wgpu::BindGroupLayout,
+  shader: wgpu::ShaderModule,
+  pipeline_layout: wgpu::PipelineLayout,
+  // 按输出格式缓存
+  pipelines: HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>,
+  histogram_pipeline: wgpu::ComputePipeline,
+  average_pipeline: wgpu::ComputePipeline,
+}
+
+impl ToneMapper {
+  pub fn new<T: DeviceTrait>(device: &T, settings: ToneMappingSettings) -> Self {
+    let hdr_entry = |visibility| wgpu::BindGroupLayoutEntry {
+      binding: 0,
+      visibility,
+      ty: wgpu::BindingType::Texture {
+        multisampled: false,
+        view_dimension: wgpu::TextureViewDimension::D2,
+        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+      },
+      count: None,
+    };
+    let uniform_entry = |visibility| wgpu::BindGroupLayoutEntry {
+      binding: 1,
+      visibility,
+      ty: wgpu::BindingType::Buffer {
+        ty: wgpu::BufferBindingType::Uniform,
+        has_dynamic_offset: false,
+        min_binding_size: None,
+      },
+      count: None,
+    };
+    let storage_entry = |binding, visibility, read_only| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility,
+      ty: wgpu::BindingType::Buffer {
+        ty: wgpu::BufferBindingType::Storage { read_only },
+        has_dynamic_offset: false,
+        min_binding_size: None,
+      },
+      count: None,
+    };
+    // 片元着色器只读取适应后的亮度，可写的存储缓冲只在 compute pass 中使用
+    let layout = device.create_bind_group_layout(
+      "tone_mapping_bind_group_layout",
+      &[
+        hdr_entry(wgpu::ShaderStages::FRAGMENT),
+        uniform_entry(wgpu::ShaderStages::FRAGMENT),
+        storage_entry(2, wgpu::ShaderStages::FRAGMENT, true),
+      ],
+    );
+    let exposure_layout = device.create_bind_group_layout(
+      "exposure_bind_group_layout",
+      &[
+        hdr_entry(wgpu::ShaderStages::COMPUTE),
+        uniform_entry(wgpu::ShaderStages::COMPUTE),
+        storage_entry(2, wgpu::ShaderStages::COMPUTE, false),
+        storage_entry(3, wgpu::ShaderStages::COMPUTE, false),
+      ],
+    );
+
+    let buffer = device.create_buffer_init(
+      "Tone Mapping Buffer",
+      bytemuck::cast_slice(&[ToneMappingUniform::new(&settings, 0.0)]),
+      wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    );
+    let histogram = device.create_buffer_init(
+      "Luminance Histogram Buffer",
+      bytemuck::cast_slice(&[0u32; HISTOGRAM_BINS as usize]),
+      wgpu::BufferUsages::STORAGE,
+    );
+    let exposure = device.create_buffer_init(
+      "Exposure Buffer",
+      bytemuck::cast_slice(&[ExposureState {
+        log_luminance: 0.0,
+        initialized: 0,
+      }]),
+      wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+    );
+
+    let shader = device.create_shader_module(wgpu::include_wgsl!("../../assets/tonemap.wgsl"));
+    let pipeline_layout =
+      device.create_pipeline_layout("Tone Mapping Pipeline Layout", &[&layout], &[]);
+
+    let exposure_shader =
+      device.create_shader_module(wgpu::include_wgsl!("../../assets/exposure.wgsl"));
+    let exposure_pipeline_layout =
+      device.create_pipeline_layout("Exposure Pipeline Layout", &[&exposure_layout], &[]);
+    let compute_pipeline = |entry_point| {
+      device
+        .get_device()
+        .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+          label: Some(entry_point),
+          layout: Some(&exposure_pipeline_layout),
+          module: &exposure_shader,
+          entry_point,
+        })
+    };
+    let histogram_pipeline = compute_pipeline("build_histogram");
+    let average_pipeline = compute_pipeline("average");
+
+    Self {
+      settings,
+      enabled: true,
+      reset: false,
+      buffer,
+      histogram,
+      exposure,
+      layout,
+      exposure_layout,
+      shader,
+      pipeline_layout,
+      pipelines: HashMap::new(),
+      histogram_pipeline,
+      average_pipeline,
+    }
+  }
+
+  pub fn settings(&self) -> &ToneMappingSettings {
+    &self.settings
+  }
+
+  /// 开启自动曝光或修改其参数时，下一帧直接采用测光结果而不是从旧值渐变过去
+  pub fn set_settings(&mut self, settings: ToneMappingSettings) {
+    if settings.auto_exposure != self.settings.auto_exposure {
+      self.reset_adaptation();
+    }
+    self.settings = settings;
+  }
+
+  /// 丢弃已适应的亮度，用于镜头切换等不希望出现渐变的场合
+  pub fn reset_adaptation(&mut self) {
+    self.reset = true;
+  }
+}
+
+impl Effect for ToneMapper {
+  fn name(&self) -> &str {
+    "tone_mapping"
+  }
+
+  fn stage(&self) -> Stage {
+    Stage::ToneMapping
+  }
+
+  fn enabled(&self) -> bool {
+    self.enabled
+  }
+
+  fn set_enabled(&mut self, enabled: bool) {
+    self.enabled = enabled;
+  }
+
+  /// delta_time 决定本帧自动曝光的适应程度
+  fn update(&mut self, queue: &wgpu::Queue, frame: &FrameInfo) {
+    queue.write_buffer(
+      &self.buffer,
+      0,
+      bytemuck::cast_slice(&[ToneMappingUniform::new(&self.settings, frame.delta_time)]),
+    );
+    if std::mem::take(&mut self.reset) {
+      queue.write_buffer(
+        &self.exposure,
+        0,
+        bytemuck::cast_slice(&[ExposureState {
+          log_luminance: 0.0,
+          initialized: 0,
+        }]),
+      );
+    }
+  }
+
+  fn render(
+    &mut self,
+    ctx: &mut EffectContext,
+    input: &wgpu::TextureView,
+    output: &wgpu::TextureView,
+    format: wgpu::TextureFormat,
+  ) {
+    let device = DeviceWarp::wrap(ctx.device);
+    if self.settings.auto_exposure.is_some() {
+      let bind_group = device.create_bind_group(
+        "exposure_bind_group",
+        &self.exposure_layout,
+        &[
+          wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(input),
+          },
+          wgpu::BindGroupEntry {
+            binding: 1,
+            resource: self.buffer.as_entire_binding(),
+          },
+          wgpu::BindGroupEntry {
+            binding: 2,
+            resource: self.histogram.as_entire_binding(),
+          },
+          wgpu::BindGroupEntry {
+            binding: 3,
+            resource: self.exposure.as_entire_binding(),
+          },
+        ],
+      );
+      let mut pass = ctx
+        .encoder
+        .begin_compute_pass(&wgpu::ComputePassDescriptor {
+          label: Some("Exposure Pass"),
+          timestamp_writes: None,
+        });
+      pass.set_bind_group(0, &bind_group, &[]);
+      pass.set_pipeline(&self.histogram_pipeline);
+      pass.dispatch_workgroups(
+        ctx.width.div_ceil(WORKGROUP_SIZE),
+        ctx.height.div_ceil(WORKGROUP_SIZE),
+        1,
+      );
+      pass.set_pipeline(&self.average_pipeline);
+      pass.dispatch_workgroups(1, 1, 1);
+    }
+
+    let pipeline = self.pipelines.entry(format).or_insert_with(|| {
+      device.create_render_pipeline(
+        "Tone Mapping Pipeline",
+        Some(&self.pipeline_layout),
+        wgpu::VertexState {
+          module: &self.shader,
+          entry_point: "vs_main",
+          buffers: &[],
+        },
+        wgpu::PrimitiveState::default(),
+        None,
+        wgpu::MultisampleState::default(),
+        wgpu::FragmentState {
+          module: &self.shader,
+          entry_point: "fs_main",
+          targets: &[Some(wgpu::ColorTargetState {
+            format,
+            blend: None,
+            write_mask: wgpu::ColorWrites::ALL,
+          })],
+        },
+        None,
+      )
+    });
+    let bind_group = device.create_bind_group(
+      "tone_mapping_bind_group",
+      &self.layout,
+      &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: wgpu::BindingResource::TextureView(input),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: self.buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 2,
+          resource: self.exposure.as_entire_binding(),
+        },
+      ],
+    );
+    let mut pass = ctx.encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+      label: Some("Tone Mapping Pass"),
+      color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+        view: output,
+        resolve_target: None,
+        ops: wgpu::Operations {
+          load: wgpu::LoadOp::Load,
+          store: wgpu::StoreOp::Store,
+        },
+      })],
+      ..Default::default()
+    });
+    pass.set_pipeline(pipeline);
+    pass.set_bind_group(0, &bind_group, &[]);
+    pass.draw(0..3, 0..1);
+  }
+}
@@ -0,0 +1,108 @@
+//! 暗角效果
+
+use super::{Effect, EffectContext, FrameInfo, FullscreenPass, Stage};
+use crate::exts::state::DeviceTrait;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VignetteSettings {
+  /// 四角最多变暗的比例，0 时没有效果
+  pub intensity: f32,
+  /// 开始变暗的位置，0 为画面中心，1 为四边中点
+  pub radius: f32,
+  /// 从不变暗过渡到最暗的距离
+  pub softness: f32,
+}
+impl Default for VignetteSettings {
+  fn default() -> Self {
+    Self {
+      intensity: 0.4,
+      radius: 0.6,
+      softness: 0.8,
+    }
+  }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct VignetteUniform {
+  intensity: f32,
+  radius: f32,
+  softness: f32,
+  _padding: f32,
+}
+impl From<VignetteSettings> for VignetteUniform {
+  fn from(settings: VignetteSettings) -> Self {
+    Self {
+      intensity: settings.intensity,
+      radius: settings.radius,
+      softness: settings.softness,
+      _padding: 0.0,
+    }
+  }
+}
+
+pub struct Vignette {
+  settings: VignetteSettings,
+  enabled: bool,
+  pass: FullscreenPass,
+}
+
+impl Vignette {
+  pub fn new<T: DeviceTrait>(device: &T, settings: VignetteSettings) -> Self {
+    let pass = FullscreenPass::new(
+      device,
+      "vignette",
+      include_str!("../../assets/vignette.wgsl"),
+      Some(bytemuck::cast_slice(&[VignetteUniform::from(settings)])),
+      false,
+    );
+    Self {
+      settings,
+      enabled: true,
+      pass,
+    }
+  }
+
+  pub fn settings(&self) -> &VignetteSettings {
+    &self.settings
+  }
+
+  pub fn set_settings(&mut self, settings: VignetteSettings) {
+    self.settings = settings;
+  }
+}
+
+impl Effect for Vignette {
+  fn name(&self) -> &str {
+    "vignette"
+  }
+
+  fn stage(&self) -> Stage {
+    Stage::Ldr
+  }
+
+  fn enabled(&self) -> bool {
+    self.enabled
+  }
+
+  fn set_enabled(&mut self, enabled: bool) {
+    self.enabled = enabled;
+  }
+
+  fn update(&mut self, queue: &wgpu::Queue, _frame: &FrameInfo) {
+    self.pass.write_params(
+      queue,
+      bytemuck::cast_slice(&[VignetteUniform::from(self.settings)]),
+    );
+  }
+
+  fn render(
+    &mut self,
+    ctx: &mut EffectContext,
+    input: &wgpu::TextureView,
+    output: &wgpu::TextureView,
+    format: wgpu::TextureFormat,
+  ) {
+    self.pass.draw(ctx, input, output, format);
+  }
+}
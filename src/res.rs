@@ -1,12 +1,22 @@
 use std::{
+  collections::HashMap,
   io::{BufReader, Cursor},
-  path::Path,
+  path::{Path, PathBuf},
 };
 
-use color_eyre::eyre::Result;
-use tracing::{debug, instrument};
+use base64::Engine;
+use color_eyre::eyre::{eyre, Result};
+use na::{Matrix3, Matrix4, Point2, Point3, Vector3};
+use tracing::{debug, instrument, warn};
 
-use crate::{exts::state::DeviceTrait, model, texture};
+use crate::{
+  compressed,
+  exts::state::DeviceTrait,
+  material::{AlphaMode, MaterialParams, ShadingModel},
+  model,
+  postprocess::grading::Lut,
+  texture,
+};
 
 #[instrument]
 pub async fn load_str(filepath: &Path) -> Result<String> {
@@ -31,22 +41,139 @@ pub async fn load_binary(filepath: &Path) -> Result<Vec<u8>> {
 }
 pub async fn load_texture<T: DeviceTrait>(
   filename: &Path,
+  is_normal_map: bool,
   device: &T,
   queue: &wgpu::Queue,
 ) -> Result<texture::Texture> {
   let data = load_binary(filename).await?;
-  texture::Texture::from_bytes(device, queue, &data, &filename.to_string_lossy())
+  // KTX2 与 DDS 容器按文件头识别，压缩纹理的色彩空间由文件中的格式决定
+  if compressed::is_container(&data) {
+    let texture = compressed::TextureData::parse(&data)?;
+    return texture::Texture::from_compressed(
+      device,
+      queue,
+      &texture,
+      Some(&filename.to_string_lossy()),
+      &texture::TextureSettings::default(),
+    );
+  }
+  texture::Texture::from_bytes(
+    device,
+    queue,
+    &data,
+    &filename.to_string_lossy(),
+    is_normal_map,
+  )
+}
+
+/// 加载 Radiance HDR 等高动态范围图像，保留超过 1 的线性颜色值
+pub async fn load_hdr(filename: &Path) -> Result<image::Rgba32FImage> {
+  let data = load_binary(filename).await?;
+  Ok(image::load_from_memory(&data)?.to_rgba32f())
+}
+
+/// 加载调色用的 LUT：.cube 文件按文本解析，其余按条带图片读取
+pub async fn load_lut(filename: &Path) -> Result<Lut> {
+  let is_cube = filename
+    .extension()
+    .is_some_and(|ext| ext.eq_ignore_ascii_case("cube"));
+  if is_cube {
+    Lut::parse_cube(&load_str(filename).await?)
+  } else {
+    let data = load_binary(filename).await?;
+    Lut::from_strip(&image::load_from_memory(&data)?.to_rgba32f())
+  }
 }
 
+/// 加载六张图片组成的立方体贴图，顺序为 +X -X +Y -Y +Z -Z
+pub async fn load_cube_texture<T: DeviceTrait>(
+  faces: [&Path; 6],
+  device: &T,
+  queue: &wgpu::Queue,
+) -> Result<texture::Texture> {
+  let mut images = Vec::with_capacity(6);
+  for face in faces {
+    let data = load_binary(face).await?;
+    images.push(image::load_from_memory(&data)?);
+  }
+  let images: [image::DynamicImage; 6] = images
+    .try_into()
+    .map_err(|_| eyre!("expected six cube map faces"))?;
+  texture::Texture::from_cube_images(device, queue, &images, Some(&faces[0].to_string_lossy()))
+}
+
+/// 按扩展名选择加载器：.gltf 与 .glb 使用 glTF，其余按 OBJ 处理。
+/// 文件中没有法线的网格按 normal_mode 生成法线
 pub async fn load_model<T: DeviceTrait>(
   filename: &Path,
+  normal_mode: model::Normals,
+  device: &T,
+  queue: &wgpu::Queue,
+  layout: &wgpu::BindGroupLayout,
+) -> Result<model::Model, ModelError> {
+  match filename.extension().and_then(|e| e.to_str()) {
+    Some(ext) if ext.eq_ignore_ascii_case("gltf") || ext.eq_ignore_ascii_case("glb") => {
+      load_gltf(filename, normal_mode, device, queue, layout).await
+    }
+    _ => load_obj(filename, normal_mode, device, queue, layout).await,
+  }
+}
+
+/// 加载 OBJ 与 glTF 模型时可能出现的错误
+///
+/// 缺失的法线、纹理坐标、MTL 文件与贴图不算错误，会以默认值代替并输出警告
+#[derive(Debug, thiserror::Error)]
+pub enum ModelError {
+  #[error("failed to read {path}")]
+  Read {
+    path: PathBuf,
+    #[source]
+    source: Box<dyn std::error::Error + Send + Sync>,
+  },
+  #[error("failed to parse {path}")]
+  Parse {
+    path: PathBuf,
+    #[source]
+    source: tobj::LoadError,
+  },
+  #[error("failed to parse {path}")]
+  ParseGltf {
+    path: PathBuf,
+    #[source]
+    source: gltf::Error,
+  },
+  #[error("{path}: {reason}")]
+  InvalidData { path: PathBuf, reason: String },
+  #[error("{path} contains no meshes")]
+  Empty { path: PathBuf },
+  #[error("{path}: mesh {mesh} is malformed: {reason}")]
+  InvalidMesh {
+    path: PathBuf,
+    mesh: String,
+    reason: String,
+  },
+  #[error("failed to create {what} for {path}")]
+  Texture {
+    path: PathBuf,
+    what: &'static str,
+    #[source]
+    source: Box<dyn std::error::Error + Send + Sync>,
+  },
+}
+
+pub async fn load_obj<T: DeviceTrait>(
+  filename: &Path,
+  normal_mode: model::Normals,
   device: &T,
   queue: &wgpu::Queue,
   layout: &wgpu::BindGroupLayout,
-) -> Result<model::Model> {
-  let obj_text = load_str(filename).await?;
-  let obj_cursor = Cursor::new(obj_text);
-  let mut obj_reader = BufReader::new(obj_cursor);
+) -> Result<model::Model, ModelError> {
+  let obj_text = load_str(filename).await.map_err(|e| ModelError::Read {
+    path: filename.to_path_buf(),
+    source: e.into(),
+  })?;
+  let parent = filename.parent().unwrap_or(Path::new(""));
+  let mut obj_reader = BufReader::new(Cursor::new(obj_text));
   let (models, obj_materials) = tobj::load_obj_buf_async(
     &mut obj_reader,
     &tobj::LoadOptions {
@@ -55,74 +182,908 @@ pub async fn load_model<T: DeviceTrait>(
       ..Default::default()
     },
     |p| async move {
-      let parent = Path::new(filename).parent().unwrap();
-      let mat_text = load_str(parent.join(&p).as_path()).await.unwrap();
-      tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mat_text)))
+      let path = parent.join(&p);
+      match load_str(&path).await {
+        Ok(mat_text) => tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mat_text))),
+        Err(e) => {
+          warn!("failed to read {}: {e}", path.display());
+          Err(tobj::LoadError::OpenFileFailed)
+        }
+      }
     },
   )
-  .await?;
+  .await
+  .map_err(|source| ModelError::Parse {
+    path: filename.to_path_buf(),
+    source,
+  })?;
+  if models.is_empty() {
+    return Err(ModelError::Empty {
+      path: filename.to_path_buf(),
+    });
+  }
+
+  let texture_error = |what| {
+    move |e: color_eyre::Report| ModelError::Texture {
+      path: filename.to_path_buf(),
+      what,
+      source: e.into(),
+    }
+  };
+  let obj_materials = obj_materials.unwrap_or_else(|e| {
+    warn!("{}: no usable materials ({e})", filename.display());
+    Vec::new()
+  });
   let mut materials = Vec::new();
-  let parent = filename.parent().unwrap();
-  for m in obj_materials? {
-    let diffuse_texture =
-      load_texture(parent.join(&m.diffuse_texture).as_path(), device, queue).await?;
-    let bind_group = device.create_bind_group(
-      "",
-      layout,
-      &[
-        wgpu::BindGroupEntry {
-          binding: 0,
-          resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-        },
-        wgpu::BindGroupEntry {
-          binding: 1,
-          resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-        },
-      ],
+  for m in obj_materials {
+    let mut params = MaterialParams {
+      specular: m.specular.into(),
+      ..Default::default()
+    };
+    if m.shininess > 0.0 {
+      params.shininess = m.shininess;
+    }
+    if m.dissolve < 1.0 {
+      params.base_color.w = m.dissolve;
+      params.alpha_mode = AlphaMode::Blend;
+    }
+    let diffuse_texture = if m.diffuse_texture.is_empty() {
+      // 没有贴图时使用 Kd 的颜色。有贴图时忽略 Kd，导出工具常在这里写入与贴图无关的灰色
+      let [r, g, b] = m.diffuse;
+      params.base_color.x = r;
+      params.base_color.y = g;
+      params.base_color.z = b;
+      texture::Texture::from_image(device, queue, &white_pixel(), Some(&m.name), false)
+    } else {
+      match load_texture(&parent.join(&m.diffuse_texture), false, device, queue).await {
+        Ok(texture) => Ok(texture),
+        Err(e) => {
+          warn!("{}: {e}, using a checkerboard", m.diffuse_texture);
+          texture::Texture::checkerboard(device, queue)
+        }
+      }
+    }
+    .map_err(texture_error("diffuse texture"))?;
+    // MTL 中的 map_Bump / bump 条目
+    let normal_texture = if m.normal_texture.is_empty() {
+      texture::Texture::flat_normal(device, queue)
+    } else {
+      match load_texture(&parent.join(&m.normal_texture), true, device, queue).await {
+        Ok(texture) => Ok(texture),
+        Err(e) => {
+          warn!("{}: {e}, ignoring the normal map", m.normal_texture);
+          texture::Texture::flat_normal(device, queue)
+        }
+      }
+    }
+    .map_err(texture_error("normal texture"))?;
+
+    // PBR 扩展：Pr / Pm 与 map_Pr / map_Pm，以及自发光 Ke / map_Ke 与 map_ao
+    let metallic_map = load_mtl_image(parent, &m, "map_Pm").await;
+    let roughness_map = load_mtl_image(parent, &m, "map_Pr").await;
+    let metallic = mtl_float(&m, "Pm");
+    let roughness = mtl_float(&m, "Pr");
+    if metallic.is_some()
+      || roughness.is_some()
+      || metallic_map.is_some()
+      || roughness_map.is_some()
+    {
+      params.shading = ShadingModel::Pbr;
+      // 只有贴图时由贴图决定，什么都没有时按非金属处理
+      params.metallic = metallic.unwrap_or(if metallic_map.is_some() { 1.0 } else { 0.0 });
+      params.roughness = roughness.unwrap_or(1.0);
+    }
+    let metallic_roughness = match pack_metallic_roughness(metallic_map, roughness_map) {
+      Some(img) => texture::Texture::from_image(
+        device,
+        queue,
+        &image::DynamicImage::ImageRgba8(img),
+        Some(&format!("{} metallic roughness", m.name)),
+        true,
+      ),
+      None => texture::Texture::white(device, queue, true),
+    }
+    .map_err(texture_error("metallic roughness texture"))?;
+    let occlusion = match load_mtl_image(parent, &m, "map_ao").await {
+      Some(img) => texture::Texture::from_image(
+        device,
+        queue,
+        &img,
+        Some(&format!("{} occlusion", m.name)),
+        true,
+      ),
+      None => texture::Texture::white(device, queue, true),
+    }
+    .map_err(texture_error("occlusion texture"))?;
+    let emissive_map = load_mtl_image(parent, &m, "map_Ke").await;
+    if let Some([r, g, b]) = mtl_color(&m, "Ke") {
+      params.emissive = Vector3::new(r, g, b);
+    } else if emissive_map.is_some() {
+      params.emissive = Vector3::new(1.0, 1.0, 1.0);
+    }
+    let emissive = match emissive_map {
+      Some(img) => texture::Texture::from_image(
+        device,
+        queue,
+        &img,
+        Some(&format!("{} emissive", m.name)),
+        false,
+      ),
+      None => texture::Texture::white(device, queue, false),
+    }
+    .map_err(texture_error("emissive texture"))?;
+
+    let textures = model::MaterialTextures {
+      diffuse: diffuse_texture,
+      normal: normal_texture,
+      metallic_roughness,
+      occlusion,
+      emissive,
+    };
+    materials.push(model::Material::new(
+      device, &m.name, textures, params, layout,
+    ));
+  }
+  // 没有材质或材质下标越界的网格使用追加在末尾的棋盘格材质
+  let fallback_material = materials.len();
+  let mut uses_fallback_material = false;
+
+  let mut meshes = Vec::new();
+  for m in models {
+    let (vertices, indices) = obj_vertices(filename, &m, normal_mode)?;
+    let vertex_buffer = device.create_buffer_init(
+      &format!("{} Vertex Buffer", filename.display()),
+      bytemuck::cast_slice(&vertices),
+      wgpu::BufferUsages::VERTEX,
+    );
+    let index_buffer = device.create_buffer_init(
+      &format!("{} Index Buffer", filename.display()),
+      bytemuck::cast_slice(&indices),
+      wgpu::BufferUsages::INDEX,
     );
-    materials.push(model::Material {
-      name: m.name,
-      diffuse_texture,
-      bind_group,
+    let material = match m.mesh.material_id {
+      Some(id) if id < fallback_material => id,
+      _ => {
+        uses_fallback_material = true;
+        fallback_material
+      }
+    };
+    meshes.push(model::Mesh {
+      name: filename.to_string_lossy().to_string(),
+      vertex_buffer,
+      index_buffer,
+      num_elements: indices.len() as u32,
+      material,
     });
   }
-  let meshes = models
-    .into_iter()
-    .map(|m| {
-      let vertices = (0..m.mesh.positions.len() / 3)
-        .map(|i| model::ModelVertex {
-          position: na::Point3::new(
-            m.mesh.positions[i * 3],
-            m.mesh.positions[i * 3 + 1],
-            m.mesh.positions[i * 3 + 2],
-          ),
-          tex_coords: na::Point2::new(m.mesh.texcoords[i * 2], m.mesh.texcoords[i * 2 + 1]),
-          normal: na::Vector3::new(
-            m.mesh.normals[i * 3],
-            m.mesh.normals[i * 3 + 1],
-            m.mesh.normals[i * 3 + 2],
-          ),
-        })
-        .collect::<Vec<_>>();
+
+  if uses_fallback_material {
+    let textures = texture::Texture::checkerboard(device, queue)
+      .and_then(|diffuse| {
+        let normal = texture::Texture::flat_normal(device, queue)?;
+        model::MaterialTextures::new(device, queue, diffuse, normal)
+      })
+      .map_err(texture_error("fallback texture"))?;
+    materials.push(model::Material::new(
+      device,
+      "fallback",
+      textures,
+      MaterialParams::default(),
+      layout,
+    ));
+  }
+  Ok(model::Model { meshes, materials })
+}
+
+/// 把 tobj 的网格转换为顶点与索引，缺失的纹理坐标为 0，缺失的法线按 normal_mode 生成
+fn obj_vertices(
+  filename: &Path,
+  m: &tobj::Model,
+  normal_mode: model::Normals,
+) -> Result<(Vec<model::ModelVertex>, Vec<u32>), ModelError> {
+  let mesh = &m.mesh;
+  let invalid = |reason: String| ModelError::InvalidMesh {
+    path: filename.to_path_buf(),
+    mesh: m.name.clone(),
+    reason,
+  };
+  if !mesh.positions.len().is_multiple_of(3) {
+    return Err(invalid(format!(
+      "{} position components is not a multiple of 3",
+      mesh.positions.len()
+    )));
+  }
+  let count = mesh.positions.len() / 3;
+  let has_normals = !mesh.normals.is_empty();
+  let has_tex_coords = !mesh.texcoords.is_empty();
+  if has_normals && mesh.normals.len() != count * 3 {
+    return Err(invalid(format!(
+      "{} normals for {count} vertices",
+      mesh.normals.len() / 3
+    )));
+  }
+  if has_tex_coords && mesh.texcoords.len() != count * 2 {
+    return Err(invalid(format!(
+      "{} texture coordinates for {count} vertices",
+      mesh.texcoords.len() / 2
+    )));
+  }
+  if !mesh.indices.len().is_multiple_of(3) {
+    return Err(invalid(format!(
+      "{} indices do not form triangles",
+      mesh.indices.len()
+    )));
+  }
+  if let Some(index) = mesh.indices.iter().find(|&&i| i as usize >= count) {
+    return Err(invalid(format!(
+      "index {index} is out of range for {count} vertices"
+    )));
+  }
+
+  let mut vertices = (0..count)
+    .map(|i| model::ModelVertex {
+      position: Point3::new(
+        mesh.positions[i * 3],
+        mesh.positions[i * 3 + 1],
+        mesh.positions[i * 3 + 2],
+      ),
+      tex_coords: if has_tex_coords {
+        Point2::new(mesh.texcoords[i * 2], mesh.texcoords[i * 2 + 1])
+      } else {
+        Point2::origin()
+      },
+      normal: if has_normals {
+        Vector3::new(
+          mesh.normals[i * 3],
+          mesh.normals[i * 3 + 1],
+          mesh.normals[i * 3 + 2],
+        )
+      } else {
+        Vector3::zeros()
+      },
+      // 稍后计算
+      tangent: Vector3::zeros(),
+      bitangent: Vector3::zeros(),
+    })
+    .collect::<Vec<_>>();
+  let mut indices = mesh.indices.clone();
+  if !has_normals {
+    model::generate_normals(normal_mode, &mut vertices, &mut indices);
+  }
+  model::compute_tangents(&mut vertices, &indices);
+  Ok((vertices, indices))
+}
+
+/// 加载 glTF 2.0 模型 (.gltf 或 .glb)
+///
+/// 场景中每个节点的变换会直接烘焙进顶点，每个图元对应一个 [`model::Mesh`]。
+/// 材质的 base color、metallic/roughness 与自发光因子放进 [`MaterialParams`]，在着色时与贴图相乘，
+/// 贴图本身保持原样。
+pub async fn load_gltf<T: DeviceTrait>(
+  filename: &Path,
+  normal_mode: model::Normals,
+  device: &T,
+  queue: &wgpu::Queue,
+  layout: &wgpu::BindGroupLayout,
+) -> Result<model::Model, ModelError> {
+  let read_error = |e: color_eyre::Report| ModelError::Read {
+    path: filename.to_path_buf(),
+    source: e.into(),
+  };
+  let invalid = |reason: String| ModelError::InvalidData {
+    path: filename.to_path_buf(),
+    reason,
+  };
+  let texture_error = |what| {
+    move |e: color_eyre::Report| ModelError::Texture {
+      path: filename.to_path_buf(),
+      what,
+      source: e.into(),
+    }
+  };
+  let data = load_binary(filename).await.map_err(read_error)?;
+  let gltf::Gltf { document, mut blob } =
+    gltf::Gltf::from_slice(&data).map_err(|source| ModelError::ParseGltf {
+      path: filename.to_path_buf(),
+      source,
+    })?;
+  let parent = filename.parent().unwrap_or(Path::new(""));
+
+  let mut buffers = Vec::new();
+  for buffer in document.buffers() {
+    let data = match buffer.source() {
+      gltf::buffer::Source::Bin => blob
+        .take()
+        .ok_or_else(|| invalid("missing GLB binary chunk".to_string()))?,
+      gltf::buffer::Source::Uri(uri) => load_uri(parent, uri).await.map_err(read_error)?,
+    };
+    if data.len() < buffer.length() {
+      return Err(invalid(format!(
+        "buffer {} is {} bytes, expected {}",
+        buffer.index(),
+        data.len(),
+        buffer.length()
+      )));
+    }
+    buffers.push(data);
+  }
+
+  // 同一张图片可能被多个材质引用，只解码一次
+  let mut images = HashMap::new();
+  let mut materials = Vec::new();
+  for material in document.materials() {
+    let name = material
+      .name()
+      .map(str::to_string)
+      .unwrap_or_else(|| format!("{} material {}", filename.display(), materials.len()));
+    let pbr = material.pbr_metallic_roughness();
+
+    let diffuse = async {
+      let base_color = match pbr.base_color_texture() {
+        Some(info) => {
+          load_gltf_image(&info.texture().source(), &buffers, parent, &mut images).await?
+        }
+        None => white_pixel(),
+      };
+      texture::Texture::from_image(device, queue, &base_color, Some(&name), false)
+    }
+    .await
+    .map_err(texture_error("base color texture"))?;
+    let normal = match material.normal_texture() {
+      Some(info) => {
+        async {
+          let image =
+            load_gltf_image(&info.texture().source(), &buffers, parent, &mut images).await?;
+          texture::Texture::from_image(device, queue, &image, Some(&format!("{name} normal")), true)
+        }
+        .await
+      }
+      None => texture::Texture::flat_normal(device, queue),
+    }
+    .map_err(texture_error("normal texture"))?;
+    let metallic_roughness = match pbr.metallic_roughness_texture() {
+      Some(info) => {
+        async {
+          let image =
+            load_gltf_image(&info.texture().source(), &buffers, parent, &mut images).await?;
+          texture::Texture::from_image(
+            device,
+            queue,
+            &image,
+            Some(&format!("{name} metallic roughness")),
+            true,
+          )
+        }
+        .await
+      }
+      None => texture::Texture::white(device, queue, true),
+    }
+    .map_err(texture_error("metallic roughness texture"))?;
+    let occlusion = match material.occlusion_texture() {
+      Some(info) => {
+        async {
+          let image =
+            load_gltf_image(&info.texture().source(), &buffers, parent, &mut images).await?;
+          texture::Texture::from_image(
+            device,
+            queue,
+            &image,
+            Some(&format!("{name} occlusion")),
+            true,
+          )
+        }
+        .await
+      }
+      None => texture::Texture::white(device, queue, true),
+    }
+    .map_err(texture_error("occlusion texture"))?;
+    let emissive = match material.emissive_texture() {
+      Some(info) => {
+        async {
+          let image =
+            load_gltf_image(&info.texture().source(), &buffers, parent, &mut images).await?;
+          texture::Texture::from_image(
+            device,
+            queue,
+            &image,
+            Some(&format!("{name} emissive")),
+            false,
+          )
+        }
+        .await
+      }
+      None => texture::Texture::white(device, queue, false),
+    }
+    .map_err(texture_error("emissive texture"))?;
+    let params = MaterialParams {
+      shading: ShadingModel::Pbr,
+      base_color: pbr.base_color_factor().into(),
+      metallic: pbr.metallic_factor(),
+      roughness: pbr.roughness_factor(),
+      occlusion_strength: material
+        .occlusion_texture()
+        .map_or(1.0, |info| info.strength()),
+      // KHR_materials_emissive_strength 允许自发光超过 1，配合泛光产生光晕
+      emissive: Vector3::from(material.emissive_factor())
+        * material.emissive_strength().unwrap_or(1.0),
+      alpha_mode: match material.alpha_mode() {
+        gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+        gltf::material::AlphaMode::Mask => AlphaMode::Mask {
+          cutoff: material.alpha_cutoff().unwrap_or(0.5),
+        },
+        gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+      },
+      double_sided: material.double_sided(),
+      ..Default::default()
+    };
+    let textures = model::MaterialTextures {
+      diffuse,
+      normal,
+      metallic_roughness,
+      occlusion,
+      emissive,
+    };
+    materials.push(model::Material::new(
+      device, &name, textures, params, layout,
+    ));
+  }
+  // 没有指定材质的图元使用追加在末尾的默认材质
+  let default_material = materials.len();
+  let mut uses_default_material = false;
+
+  let scene = document
+    .default_scene()
+    .or_else(|| document.scenes().next())
+    .ok_or_else(|| invalid("no scene to load".to_string()))?;
+  let mut meshes = Vec::new();
+  let mut nodes = scene
+    .nodes()
+    .map(|node| (node, Matrix4::identity()))
+    .collect::<Vec<_>>();
+  while let Some((node, parent_transform)) = nodes.pop() {
+    let transform = parent_transform * Matrix4::from(node.transform().matrix());
+    nodes.extend(node.children().map(|child| (child, transform)));
+    let Some(mesh) = node.mesh() else {
+      continue;
+    };
+
+    for primitive in mesh.primitives() {
+      let label = format!(
+        "{} {}#{}",
+        filename.display(),
+        mesh.name().unwrap_or("mesh"),
+        primitive.index()
+      );
+      if primitive.mode() != gltf::mesh::Mode::Triangles {
+        warn!(
+          "{label}: skipping primitive with mode {:?}",
+          primitive.mode()
+        );
+        continue;
+      }
+      let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+      let Some(positions) = reader.read_positions() else {
+        warn!("{label}: skipping primitive without positions");
+        continue;
+      };
+      let (vertices, indices) = gltf_vertices(
+        filename,
+        &label,
+        GltfPrimitive {
+          positions: positions.collect(),
+          normals: reader.read_normals().map(Iterator::collect),
+          tex_coords: reader.read_tex_coords(0).map(|t| t.into_f32().collect()),
+          indices: reader.read_indices().map(|i| i.into_u32().collect()),
+        },
+        &transform,
+        normal_mode,
+      )?;
 
       let vertex_buffer = device.create_buffer_init(
-        &format!("{} Vertex Buffer", filename.display()),
+        &format!("{label} Vertex Buffer"),
         bytemuck::cast_slice(&vertices),
         wgpu::BufferUsages::VERTEX,
       );
       let index_buffer = device.create_buffer_init(
-        &format!("{} Index Buffer", filename.display()),
-        bytemuck::cast_slice(&m.mesh.indices),
+        &format!("{label} Index Buffer"),
+        bytemuck::cast_slice(&indices),
         wgpu::BufferUsages::INDEX,
       );
-      model::Mesh {
-        name: filename.to_string_lossy().to_string(),
+      let material = primitive.material().index().unwrap_or_else(|| {
+        uses_default_material = true;
+        default_material
+      });
+      meshes.push(model::Mesh {
+        name: label,
         vertex_buffer,
         index_buffer,
-        num_elements: m.mesh.indices.len() as u32,
-        material: m.mesh.material_id.unwrap_or(0),
-      }
+        num_elements: indices.len() as u32,
+        material,
+      });
+    }
+  }
+
+  if uses_default_material {
+    let textures = texture::Texture::white(device, queue, false)
+      .and_then(|diffuse| {
+        let normal = texture::Texture::flat_normal(device, queue)?;
+        model::MaterialTextures::new(device, queue, diffuse, normal)
+      })
+      .map_err(texture_error("default texture"))?;
+    // 与 glTF 规范中未指定材质时的默认值一致
+    let params = MaterialParams {
+      shading: ShadingModel::Pbr,
+      ..Default::default()
+    };
+    materials.push(model::Material::new(
+      device, "default", textures, params, layout,
+    ));
+  }
+  Ok(model::Model { meshes, materials })
+}
+
+/// 从 glTF 图元中读出的顶点属性
+struct GltfPrimitive {
+  positions: Vec<[f32; 3]>,
+  normals: Option<Vec<[f32; 3]>>,
+  tex_coords: Option<Vec<[f32; 2]>>,
+  /// 为 None 时按顺序每三个顶点组成一个三角形
+  indices: Option<Vec<u32>>,
+}
+
+/// 把图元烘焙上节点的变换，转换为顶点与索引。缺失的纹理坐标为 0，缺失的法线按 normal_mode 生成
+fn gltf_vertices(
+  filename: &Path,
+  label: &str,
+  primitive: GltfPrimitive,
+  transform: &Matrix4<f32>,
+  normal_mode: model::Normals,
+) -> Result<(Vec<model::ModelVertex>, Vec<u32>), ModelError> {
+  let invalid = |reason: String| ModelError::InvalidMesh {
+    path: filename.to_path_buf(),
+    mesh: label.to_string(),
+    reason,
+  };
+  let GltfPrimitive {
+    positions,
+    normals,
+    tex_coords,
+    indices,
+  } = primitive;
+  let count = positions.len();
+  if let Some(normals) = normals.as_ref().filter(|n| n.len() != count) {
+    return Err(invalid(format!(
+      "{} normals for {count} vertices",
+      normals.len()
+    )));
+  }
+  if let Some(tex_coords) = tex_coords.as_ref().filter(|t| t.len() != count) {
+    return Err(invalid(format!(
+      "{} texture coordinates for {count} vertices",
+      tex_coords.len()
+    )));
+  }
+  let mut indices = indices.unwrap_or_else(|| (0..count as u32).collect());
+  if !indices.len().is_multiple_of(3) {
+    return Err(invalid(format!(
+      "{} indices do not form triangles",
+      indices.len()
+    )));
+  }
+  if let Some(index) = indices.iter().find(|&&i| i as usize >= count) {
+    return Err(invalid(format!(
+      "index {index} is out of range for {count} vertices"
+    )));
+  }
+
+  // 法线使用逆转置矩阵变换，镜像变换会翻转三角形的环绕方向
+  let linear = transform.fixed_slice::<3, 3>(0, 0).into_owned();
+  let normal_matrix = linear
+    .try_inverse()
+    .map(|m| m.transpose())
+    .unwrap_or_else(Matrix3::identity);
+  if linear.determinant() < 0.0 {
+    for triangle in indices.chunks_exact_mut(3) {
+      triangle.swap(1, 2);
+    }
+  }
+
+  let mut vertices = positions
+    .iter()
+    .enumerate()
+    .map(|(i, position)| model::ModelVertex {
+      position: transform.transform_point(&Point3::from(*position)),
+      // glTF 的纹理坐标原点在左上角，与 wgpu 一致
+      tex_coords: tex_coords
+        .as_ref()
+        .map_or(Point2::origin(), |t| Point2::from(t[i])),
+      normal: normals.as_ref().map_or(Vector3::zeros(), |n| {
+        (normal_matrix * Vector3::from(n[i])).normalize()
+      }),
+      // 稍后计算
+      tangent: Vector3::zeros(),
+      bitangent: Vector3::zeros(),
     })
     .collect::<Vec<_>>();
-  Ok(model::Model { meshes, materials })
+  if normals.is_none() {
+    model::generate_normals(normal_mode, &mut vertices, &mut indices);
+  }
+  model::compute_tangents(&mut vertices, &indices);
+  Ok((vertices, indices))
+}
+
+fn white_pixel() -> image::DynamicImage {
+  image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba([255; 4])))
+}
+
+/// tobj 不解析的 MTL 条目保存在 unknown_param 中，这里按不区分大小写的键查找
+fn mtl_param<'a>(m: &'a tobj::Material, key: &str) -> Option<&'a str> {
+  m.unknown_param
+    .iter()
+    .find(|(k, _)| k.eq_ignore_ascii_case(key))
+    .map(|(_, v)| v.trim())
+    .filter(|v| !v.is_empty())
+}
+
+fn mtl_float(m: &tobj::Material, key: &str) -> Option<f32> {
+  mtl_param(m, key)?.parse().ok()
+}
+
+fn mtl_color(m: &tobj::Material, key: &str) -> Option<[f32; 3]> {
+  let mut values = mtl_param(m, key)?.split_whitespace().map(str::parse::<f32>);
+  let r = values.next()?.ok()?;
+  // 只写一个值时表示灰度
+  match (values.next(), values.next()) {
+    (Some(g), Some(b)) => Some([r, g.ok()?, b.ok()?]),
+    _ => Some([r; 3]),
+  }
+}
+
+/// 加载 MTL 中的可选贴图，缺失时返回 None，加载失败时给出警告后忽略
+async fn load_mtl_image(
+  parent: &Path,
+  m: &tobj::Material,
+  key: &str,
+) -> Option<image::DynamicImage> {
+  let file = mtl_param(m, key)?;
+  let result = async {
+    Ok::<_, color_eyre::Report>(image::load_from_memory(
+      &load_binary(&parent.join(file)).await?,
+    )?)
+  };
+  match result.await {
+    Ok(img) => Some(img),
+    Err(e) => {
+      warn!("{file}: {e}, ignoring {key}");
+      None
+    }
+  }
+}
+
+/// 把 MTL 中分开的金属度与粗糙度灰度图合并为 glTF 约定的一张贴图：
+/// G 通道为粗糙度，B 通道为金属度。尺寸不同时缩放到较大的一张
+fn pack_metallic_roughness(
+  metallic: Option<image::DynamicImage>,
+  roughness: Option<image::DynamicImage>,
+) -> Option<image::RgbaImage> {
+  let metallic = metallic.map(|img| img.to_luma8());
+  let roughness = roughness.map(|img| img.to_luma8());
+  let (width, height) = [&metallic, &roughness]
+    .into_iter()
+    .flatten()
+    .map(|img| img.dimensions())
+    .max_by_key(|&(w, h)| w * h)?;
+  let fit = |img: Option<image::GrayImage>| {
+    img.map(|img| {
+      if img.dimensions() == (width, height) {
+        img
+      } else {
+        image::imageops::resize(&img, width, height, image::imageops::FilterType::Triangle)
+      }
+    })
+  };
+  let (metallic, roughness) = (fit(metallic), fit(roughness));
+  let channel =
+    |img: &Option<image::GrayImage>, x, y| img.as_ref().map_or(255, |img| img.get_pixel(x, y)[0]);
+  Some(image::RgbaImage::from_fn(width, height, |x, y| {
+    image::Rgba([0, channel(&roughness, x, y), channel(&metallic, x, y), 255])
+  }))
+}
+
+async fn load_gltf_image(
+  image: &gltf::Image<'_>,
+  buffers: &[Vec<u8>],
+  parent: &Path,
+  cache: &mut HashMap<usize, image::DynamicImage>,
+) -> Result<image::DynamicImage> {
+  if let Some(decoded) = cache.get(&image.index()) {
+    return Ok(decoded.clone());
+  }
+  let decoded = match image.source() {
+    gltf::image::Source::View { view, .. } => {
+      let bytes = buffers
+        .get(view.buffer().index())
+        .and_then(|buffer| buffer.get(view.offset()..view.offset() + view.length()))
+        .ok_or_else(|| eyre!("image {} points outside of its buffer", image.index()))?;
+      image::load_from_memory(bytes)?
+    }
+    gltf::image::Source::Uri { uri, .. } => image::load_from_memory(&load_uri(parent, uri).await?)?,
+  };
+  cache.insert(image.index(), decoded.clone());
+  Ok(decoded)
+}
+
+/// data URI 直接解码，其余视为相对于模型文件的路径
+async fn load_uri(parent: &Path, uri: &str) -> Result<Vec<u8>> {
+  if let Some(data) = uri.strip_prefix("data:") {
+    let (_, encoded) = data
+      .split_once(";base64,")
+      .ok_or_else(|| eyre!("unsupported data uri {}", &uri[..uri.len().min(32)]))?;
+    return Ok(base64::engine::general_purpose::STANDARD.decode(encoded)?);
+  }
+  load_binary(&parent.join(percent_decode(uri))).await
+}
+
+// glTF 的相对路径按 URI 编码，例如空格会写成 %20
+fn percent_decode(uri: &str) -> PathBuf {
+  let bytes = uri.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut i = 0;
+  while i < bytes.len() {
+    let hex = bytes
+      .get(i + 1..i + 3)
+      .and_then(|h| std::str::from_utf8(h).ok())
+      .and_then(|h| u8::from_str_radix(h, 16).ok());
+    match (bytes[i], hex) {
+      (b'%', Some(byte)) => {
+        decoded.push(byte);
+        i += 3;
+      }
+      (byte, _) => {
+        decoded.push(byte);
+        i += 1;
+      }
+    }
+  }
+  PathBuf::from(String::from_utf8_lossy(&decoded).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse_obj(text: &str) -> Vec<tobj::Model> {
+    let (models, _) = tobj::load_obj_buf(
+      &mut BufReader::new(Cursor::new(text)),
+      &tobj::LoadOptions {
+        triangulate: true,
+        single_index: true,
+        ..Default::default()
+      },
+      |_| Err(tobj::LoadError::OpenFileFailed),
+    )
+    .unwrap();
+    models
+  }
+
+  #[test]
+  fn obj_without_normals_or_uvs() {
+    let models = parse_obj("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n");
+    let (vertices, _) = obj_vertices(
+      Path::new("triangle.obj"),
+      &models[0],
+      model::Normals::Smooth,
+    )
+    .unwrap();
+    assert_eq!(vertices.len(), 3);
+    for v in &vertices {
+      assert_eq!(v.tex_coords, Point2::origin());
+      assert!((v.normal - Vector3::z()).norm() < 1e-5);
+    }
+  }
+
+  #[test]
+  fn obj_with_out_of_range_index() {
+    let mut models = parse_obj("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n");
+    models[0].mesh.indices[2] = 7;
+    let err =
+      obj_vertices(Path::new("broken.obj"), &models[0], model::Normals::Smooth).unwrap_err();
+    assert!(matches!(err, ModelError::InvalidMesh { .. }), "{err}");
+  }
+
+  fn triangle() -> GltfPrimitive {
+    GltfPrimitive {
+      positions: vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+      normals: Some(vec![[0.0, 0.0, 1.0]; 3]),
+      tex_coords: None,
+      indices: Some(vec![0, 1, 2]),
+    }
+  }
+
+  #[test]
+  fn gltf_with_mismatched_attributes() {
+    let path = Path::new("broken.gltf");
+    let (vertices, _) = gltf_vertices(
+      path,
+      "mesh#0",
+      triangle(),
+      &Matrix4::identity(),
+      model::Normals::Smooth,
+    )
+    .unwrap();
+    assert_eq!(vertices.len(), 3);
+
+    let broken = [
+      GltfPrimitive {
+        normals: Some(vec![[0.0, 0.0, 1.0]; 2]),
+        ..triangle()
+      },
+      GltfPrimitive {
+        tex_coords: Some(vec![[0.0, 0.0]; 4]),
+        ..triangle()
+      },
+      GltfPrimitive {
+        indices: Some(vec![0, 1, 7]),
+        ..triangle()
+      },
+      GltfPrimitive {
+        indices: Some(vec![0, 1]),
+        ..triangle()
+      },
+    ];
+    for primitive in broken {
+      let err = gltf_vertices(
+        path,
+        "mesh#0",
+        primitive,
+        &Matrix4::identity(),
+        model::Normals::Smooth,
+      )
+      .unwrap_err();
+      assert!(matches!(err, ModelError::InvalidMesh { .. }), "{err}");
+    }
+  }
+
+  #[test]
+  fn mtl_pbr_extensions() {
+    let text = "newmtl metal\nPr 0.25\npm 1\nKe 0.5\nmap_Pr rough.png\n";
+    let materials = tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(text)))
+      .unwrap()
+      .0;
+    let m = &materials[0];
+    assert_eq!(mtl_float(m, "Pr"), Some(0.25));
+    assert_eq!(mtl_float(m, "Pm"), Some(1.0));
+    assert_eq!(mtl_color(m, "Ke"), Some([0.5; 3]));
+    assert_eq!(mtl_param(m, "map_Pr"), Some("rough.png"));
+    assert_eq!(mtl_param(m, "map_Pm"), None);
+  }
+
+  #[test]
+  fn metallic_roughness_packing() {
+    let roughness = image::GrayImage::from_pixel(2, 2, image::Luma([64]));
+    let metallic = image::GrayImage::from_pixel(4, 4, image::Luma([200]));
+    let packed = pack_metallic_roughness(
+      Some(image::DynamicImage::ImageLuma8(metallic)),
+      Some(image::DynamicImage::ImageLuma8(roughness)),
+    )
+    .unwrap();
+    assert_eq!(packed.dimensions(), (4, 4));
+    assert_eq!(packed.get_pixel(3, 3).0, [0, 64, 200, 255]);
+    assert!(pack_metallic_roughness(None, None).is_none());
+  }
+
+  #[tokio::test]
+  async fn gltf_image_outside_of_buffer() {
+    let json = r#"{
+      "asset": { "version": "2.0" },
+      "buffers": [{ "byteLength": 8 }],
+      "bufferViews": [{ "buffer": 0, "byteOffset": 4, "byteLength": 16 }],
+      "images": [{ "bufferView": 0, "mimeType": "image/png" }]
+    }"#;
+    let gltf = gltf::Gltf::from_slice_without_validation(json.as_bytes()).unwrap();
+    let image = gltf.images().next().unwrap();
+    let buffers = vec![vec![0; 8]];
+    let result = load_gltf_image(&image, &buffers, Path::new("."), &mut HashMap::new()).await;
+    assert!(result.is_err());
+  }
 }
@@ -0,0 +1,671 @@
+//! 光源的阴影贴图
+//!
+//! 平行光与聚光灯共用一个深度纹理数组。聚光灯占一层；平行光使用级联阴影 (CSM)，
+//! 将摄像机视锥按距离切分为 CASCADE_COUNT 段，每段拟合一个正交投影并占一层。
+//! 点光源各自使用一个立方体深度贴图，六个面中保存的是到光源的线性距离。
+//! 阴影 pass 从光源的视角渲染所有实例，主 pass 再用比较采样器对其做 PCF 过滤。
+
+use na::{Matrix4, Point3, Vector3, Vector4};
+use wgpu::include_wgsl;
+
+use crate::{
+  exts::state::{DeviceTrait, DeviceWarp},
+  geom::camera::{Camera, OPENGL_TO_WGPU_MATRIX},
+  instance::InstanceRaw,
+  light::{LightKind, Lights},
+  model::{self, VertexTrait},
+  texture,
+};
+
+/// 平行光的级联数量，shader 中以 vec4 保存各级联的分割距离
+pub const CASCADE_COUNT: usize = 4;
+/// 阴影贴图数组的层数上限
+pub const MAX_SHADOW_LAYERS: usize = 8;
+/// 投射阴影的点光源数量上限
+pub const MAX_POINT_SHADOWS: usize = 4;
+
+// 立方体贴图各个面的朝向与 up 向量，顺序为 +X -X +Y -Y +Z -Z
+const CUBE_FACES: [(Vector3<f32>, Vector3<f32>); 6] = [
+  (Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, -1.0, 0.0)),
+  (Vector3::new(-1.0, 0.0, 0.0), Vector3::new(0.0, -1.0, 0.0)),
+  (Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, 0.0, 1.0)),
+  (Vector3::new(0.0, -1.0, 0.0), Vector3::new(0.0, 0.0, -1.0)),
+  (Vector3::new(0.0, 0.0, 1.0), Vector3::new(0.0, -1.0, 0.0)),
+  (Vector3::new(0.0, 0.0, -1.0), Vector3::new(0.0, -1.0, 0.0)),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowSettings {
+  /// 阴影贴图的边长(像素)
+  pub resolution: u32,
+  /// 比较深度时减去的最小偏移，用于消除阴影粉刺 (shadow acne)
+  pub depth_bias: f32,
+  /// 随表面与光线夹角增大的偏移
+  pub slope_bias: f32,
+  /// PCF 的采样半径，0 时只使用比较采样器自带的 2x2 过滤
+  pub pcf_radius: u32,
+  /// 级联阴影覆盖的最远距离，不超过摄像机的远平面
+  pub cascade_distance: f32,
+  /// 对数划分与均匀划分的混合比例，越接近 1 近处的级联越精细
+  pub cascade_split_lambda: f32,
+  /// 级联范围之外、沿光线方向仍会被渲染进阴影贴图的距离，使视锥外的物体也能投下阴影
+  pub caster_distance: f32,
+  /// 聚光灯阴影的远平面
+  pub spot_far: f32,
+  /// 用不同颜色标出每个片元所处的级联
+  pub debug_cascades: bool,
+  /// 点光源立方体阴影贴图每个面的边长(像素)
+  pub point_resolution: u32,
+  /// 投射阴影的点光源数量，超出的点光源不投射阴影，不能大于 MAX_POINT_SHADOWS
+  pub max_point_shadows: usize,
+  /// 点光源阴影的最远距离
+  pub point_far: f32,
+}
+impl Default for ShadowSettings {
+  fn default() -> Self {
+    Self {
+      resolution: 2048,
+      depth_bias: 0.0005,
+      slope_bias: 0.002,
+      pcf_radius: 1,
+      cascade_distance: 60.0,
+      cascade_split_lambda: 0.75,
+      caster_distance: 30.0,
+      spot_far: 60.0,
+      debug_cascades: false,
+      point_resolution: 512,
+      max_point_shadows: 2,
+      point_far: 40.0,
+    }
+  }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShadowUniform {
+  light_view_proj: [Matrix4<f32>; MAX_SHADOW_LAYERS],
+  // 每个级联的远端距离
+  cascade_splits: [f32; CASCADE_COUNT],
+  // 与 (position, 1) 点乘即得到片元到摄像机的深度
+  view_depth: Vector4<f32>,
+  depth_bias: f32,
+  slope_bias: f32,
+  texel_size: f32,
+  pcf_radius: u32,
+  debug_cascades: u32,
+  point_far: f32,
+  point_texel_size: f32,
+  _padding: u32,
+}
+
+// 点光源阴影 pass 中每个面的参数
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PointFaceUniform {
+  view_proj: Matrix4<f32>,
+  light_position: [f32; 3],
+  far: f32,
+}
+
+/// 按 lambda 混合对数划分与均匀划分，返回每个级联的远端距离
+pub fn cascade_splits(near: f32, far: f32, lambda: f32) -> [f32; CASCADE_COUNT] {
+  let mut splits = [0.0; CASCADE_COUNT];
+  for (i, split) in splits.iter_mut().enumerate() {
+    let p = (i + 1) as f32 / CASCADE_COUNT as f32;
+    let log = near * (far / near).powf(p);
+    let uniform = near + (far - near) * p;
+    *split = lambda * log + (1.0 - lambda) * uniform;
+  }
+  splits
+}
+
+/// 摄像机视锥在 [near, far] 之间的一段的八个角点(世界空间)
+fn frustum_corners(camera: &Camera, aspect: f32, near: f32, far: f32) -> [Point3<f32>; 8] {
+  let view_proj = camera.get_proj_mat_with_range(aspect, near, far) * camera.get_view_mat();
+  let inverse = view_proj.try_inverse().unwrap_or_else(Matrix4::identity);
+  let mut corners = [Point3::origin(); 8];
+  for (i, corner) in corners.iter_mut().enumerate() {
+    // 透视矩阵沿用 OpenGL 的 NDC，z ∈ [-1, 1]
+    let ndc = Vector4::new(
+      if i & 1 == 0 { -1.0 } else { 1.0 },
+      if i & 2 == 0 { -1.0 } else { 1.0 },
+      if i & 4 == 0 { -1.0 } else { 1.0 },
+      1.0,
+    );
+    let world = inverse * ndc;
+    *corner = Point3::from(world.xyz() / world.w);
+  }
+  corners
+}
+
+/// 为一段视锥拟合光源空间的正交投影
+fn cascade_view_proj(
+  direction: &Vector3<f32>,
+  corners: &[Point3<f32>; 8],
+  settings: &ShadowSettings,
+) -> Matrix4<f32> {
+  let center = corners
+    .iter()
+    .fold(Vector3::zeros(), |acc, p| acc + p.coords)
+    / corners.len() as f32;
+  // 使用包围球而非包围盒，摄像机旋转时投影大小保持不变
+  let radius = corners
+    .iter()
+    .map(|p| (p.coords - center).norm())
+    .fold(0.0, f32::max);
+  let radius = (radius * 16.0).ceil() / 16.0;
+
+  let center = Point3::from(center);
+  let eye = center - direction.normalize() * (radius + settings.caster_distance);
+  let view = Matrix4::look_at_rh(&eye, &center, &up_for(direction));
+  let proj = Matrix4::new_orthographic(
+    -radius,
+    radius,
+    -radius,
+    radius,
+    0.0,
+    radius * 2.0 + settings.caster_distance,
+  );
+  let mut view_proj = OPENGL_TO_WGPU_MATRIX * proj * view;
+
+  // 将投影对齐到阴影贴图的纹素，避免摄像机移动时阴影边缘闪烁
+  let half_resolution = settings.resolution as f32 / 2.0;
+  let origin = view_proj * Vector4::new(0.0, 0.0, 0.0, 1.0);
+  let (x, y) = (origin.x * half_resolution, origin.y * half_resolution);
+  view_proj[(0, 3)] += (x.round() - x) / half_resolution;
+  view_proj[(1, 3)] += (y.round() - y) / half_resolution;
+  view_proj
+}
+
+/// 聚光灯的观察-投影矩阵，输出 wgpu 的裁剪空间 (z ∈ [0, 1])
+fn spot_view_proj(
+  position: &Point3<f32>,
+  direction: &Vector3<f32>,
+  outer_angle: f32,
+  settings: &ShadowSettings,
+) -> Matrix4<f32> {
+  let view = Matrix4::look_at_rh(position, &(position + direction), &up_for(direction));
+  let fovy = (outer_angle * 2.0)
+    .to_radians()
+    .min(std::f32::consts::PI * 0.95);
+  let proj = Matrix4::new_perspective(1.0, fovy, 0.1, settings.spot_far);
+  OPENGL_TO_WGPU_MATRIX * proj * view
+}
+
+/// 点光源六个面的观察-投影矩阵，顺序与 CUBE_FACES 一致
+fn point_view_projs(position: &Point3<f32>, far: f32) -> [Matrix4<f32>; 6] {
+  // 立方体贴图的纹理坐标沿用 OpenGL 的约定，但 wgpu 的帧缓冲以左上角为原点，
+  // 所以要翻转 y 轴，否则每个面都是上下颠倒的
+  let flip_y = Matrix4::new_nonuniform_scaling(&Vector3::new(1.0, -1.0, 1.0));
+  let proj = Matrix4::new_perspective(1.0, std::f32::consts::FRAC_PI_2, 0.05, far);
+  CUBE_FACES.map(|(direction, up)| {
+    let view = Matrix4::look_at_rh(position, &(position + direction), &up);
+    flip_y * OPENGL_TO_WGPU_MATRIX * proj * view
+  })
+}
+
+// 光线接近竖直时换一个 up 向量，避免 look_at 退化
+fn up_for(direction: &Vector3<f32>) -> Vector3<f32> {
+  if direction.normalize().y.abs() > 0.99 {
+    Vector3::x()
+  } else {
+    Vector3::y()
+  }
+}
+
+// 随分辨率与点光源数量变化而重建的资源，纹理本身由视图与 bind group 持有
+struct ShadowMaps {
+  // 每层一个视图，作为阴影 pass 的深度附件
+  layer_views: Vec<wgpu::TextureView>,
+  // 立方体贴图每个面一个视图
+  face_views: Vec<wgpu::TextureView>,
+  bind_group: wgpu::BindGroup,
+}
+
+pub struct Shadows {
+  settings: ShadowSettings,
+  maps: ShadowMaps,
+  uniform: ShadowUniform,
+  uniform_buffer: wgpu::Buffer,
+  layout: wgpu::BindGroupLayout,
+
+  // 阴影 pass 中每层使用各自的光源矩阵
+  pass_buffers: Vec<wgpu::Buffer>,
+  pass_bind_groups: Vec<wgpu::BindGroup>,
+  pipeline: wgpu::RenderPipeline,
+  active: usize,
+
+  // 点光源阴影 pass 中每个面使用各自的矩阵
+  face_buffers: Vec<wgpu::Buffer>,
+  face_bind_groups: Vec<wgpu::BindGroup>,
+  point_pipeline: wgpu::RenderPipeline,
+  active_points: usize,
+}
+
+impl Shadows {
+  pub fn new<T: DeviceTrait>(device: &T, settings: ShadowSettings) -> Self {
+    let settings = ShadowSettings {
+      max_point_shadows: settings.max_point_shadows.min(MAX_POINT_SHADOWS),
+      ..settings
+    };
+    let layout = device.create_bind_group_layout(
+      "shadow_bind_group_layout",
+      &[
+        wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::D2Array,
+            sample_type: wgpu::TextureSampleType::Depth,
+          },
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 1,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 2,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+          },
+          count: None,
+        },
+        // 点光源的立方体阴影贴图
+        wgpu::BindGroupLayoutEntry {
+          binding: 3,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::CubeArray,
+            sample_type: wgpu::TextureSampleType::Depth,
+          },
+          count: None,
+        },
+      ],
+    );
+    let uniform = ShadowUniform {
+      light_view_proj: [Matrix4::identity(); MAX_SHADOW_LAYERS],
+      cascade_splits: [0.0; CASCADE_COUNT],
+      view_depth: Vector4::zeros(),
+      depth_bias: settings.depth_bias,
+      slope_bias: settings.slope_bias,
+      texel_size: 1.0 / settings.resolution as f32,
+      pcf_radius: settings.pcf_radius,
+      debug_cascades: settings.debug_cascades as u32,
+      point_far: settings.point_far,
+      point_texel_size: 1.0 / settings.point_resolution as f32,
+      _padding: 0,
+    };
+    let uniform_buffer = device.create_buffer_init(
+      "Shadow Uniform Buffer",
+      bytemuck::cast_slice(&[uniform]),
+      wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    );
+
+    let pass_layout = device.create_bind_group_layout(
+      "shadow_pass_bind_group_layout",
+      &[wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+          ty: wgpu::BufferBindingType::Uniform,
+          has_dynamic_offset: false,
+          min_binding_size: None,
+        },
+        count: None,
+      }],
+    );
+    let (pass_buffers, pass_bind_groups) = Self::create_pass_buffers(
+      device,
+      &pass_layout,
+      "Shadow Pass Buffer",
+      "shadow_pass_bind_group",
+      MAX_SHADOW_LAYERS,
+      bytemuck::cast_slice(&[Matrix4::<f32>::identity()]),
+    );
+    let (face_buffers, face_bind_groups) = Self::create_pass_buffers(
+      device,
+      &pass_layout,
+      "Point Shadow Pass Buffer",
+      "point_shadow_pass_bind_group",
+      MAX_POINT_SHADOWS * 6,
+      bytemuck::cast_slice(&[PointFaceUniform {
+        view_proj: Matrix4::identity(),
+        light_position: [0.0; 3],
+        far: 1.0,
+      }]),
+    );
+
+    let pipeline_layout =
+      device.create_pipeline_layout("Shadow Pipeline Layout", &[&pass_layout], &[]);
+    let primitive = wgpu::PrimitiveState {
+      topology: wgpu::PrimitiveTopology::TriangleList,
+      front_face: wgpu::FrontFace::Ccw,
+      cull_mode: Some(wgpu::Face::Back),
+      ..Default::default()
+    };
+    let depth_stencil = wgpu::DepthStencilState {
+      format: texture::Texture::DEPTH_FORMAT,
+      depth_write_enabled: true,
+      depth_compare: wgpu::CompareFunction::LessEqual,
+      stencil: wgpu::StencilState::default(),
+      bias: wgpu::DepthBiasState::default(),
+    };
+    let shader = device.create_shader_module(include_wgsl!("../assets/shadow.wgsl"));
+    let pipeline = device.create_depth_only_pipeline(
+      "Shadow Pipeline",
+      Some(&pipeline_layout),
+      wgpu::VertexState {
+        module: &shader,
+        entry_point: "vs_main",
+        buffers: &[model::ModelVertex::desc(), InstanceRaw::desc()],
+      },
+      primitive,
+      depth_stencil.clone(),
+    );
+    // 点光源的深度由片元着色器写入到光源的线性距离，没有颜色输出
+    let point_shader = device.create_shader_module(include_wgsl!("../assets/point_shadow.wgsl"));
+    let point_pipeline = device.create_render_pipeline(
+      "Point Shadow Pipeline",
+      Some(&pipeline_layout),
+      wgpu::VertexState {
+        module: &point_shader,
+        entry_point: "vs_main",
+        buffers: &[model::ModelVertex::desc(), InstanceRaw::desc()],
+      },
+      wgpu::PrimitiveState {
+        // 翻转 y 轴后三角形的环绕方向也随之反转
+        front_face: wgpu::FrontFace::Cw,
+        ..primitive
+      },
+      Some(depth_stencil),
+      wgpu::MultisampleState::default(),
+      wgpu::FragmentState {
+        module: &point_shader,
+        entry_point: "fs_main",
+        targets: &[],
+      },
+      None,
+    );
+
+    let maps = Self::create_maps(device, &layout, &uniform_buffer, &settings);
+    Self {
+      settings,
+      maps,
+      uniform,
+      uniform_buffer,
+      layout,
+      pass_buffers,
+      pass_bind_groups,
+      pipeline,
+      active: 0,
+      face_buffers,
+      face_bind_groups,
+      point_pipeline,
+      active_points: 0,
+    }
+  }
+
+  fn create_pass_buffers<T: DeviceTrait>(
+    device: &T,
+    layout: &wgpu::BindGroupLayout,
+    buffer_label: &str,
+    bind_group_label: &str,
+    count: usize,
+    contents: &[u8],
+  ) -> (Vec<wgpu::Buffer>, Vec<wgpu::BindGroup>) {
+    (0..count)
+      .map(|i| {
+        let buffer = device.create_buffer_init(
+          &format!("{buffer_label} {i}"),
+          contents,
+          wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        );
+        let bind_group = device.create_bind_group(
+          &format!("{bind_group_label}_{i}"),
+          layout,
+          &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: buffer.as_entire_binding(),
+          }],
+        );
+        (buffer, bind_group)
+      })
+      .unzip()
+  }
+
+  fn create_maps<T: DeviceTrait>(
+    device: &T,
+    layout: &wgpu::BindGroupLayout,
+    uniform_buffer: &wgpu::Buffer,
+    settings: &ShadowSettings,
+  ) -> ShadowMaps {
+    let map = texture::Texture::create_shadow_map(
+      device,
+      settings.resolution,
+      MAX_SHADOW_LAYERS as u32,
+      "shadow_map",
+    );
+    // 至少保留一个立方体，使 bind group 始终有效
+    let cubes = settings.max_point_shadows.max(1) as u32;
+    let cube_map = texture::Texture::create_shadow_cube_map(
+      device,
+      settings.point_resolution,
+      cubes,
+      "point_shadow_map",
+    );
+    let layer_views = Self::create_layer_views(&map.texture, MAX_SHADOW_LAYERS as u32);
+    let face_views = Self::create_layer_views(&cube_map.texture, cubes * 6);
+    let bind_group = device.create_bind_group(
+      "shadow_bind_group",
+      layout,
+      &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: wgpu::BindingResource::TextureView(&map.view),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: wgpu::BindingResource::Sampler(&map.sampler),
+        },
+        wgpu::BindGroupEntry {
+          binding: 2,
+          resource: uniform_buffer.as_entire_binding(),
+        },
+        wgpu::BindGroupEntry {
+          binding: 3,
+          resource: wgpu::BindingResource::TextureView(&cube_map.view),
+        },
+      ],
+    );
+    ShadowMaps {
+      layer_views,
+      face_views,
+      bind_group,
+    }
+  }
+
+  fn create_layer_views(texture: &wgpu::Texture, layers: u32) -> Vec<wgpu::TextureView> {
+    (0..layers)
+      .map(|layer| {
+        texture.create_view(&wgpu::TextureViewDescriptor {
+          label: Some("shadow_map_layer"),
+          dimension: Some(wgpu::TextureViewDimension::D2),
+          base_array_layer: layer,
+          array_layer_count: Some(1),
+          ..Default::default()
+        })
+      })
+      .collect()
+  }
+
+  pub fn settings(&self) -> &ShadowSettings {
+    &self.settings
+  }
+
+  /// 修改设置，分辨率或点光源数量变化时会重建阴影贴图
+  pub fn set_settings(&mut self, device: &wgpu::Device, settings: ShadowSettings) {
+    let settings = ShadowSettings {
+      max_point_shadows: settings.max_point_shadows.min(MAX_POINT_SHADOWS),
+      ..settings
+    };
+    if settings.resolution != self.settings.resolution
+      || settings.point_resolution != self.settings.point_resolution
+      || settings.max_point_shadows != self.settings.max_point_shadows
+    {
+      self.maps = Self::create_maps(
+        &DeviceWarp::wrap(device),
+        &self.layout,
+        &self.uniform_buffer,
+        &settings,
+      );
+    }
+    self.settings = settings;
+  }
+
+  pub fn layout(&self) -> &wgpu::BindGroupLayout {
+    &self.layout
+  }
+
+  pub fn bind_group(&self) -> &wgpu::BindGroup {
+    &self.maps.bind_group
+  }
+
+  /// 根据当前的光源与摄像机更新每层的矩阵
+  pub fn update(&mut self, queue: &wgpu::Queue, lights: &Lights, camera: &Camera, aspect: f32) {
+    let near = camera.znear();
+    let far = camera.zfar().min(self.settings.cascade_distance);
+    let splits = cascade_splits(near, far, self.settings.cascade_split_lambda);
+    // 视锥的切分只与摄像机有关，所有平行光共用
+    let slices = (0..CASCADE_COUNT)
+      .map(|i| {
+        let slice_near = if i == 0 { near } else { splits[i - 1] };
+        frustum_corners(camera, aspect, slice_near, splits[i])
+      })
+      .collect::<Vec<_>>();
+
+    self.active = 0;
+    self.active_points = 0;
+    for (base, light) in lights.shadow_casters(self.settings.max_point_shadows) {
+      match light.kind {
+        LightKind::Directional { direction } => {
+          for (i, corners) in slices.iter().enumerate() {
+            let matrix = cascade_view_proj(&direction, corners, &self.settings);
+            self.set_layer(queue, base + i, matrix);
+          }
+          self.active = self.active.max(base + CASCADE_COUNT);
+        }
+        LightKind::Spot {
+          position,
+          direction,
+          outer_angle,
+          ..
+        } => {
+          let matrix = spot_view_proj(&position, &direction, outer_angle, &self.settings);
+          self.set_layer(queue, base, matrix);
+          self.active = self.active.max(base + 1);
+        }
+        LightKind::Point { position, .. } => {
+          let far = self.settings.point_far;
+          for (face, view_proj) in point_view_projs(&position, far).into_iter().enumerate() {
+            let uniform = PointFaceUniform {
+              view_proj,
+              light_position: position.into(),
+              far,
+            };
+            queue.write_buffer(
+              &self.face_buffers[base * 6 + face],
+              0,
+              bytemuck::cast_slice(&[uniform]),
+            );
+          }
+          self.active_points = self.active_points.max(base + 1);
+        }
+      }
+    }
+
+    self.uniform.cascade_splits = splits;
+    self.uniform.view_depth = -camera.get_view_mat().row(2).transpose();
+    self.uniform.depth_bias = self.settings.depth_bias;
+    self.uniform.slope_bias = self.settings.slope_bias;
+    self.uniform.texel_size = 1.0 / self.settings.resolution as f32;
+    self.uniform.pcf_radius = self.settings.pcf_radius;
+    self.uniform.debug_cascades = self.settings.debug_cascades as u32;
+    self.uniform.point_far = self.settings.point_far;
+    self.uniform.point_texel_size = 1.0 / self.settings.point_resolution as f32;
+    queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+  }
+
+  fn set_layer(&mut self, queue: &wgpu::Queue, layer: usize, matrix: Matrix4<f32>) {
+    self.uniform.light_view_proj[layer] = matrix;
+    queue.write_buffer(&self.pass_buffers[layer], 0, bytemuck::cast_slice(&[matrix]));
+  }
+
+  /// 从每个投射阴影的光源的视角渲染所有实例
+  pub fn render(
+    &self,
+    encoder: &mut wgpu::CommandEncoder,
+    model: &model::Model,
+    instance_buffer: &wgpu::Buffer,
+    instances: u32,
+  ) {
+    let layers = (0..self.active).map(|layer| {
+      (
+        &self.pipeline,
+        &self.maps.layer_views[layer],
+        &self.pass_bind_groups[layer],
+      )
+    });
+    let faces = (0..self.active_points * 6).map(|face| {
+      (
+        &self.point_pipeline,
+        &self.maps.face_views[face],
+        &self.face_bind_groups[face],
+      )
+    });
+    for (pipeline, view, bind_group) in layers.chain(faces) {
+      let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Shadow Pass"),
+        color_attachments: &[],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+          view,
+          depth_ops: Some(wgpu::Operations {
+            load: wgpu::LoadOp::Clear(1.0),
+            store: wgpu::StoreOp::Store,
+          }),
+          stencil_ops: None,
+        }),
+        ..Default::default()
+      });
+      pass.set_pipeline(pipeline);
+      pass.set_bind_group(0, bind_group, &[]);
+      pass.set_vertex_buffer(1, instance_buffer.slice(..));
+      for mesh in &model.meshes {
+        pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        pass.draw_indexed(0..mesh.num_elements, 0, 0..instances);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn cascade_splits_cover_range() {
+    let splits = cascade_splits(0.1, 60.0, 0.75);
+    assert!(splits.windows(2).all(|w| w[0] < w[1]));
+    assert!((splits[CASCADE_COUNT - 1] - 60.0).abs() < 1e-3);
+    // lambda 为 0 时退化为均匀划分
+    assert!((cascade_splits(1.0, 41.0, 0.0)[0] - 11.0).abs() < 1e-3);
+  }
+}
@@ -0,0 +1,440 @@
+//! 屏幕空间环境光遮蔽 (SSAO)
+//!
+//! 读取 State 的深度预渲染得到的单采样深度：不开 MSAA 时就是主 pass 的深度纹理，
+//! 开启 MSAA 时是另外的一张单采样深度纹理。AO pass 从深度重建视图空间的位置与法线，在法线方向的半球内取样，
+//! 比较样本与场景的深度得到遮蔽比例；4x4 的噪声纹理随机旋转半球，
+//! 把少量样本产生的条带变成高频噪声，再由按深度加权的双边模糊滤掉，且不越过物体边缘。
+//! 结果通过 [`Ssao::bind_group`] 交给主 pass，与材质的遮蔽贴图一起作用于环境光。
+
+use color_eyre::eyre::Result;
+use na::Matrix4;
+
+use crate::{
+  exts::state::{DeviceTrait, DeviceWarp},
+  geom::camera::Camera,
+  texture,
+};
+
+/// 半球核的最大样本数
+pub const MAX_SAMPLES: usize = 64;
+/// 噪声纹理的边长，在屏幕上平铺
+const NOISE_SIZE: u32 = 4;
+/// 遮蔽结果的格式
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SsaoSettings {
+  pub enabled: bool,
+  /// 采样半球的半径 (世界单位)，超出半径的遮挡物不产生遮蔽
+  pub radius: f32,
+  /// 比较深度时的偏移与片元深度之比，用于消除倾斜平面上的自遮蔽
+  pub bias: f32,
+  /// 每个像素的样本数，不能大于 MAX_SAMPLES
+  pub sample_count: u32,
+  /// 遮蔽的强度，为 0 时没有效果
+  pub strength: f32,
+}
+impl Default for SsaoSettings {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      radius: 0.5,
+      bias: 0.025,
+      sample_count: 16,
+      strength: 1.0,
+    }
+  }
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SsaoUniform {
+  proj: Matrix4<f32>,
+  inv_proj: Matrix4<f32>,
+  // 切线空间中 +z 半球内的样本，越靠前的样本越靠近中心
+  kernel: [[f32; 4]; MAX_SAMPLES],
+  radius: f32,
+  bias: f32,
+  strength: f32,
+  sample_count: u32,
+}
+
+/// 确定性的伪随机数，保证每次运行得到相同的核与噪声
+struct Random(u32);
+impl Random {
+  /// [0, 1) 之间的浮点数
+  fn next(&mut self) -> f32 {
+    // xorshift32
+    self.0 ^= self.0 << 13;
+    self.0 ^= self.0 >> 17;
+    self.0 ^= self.0 << 5;
+    (self.0 >> 8) as f32 / (1 << 24) as f32
+  }
+}
+
+/// 生成切线空间 +z 半球内的样本，样本向中心聚集，使近处的遮挡物权重更大
+pub fn hemisphere_kernel(count: usize) -> Vec<[f32; 3]> {
+  let mut random = Random(0x9e37_79b9);
+  (0..count)
+    .map(|i| {
+      let direction = na::Vector3::new(
+        random.next() * 2.0 - 1.0,
+        random.next() * 2.0 - 1.0,
+        random.next(),
+      )
+      .try_normalize(1e-6)
+      .unwrap_or_else(na::Vector3::z);
+      let t = i as f32 / count as f32;
+      let scale = 0.1 + 0.9 * t * t;
+      (direction * random.next() * scale).into()
+    })
+    .collect()
+}
+
+/// 噪声纹理的像素：xy 为切线平面内的随机方向，映射到 [0, 1]
+fn noise_image() -> image::RgbaImage {
+  let mut random = Random(0x85eb_ca6b);
+  image::RgbaImage::from_fn(NOISE_SIZE, NOISE_SIZE, |_, _| {
+    let angle = random.next() * std::f32::consts::TAU;
+    let encode = |v: f32| ((v * 0.5 + 0.5) * 255.0).round() as u8;
+    image::Rgba([encode(angle.cos()), encode(angle.sin()), 128, 255])
+  })
+}
+
+// 随窗口尺寸重建的资源
+struct Targets {
+  // 遮蔽结果，模糊时与 scratch 交替读写，最终结果在这里
+  occlusion: texture::Texture,
+  scratch: texture::Texture,
+  occlusion_bind_group: wgpu::BindGroup,
+  blur_x_bind_group: wgpu::BindGroup,
+  blur_y_bind_group: wgpu::BindGroup,
+  bind_group: wgpu::BindGroup,
+}
+
+pub struct Ssao {
+  settings: SsaoSettings,
+  uniform: SsaoUniform,
+  uniform_buffer: wgpu::Buffer,
+  noise: texture::Texture,
+  // 关闭时绑定白色纹理，主 pass 不需要区分
+  disabled_bind_group: wgpu::BindGroup,
+  layout: wgpu::BindGroupLayout,
+  pass_layout: wgpu::BindGroupLayout,
+  occlusion_pipeline: wgpu::RenderPipeline,
+  blur_x_pipeline: wgpu::RenderPipeline,
+  blur_y_pipeline: wgpu::RenderPipeline,
+  targets: Targets,
+}
+
+impl Ssao {
+  /// depth 为单采样的场景深度，尺寸与 config 一致
+  pub fn new<T: DeviceTrait>(
+    device: &T,
+    queue: &wgpu::Queue,
+    config: &wgpu::SurfaceConfiguration,
+    depth: &wgpu::TextureView,
+    settings: SsaoSettings,
+  ) -> Result<Self> {
+    let entry = |binding, ty| wgpu::BindGroupLayoutEntry {
+      binding,
+      visibility: wgpu::ShaderStages::FRAGMENT,
+      ty,
+      count: None,
+    };
+    let texture = |sample_type| wgpu::BindingType::Texture {
+      multisampled: false,
+      view_dimension: wgpu::TextureViewDimension::D2,
+      sample_type,
+    };
+    // 深度纹理也以非过滤的浮点纹理绑定，见 ssao.wgsl
+    let float = texture(wgpu::TextureSampleType::Float { filterable: false });
+    let uniform = entry(
+      0,
+      wgpu::BindingType::Buffer {
+        ty: wgpu::BufferBindingType::Uniform,
+        has_dynamic_offset: false,
+        min_binding_size: None,
+      },
+    );
+    let layout = device.create_bind_group_layout("ssao_bind_group_layout", &[entry(0, float)]);
+    // binding 2 在 AO pass 中为噪声纹理，在模糊 pass 中为上一步的遮蔽结果
+    let pass_layout = device.create_bind_group_layout(
+      "ssao_pass_bind_group_layout",
+      &[uniform, entry(1, float), entry(2, float)],
+    );
+
+    let uniform = SsaoUniform {
+      proj: Matrix4::identity(),
+      inv_proj: Matrix4::identity(),
+      kernel: [[0.0; 4]; MAX_SAMPLES],
+      radius: 0.0,
+      bias: 0.0,
+      strength: 0.0,
+      sample_count: 0,
+    };
+    let uniform_buffer = device.create_buffer_init(
+      "SSAO Uniform Buffer",
+      bytemuck::cast_slice(&[uniform]),
+      wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    );
+
+    let noise = texture::Texture::from_image_with(
+      device,
+      queue,
+      &image::DynamicImage::ImageRgba8(noise_image()),
+      Some("ssao_noise"),
+      true,
+      &texture::TextureSettings {
+        mipmaps: false,
+        ..Default::default()
+      },
+    )?;
+    let white = texture::Texture::white(device, queue, true)?;
+    let disabled_bind_group = device.create_bind_group(
+      "ssao_disabled_bind_group",
+      &layout,
+      &[wgpu::BindGroupEntry {
+        binding: 0,
+        resource: wgpu::BindingResource::TextureView(&white.view),
+      }],
+    );
+
+    let shader = device.create_shader_module(wgpu::include_wgsl!("../assets/ssao.wgsl"));
+    let pass_pipeline_layout =
+      device.create_pipeline_layout("SSAO Pipeline Layout", &[&pass_layout], &[]);
+    let fullscreen = |entry_point| {
+      device.create_render_pipeline(
+        entry_point,
+        Some(&pass_pipeline_layout),
+        wgpu::VertexState {
+          module: &shader,
+          entry_point: "vs_fullscreen",
+          buffers: &[],
+        },
+        wgpu::PrimitiveState::default(),
+        None,
+        wgpu::MultisampleState::default(),
+        wgpu::FragmentState {
+          module: &shader,
+          entry_point,
+          targets: &[Some(wgpu::ColorTargetState {
+            format: FORMAT,
+            blend: None,
+            write_mask: wgpu::ColorWrites::ALL,
+          })],
+        },
+        None,
+      )
+    };
+    let occlusion_pipeline = fullscreen("fs_occlusion");
+    let blur_x_pipeline = fullscreen("fs_blur_x");
+    let blur_y_pipeline = fullscreen("fs_blur_y");
+
+    let targets = Self::create_targets(
+      device,
+      config,
+      depth,
+      &uniform_buffer,
+      &noise,
+      &layout,
+      &pass_layout,
+    );
+    let mut ssao = Self {
+      settings,
+      uniform,
+      uniform_buffer,
+      noise,
+      disabled_bind_group,
+      layout,
+      pass_layout,
+      occlusion_pipeline,
+      blur_x_pipeline,
+      blur_y_pipeline,
+      targets,
+    };
+    ssao.set_settings(settings);
+    Ok(ssao)
+  }
+
+  fn create_targets<T: DeviceTrait>(
+    device: &T,
+    config: &wgpu::SurfaceConfiguration,
+    depth: &wgpu::TextureView,
+    uniform_buffer: &wgpu::Buffer,
+    noise: &texture::Texture,
+    layout: &wgpu::BindGroupLayout,
+    pass_layout: &wgpu::BindGroupLayout,
+  ) -> Targets {
+    let occlusion = texture::Texture::create_render_target(device, config, FORMAT, "ssao");
+    let scratch = texture::Texture::create_render_target(device, config, FORMAT, "ssao_scratch");
+    let pass_bind_group = |label, input: &wgpu::TextureView| {
+      device.create_bind_group(
+        label,
+        pass_layout,
+        &[
+          wgpu::BindGroupEntry {
+            binding: 0,
+            resource: uniform_buffer.as_entire_binding(),
+          },
+          wgpu::BindGroupEntry {
+            binding: 1,
+            resource: wgpu::BindingResource::TextureView(depth),
+          },
+          wgpu::BindGroupEntry {
+            binding: 2,
+            resource: wgpu::BindingResource::TextureView(input),
+          },
+        ],
+      )
+    };
+    let occlusion_bind_group = pass_bind_group("ssao_occlusion_bind_group", &noise.view);
+    let blur_x_bind_group = pass_bind_group("ssao_blur_x_bind_group", &occlusion.view);
+    let blur_y_bind_group = pass_bind_group("ssao_blur_y_bind_group", &scratch.view);
+    let bind_group = device.create_bind_group(
+      "ssao_bind_group",
+      layout,
+      &[wgpu::BindGroupEntry {
+        binding: 0,
+        resource: wgpu::BindingResource::TextureView(&occlusion.view),
+      }],
+    );
+    Targets {
+      occlusion,
+      scratch,
+      occlusion_bind_group,
+      blur_x_bind_group,
+      blur_y_bind_group,
+      bind_group,
+    }
+  }
+
+  /// 窗口尺寸或深度纹理改变后重建遮蔽纹理与绑定
+  pub fn resize(
+    &mut self,
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+    depth: &wgpu::TextureView,
+  ) {
+    self.targets = Self::create_targets(
+      &DeviceWarp::wrap(device),
+      config,
+      depth,
+      &self.uniform_buffer,
+      &self.noise,
+      &self.layout,
+      &self.pass_layout,
+    );
+  }
+
+  pub fn settings(&self) -> &SsaoSettings {
+    &self.settings
+  }
+
+  /// 在下一次 update 时写入 GPU
+  pub fn set_settings(&mut self, settings: SsaoSettings) {
+    let sample_count = settings.sample_count.clamp(1, MAX_SAMPLES as u32);
+    self.settings = SsaoSettings {
+      sample_count,
+      ..settings
+    };
+    let mut kernel = [[0.0; 4]; MAX_SAMPLES];
+    for (sample, [x, y, z]) in kernel
+      .iter_mut()
+      .zip(hemisphere_kernel(sample_count as usize))
+    {
+      *sample = [x, y, z, 0.0];
+    }
+    self.uniform.kernel = kernel;
+    self.uniform.radius = settings.radius;
+    self.uniform.bias = settings.bias;
+    self.uniform.strength = settings.strength;
+    self.uniform.sample_count = sample_count;
+  }
+
+  /// 主渲染管线的 group 5
+  pub fn layout(&self) -> &wgpu::BindGroupLayout {
+    &self.layout
+  }
+
+  /// 遮蔽结果，关闭时为白色纹理
+  pub fn bind_group(&self) -> &wgpu::BindGroup {
+    if self.settings.enabled {
+      &self.targets.bind_group
+    } else {
+      &self.disabled_bind_group
+    }
+  }
+
+  pub fn update(&mut self, queue: &wgpu::Queue, camera: &Camera, aspect: f32) {
+    let proj = camera.get_proj_mat(aspect);
+    self.uniform.proj = proj;
+    self.uniform.inv_proj = proj.try_inverse().unwrap_or_else(Matrix4::identity);
+    queue.write_buffer(
+      &self.uniform_buffer,
+      0,
+      bytemuck::cast_slice(&[self.uniform]),
+    );
+  }
+
+  /// 由深度计算遮蔽，需要在深度预渲染之后、主 pass 之前调用。关闭时什么也不做
+  pub fn render(&self, encoder: &mut wgpu::CommandEncoder) {
+    if !self.settings.enabled {
+      return;
+    }
+    let targets = &self.targets;
+    for (label, pipeline, bind_group, output) in [
+      (
+        "SSAO Pass",
+        &self.occlusion_pipeline,
+        &targets.occlusion_bind_group,
+        &targets.occlusion,
+      ),
+      (
+        "SSAO Blur X Pass",
+        &self.blur_x_pipeline,
+        &targets.blur_x_bind_group,
+        &targets.scratch,
+      ),
+      (
+        "SSAO Blur Y Pass",
+        &self.blur_y_pipeline,
+        &targets.blur_y_bind_group,
+        &targets.occlusion,
+      ),
+    ] {
+      let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+          view: &output.view,
+          resolve_target: None,
+          ops: wgpu::Operations {
+            load: wgpu::LoadOp::Load,
+            store: wgpu::StoreOp::Store,
+          },
+        })],
+        ..Default::default()
+      });
+      pass.set_pipeline(pipeline);
+      pass.set_bind_group(0, bind_group, &[]);
+      pass.draw(0..3, 0..1);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn kernel_stays_in_unit_hemisphere() {
+    let kernel = hemisphere_kernel(MAX_SAMPLES);
+    assert_eq!(kernel.len(), MAX_SAMPLES);
+    for [x, y, z] in kernel {
+      assert!(z >= 0.0);
+      assert!((x * x + y * y + z * z).sqrt() <= 1.0);
+    }
+    // 相同的数量总是得到相同的核
+    assert_eq!(hemisphere_kernel(16), hemisphere_kernel(16));
+  }
+}
@@ -1,47 +1,121 @@
 use std::{path::Path, sync::Arc};
 
-use color_eyre::eyre::Result;
-use na::Point3;
+use color_eyre::eyre::{bail, eyre, Result};
+use na::{Matrix4, Point3, Vector2, Vector3};
+use tracing::error;
 use wgpu::{include_wgsl, Backends};
 use winit::window::Window;
 
 use crate::{
+  background, capture,
   exts::state::{DeviceTrait, DeviceWarp},
   geom::{
     self,
     camera::{Camera, CameraUniform},
   },
-  instance::{self, Instance, InstanceRaw},
+  instance::{self, Instance},
+  ibl, light, material,
   model::{self, VertexTrait},
-  res, texture,
+  postprocess::{
+    self,
+    bloom::{Bloom, BloomSettings},
+    dof::{DepthOfField, DofSettings},
+    fxaa::Fxaa,
+    grading::{ColorGrading, ColorGradingSettings},
+    motion_blur::{MotionBlur, MotionBlurSettings},
+    taa::{self, Taa, TaaSettings},
+    tonemap::{self, ToneMapper},
+    vignette::{Vignette, VignetteSettings},
+    Effect,
+  },
+  res, shadow, ssao, texture, time,
 };
 
+/// 抗锯齿方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AntiAliasing {
+  None,
+  /// 色调映射后的后处理，开销最小，但会让细节变模糊
+  Fxaa,
+  /// 在多帧中累积带亚像素偏移的采样，也能消除着色器产生的锯齿
+  Taa,
+  /// 多重采样，参数为采样数，只处理几何边缘
+  Msaa(u32),
+}
+
+/// 渲染结果的去向：窗口的 surface，或是无窗口模式下的离屏纹理
+enum RenderTarget {
+  Surface(wgpu::Surface<'static>),
+  Offscreen(texture::Texture),
+}
+
 pub struct State {
-  surface: wgpu::Surface<'static>,
+  target: RenderTarget,
   device: wgpu::Device,
   queue: wgpu::Queue,
   config: wgpu::SurfaceConfiguration,
   pub size: winit::dpi::PhysicalSize<u32>,
-  render_pipeline: wgpu::RenderPipeline,
+  // 按材质特性缓存的主渲染管线
+  pipelines: material::PipelineCache,
+  texture_bind_group_layout: wgpu::BindGroupLayout,
+  // 模型贴图使用的采样器，加载新模型时也会应用
+  sampler_settings: texture::SamplerSettings,
   obj_model: model::Model,
-  clear_color: na::Vector3<f64>,
   camera: geom::camera::Camera,
   camera_uniform: CameraUniform,
   camera_buffer: wgpu::Buffer,
   camera_bind_group: wgpu::BindGroup,
 
+  lights: light::Lights,
+  shadows: shadow::Shadows,
+  // 基于图像的光照
+  environment: ibl::Environment,
+  ssao: ssao::Ssao,
+  background: background::Background,
+  // 用于调试的光源模型，show_light 为 false 时不绘制
+  light_pipeline_layout: wgpu::PipelineLayout,
+  light_render_pipeline: wgpu::RenderPipeline,
+  pub show_light: bool,
+  /// 为 false 时 PBR 材质也使用 Blinn-Phong 着色，用于对比或在低端设备上回退
+  pub pbr_enabled: bool,
+
   instances: Vec<instance::Instance>,
   instance_buffer: wgpu::Buffer,
 
   depth_texture: texture::Texture,
+  // 多重采样时深度预渲染使用的单采样深度纹理；sample_count 为 1 时预渲染直接写入 depth_texture
+  prepass_depth: Option<texture::Texture>,
+  // 场景先渲染到 HDR 纹理，再经后处理链 (包括色调映射) 输出
+  hdr_texture: texture::Texture,
+  post_process: postprocess::PostProcess,
+  // 多重采样时先渲染到这里，再解析到 HDR 纹理；sample_count 为 1 时为 None
+  msaa_texture: Option<texture::Texture>,
+  sample_count: u32,
+  // 适配器对颜色与深度格式都支持的采样数
+  supported_sample_counts: Vec<u32>,
+  // 上一帧不带 jitter 的视图投影矩阵与帧序号，用于 TAA 与运动模糊。第一帧之前为 None
+  prev_view_proj: Option<Matrix4<f32>>,
+  frame_index: u32,
+
+  capture: capture::Capture,
+  // surface 不能直接读回时，需要截图或录制的帧先输出到这里，再复制到 surface
+  capture_target: Option<texture::Texture>,
+  // 加载模型贴图时共享的 mip 生成管线
+  mipmaps: texture::MipmapGenerator,
 }
 const NUM_INSTANCES_PER_ROW: u32 = 10;
+/// 截图与录制读回的格式
+const CAPTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
 
 impl DeviceTrait for State {
   #[inline(always)]
   fn get_device(&self) -> &wgpu::Device {
     &self.device
   }
+  #[inline(always)]
+  fn mipmap_generator(&self) -> Option<&texture::MipmapGenerator> {
+    Some(&self.mipmaps)
+  }
 }
 impl State {
   // Creating some of the wgpu types requires async code
@@ -61,20 +135,16 @@ impl State {
       })
       .await
       .unwrap();
-    let (rdevice, queue) = adapter
-      .request_device(
-        &wgpu::DeviceDescriptor {
-          label: None,
-          required_features: wgpu::Features::empty(),
-          required_limits: wgpu::Limits::default(),
-        },
-        None,
-      )
-      .await?;
-    let device = DeviceWarp { inner: &rdevice };
+    let (device, queue) = Self::request_device(&adapter).await?;
     let caps = surface.get_capabilities(&adapter);
+    // 截图需要把 surface 纹理复制到缓冲区，不支持时改为经过 capture_target 读回
+    let usage = if caps.usages.contains(wgpu::TextureUsages::COPY_SRC) {
+      wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC
+    } else {
+      wgpu::TextureUsages::RENDER_ATTACHMENT
+    };
     let config = wgpu::SurfaceConfiguration {
-      usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+      usage,
       format: caps.formats[0],
       width: size.width,
       height: size.height,
@@ -83,35 +153,139 @@ impl State {
       view_formats: vec![],
       desired_maximum_frame_latency: 2,
     };
-    surface.configure(&device.inner, &config);
+    surface.configure(&device, &config);
 
-    let texture_bind_group_layout = device.create_bind_group_layout(
-      "texture_bind_group_layout",
-      &[
-        wgpu::BindGroupLayoutEntry {
-          binding: 0,
-          visibility: wgpu::ShaderStages::FRAGMENT,
-          ty: wgpu::BindingType::Texture {
-            multisampled: false,
-            view_dimension: wgpu::TextureViewDimension::D2,
-            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+    Self::with_target(
+      &adapter,
+      device,
+      queue,
+      config,
+      RenderTarget::Surface(surface),
+    )
+    .await
+  }
+
+  /// 无窗口模式：渲染到离屏纹理，优先使用 fallback (软件) 适配器，
+  /// 便于在没有显示器和 GPU 的 CI 环境中运行
+  pub async fn new_headless(width: u32, height: u32) -> Result<Self> {
+    Self::new_headless_with_format(width, height, wgpu::TextureFormat::Rgba8UnormSrgb).await
+  }
+
+  /// 以指定格式的离屏纹理作为输出的无窗口模式。非 8 位格式不能用 [`State::read_frame`]
+  /// 读回，截图与录制仍然可用
+  pub async fn new_headless_with_format(
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+  ) -> Result<Self> {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+      backends: wgpu::Backends::all(),
+      ..Default::default()
+    });
+    let mut options = wgpu::RequestAdapterOptions {
+      power_preference: wgpu::PowerPreference::default(),
+      force_fallback_adapter: true,
+      compatible_surface: None,
+    };
+    let adapter = match instance.request_adapter(&options).await {
+      Some(adapter) => adapter,
+      None => {
+        options.force_fallback_adapter = false;
+        instance
+          .request_adapter(&options)
+          .await
+          .ok_or_else(|| eyre!("no suitable adapter for headless rendering"))?
+      }
+    };
+    let (device, queue) = Self::request_device(&adapter).await?;
+    let config = wgpu::SurfaceConfiguration {
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+      format,
+      width: width.max(1),
+      height: height.max(1),
+      present_mode: wgpu::PresentMode::Fifo,
+      alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+      view_formats: vec![],
+      desired_maximum_frame_latency: 2,
+    };
+    let target = texture::Texture::create_render_target(
+      &DeviceWarp::wrap(&device),
+      &config,
+      config.format,
+      "offscreen_texture",
+    );
+
+    Self::with_target(
+      &adapter,
+      device,
+      queue,
+      config,
+      RenderTarget::Offscreen(target),
+    )
+    .await
+  }
+
+  async fn request_device(adapter: &wgpu::Adapter) -> Result<(wgpu::Device, wgpu::Queue)> {
+    let (device, queue) = adapter
+      .request_device(
+        &wgpu::DeviceDescriptor {
+          label: None,
+          // 适配器支持时启用压缩纹理格式，否则加载时在 CPU 上解码
+          // 适配器特有的格式特性用于 4x 以外的 MSAA 采样数
+          required_features: adapter.features()
+            & (wgpu::Features::TEXTURE_COMPRESSION_BC
+              | wgpu::Features::TEXTURE_COMPRESSION_ASTC
+              | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES),
+          // 主渲染管线使用 6 个 bind group：材质、摄像机、光源、阴影、环境贴图与 SSAO
+          required_limits: wgpu::Limits {
+            max_bind_groups: 6,
+            ..Default::default()
           },
-          count: None,
-        },
-        wgpu::BindGroupLayoutEntry {
-          binding: 1,
-          visibility: wgpu::ShaderStages::FRAGMENT,
-          ty: wgpu::BindingType::Sampler(
-            // SamplerBindingType::Comparison 仅可供 TextureSampleType::Depth 使用
-            // 如果纹理的 sample_type 是 TextureSampleType::Float { filterable: true }
-            // 那么就应当使用 SamplerBindingType::Filtering
-            // 否则会报错
-            wgpu::SamplerBindingType::Filtering,
-          ),
-          count: None,
         },
-      ],
-    );
+        None,
+      )
+      .await?;
+    Ok((device, queue))
+  }
+
+  /// 颜色格式与深度格式都能使用的 MSAA 采样数。没有适配器特有的格式特性时，
+  /// 只能使用 WebGPU 保证的 1 与 4
+  fn query_sample_counts(
+    adapter: &wgpu::Adapter,
+    device: &wgpu::Device,
+    color_format: wgpu::TextureFormat,
+  ) -> Vec<u32> {
+    let format_features = |format: wgpu::TextureFormat| {
+      if device
+        .features()
+        .contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+      {
+        adapter.get_texture_format_features(format)
+      } else {
+        format.guaranteed_format_features(device.features())
+      }
+    };
+    let color = format_features(color_format);
+    let depth = format_features(texture::Texture::DEPTH_FORMAT);
+    [1, 2, 4, 8]
+      .into_iter()
+      .filter(|&count| {
+        color.flags.sample_count_supported(count) && depth.flags.sample_count_supported(count)
+      })
+      .collect()
+  }
+
+  async fn with_target(
+    adapter: &wgpu::Adapter,
+    rdevice: wgpu::Device,
+    queue: wgpu::Queue,
+    config: wgpu::SurfaceConfiguration,
+    target: RenderTarget,
+  ) -> Result<Self> {
+    let size = winit::dpi::PhysicalSize::new(config.width, config.height);
+    let device = DeviceWarp::wrap(&rdevice);
+
+    let texture_bind_group_layout = model::Material::create_bind_group_layout(&device);
 
     let camera = Camera::new(Point3::new(0.0, 0.0, -2.0));
     let mut camera_uniform = CameraUniform::new();
@@ -126,7 +300,7 @@ impl State {
       "camera_bind_group_layout",
       &[wgpu::BindGroupLayoutEntry {
         binding: 0,
-        visibility: wgpu::ShaderStages::VERTEX,
+        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
         ty: wgpu::BindingType::Buffer {
           ty: wgpu::BufferBindingType::Uniform,
           has_dynamic_offset: false,
@@ -143,128 +317,245 @@ impl State {
         resource: camera_buffer.as_entire_binding(),
       }],
     );
-    let depth_texture = texture::Texture::create_depth_texture(&device, &config, "depth_texture");
 
-    let shader = device.create_shader_module(include_wgsl!("../assets/shader.wgsl"));
+    let mut lights = light::Lights::new(&device);
+    lights.add(light::Light {
+      orbit_speed: 30.0,
+      ..light::Light::point(Point3::new(4.0, 8.0, 4.0), Vector3::new(1.0, 1.0, 1.0))
+    });
+    lights.add(light::Light {
+      intensity: 0.3,
+      ..light::Light::directional(Vector3::new(-0.3, -1.0, 0.5), Vector3::new(1.0, 0.95, 0.9))
+    });
+    lights.add(light::Light {
+      intensity: 2.0,
+      ..light::Light::spot(
+        Point3::new(-6.0, 6.0, -6.0),
+        Vector3::new(0.6, -1.0, 0.6),
+        15.0,
+        25.0,
+        Vector3::new(1.0, 0.6, 0.3),
+      )
+    });
+    let mut shadows = shadow::Shadows::new(&device, shadow::ShadowSettings::default());
+    lights.upload(&device, &queue, shadows.settings().max_point_shadows);
+    shadows.update(
+      &queue,
+      &lights,
+      &camera,
+      config.width as f32 / config.height as f32,
+    );
+
+    let depth_texture =
+      texture::Texture::create_depth_texture(&device, &config, 1, "depth_texture");
+    let hdr_texture = texture::Texture::create_render_target(
+      &device,
+      &config,
+      postprocess::HDR_FORMAT,
+      "hdr_texture",
+    );
+    let mut post_process = postprocess::PostProcess::new(&device, &config);
+    let mut taa = Taa::new(&device, config.width, config.height, TaaSettings::default());
+    taa.set_enabled(false);
+    post_process.add(taa);
+    let mut dof = DepthOfField::new(&device, config.height, DofSettings::default());
+    dof.set_enabled(false);
+    post_process.add(dof);
+    let mut motion_blur = MotionBlur::new(&device, MotionBlurSettings::default());
+    motion_blur.set_enabled(false);
+    post_process.add(motion_blur);
+    let mut bloom = Bloom::new(
+      &device,
+      config.width,
+      config.height,
+      BloomSettings::default(),
+    );
+    bloom.set_enabled(false);
+    post_process.add(bloom);
+    post_process.add(ToneMapper::new(&device, Default::default()));
+    let mut grading = ColorGrading::new(&device, &queue, ColorGradingSettings::default())?;
+    grading.set_enabled(false);
+    post_process.add(grading);
+    let mut fxaa = Fxaa::new(&device);
+    fxaa.set_enabled(false);
+    post_process.add(fxaa);
+    let mut vignette = Vignette::new(&device, VignetteSettings::default());
+    vignette.set_enabled(false);
+    post_process.add(vignette);
+    let supported_sample_counts =
+      Self::query_sample_counts(adapter, &rdevice, postprocess::HDR_FORMAT);
+    let environment = ibl::Environment::new(&device, ibl::EnvironmentSettings::default());
+    let background = background::Background::new(&device, postprocess::HDR_FORMAT);
+    let ssao = ssao::Ssao::new(
+      &device,
+      &queue,
+      &config,
+      &depth_texture.view,
+      ssao::SsaoSettings::default(),
+    )?;
+
     let render_pipeline_layout = device.create_pipeline_layout(
       "Render Pipeline Layout",
+      &[
+        &texture_bind_group_layout,
+        &camera_bind_group_layout,
+        lights.layout(),
+        shadows.layout(),
+        environment.layout(),
+        ssao.layout(),
+      ],
+      &[],
+    );
+    let depth_pipeline_layout = device.create_pipeline_layout(
+      "Depth Pipeline Layout",
       &[&texture_bind_group_layout, &camera_bind_group_layout],
       &[],
     );
-    let render_pipeline = device.create_render_pipeline(
-      "Render Pipline",
-      Some(&render_pipeline_layout),
+    let pipelines = material::PipelineCache::new(
+      render_pipeline_layout,
+      depth_pipeline_layout,
+      postprocess::HDR_FORMAT,
+    );
+
+    let light_pipeline_layout = device.create_pipeline_layout(
+      "Light Pipeline Layout",
+      &[&camera_bind_group_layout, lights.layout()],
+      &[],
+    );
+    let light_render_pipeline =
+      Self::create_light_pipeline(&device, &light_pipeline_layout, postprocess::HDR_FORMAT, 1);
+
+    let mipmaps = texture::MipmapGenerator::new(&rdevice);
+    let obj_model = res::load_model(
+      Path::new("cube/cube.obj"),
+      model::Normals::default(),
+      &DeviceWarp::with_mipmaps(&rdevice, &mipmaps),
+      &queue,
+      &texture_bind_group_layout,
+    )
+    .await?;
+
+    let instances = instance::grid(NUM_INSTANCES_PER_ROW, 3.0);
+    let instance_data = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
+    let instance_buffer = device.create_buffer_init(
+      "Instance Buffer",
+      bytemuck::cast_slice(&instance_data),
+      wgpu::BufferUsages::VERTEX,
+    );
+    Ok(Self {
+      target,
+      device: rdevice,
+      queue,
+      config,
+      size,
+      pipelines,
+      texture_bind_group_layout,
+      sampler_settings: texture::SamplerSettings::default(),
+      obj_model,
+      camera,
+      camera_uniform,
+      camera_buffer,
+      camera_bind_group,
+      lights,
+      shadows,
+      environment,
+      ssao,
+      background,
+      light_pipeline_layout,
+      light_render_pipeline,
+      show_light: false,
+      pbr_enabled: true,
+      instances,
+      instance_buffer,
+      depth_texture,
+      prepass_depth: None,
+      hdr_texture,
+      post_process,
+      msaa_texture: None,
+      sample_count: 1,
+      supported_sample_counts,
+      prev_view_proj: None,
+      frame_index: 0,
+      capture: capture::Capture::new(),
+      capture_target: None,
+      mipmaps,
+    })
+  }
+
+  /// 绘制光源模型的管线
+  fn create_light_pipeline<T: DeviceTrait>(
+    device: &T,
+    layout: &wgpu::PipelineLayout,
+    color_format: wgpu::TextureFormat,
+    sample_count: u32,
+  ) -> wgpu::RenderPipeline {
+    let light_shader = device.create_shader_module(include_wgsl!("../assets/light.wgsl"));
+    device.create_render_pipeline(
+      "Light Render Pipeline",
+      Some(layout),
       wgpu::VertexState {
-        module: &shader,
-        // 指定应将着色器中的哪个函数作为 entry_point
+        module: &light_shader,
         entry_point: "vs_main",
-        // buffers 字段用于告知 wgpu 我们要传递给顶点着色器的顶点类型
-        buffers: &[model::ModelVertex::desc(), InstanceRaw::desc()],
+        buffers: &[model::ModelVertex::desc()],
       },
-      // primitive 字段描述了应如何将我们所提供的顶点数据转为三角形
       wgpu::PrimitiveState {
-        // PrimitiveTopology::TriangleList 表示每三个顶点将对应一个三角形
         topology: wgpu::PrimitiveTopology::TriangleList,
-        strip_index_format: None,
-        // front_face 和 cull_mode 字段告诉 wgpu 应如何确定某个三角形是否朝前
-        // FrontFace::Ccw 表示如果顶点按逆时针方向排列，则判定三角形是朝前的
         front_face: wgpu::FrontFace::Ccw,
-        // 不满足朝前条件的三角形会被剔除（即不被渲染），这是用 CullMode::Back 所确定的
         cull_mode: Some(wgpu::Face::Back),
-        // cull_mode: None,
-        // 如果将该字段设置为除了 Fill 之外的任何值，都需要 Features::NON_FILL_POLYGON_MODE
-        polygon_mode: wgpu::PolygonMode::Fill,
-        // 需要 Features::DEPTH_CLIP_ENABLE
-        unclipped_depth: false,
-        // 需要 Features::CONSERVATIVE_RASTERIZATION
-        conservative: false,
+        ..Default::default()
       },
-      // 深度 / 模板缓冲区
       Some(wgpu::DepthStencilState {
         format: texture::Texture::DEPTH_FORMAT,
         depth_write_enabled: true,
-        // 用于确定何时丢弃一个新像素，使用 LESS 意味着像素将从前往后绘制
         depth_compare: wgpu::CompareFunction::Less,
         stencil: wgpu::StencilState::default(),
         bias: wgpu::DepthBiasState::default(),
       }),
       wgpu::MultisampleState {
-        // count 决定了 pipeline 将使用多少次采样
-        count: 1,
-        // mask 指定了哪些采样应被设为活跃。目前我们将使用所有的采样
-        mask: !0,
-        // 抗锯齿
-        alpha_to_coverage_enabled: false,
+        count: sample_count,
+        ..Default::default()
       },
       wgpu::FragmentState {
-        module: &shader,
-        // 指定应将着色器中的哪个函数作为 entry_point
+        module: &light_shader,
         entry_point: "fs_main",
-        // targets 字段告诉 wgpu 应该设置哪些颜色输出
         targets: &[Some(wgpu::ColorTargetState {
-          format: config.format,
-          // 指定混合模式（blending）为仅用新数据替换旧像素数据
+          format: color_format,
           blend: Some(wgpu::BlendState::REPLACE),
-          // 要求 wgpu 写入所有像素通道的颜色，即红、蓝、绿和 alpha
           write_mask: wgpu::ColorWrites::ALL,
         })],
       },
       None,
-    );
+    )
+  }
 
-    let obj_model = res::load_model(
-      Path::new("cube/cube.obj"),
+  /// 按当前尺寸与采样数重建深度纹理、HDR 纹理和多重采样的颜色目标，以及读取深度的 SSAO
+  fn create_attachments(&mut self) {
+    let device = DeviceWarp::wrap(&self.device);
+    self.depth_texture = texture::Texture::create_depth_texture(
       &device,
-      &queue,
-      &texture_bind_group_layout,
-    )
-    .await
-    .unwrap();
-
-    const SPACE_BETWEEN: f32 = 3.0;
-    let instances = (0..NUM_INSTANCES_PER_ROW)
-      .flat_map(|z| {
-        (0..NUM_INSTANCES_PER_ROW).map(move |x| {
-          let x = SPACE_BETWEEN * (x as f32 - NUM_INSTANCES_PER_ROW as f32 / 2.0);
-          let z = SPACE_BETWEEN * (z as f32 - NUM_INSTANCES_PER_ROW as f32 / 2.0);
-          let position = na::Point3::new(x as f32, 0.0, z as f32);
-          let rotation = if position.xyz() == na::Point3::new(0.0, 0.0, 0.0) {
-            // 需要这行特殊处理，这样在 (0, 0, 0) 的物体不会被缩放到 0
-            // 因为错误的四元数会影响到缩放
-            na::UnitQuaternion::from_axis_angle(&na::Vector3::z_axis(), 0.0_f32.to_radians())
-          } else {
-            na::UnitQuaternion::from_axis_angle(
-              &na::Unit::new_unchecked(position.coords.normalize()),
-              45.0_f32.to_radians(),
-            )
-          };
-          Instance { position, rotation }
-        })
-      })
-      .collect::<Vec<_>>();
-    let instance_data = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
-    let instance_buffer = device.create_buffer_init(
-      "Instance Buffer",
-      bytemuck::cast_slice(&instance_data),
-      wgpu::BufferUsages::VERTEX,
+      &self.config,
+      self.sample_count,
+      "depth_texture",
     );
-    Ok(Self {
-      surface,
-      device: rdevice,
-      queue,
-      config,
-      size,
-      clear_color: na::Vector3::new(0.1, 0.7, 0.2),
-      render_pipeline,
-      obj_model,
-      camera,
-      camera_uniform,
-      camera_buffer,
-      camera_bind_group,
-      instances,
-      instance_buffer,
-      depth_texture,
-    })
+    self.hdr_texture = texture::Texture::create_render_target(
+      &device,
+      &self.config,
+      postprocess::HDR_FORMAT,
+      "hdr_texture",
+    );
+    self.msaa_texture = (self.sample_count > 1).then(|| {
+      texture::Texture::create_multisampled_target(
+        &device,
+        &self.config,
+        postprocess::HDR_FORMAT,
+        self.sample_count,
+        "msaa_texture",
+      )
+    });
+    self.prepass_depth = (self.sample_count > 1)
+      .then(|| texture::Texture::create_depth_texture(&device, &self.config, 1, "prepass_depth"));
+    let depth = self.prepass_depth.as_ref().unwrap_or(&self.depth_texture);
+    self.ssao.resize(&self.device, &self.config, &depth.view);
   }
 
   pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
@@ -272,87 +563,563 @@ impl State {
       self.size = new_size;
       self.config.width = new_size.width;
       self.config.height = new_size.height;
-      self.depth_texture =
-        texture::Texture::create_depth_texture(self, &self.config, "depth_texture");
-      self.surface.configure(&self.device, &self.config);
+      self.create_attachments();
+      self.capture_target = None;
+      self
+        .post_process
+        .resize(&DeviceWarp::wrap(&self.device), &self.config);
+      match &mut self.target {
+        RenderTarget::Surface(surface) => surface.configure(&self.device, &self.config),
+        RenderTarget::Offscreen(texture) => {
+          *texture = texture::Texture::create_render_target(
+            &DeviceWarp::wrap(&self.device),
+            &self.config,
+            self.config.format,
+            "offscreen_texture",
+          )
+        }
+      }
+    };
+  }
+
+  /// surface 纹理能否直接读回为 8 位 RGBA
+  fn surface_readable(&self) -> bool {
+    self.config.usage.contains(wgpu::TextureUsages::COPY_SRC)
+      && texture::Texture::is_rgba8(self.config.format)
+  }
+
+  pub fn capture_mut(&mut self) -> &mut capture::Capture {
+    &mut self.capture
+  }
+
+  pub fn sample_count(&self) -> u32 {
+    self.sample_count
+  }
+
+  /// 当前适配器支持的 MSAA 采样数，总是包含 1
+  pub fn supported_sample_counts(&self) -> &[u32] {
+    &self.supported_sample_counts
+  }
+
+  /// 设置 MSAA 采样数，1 表示关闭。适配器不支持该采样数时返回错误且不做任何修改
+  pub fn set_sample_count(&mut self, sample_count: u32) -> Result<()> {
+    if !self.supported_sample_counts.contains(&sample_count) {
+      bail!(
+        "{sample_count}x MSAA is not supported by the adapter, supported sample counts: {:?}",
+        self.supported_sample_counts
+      );
+    }
+    if sample_count == self.sample_count {
+      return Ok(());
+    }
+    self.sample_count = sample_count;
+    self.create_attachments();
+    let device = DeviceWarp::wrap(&self.device);
+    self.pipelines.set_sample_count(sample_count);
+    self.background.set_sample_count(&device, sample_count);
+    self.light_render_pipeline = Self::create_light_pipeline(
+      &device,
+      &self.light_pipeline_layout,
+      postprocess::HDR_FORMAT,
+      sample_count,
+    );
+    Ok(())
+  }
+
+  pub fn anti_aliasing(&self) -> AntiAliasing {
+    let enabled = |name| {
+      self
+        .post_process
+        .effects()
+        .any(|effect| effect.name() == name && effect.enabled())
+    };
+    if self.sample_count > 1 {
+      AntiAliasing::Msaa(self.sample_count)
+    } else if enabled("taa") {
+      AntiAliasing::Taa
+    } else if enabled("fxaa") {
+      AntiAliasing::Fxaa
+    } else {
+      AntiAliasing::None
+    }
+  }
+
+  /// 切换抗锯齿方式，各方式互斥。MSAA 的采样数不受支持时返回错误且不做任何修改
+  pub fn set_anti_aliasing(&mut self, mode: AntiAliasing) -> Result<()> {
+    let sample_count = match mode {
+      AntiAliasing::Msaa(count) => count,
+      _ => 1,
     };
+    self.set_sample_count(sample_count)?;
+    self
+      .post_process
+      .set_enabled("fxaa", mode == AntiAliasing::Fxaa);
+    self
+      .post_process
+      .set_enabled("taa", mode == AntiAliasing::Taa);
+    Ok(())
+  }
+
+  pub fn post_process(&self) -> &postprocess::PostProcess {
+    &self.post_process
+  }
+
+  /// 用于添加效果或调整已有效果的参数
+  pub fn post_process_mut(&mut self) -> &mut postprocess::PostProcess {
+    &mut self.post_process
+  }
+
+  pub fn tone_mapping(&self) -> &tonemap::ToneMappingSettings {
+    self
+      .post_process
+      .get::<ToneMapper>()
+      .expect("the tone mapper is added when the state is created")
+      .settings()
+  }
+
+  pub fn set_tone_mapping(&mut self, settings: tonemap::ToneMappingSettings) {
+    self
+      .post_process
+      .get_mut::<ToneMapper>()
+      .expect("the tone mapper is added when the state is created")
+      .set_settings(settings);
+  }
+
+  pub fn color_grading(&self) -> &ColorGradingSettings {
+    self
+      .post_process
+      .get::<ColorGrading>()
+      .expect("color grading is added when the state is created")
+      .settings()
+  }
+
+  pub fn set_color_grading(&mut self, settings: ColorGradingSettings) {
+    self
+      .post_process
+      .get_mut::<ColorGrading>()
+      .expect("color grading is added when the state is created")
+      .set_settings(settings);
+  }
+
+  /// 加载 .cube 文件或条带图片作为调色的 LUT。不会启用调色效果
+  pub async fn load_lut(&mut self, path: &Path) -> Result<()> {
+    let lut = res::load_lut(path).await?;
+    self
+      .post_process
+      .get_mut::<ColorGrading>()
+      .expect("color grading is added when the state is created")
+      .set_lut(&self.device, &self.queue, Some(lut))
+  }
+
+  pub fn shadow_settings(&self) -> &shadow::ShadowSettings {
+    self.shadows.settings()
+  }
+
+  pub fn set_shadow_settings(&mut self, settings: shadow::ShadowSettings) {
+    self.shadows.set_settings(&self.device, settings);
+  }
+
+  pub fn ssao_settings(&self) -> &ssao::SsaoSettings {
+    self.ssao.settings()
+  }
+
+  pub fn set_ssao_settings(&mut self, settings: ssao::SsaoSettings) {
+    self.ssao.set_settings(settings);
+  }
+
+  pub fn environment_settings(&self) -> &ibl::EnvironmentSettings {
+    self.environment.settings()
+  }
+
+  pub fn set_environment_settings(&mut self, settings: ibl::EnvironmentSettings) {
+    self.environment.set_settings(&self.queue, settings);
+  }
+
+  /// 加载等距柱状投影的 .hdr 图像作为环境光照，同时用作天空盒并切换到天空盒背景
+  pub async fn load_environment(&mut self, path: &Path) -> Result<()> {
+    let image = res::load_hdr(path).await?;
+    let device = DeviceWarp::wrap(&self.device);
+    self.environment.load_equirect(&device, &self.queue, &image)?;
+    if let Some(view) = self.environment.environment_view() {
+      self.background.set_skybox(&device, view);
+      self.background.set_mode(background::BackgroundMode::Skybox);
+    }
+    Ok(())
+  }
+
+  /// 加载六张图片作为天空盒并切换到天空盒背景，顺序为 +X -X +Y -Y +Z -Z。
+  /// 只影响背景，不改变环境光照
+  pub async fn load_skybox(&mut self, faces: [&Path; 6]) -> Result<()> {
+    let device = DeviceWarp::wrap(&self.device);
+    let skybox = res::load_cube_texture(faces, &device, &self.queue).await?;
+    self.background.set_skybox(&device, &skybox.view);
+    self.background.set_mode(background::BackgroundMode::Skybox);
+    Ok(())
+  }
+
+  pub fn background_mode(&self) -> &background::BackgroundMode {
+    self.background.mode()
+  }
+
+  pub fn set_background_mode(&mut self, mode: background::BackgroundMode) {
+    self.background.set_mode(mode);
+  }
+
+  pub fn lights(&self) -> &light::Lights {
+    &self.lights
+  }
+
+  /// 增删、移动光源后，会在下一次 update 时写入 GPU
+  pub fn lights_mut(&mut self) -> &mut light::Lights {
+    &mut self.lights
+  }
+
+  pub fn camera_mut(&mut self) -> &mut Camera {
+    &mut self.camera
+  }
+
+  /// 替换场景中绘制的模型，支持 OBJ 与 glTF。文件中没有法线的网格使用平滑法线
+  pub async fn load_model(&mut self, path: &Path) -> Result<(), res::ModelError> {
+    self
+      .load_model_with_normals(path, model::Normals::Smooth)
+      .await
+  }
+
+  /// 同 [`State::load_model`]，没有法线的网格按 normal_mode 生成法线
+  pub async fn load_model_with_normals(
+    &mut self,
+    path: &Path,
+    normal_mode: model::Normals,
+  ) -> Result<(), res::ModelError> {
+    self.obj_model = res::load_model(
+      path,
+      normal_mode,
+      &DeviceWarp::with_mipmaps(&self.device, &self.mipmaps),
+      &self.queue,
+      &self.texture_bind_group_layout,
+    )
+    .await?;
+    if self.sampler_settings != texture::SamplerSettings::default() {
+      self.apply_sampler_settings();
+    }
+    Ok(())
+  }
+
+  pub fn sampler_settings(&self) -> &texture::SamplerSettings {
+    &self.sampler_settings
+  }
+
+  /// 修改模型贴图的过滤方式、各向异性与寻址方式
+  pub fn set_sampler_settings(&mut self, settings: texture::SamplerSettings) {
+    self.sampler_settings = settings;
+    self.apply_sampler_settings();
+  }
+
+  fn apply_sampler_settings(&mut self) {
+    let device = DeviceWarp::wrap(&self.device);
+    for material in &mut self.obj_model.materials {
+      material.set_sampler(
+        &device,
+        &self.sampler_settings,
+        &self.texture_bind_group_layout,
+      );
+    }
+  }
+
+  /// 替换场景中的实例，并重建实例缓冲区
+  pub fn set_instances(&mut self, instances: Vec<Instance>) {
+    let instance_data = instances.iter().map(Instance::to_raw).collect::<Vec<_>>();
+    self.instance_buffer = self.create_buffer_init(
+      "Instance Buffer",
+      bytemuck::cast_slice(&instance_data),
+      wgpu::BufferUsages::VERTEX,
+    );
+    self.instances = instances;
   }
 
   pub fn update(&mut self) {
     self.camera.handle_input();
-    self.camera_uniform.update_view_proj(
-      &self.camera,
-      self.config.width as f32 / self.config.height as f32,
-    );
+    let aspect = self.config.width as f32 / self.config.height as f32;
+    // TAA 每帧偏移投影，在多帧中累积不同的采样位置
+    let jitter = if self.anti_aliasing() == AntiAliasing::Taa {
+      taa::jitter(self.frame_index, self.config.width, self.config.height)
+    } else {
+      Vector2::zeros()
+    };
+    self.camera.set_jitter(jitter);
+    self.frame_index = self.frame_index.wrapping_add(1);
+    self.camera_uniform.update_view_proj(&self.camera, aspect);
     self.queue.write_buffer(
       &self.camera_buffer,
       0,
       bytemuck::cast_slice(&[self.camera_uniform]),
     );
+
+    self.lights.update(time::get_delta());
+    self.lights.upload(
+      &DeviceWarp::wrap(&self.device),
+      &self.queue,
+      self.shadows.settings().max_point_shadows,
+    );
+    self
+      .shadows
+      .update(&self.queue, &self.lights, &self.camera, aspect);
+    self.ssao.update(&self.queue, &self.camera, aspect);
+    self.background.update(&self.queue, &self.camera, aspect);
+    let view_proj = self.camera.get_unjittered_vp_mat(aspect);
+    self.post_process.update(
+      &self.queue,
+      &postprocess::FrameInfo {
+        delta_time: time::get_delta(),
+        view_proj,
+        prev_view_proj: self.prev_view_proj.unwrap_or(view_proj),
+        jitter,
+      },
+    );
+    self.prev_view_proj = Some(view_proj);
   }
 
   pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-    let output = self.surface.get_current_texture()?;
-    let view = output
-      .texture
-      .create_view(&wgpu::TextureViewDescriptor::default());
+    let (output, view) = match &self.target {
+      RenderTarget::Surface(surface) => {
+        let output = surface.get_current_texture()?;
+        let view = output
+          .texture
+          .create_view(&wgpu::TextureViewDescriptor::default());
+        (Some(output), view)
+      }
+      RenderTarget::Offscreen(texture) => (
+        None,
+        texture
+          .texture
+          .create_view(&wgpu::TextureViewDescriptor::default()),
+      ),
+    };
     let mut encoder = self
       .device
       .create_command_encoder(&wgpu::CommandEncoderDescriptor {
         label: Some("Render Encoder"),
       });
 
+    self.shadows.render(
+      &mut encoder,
+      &self.obj_model,
+      &self.instance_buffer,
+      self.instances.len() as u32,
+    );
+
+    let pbr_enabled = self.pbr_enabled;
+    let pipeline_key = |material: &model::Material| {
+      let mut key = material.pipeline_key();
+      if !pbr_enabled {
+        key.shading = material::ShadingModel::Phong;
+      }
+      key
+    };
+    self.pipelines.prepare(
+      &DeviceWarp::wrap(&self.device),
+      self.obj_model.materials.iter().map(pipeline_key),
+    );
+
+    // SSAO 需要在主 pass 之前得到场景的深度。不开 MSAA 时预渲染直接写入主 pass 的深度纹理，
+    // 主 pass 沿用其中的深度而不再清除；开启 MSAA 时多重采样的深度不能被采样，
+    // 依赖深度的后处理效果也改为读取预渲染的单采样深度
+    let depth_prepass =
+      self.ssao.settings().enabled || (self.sample_count > 1 && self.post_process.needs_depth());
+    if depth_prepass {
+      self.pipelines.prepare_depth(
+        &DeviceWarp::wrap(&self.device),
+        self.obj_model.materials.iter().map(pipeline_key),
+      );
+      let depth = self.prepass_depth.as_ref().unwrap_or(&self.depth_texture);
+      let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Depth Prepass"),
+        color_attachments: &[],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+          view: &depth.view,
+          depth_ops: Some(wgpu::Operations {
+            load: wgpu::LoadOp::Clear(1.0),
+            store: wgpu::StoreOp::Store,
+          }),
+          stencil_ops: None,
+        }),
+        ..Default::default()
+      });
+      pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+      pass.set_bind_group(1, &self.camera_bind_group, &[]);
+      for mesh in &self.obj_model.meshes {
+        let material = &self.obj_model.materials[mesh.material];
+        let Some(pipeline) = self.pipelines.get_depth(&pipeline_key(material)) else {
+          continue;
+        };
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &material.bind_group, &[]);
+        pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+        pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        pass.draw_indexed(0..mesh.num_elements, 0, 0..self.instances.len() as u32);
+      }
+    }
+    self.ssao.render(&mut encoder);
+
     let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
       label: Some("Render Pass"),
       color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-        // 用于告知 wgpu 应将颜色存储到哪个纹理
-        view: &view,
+        // 用于告知 wgpu 应将颜色存储到哪个纹理，开启 MSAA 时为多重采样纹理
+        view: self
+          .msaa_texture
+          .as_ref()
+          .map_or(&self.hdr_texture.view, |msaa| &msaa.view),
         // 用于接收多重采样解析后所输出内容的纹理
-        resolve_target: None,
+        resolve_target: self.msaa_texture.as_ref().map(|_| &self.hdr_texture.view),
         // 用于告知 wgpu 应如何处理屏幕上的颜色
         ops: wgpu::Operations {
           // load 字段告诉 wgpu 该如何处理存储在前一帧的颜色
-          load: wgpu::LoadOp::Clear(wgpu::Color {
-            r: self.clear_color.x,
-            g: self.clear_color.y,
-            b: self.clear_color.z,
-            a: 1.0,
-          }),
+          load: wgpu::LoadOp::Clear(self.background.clear_color()),
           // store 字段用于告知 wgpu 是否应将渲染的结果存储到 TextureView 下层的 Texture
-          store: wgpu::StoreOp::Store,
+          // 多重采样的内容解析后就不再需要
+          store: if self.msaa_texture.is_some() {
+            wgpu::StoreOp::Discard
+          } else {
+            wgpu::StoreOp::Store
+          },
         },
       })],
       depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
         view: &self.depth_texture.view,
         depth_ops: Some(wgpu::Operations {
-          load: wgpu::LoadOp::Clear(1.0),
+          load: if depth_prepass && self.sample_count == 1 {
+            wgpu::LoadOp::Load
+          } else {
+            wgpu::LoadOp::Clear(1.0)
+          },
           store: wgpu::StoreOp::Store,
         }),
         stencil_ops: None,
       }),
       ..Default::default()
     });
+    use model::{DrawLight, DrawModel};
+    if self.show_light {
+      render_pass.set_pipeline(&self.light_render_pipeline);
+      render_pass.draw_light_model_instanced(
+        &self.obj_model,
+        0..self.lights.len() as u32,
+        &self.camera_bind_group,
+        self.lights.bind_group(),
+      );
+    }
+
     render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-    render_pass.set_pipeline(&self.render_pipeline);
-
-    use model::DrawModel;
-    let mesh = &self.obj_model.meshes[0];
-    let material = &self.obj_model.materials[mesh.material];
-    render_pass.draw_mesh_instanced(
-      mesh,
-      material,
-      0..self.instances.len() as u32,
-      &self.camera_bind_group,
-    );
+    render_pass.set_bind_group(3, self.shadows.bind_group(), &[]);
+    render_pass.set_bind_group(4, self.environment.bind_group(), &[]);
+    render_pass.set_bind_group(5, self.ssao.bind_group(), &[]);
+
+    // 半透明的网格放在背景之后绘制，才能与已经画好的不透明物体和背景混合
+    let (blended, opaque): (Vec<_>, Vec<_>) = self
+      .obj_model
+      .meshes
+      .iter()
+      .map(|mesh| (mesh, &self.obj_model.materials[mesh.material]))
+      .partition(|(_, material)| material.pipeline_key().blend == material::BlendMode::Alpha);
+    for (i, meshes) in [opaque, blended].into_iter().enumerate() {
+      if i == 1 {
+        // 背景只覆盖深度测试后剩下的像素，绘制后恢复主管线使用的绑定
+        self.background.draw(&mut render_pass);
+        render_pass.set_bind_group(3, self.shadows.bind_group(), &[]);
+        render_pass.set_bind_group(4, self.environment.bind_group(), &[]);
+        render_pass.set_bind_group(5, self.ssao.bind_group(), &[]);
+      }
+      for (mesh, material) in meshes {
+        let Some(pipeline) = self.pipelines.get(&pipeline_key(material)) else {
+          continue;
+        };
+        render_pass.set_pipeline(pipeline);
+        render_pass.draw_mesh_instanced(
+          mesh,
+          material,
+          0..self.instances.len() as u32,
+          &self.camera_bind_group,
+          self.lights.bind_group(),
+        );
+      }
+    }
 
     drop(render_pass);
 
+    // 需要截图或录制、而 surface 又不能直接读回时，后处理先输出到 8 位的中转纹理，
+    // 读回的是中转纹理，再把它复制到 surface 上显示
+    let capture_frame = self.capture.wants_frame();
+    let via_capture_target = capture_frame && !self.surface_readable();
+    if via_capture_target && self.capture_target.is_none() {
+      self.capture_target = Some(texture::Texture::create_render_target(
+        &DeviceWarp::wrap(&self.device),
+        &self.config,
+        CAPTURE_FORMAT,
+        "capture_texture",
+      ));
+    }
+    let (final_view, final_format) = match &self.capture_target {
+      Some(target) if via_capture_target => (&target.view, CAPTURE_FORMAT),
+      _ => (&view, self.config.format),
+    };
+
+    let depth = self.prepass_depth.as_ref().unwrap_or(&self.depth_texture);
+    self.post_process.render(
+      &self.device,
+      &mut encoder,
+      &self.hdr_texture.view,
+      Some(&depth.view),
+      final_view,
+      final_format,
+    );
+    if via_capture_target {
+      self.post_process.blit(
+        &self.device,
+        &mut encoder,
+        final_view,
+        &view,
+        self.config.format,
+      );
+    }
+
     // submit 方法能传入任何实现了 IntoIter 的参数
     self.queue.submit(std::iter::once(encoder.finish()));
-    output.present();
+
+    // 需要截图或录制时，在 present 之前把这一帧读回
+    if capture_frame {
+      let texture = match (&output, &self.target, &self.capture_target) {
+        (_, _, Some(target)) if via_capture_target => &target.texture,
+        (Some(output), _, _) => &output.texture,
+        (None, RenderTarget::Offscreen(texture), _) => &texture.texture,
+        (None, RenderTarget::Surface(_), _) => unreachable!(),
+      };
+      match texture::Texture::read_rgba8(&DeviceWarp::wrap(&self.device), &self.queue, texture) {
+        Ok(frame) => self.capture.submit(frame),
+        Err(e) => error!("failed to capture frame: {e:?}"),
+      }
+    }
+
+    if let Some(output) = output {
+      output.present();
+    }
 
     Ok(())
   }
 
+  /// 读取最近一次 render 的结果，仅支持无窗口模式。
+  /// 有窗口时 surface 纹理在 present 后即失效，请使用 [`capture::Capture`]
+  pub fn read_frame(&self) -> Result<image::RgbaImage> {
+    match &self.target {
+      RenderTarget::Offscreen(texture) => {
+        texture::Texture::read_rgba8(self, &self.queue, &texture.texture)
+      }
+      RenderTarget::Surface(_) => bail!("reading back the surface is not supported"),
+    }
+  }
 
+  /// 渲染一帧并将其读回为图像
+  pub fn render_to_image(&mut self) -> Result<image::RgbaImage> {
+    self.render()?;
+    self.read_frame()
+  }
 }
@@ -1,9 +1,77 @@
-use std::path::Path;
+use std::{collections::HashMap, path::Path, sync::Mutex};
 
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{bail, eyre, Result};
 use image::GenericImageView;
+use tracing::debug;
 
-use crate::exts::state::DeviceTrait;
+use crate::{
+  compressed,
+  exts::state::{DeviceTrait, DeviceWarp},
+};
+
+/// 纹理采样器的设置
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplerSettings {
+  pub address_mode_u: wgpu::AddressMode,
+  pub address_mode_v: wgpu::AddressMode,
+  pub mag_filter: wgpu::FilterMode,
+  pub min_filter: wgpu::FilterMode,
+  pub mipmap_filter: wgpu::FilterMode,
+  /// 各向异性过滤的最大倍数，范围为 1 到 16。任一过滤方式不是 Linear 时按 1 处理
+  pub anisotropy: u16,
+}
+impl Default for SamplerSettings {
+  fn default() -> Self {
+    Self {
+      address_mode_u: wgpu::AddressMode::Repeat,
+      address_mode_v: wgpu::AddressMode::Repeat,
+      mag_filter: wgpu::FilterMode::Linear,
+      min_filter: wgpu::FilterMode::Linear,
+      mipmap_filter: wgpu::FilterMode::Linear,
+      anisotropy: 16,
+    }
+  }
+}
+impl SamplerSettings {
+  pub fn create_sampler<T: DeviceTrait>(&self, device: &T) -> wgpu::Sampler {
+    // wgpu 要求开启各向异性过滤时三种过滤方式都是 Linear
+    let linear = [self.mag_filter, self.min_filter, self.mipmap_filter]
+      .iter()
+      .all(|filter| *filter == wgpu::FilterMode::Linear);
+    device
+      .get_device()
+      .create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: self.address_mode_u,
+        address_mode_v: self.address_mode_v,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: self.mag_filter,
+        min_filter: self.min_filter,
+        mipmap_filter: self.mipmap_filter,
+        anisotropy_clamp: if linear {
+          self.anisotropy.clamp(1, 16)
+        } else {
+          1
+        },
+        ..Default::default()
+      })
+  }
+}
+
+/// 从图像创建纹理时的设置
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextureSettings {
+  /// 是否在 GPU 上生成完整的 mip 链。总是按原尺寸显示的纹理（如 UI）可以关闭
+  pub mipmaps: bool,
+  pub sampler: SamplerSettings,
+}
+impl Default for TextureSettings {
+  fn default() -> Self {
+    Self {
+      mipmaps: true,
+      sampler: SamplerSettings::default(),
+    }
+  }
+}
 
 pub struct Texture {
   pub texture: wgpu::Texture,
@@ -18,11 +86,12 @@ impl Texture {
     device: &T,
     queue: &wgpu::Queue,
     path: P,
+    is_normal_map: bool,
   ) -> Result<Self> {
     let path_copy = path.as_ref().to_path_buf();
     let label = path_copy.to_str();
     let img = image::open(path)?;
-    Self::from_image(device, queue, &img, label)
+    Self::from_image(device, queue, &img, label, is_normal_map)
   }
 
   pub fn from_bytes<T: DeviceTrait>(
@@ -30,9 +99,52 @@ impl Texture {
     queue: &wgpu::Queue,
     bytes: &[u8],
     label: &str,
+    is_normal_map: bool,
   ) -> Result<Self> {
     let img = image::load_from_memory(bytes)?;
-    Self::from_image(device, queue, &img, Some(label))
+    Self::from_image(device, queue, &img, Some(label), is_normal_map)
+  }
+
+  /// 1x1 的平坦法线贴图，供没有法线贴图的材质使用
+  pub fn flat_normal<T: DeviceTrait>(device: &T, queue: &wgpu::Queue) -> Result<Self> {
+    let img = image::RgbaImage::from_pixel(1, 1, image::Rgba([128, 128, 255, 255]));
+    Self::from_image(
+      device,
+      queue,
+      &image::DynamicImage::ImageRgba8(img),
+      Some("flat_normal"),
+      true,
+    )
+  }
+
+  /// 1x1 的白色贴图，作为缺失贴图时不影响结果的默认值
+  pub fn white<T: DeviceTrait>(device: &T, queue: &wgpu::Queue, is_linear: bool) -> Result<Self> {
+    let img = image::RgbaImage::from_pixel(1, 1, image::Rgba([255; 4]));
+    Self::from_image(
+      device,
+      queue,
+      &image::DynamicImage::ImageRgba8(img),
+      Some("white"),
+      is_linear,
+    )
+  }
+
+  /// 品红与黑色相间的棋盘格，用于缺失贴图或材质的网格，使问题在画面中一目了然
+  pub fn checkerboard<T: DeviceTrait>(device: &T, queue: &wgpu::Queue) -> Result<Self> {
+    let img = image::RgbaImage::from_fn(64, 64, |x, y| {
+      if (x / 8 + y / 8) % 2 == 0 {
+        image::Rgba([255, 0, 255, 255])
+      } else {
+        image::Rgba([0, 0, 0, 255])
+      }
+    });
+    Self::from_image(
+      device,
+      queue,
+      &image::DynamicImage::ImageRgba8(img),
+      Some("checkerboard"),
+      false,
+    )
   }
 
   pub fn from_image<T: DeviceTrait>(
@@ -40,6 +152,25 @@ impl Texture {
     queue: &wgpu::Queue,
     img: &image::DynamicImage,
     label: Option<&str>,
+    is_normal_map: bool,
+  ) -> Result<Self> {
+    Self::from_image_with(
+      device,
+      queue,
+      img,
+      label,
+      is_normal_map,
+      &TextureSettings::default(),
+    )
+  }
+
+  pub fn from_image_with<T: DeviceTrait>(
+    device: &T,
+    queue: &wgpu::Queue,
+    img: &image::DynamicImage,
+    label: Option<&str>,
+    is_normal_map: bool,
+    settings: &TextureSettings,
   ) -> Result<Self> {
     let rgba = img.to_rgba8();
     let dimensions = img.dimensions();
@@ -49,20 +180,37 @@ impl Texture {
       height: dimensions.1,
       depth_or_array_layers: 1,
     };
+    // 每一级的边长减半，直到 1x1
+    let mip_level_count = if settings.mipmaps {
+      u32::BITS - dimensions.0.max(dimensions.1).leading_zeros()
+    } else {
+      1
+    };
+    // 多数图像都使用 sRGB 格式，所以我们需要在此将其体现出来
+    // 但法线贴图存储的是向量而非颜色，必须按线性数据读取
+    let format = if is_normal_map {
+      wgpu::TextureFormat::Rgba8Unorm
+    } else {
+      wgpu::TextureFormat::Rgba8UnormSrgb
+    };
+    // TEXTURE_BINDING 告诉 wgpu 我们想在着色器中使用这个纹理
+    // COPY_DST 则表示我们想把数据复制到这个纹理
+    // 生成 mip 时每一级都要作为渲染目标写入
+    let mut usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
+    if mip_level_count > 1 {
+      usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+    }
     let texture = device
       .get_device()
       .create_texture(&wgpu::TextureDescriptor {
         label,
         // 所有纹理都会以三维数组形式存储，我们通过设置深度为 1 来表示这是二维的纹理
         size,
-        mip_level_count: 1, // 我们后面会介绍这里的细节
+        mip_level_count,
         sample_count: 1,
         dimension: wgpu::TextureDimension::D2,
-        // 多数图像都使用 sRGB 格式，所以我们需要在此将其体现出来
-        format: wgpu::TextureFormat::Rgba8UnormSrgb,
-        // TEXTURE_BINDING 告诉 wgpu 我们想在着色器中使用这个纹理
-        // COPY_DST 则表示我们想把数据复制到这个纹理
-        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        format,
+        usage,
         view_formats: &[],
       });
 
@@ -85,8 +233,214 @@ impl Texture {
       },
       size,
     );
+    if mip_level_count > 1 {
+      Self::generate_mipmaps(device, queue, &texture, format, mip_level_count);
+    }
     // 我们无需手动配置纹理视图，让 wgpu 定义它即可
     let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let sampler = settings.sampler.create_sampler(device);
+    Ok(Self {
+      texture,
+      view,
+      sampler,
+    })
+  }
+
+  /// 生成 mip 链。设备没有提供共享的 [`MipmapGenerator`] 时临时创建一个
+  fn generate_mipmaps<T: DeviceTrait>(
+    device: &T,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    format: wgpu::TextureFormat,
+    mip_level_count: u32,
+  ) {
+    match device.mipmap_generator() {
+      Some(generator) => generator.generate(device, queue, texture, format, mip_level_count),
+      None => MipmapGenerator::new(device.get_device()).generate(
+        device,
+        queue,
+        texture,
+        format,
+        mip_level_count,
+      ),
+    }
+  }
+
+  /// 用新的设置替换采样器，使用该纹理的 bind group 需要重新创建
+  pub fn set_sampler<T: DeviceTrait>(&mut self, device: &T, settings: &SamplerSettings) {
+    self.sampler = settings.create_sampler(device);
+  }
+
+  /// 从 KTX2 或 DDS 中的压缩数据创建纹理，色彩空间由文件中的格式决定
+  ///
+  /// 设备不支持该压缩格式时先在 CPU 上解码；文件中只有一级 mip 时，
+  /// 解码后的纹理照常在 GPU 上生成 mip 链，压缩纹理则无法作为渲染目标生成
+  pub fn from_compressed<T: DeviceTrait>(
+    device: &T,
+    queue: &wgpu::Queue,
+    data: &compressed::TextureData,
+    label: Option<&str>,
+    settings: &TextureSettings,
+  ) -> Result<Self> {
+    // 压缩纹理第 0 级的尺寸必须是块尺寸的整数倍
+    let (block_width, block_height) = data.format.block_dimensions();
+    let supported = device
+      .get_device()
+      .features()
+      .contains(data.format.required_features())
+      && data.width.is_multiple_of(block_width)
+      && data.height.is_multiple_of(block_height);
+    let decoded;
+    let data = if supported {
+      data
+    } else {
+      debug!(
+        "{:?} is not supported by the device, decoding on the CPU",
+        data.format
+      );
+      decoded = data.decompress();
+      &decoded
+    };
+
+    let size = wgpu::Extent3d {
+      width: data.width,
+      height: data.height,
+      depth_or_array_layers: 1,
+    };
+    let renderable = data
+      .format
+      .guaranteed_format_features(device.get_device().features())
+      .allowed_usages
+      .contains(wgpu::TextureUsages::RENDER_ATTACHMENT);
+    let generate_mipmaps = settings.mipmaps && data.levels.len() == 1 && renderable;
+    let mip_level_count = if generate_mipmaps {
+      u32::BITS - data.width.max(data.height).leading_zeros()
+    } else if settings.mipmaps {
+      data.levels.len() as u32
+    } else {
+      1
+    };
+    let mut usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST;
+    if generate_mipmaps && mip_level_count > 1 {
+      usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+    }
+    let texture = device
+      .get_device()
+      .create_texture(&wgpu::TextureDescriptor {
+        label,
+        size,
+        mip_level_count,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: data.format,
+        usage,
+        view_formats: &[],
+      });
+
+    let (block_width, block_height) = data.format.block_dimensions();
+    let block_size = data.format.block_copy_size(None).unwrap_or(4);
+    let uploaded = if generate_mipmaps { 1 } else { mip_level_count };
+    for (level, bytes) in data.levels.iter().take(uploaded as usize).enumerate() {
+      let level = level as u32;
+      // 小于一个块的 mip 也按整块复制
+      let extent = size
+        .mip_level_size(level, wgpu::TextureDimension::D2)
+        .physical_size(data.format);
+      queue.write_texture(
+        wgpu::ImageCopyTexture {
+          texture: &texture,
+          mip_level: level,
+          origin: wgpu::Origin3d::ZERO,
+          aspect: wgpu::TextureAspect::All,
+        },
+        bytes,
+        wgpu::ImageDataLayout {
+          offset: 0,
+          bytes_per_row: Some(extent.width / block_width * block_size),
+          rows_per_image: Some(extent.height / block_height),
+        },
+        extent,
+      );
+    }
+    if generate_mipmaps && mip_level_count > 1 {
+      Self::generate_mipmaps(device, queue, &texture, data.format, mip_level_count);
+    }
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let sampler = settings.sampler.create_sampler(device);
+    Ok(Self {
+      texture,
+      view,
+      sampler,
+    })
+  }
+
+  /// 由六张图片组成的立方体贴图，顺序为 +X -X +Y -Y +Z -Z，
+  /// 每张图片都必须是同样大小的正方形
+  pub fn from_cube_images<T: DeviceTrait>(
+    device: &T,
+    queue: &wgpu::Queue,
+    faces: &[image::DynamicImage; 6],
+    label: Option<&str>,
+  ) -> Result<Self> {
+    let (width, height) = faces[0].dimensions();
+    if width != height {
+      return Err(eyre!("cube map face is not square: {width}x{height}"));
+    }
+    if let Some(face) = faces
+      .iter()
+      .find(|face| face.dimensions() != (width, height))
+    {
+      let (w, h) = face.dimensions();
+      return Err(eyre!(
+        "cube map faces differ in size: {width}x{height} and {w}x{h}"
+      ));
+    }
+
+    let size = wgpu::Extent3d {
+      width,
+      height,
+      depth_or_array_layers: 6,
+    };
+    let texture = device
+      .get_device()
+      .create_texture(&wgpu::TextureDescriptor {
+        label,
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+      });
+    for (layer, face) in faces.iter().enumerate() {
+      queue.write_texture(
+        wgpu::ImageCopyTexture {
+          texture: &texture,
+          mip_level: 0,
+          origin: wgpu::Origin3d {
+            x: 0,
+            y: 0,
+            z: layer as u32,
+          },
+          aspect: wgpu::TextureAspect::All,
+        },
+        &face.to_rgba8(),
+        wgpu::ImageDataLayout {
+          offset: 0,
+          bytes_per_row: Some(4 * width),
+          rows_per_image: Some(height),
+        },
+        wgpu::Extent3d {
+          depth_or_array_layers: 1,
+          ..size
+        },
+      );
+    }
+    let view = texture.create_view(&wgpu::TextureViewDescriptor {
+      dimension: Some(wgpu::TextureViewDimension::Cube),
+      ..Default::default()
+    });
     let sampler = device
       .get_device()
       .create_sampler(&wgpu::SamplerDescriptor {
@@ -94,8 +448,7 @@ impl Texture {
         address_mode_v: wgpu::AddressMode::ClampToEdge,
         address_mode_w: wgpu::AddressMode::ClampToEdge,
         mag_filter: wgpu::FilterMode::Linear,
-        min_filter: wgpu::FilterMode::Nearest,
-        mipmap_filter: wgpu::FilterMode::Nearest,
+        min_filter: wgpu::FilterMode::Linear,
         ..Default::default()
       });
     Ok(Self {
@@ -105,9 +458,11 @@ impl Texture {
     })
   }
 
+  /// sample_count 需要与使用它的 render pass 的颜色附件一致
   pub fn create_depth_texture<T>(
     device: &T,
     config: &wgpu::SurfaceConfiguration,
+    sample_count: u32,
     label: &str,
   ) -> Self
   where
@@ -122,15 +477,21 @@ impl Texture {
       height: config.height,
       depth_or_array_layers: 1,
     };
+    // 对这个纹理做渲染，因此需要给它添加 RENDER_ATTACHMENT 配置。
+    // 多重采样的深度纹理只作为附件使用，GL 后端下与颜色附件的采样位置才能一致
+    let usage = if sample_count > 1 {
+      wgpu::TextureUsages::RENDER_ATTACHMENT
+    } else {
+      wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+    };
     let desc = wgpu::TextureDescriptor {
       label: Some(label),
       size,
       mip_level_count: 1,
-      sample_count: 1,
+      sample_count,
       dimension: wgpu::TextureDimension::D2,
       format: Self::DEPTH_FORMAT,
-      usage: wgpu::TextureUsages::RENDER_ATTACHMENT // 对这个纹理做渲染，因此需要给它添加 RENDER_ATTACHMENT 配置
-            | wgpu::TextureUsages::TEXTURE_BINDING,
+      usage,
       view_formats: &[],
     };
     let texture = device.create_texture(&desc);
@@ -157,4 +518,393 @@ impl Texture {
       sampler,
     }
   }
+
+  /// 创建阴影贴图：每个投射阴影的光源占用数组中的一层，view 为整个数组
+  pub fn create_shadow_map<T: DeviceTrait>(
+    device: &T,
+    resolution: u32,
+    layers: u32,
+    label: &str,
+  ) -> Self {
+    Self::create_depth_array(
+      device,
+      resolution,
+      layers,
+      wgpu::TextureViewDimension::D2Array,
+      label,
+    )
+  }
+
+  /// 创建点光源的立方体阴影贴图：每个光源占用连续的 6 层，view 为立方体数组
+  pub fn create_shadow_cube_map<T: DeviceTrait>(
+    device: &T,
+    resolution: u32,
+    cubes: u32,
+    label: &str,
+  ) -> Self {
+    Self::create_depth_array(
+      device,
+      resolution,
+      cubes * 6,
+      wgpu::TextureViewDimension::CubeArray,
+      label,
+    )
+  }
+
+  fn create_depth_array<T: DeviceTrait>(
+    device: &T,
+    resolution: u32,
+    layers: u32,
+    dimension: wgpu::TextureViewDimension,
+    label: &str,
+  ) -> Self {
+    let device = device.get_device();
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+      label: Some(label),
+      size: wgpu::Extent3d {
+        width: resolution,
+        height: resolution,
+        depth_or_array_layers: layers,
+      },
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format: Self::DEPTH_FORMAT,
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+      view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor {
+      dimension: Some(dimension),
+      ..Default::default()
+    });
+    // 比较采样器配合线性过滤，每次采样即可得到 2x2 的硬件 PCF
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+      address_mode_u: wgpu::AddressMode::ClampToEdge,
+      address_mode_v: wgpu::AddressMode::ClampToEdge,
+      address_mode_w: wgpu::AddressMode::ClampToEdge,
+      mag_filter: wgpu::FilterMode::Linear,
+      min_filter: wgpu::FilterMode::Linear,
+      mipmap_filter: wgpu::FilterMode::Nearest,
+      compare: Some(wgpu::CompareFunction::LessEqual),
+      ..Default::default()
+    });
+
+    Self {
+      texture,
+      view,
+      sampler,
+    }
+  }
+
+  /// 创建一个可被渲染、采样并复制回 CPU 的颜色纹理，用于离屏渲染或 HDR 场景纹理
+  pub fn create_render_target<T>(
+    device: &T,
+    config: &wgpu::SurfaceConfiguration,
+    format: wgpu::TextureFormat,
+    label: &str,
+  ) -> Self
+  where
+    T: DeviceTrait,
+  {
+    let device = device.get_device();
+
+    let size = wgpu::Extent3d {
+      width: config.width,
+      height: config.height,
+      depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+      label: Some(label),
+      size,
+      mip_level_count: 1,
+      sample_count: 1,
+      dimension: wgpu::TextureDimension::D2,
+      format,
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+        | wgpu::TextureUsages::TEXTURE_BINDING
+        | wgpu::TextureUsages::COPY_SRC,
+      view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+      address_mode_u: wgpu::AddressMode::ClampToEdge,
+      address_mode_v: wgpu::AddressMode::ClampToEdge,
+      address_mode_w: wgpu::AddressMode::ClampToEdge,
+      mag_filter: wgpu::FilterMode::Linear,
+      min_filter: wgpu::FilterMode::Linear,
+      mipmap_filter: wgpu::FilterMode::Nearest,
+      ..Default::default()
+    });
+
+    Self {
+      texture,
+      view,
+      sampler,
+    }
+  }
+
+  /// 多重采样的颜色目标，渲染后解析到 HDR 场景纹理中，自身不需要保留内容
+  pub fn create_multisampled_target<T: DeviceTrait>(
+    device: &T,
+    config: &wgpu::SurfaceConfiguration,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+    label: &str,
+  ) -> Self {
+    let device = device.get_device();
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+      label: Some(label),
+      size: wgpu::Extent3d {
+        width: config.width,
+        height: config.height,
+        depth_or_array_layers: 1,
+      },
+      mip_level_count: 1,
+      sample_count,
+      dimension: wgpu::TextureDimension::D2,
+      format,
+      usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+      view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+    Self {
+      texture,
+      view,
+      sampler,
+    }
+  }
+
+  /// 能被 [`Texture::read_rgba8`] 读回的格式
+  pub fn is_rgba8(format: wgpu::TextureFormat) -> bool {
+    matches!(
+      format,
+      wgpu::TextureFormat::Rgba8Unorm
+        | wgpu::TextureFormat::Rgba8UnormSrgb
+        | wgpu::TextureFormat::Bgra8Unorm
+        | wgpu::TextureFormat::Bgra8UnormSrgb
+    )
+  }
+
+  /// 将一个 8 位颜色纹理复制回 CPU，返回 RGBA 图像
+  ///
+  /// 纹理需要带有 COPY_SRC 用途，BGRA 格式会被转换为 RGBA。其他格式或没有 COPY_SRC
+  /// 时返回错误
+  pub fn read_rgba8<T: DeviceTrait>(
+    device: &T,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+  ) -> Result<image::RgbaImage> {
+    if !Self::is_rgba8(texture.format()) {
+      bail!("cannot read back {:?} as 8-bit RGBA", texture.format());
+    }
+    if !texture.usage().contains(wgpu::TextureUsages::COPY_SRC) {
+      bail!("texture was not created with COPY_SRC and cannot be read back");
+    }
+    let device = device.get_device();
+    let size = texture.size();
+
+    // copy_texture_to_buffer 要求每行的字节数是 COPY_BYTES_PER_ROW_ALIGNMENT 的整数倍
+    let unpadded_bytes_per_row = 4 * size.width;
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(align) * align;
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Readback Buffer"),
+      size: (padded_bytes_per_row * size.height) as wgpu::BufferAddress,
+      usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+      mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+      label: Some("Readback Encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+      texture.as_image_copy(),
+      wgpu::ImageCopyBuffer {
+        buffer: &buffer,
+        layout: wgpu::ImageDataLayout {
+          offset: 0,
+          bytes_per_row: Some(padded_bytes_per_row),
+          rows_per_image: Some(size.height),
+        },
+      },
+      wgpu::Extent3d {
+        depth_or_array_layers: 1,
+        ..size
+      },
+    );
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    let (tx, rx) = std::sync::mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+      let _ = tx.send(result);
+    });
+    device.poll(wgpu::Maintain::Wait);
+    rx.recv()??;
+
+    let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * size.height) as usize);
+    {
+      let data = slice.get_mapped_range();
+      for row in data.chunks(padded_bytes_per_row as usize) {
+        pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+      }
+    }
+    buffer.unmap();
+
+    if matches!(
+      texture.format(),
+      wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+    ) {
+      for pixel in pixels.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+      }
+    }
+
+    image::RgbaImage::from_raw(size.width, size.height, pixels)
+      .ok_or_else(|| eyre!("readback buffer does not match texture size"))
+  }
+}
+
+/// 生成 mip 用的着色器、采样器与按格式缓存的管线。
+/// State 持有一个并通过 [`DeviceTrait::mipmap_generator`] 提供给加载的所有纹理共享
+pub struct MipmapGenerator {
+  shader: wgpu::ShaderModule,
+  layout: wgpu::BindGroupLayout,
+  pipeline_layout: wgpu::PipelineLayout,
+  sampler: wgpu::Sampler,
+  pipelines: Mutex<HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>>,
+}
+
+impl MipmapGenerator {
+  pub fn new(device: &wgpu::Device) -> Self {
+    let device = DeviceWarp::wrap(device);
+    let shader = device.create_shader_module(wgpu::include_wgsl!("../assets/mipmap.wgsl"));
+    let layout = device.create_bind_group_layout(
+      "mipmap_bind_group_layout",
+      &[
+        wgpu::BindGroupLayoutEntry {
+          binding: 0,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+          },
+          count: None,
+        },
+        wgpu::BindGroupLayoutEntry {
+          binding: 1,
+          visibility: wgpu::ShaderStages::FRAGMENT,
+          ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+          count: None,
+        },
+      ],
+    );
+    let pipeline_layout = device.create_pipeline_layout("Mipmap Pipeline Layout", &[&layout], &[]);
+    let sampler = device
+      .get_device()
+      .create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("mipmap_sampler"),
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+      });
+    Self {
+      shader,
+      layout,
+      pipeline_layout,
+      sampler,
+      pipelines: Mutex::new(HashMap::new()),
+    }
+  }
+
+  /// 依次把上一级 mip 线性缩小一半绘制到下一级，sRGB 纹理会在线性空间中平均
+  fn generate<T: DeviceTrait>(
+    &self,
+    device: &T,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    format: wgpu::TextureFormat,
+    mip_level_count: u32,
+  ) {
+    let views = (0..mip_level_count)
+      .map(|level| {
+        texture.create_view(&wgpu::TextureViewDescriptor {
+          label: Some("mip"),
+          base_mip_level: level,
+          mip_level_count: Some(1),
+          ..Default::default()
+        })
+      })
+      .collect::<Vec<_>>();
+
+    let mut encoder = device
+      .get_device()
+      .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Mipmap Encoder"),
+      });
+    let mut pipelines = self.pipelines.lock().unwrap();
+    let pipeline = pipelines
+      .entry(format)
+      .or_insert_with(|| self.create_pipeline(device.get_device(), format));
+    for pair in views.windows(2) {
+      let bind_group = device.create_bind_group(
+        "mipmap_bind_group",
+        &self.layout,
+        &[
+          wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(&pair[0]),
+          },
+          wgpu::BindGroupEntry {
+            binding: 1,
+            resource: wgpu::BindingResource::Sampler(&self.sampler),
+          },
+        ],
+      );
+      let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Mipmap Pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+          view: &pair[1],
+          resolve_target: None,
+          ops: wgpu::Operations {
+            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+            store: wgpu::StoreOp::Store,
+          },
+        })],
+        ..Default::default()
+      });
+      pass.set_pipeline(pipeline);
+      pass.set_bind_group(0, &bind_group, &[]);
+      pass.draw(0..3, 0..1);
+    }
+    drop(pipelines);
+    queue.submit(std::iter::once(encoder.finish()));
+  }
+
+  fn create_pipeline(
+    &self,
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+  ) -> wgpu::RenderPipeline {
+    DeviceWarp::wrap(device).create_render_pipeline(
+      "Mipmap Pipeline",
+      Some(&self.pipeline_layout),
+      wgpu::VertexState {
+        module: &self.shader,
+        entry_point: "vs_main",
+        buffers: &[],
+      },
+      wgpu::PrimitiveState::default(),
+      None,
+      wgpu::MultisampleState::default(),
+      wgpu::FragmentState {
+        module: &self.shader,
+        entry_point: "fs_main",
+        targets: &[Some(format.into())],
+      },
+      None,
+    )
+  }
 }
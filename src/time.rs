@@ -7,6 +7,8 @@ use once_cell::sync::Lazy;
 
 static PREVIOUS_TIME: Lazy<Mutex<Instant>> = Lazy::new(|| Mutex::new(Instant::now()));
 static DELTA_TIME: Lazy<Mutex<Duration>> = Lazy::new(|| Mutex::new(Duration::from_millis(1)));
+// 录制时使用固定的模拟帧间隔，而非真实流逝的时间
+static FIXED_DELTA: Lazy<Mutex<Option<f32>>> = Lazy::new(|| Mutex::new(None));
 
 pub fn update() {
   let mut previous_guard = PREVIOUS_TIME.lock().unwrap();
@@ -18,11 +20,18 @@ pub fn update() {
   *delta_guard = delta;
 }
 pub fn get_delta() -> f32 {
+  if let Some(delta) = *FIXED_DELTA.lock().unwrap() {
+    return delta;
+  }
   let delta_guard = DELTA_TIME.lock().unwrap();
   let delta = *delta_guard;
   delta.as_secs_f32()
 }
 
+pub fn set_fixed_delta(delta: Option<f32>) {
+  *FIXED_DELTA.lock().unwrap() = delta;
+}
+
 static START: Lazy<Instant> = Lazy::new(|| Instant::now());
 pub fn get_now() -> f32 {
   START.elapsed().as_secs_f32()